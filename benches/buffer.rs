@@ -0,0 +1,95 @@
+/*
+    Copyright 2020, John Pothier
+    This file is part of Five.
+
+    Five is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    Five is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with Five.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+// Compares ways of loading file content into a Buffer and saving it back out. Run with `cargo bench`.
+
+// The binary crate has no library target, so the modules under test are compiled in directly, along
+// with the parts of them that only the editor uses. Without a test harness, their test modules are
+// left with nothing but imports.
+#[allow(dead_code, unused_imports)]
+#[path = "../src/buffer.rs"]
+mod buffer;
+#[allow(dead_code, unused_imports)]
+#[path = "../src/text_position.rs"]
+mod text_position;
+
+use buffer::Buffer;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+use text_position::TextPosition;
+
+const CONTENT_SIZE: usize = 64 * 1024 * 1024;
+const ITERATIONS: u32 = 5;
+
+fn bench<F: FnMut()>(name: &str, mut f: F) -> Duration {
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        f();
+    }
+    let average = start.elapsed() / ITERATIONS;
+    println!("{:<40} {:>10.2?}", name, average);
+    average
+}
+
+fn main() {
+    let line = "The quick brown fox jumps over the lazy dog.\n";
+    let content = line.repeat(CONTENT_SIZE / line.len());
+    println!(
+        "{} MiB, average of {} iterations",
+        content.len() / (1024 * 1024),
+        ITERATIONS
+    );
+
+    let insert = bench("insert_at_cursor + move to beginning", || {
+        let mut buffer = Buffer::new();
+        buffer.insert_at_cursor(&content);
+        buffer.move_cursor_to_beginning();
+        assert_eq!(buffer.get().1.len(), content.len());
+    });
+    let from_str = bench("Buffer::from_str", || {
+        let buffer = Buffer::from_str(&content).unwrap();
+        assert_eq!(buffer.get().1.len(), content.len());
+    });
+    let from_reader = bench("Buffer::from_reader", || {
+        let buffer = Buffer::from_reader(&mut content.as_bytes(), content.len()).unwrap();
+        assert_eq!(buffer.get().1.len(), content.len());
+    });
+    println!(
+        "from_str is {:.1}x faster, from_reader is {:.1}x faster\n",
+        insert.as_secs_f64() / from_str.as_secs_f64(),
+        insert.as_secs_f64() / from_reader.as_secs_f64()
+    );
+
+    let mut buffer = Buffer::from_str(&content).unwrap();
+    buffer.move_cursor_to_position(TextPosition::new(1000, 0));
+    let mut output = Vec::with_capacity(content.len());
+    let concatenate = bench("get + concatenate", || {
+        output.clear();
+        let (left, right) = buffer.get();
+        let text = String::from(left) + right;
+        output.extend_from_slice(text.as_bytes());
+    });
+    let to_writer = bench("Buffer::to_writer", || {
+        output.clear();
+        buffer.to_writer(&mut output).unwrap();
+    });
+    println!(
+        "to_writer is {:.1}x faster",
+        concatenate.as_secs_f64() / to_writer.as_secs_f64()
+    );
+}
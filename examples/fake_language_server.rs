@@ -0,0 +1,252 @@
+/*
+    Copyright 2020, John Pothier
+    This file is part of Five.
+
+    Five is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    Five is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with Five.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+// A stand-in language server for the tests, which follows a simple script instead of understanding
+// any language:
+//
+// - every line containing "error" or "warning" gets a diagnostic on that word
+// - hovering over a word describes it as "`word`"
+// - the definition of a word is the line that contains "fn word"
+// - the references to a word are all of its occurrences
+// - completions are all the words in the document
+//
+// Documents are synced incrementally, so the diagnostics show whether edits were applied correctly.
+
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Write};
+
+fn read_message(reader: &mut impl BufRead) -> Option<Value> {
+    let mut content_length = 0;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header).ok()? == 0 {
+            return None;
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some(length) = header.strip_prefix("Content-Length: ") {
+            content_length = length.parse().ok()?;
+        }
+    }
+    let mut content = vec![0; content_length];
+    reader.read_exact(&mut content).ok()?;
+    serde_json::from_slice(&content).ok()
+}
+
+fn write_message(message: Value) {
+    let content = message.to_string();
+    let mut stdout = std::io::stdout();
+    write!(
+        stdout,
+        "Content-Length: {}\r\n\r\n{}",
+        content.len(),
+        content
+    )
+    .unwrap();
+    stdout.flush().unwrap();
+}
+
+fn byte_index(text: &str, position: &Value) -> usize {
+    let line = position["line"].as_u64().unwrap() as usize;
+    let character = position["character"].as_u64().unwrap() as usize;
+    let line_start: usize = text
+        .split_inclusive('\n')
+        .take(line)
+        .map(|line| line.len())
+        .sum();
+    let mut column = 0;
+    for (i, c) in text[line_start..].char_indices() {
+        if column >= character || c == '\n' {
+            return line_start + i;
+        }
+        column += c.len_utf16();
+    }
+    text.len()
+}
+
+fn position(text: &str, byte_index: usize) -> Value {
+    let before = &text[..byte_index];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    json!({
+        "line": before.matches('\n').count(),
+        "character": before[line_start..].encode_utf16().count(),
+    })
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+// The byte ranges of every word in the text.
+fn words(text: &str) -> Vec<(usize, usize)> {
+    let mut words = vec![];
+    let mut start = None;
+    for (i, c) in text
+        .char_indices()
+        .chain(std::iter::once((text.len(), ' ')))
+    {
+        match (start, is_word_char(c)) {
+            (None, true) => start = Some(i),
+            (Some(word_start), false) => {
+                words.push((word_start, i));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    words
+}
+
+fn word_at(text: &str, position: &Value) -> Option<(usize, usize)> {
+    let index = byte_index(text, position);
+    words(text)
+        .into_iter()
+        .find(|(start, end)| *start <= index && index <= *end)
+}
+
+fn location(uri: &str, text: &str, start: usize, end: usize) -> Value {
+    json!({
+        "uri": uri,
+        "range": {"start": position(text, start), "end": position(text, end)},
+    })
+}
+
+fn publish_diagnostics(uri: &str, text: &str) {
+    let diagnostics: Vec<Value> = words(text)
+        .into_iter()
+        .filter_map(|(start, end)| {
+            let severity = match &text[start..end] {
+                "error" => 1,
+                "warning" => 2,
+                _ => return None,
+            };
+            Some(json!({
+                "range": {"start": position(text, start), "end": position(text, end)},
+                "severity": severity,
+                "message": format!("found {}", &text[start..end]),
+            }))
+        })
+        .collect();
+    write_message(json!({
+        "jsonrpc": "2.0",
+        "method": "textDocument/publishDiagnostics",
+        "params": {"uri": uri, "diagnostics": diagnostics},
+    }));
+}
+
+fn respond(method: &str, params: &Value, documents: &HashMap<String, String>) -> Value {
+    let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+    let text = match documents.get(uri) {
+        Some(text) => text.as_str(),
+        None if method == "initialize" => {
+            return json!({
+                "capabilities": {
+                    "textDocumentSync": {"openClose": true, "change": 2, "save": true},
+                    "hoverProvider": true,
+                    "definitionProvider": true,
+                    "referencesProvider": true,
+                    "completionProvider": {},
+                },
+            })
+        }
+        None => return Value::Null,
+    };
+    let word = word_at(text, &params["position"]).map(|(start, end)| &text[start..end]);
+    match (method, word) {
+        ("textDocument/hover", Some(word)) => json!({
+            "contents": {"kind": "markdown", "value": format!("`{}`", word)},
+        }),
+        ("textDocument/definition", Some(word)) => {
+            let definition = format!("fn {}", word);
+            match text.find(&definition) {
+                Some(index) => json!([location(uri, text, index + 3, index + definition.len())]),
+                None => json!([]),
+            }
+        }
+        ("textDocument/references", Some(word)) => Value::from(
+            words(text)
+                .into_iter()
+                .filter(|(start, end)| &text[*start..*end] == word)
+                .map(|(start, end)| location(uri, text, start, end))
+                .collect::<Vec<Value>>(),
+        ),
+        ("textDocument/completion", _) => {
+            let mut labels: Vec<&str> = words(text)
+                .into_iter()
+                .map(|(start, end)| &text[start..end])
+                .collect();
+            labels.sort_unstable();
+            labels.dedup();
+            let items: Vec<Value> = labels
+                .into_iter()
+                .map(|label| json!({"label": label, "detail": "word"}))
+                .collect();
+            json!({"isIncomplete": false, "items": items})
+        }
+        _ => Value::Null,
+    }
+}
+
+fn main() {
+    let stdin = std::io::stdin();
+    let mut reader = BufReader::new(stdin.lock());
+    let mut documents: HashMap<String, String> = HashMap::new();
+    while let Some(message) = read_message(&mut reader) {
+        let method = message["method"].as_str().unwrap_or_default();
+        let params = &message["params"];
+        let uri = params["textDocument"]["uri"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string();
+        match method {
+            "exit" => return,
+            "textDocument/didOpen" => {
+                let text = params["textDocument"]["text"].as_str().unwrap_or_default();
+                documents.insert(uri.clone(), text.to_string());
+                publish_diagnostics(&uri, text);
+            }
+            "textDocument/didChange" => {
+                let text = documents.entry(uri.clone()).or_default();
+                for change in params["contentChanges"].as_array().unwrap() {
+                    let new_text = change["text"].as_str().unwrap();
+                    match change.get("range") {
+                        None => *text = new_text.to_string(),
+                        Some(range) => {
+                            let start = byte_index(text, &range["start"]);
+                            let end = byte_index(text, &range["end"]);
+                            text.replace_range(start..end, new_text);
+                        }
+                    }
+                }
+                publish_diagnostics(&uri, text);
+            }
+            "textDocument/didClose" => {
+                documents.remove(&uri);
+            }
+            _ => {
+                if let Some(id) = message.get("id") {
+                    let result = respond(method, params, &documents);
+                    write_message(json!({"jsonrpc": "2.0", "id": id, "result": result}));
+                }
+            }
+        }
+    }
+}
@@ -71,10 +71,21 @@
 // or use them separately. Usually, the client will just be rendering Left and Right next to
 // each other with the cursor in the middle, requiring no extra allocations.
 
+use crate::text_position::TextPosition;
+use std::borrow::Cow;
+use std::io::{Read, Write};
+use std::iter::once;
 use std::ops::Range;
+use std::str::FromStr;
 use unicode_segmentation::UnicodeSegmentation;
 const DEFAULT_INITIAL_CAPACITY: usize = 10 * 1024;
 
+// Buffers created from existing content get a gap proportional to the content, so that editing a large
+// file doesn't immediately force a reallocation.
+fn initial_gap_size(content_length: usize) -> usize {
+    std::cmp::max(DEFAULT_INITIAL_CAPACITY, content_length / 8)
+}
+
 pub struct Buffer {
     buffer: Vec<u8>,
     left_string_range: Range<usize>,
@@ -93,6 +104,53 @@ impl Buffer {
             right_string_range: capacity..capacity,
         }
     }
+
+    // Lays out the content as the Right string, behind the gap, so the cursor starts at the beginning
+    // without any copying through the gap:
+    // [X|X|X|X|a|b|c]
+    // The gap is sized up front from the expected length of the content, since moving the content to
+    // make room afterwards would copy all of it. Files are read into memory whole before they get here,
+    // because their encoding is detected from their content, so the reader is usually a byte slice.
+    pub fn from_reader<Reader: Read>(
+        reader: &mut Reader,
+        expected_length: usize,
+    ) -> std::io::Result<Buffer> {
+        let gap_size = initial_gap_size(expected_length);
+        let mut buffer = Vec::with_capacity(gap_size + expected_length);
+        buffer.resize(gap_size, 0);
+        reader.read_to_end(&mut buffer)?;
+        if let Err(error) = std::str::from_utf8(&buffer[gap_size..]) {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, error));
+        }
+        let length = buffer.len();
+        Ok(Buffer {
+            buffer,
+            left_string_range: 0..0,
+            right_string_range: gap_size..length,
+        })
+    }
+
+    pub fn to_writer<Writer: Write>(&self, writer: &mut Writer) -> std::io::Result<()> {
+        writer.write_all(&self.buffer[self.left_string_range.clone()])?;
+        writer.write_all(&self.buffer[self.right_string_range.clone()])
+    }
+}
+
+impl FromStr for Buffer {
+    type Err = std::convert::Infallible;
+
+    fn from_str(content: &str) -> Result<Buffer, Self::Err> {
+        let gap_size = initial_gap_size(content.len());
+        let mut buffer = Vec::with_capacity(gap_size + content.len());
+        buffer.resize(gap_size, 0);
+        buffer.extend_from_slice(content.as_bytes());
+        let length = buffer.len();
+        Ok(Buffer {
+            buffer,
+            left_string_range: 0..0,
+            right_string_range: gap_size..length,
+        })
+    }
 }
 
 impl Buffer {
@@ -105,6 +163,36 @@ impl Buffer {
         }
     }
 
+    // Iterates over all lines in the buffer. Only the line containing the cursor spans the gap, so
+    // it is the only one that needs to be copied.
+    pub fn lines(&self) -> impl Iterator<Item = Cow<'_, str>> {
+        let (left, right) = self.get();
+        let mut left_lines = left.split('\n');
+        let mut right_lines = right.split('\n');
+        let left_part = left_lines.next_back().unwrap();
+        let right_part = right_lines.next().unwrap();
+        let cursor_line = if left_part.is_empty() {
+            Cow::Borrowed(right_part)
+        } else if right_part.is_empty() {
+            Cow::Borrowed(left_part)
+        } else {
+            Cow::Owned(String::from(left_part) + right_part)
+        };
+        left_lines
+            .map(Cow::Borrowed)
+            .chain(once(cursor_line))
+            .chain(right_lines.map(Cow::Borrowed))
+    }
+
+    pub fn line(&self, index: usize) -> Option<Cow<'_, str>> {
+        self.lines().nth(index)
+    }
+
+    pub fn num_lines(&self) -> usize {
+        let (left, right) = self.get();
+        left.matches('\n').count() + right.matches('\n').count() + 1
+    }
+
     fn gap_size(&self) -> usize {
         self.right_string_range.start - self.left_string_range.end
     }
@@ -134,6 +222,7 @@ impl Buffer {
         self.left_string_range.end = target_cursor_buffer_index;
     }
 
+    #[cfg(test)]
     pub fn delete_all(&mut self) {
         self.left_string_range = 0..0;
         self.right_string_range = self.buffer.len()..self.buffer.len()
@@ -174,6 +263,9 @@ impl Buffer {
         self.move_cursor_left_to(target_cursor_buffer_index);
     }
 
+    // Moves to the same column of the next line, counted in graphemes, or to the end of the next line if
+    // it is shorter. The file view moves by visual columns instead, which depend on the tab width.
+    #[cfg(test)]
     pub fn move_cursor_down(&mut self) {
         // Algorithm should be:
         // - Figure out what column the cursor is at
@@ -181,37 +273,25 @@ impl Buffer {
         // - If the line has enough columns, move to that column, else move to the end of the line
         let (left, right) = self.get();
         let mut newline_indices = right.char_indices().filter(|(_, c)| *c == '\n');
-        let maybe_target_line_begin_index = newline_indices.nth(0);
-        if maybe_target_line_begin_index.is_none() {
-            return;
-        }
-
-        debug_assert!(right.len() > maybe_target_line_begin_index.unwrap().0 + 1);
-        let target_line_begin_index = maybe_target_line_begin_index.unwrap().0 + 1;
-        let target_line = match newline_indices.nth(1) {
+        let target_line_begin_index = match newline_indices.next() {
+            Some((i, _)) => i + 1,
+            None => return,
+        };
+        let target_line = match newline_indices.next() {
             Some((i, _)) => &right[target_line_begin_index..i],
             None => &right[target_line_begin_index..],
         };
 
-        let current_line = match left.rfind("\n") {
-            Some(i) => {
-                if left.len() > 1 {
-                    &left[(i + 1)..]
-                } else {
-                    ""
-                }
-            }
+        let current_line = match left.rfind('\n') {
+            Some(i) => &left[(i + 1)..],
             None => left,
         };
         let current_column = current_line.graphemes(true).count();
-        let target_index = target_line.grapheme_indices(true).nth(current_column);
-        let target_cursor_offset_from_target_line = match target_index {
-            Some((i, _)) => i,
-            None => target_line
-                .grapheme_indices(true)
-                .last()
-                .map_or(0, |(i, _)| i),
-        };
+        let target_cursor_offset_from_target_line =
+            match target_line.grapheme_indices(true).nth(current_column) {
+                Some((i, _)) => i,
+                None => target_line.len(),
+            };
         self.move_cursor_right_to(
             self.right_string_range.start
                 + target_cursor_offset_from_target_line
@@ -224,7 +304,86 @@ impl Buffer {
     }
 
     pub fn move_cursor_to_end(&mut self) {
-        self.move_cursor_right_to(self.right_string_range.end - 1);
+        self.move_cursor_right_to(self.right_string_range.end);
+    }
+
+    // Lines past the end of the buffer put the cursor at the end of the buffer. Columns past the
+    // end of the line put the cursor at the end of the line.
+    pub fn move_cursor_to_position(&mut self, position: TextPosition) {
+        // Offsets here are "logical": [0, left.len()) is the Left string, and everything after
+        // that is the Right string. Searching relative to the cursor keeps short moves cheap.
+        let cursor_position = self.cursor_position();
+        let (left, right) = self.get();
+        let line_begin_offset = if position.line <= cursor_position.line {
+            match left
+                .rmatch_indices('\n')
+                .nth(cursor_position.line - position.line)
+            {
+                Some((i, _)) => i + 1,
+                None => 0,
+            }
+        } else {
+            match right
+                .match_indices('\n')
+                .nth(position.line - cursor_position.line - 1)
+            {
+                Some((i, _)) => left.len() + i + 1,
+                None => {
+                    self.move_cursor_to_end();
+                    return;
+                }
+            }
+        };
+
+        let line = self.line_at_offset(line_begin_offset);
+        let column_offset = match line.grapheme_indices(true).nth(position.column) {
+            Some((i, _)) => i,
+            None => line.len(),
+        };
+        self.move_cursor_to_offset(line_begin_offset + column_offset);
+    }
+
+    // Returns the line starting at the given logical offset.
+    fn line_at_offset(&self, offset: usize) -> Cow<'_, str> {
+        let (left, right) = self.get();
+        fn until_newline(s: &str) -> &str {
+            match s.find('\n') {
+                Some(i) => &s[..i],
+                None => s,
+            }
+        }
+
+        if offset >= left.len() {
+            return Cow::Borrowed(until_newline(&right[offset - left.len()..]));
+        }
+
+        let left_part = &left[offset..];
+        if left_part.contains('\n') {
+            return Cow::Borrowed(until_newline(left_part));
+        }
+        let right_part = until_newline(right);
+        if right_part.is_empty() {
+            Cow::Borrowed(left_part)
+        } else {
+            Cow::Owned(String::from(left_part) + right_part)
+        }
+    }
+
+    fn move_cursor_to_offset(&mut self, offset: usize) {
+        let num_left_bytes = self.left_string_range.len();
+        if offset < num_left_bytes {
+            self.move_cursor_left_to(self.left_string_range.start + offset);
+        } else {
+            self.move_cursor_right_to(self.right_string_range.start + offset - num_left_bytes);
+        }
+    }
+
+    pub fn cursor_position(&self) -> TextPosition {
+        let (left, _) = self.get();
+        let line = left.matches('\n').count();
+        let line_begin_index = left.rfind('\n').map_or(0, |i| i + 1);
+        let column = left[line_begin_index..].graphemes(true).count();
+        TextPosition::new(line, column)
     }
 
     fn move_cursor_right_to(&mut self, target_cursor_buffer_index: usize) {
@@ -275,6 +434,38 @@ mod tests {
         assert_eq!(buffer.get(), ("", ""));
     }
 
+    #[test]
+    fn initialize_from_content() {
+        let mut buffer = Buffer::from_str("abc\ndef").unwrap();
+        assert_eq!(buffer.get(), ("", "abc\ndef"));
+        assert_eq!(buffer.gap_size(), DEFAULT_INITIAL_CAPACITY);
+        buffer.insert_at_cursor("x");
+        buffer.move_cursor_to_end();
+        assert_eq!(buffer.get(), ("xabc\ndef", ""));
+
+        let large_content = "a".repeat(DEFAULT_INITIAL_CAPACITY * 10);
+        let buffer =
+            Buffer::from_reader(&mut large_content.as_bytes(), large_content.len()).unwrap();
+        assert_eq!(buffer.get(), ("", large_content.as_str()));
+        assert_eq!(buffer.gap_size(), large_content.len() / 8);
+
+        // Content longer than expected still fits, behind the smaller gap
+        let buffer = Buffer::from_reader(&mut large_content.as_bytes(), 0).unwrap();
+        assert_eq!(buffer.get(), ("", large_content.as_str()));
+        assert_eq!(buffer.gap_size(), DEFAULT_INITIAL_CAPACITY);
+
+        assert!(Buffer::from_reader(&mut &b"\xff\xfe"[..], 2).is_err());
+    }
+
+    #[test]
+    fn write_to_writer() {
+        let mut buffer = Buffer::from_str("hello world").unwrap();
+        buffer.move_cursor(5);
+        let mut output = Vec::new();
+        buffer.to_writer(&mut output).unwrap();
+        assert_eq!(output, b"hello world");
+    }
+
     #[test]
     fn simple_insert() {
         let mut buffer = Buffer::with_initial_capacity(TEST_CAPACITY);
@@ -393,6 +584,78 @@ mod tests {
         assert_eq!(buffer.get(), ("Delete", ", please"));
     }
 
+    #[test]
+    fn move_cursor_to_end() {
+        let mut buffer = Buffer::with_initial_capacity(TEST_CAPACITY);
+        buffer.insert_at_cursor("To the end");
+        buffer.move_cursor_to_beginning();
+        buffer.move_cursor_to_end();
+        assert_eq!(buffer.get(), ("To the end", ""));
+    }
+
+    #[test]
+    fn move_cursor_to_position() {
+        let mut buffer = Buffer::with_initial_capacity(TEST_CAPACITY);
+        buffer.insert_at_cursor("first\nsecond\nthird");
+        buffer.move_cursor_to_position(TextPosition::new(1, 3));
+        assert_eq!(buffer.get(), ("first\nsec", "ond\nthird"));
+        assert_eq!(buffer.cursor_position(), TextPosition::new(1, 3));
+        buffer.move_cursor_to_position(TextPosition::new(2, 1));
+        assert_eq!(buffer.get(), ("first\nsecond\nt", "hird"));
+        buffer.move_cursor_to_position(TextPosition::new(1, 5));
+        assert_eq!(buffer.get(), ("first\nsecon", "d\nthird"));
+        buffer.move_cursor_to_position(TextPosition::new(0, 2));
+        assert_eq!(buffer.get(), ("fi", "rst\nsecond\nthird"));
+    }
+
+    #[test]
+    fn move_cursor_to_position_saturating() {
+        let mut buffer = Buffer::with_initial_capacity(TEST_CAPACITY);
+        buffer.insert_at_cursor("first\nsecond\nthird");
+        buffer.move_cursor_to_position(TextPosition::new(0, 100));
+        assert_eq!(buffer.get(), ("first", "\nsecond\nthird"));
+        buffer.move_cursor_to_position(TextPosition::new(100, 0));
+        assert_eq!(buffer.get(), ("first\nsecond\nthird", ""));
+        assert_eq!(buffer.cursor_position(), TextPosition::new(2, 5));
+    }
+
+    #[test]
+    fn lines() {
+        let mut buffer = Buffer::with_initial_capacity(TEST_CAPACITY);
+        buffer.insert_at_cursor("first\nsecond\nthird\n");
+        buffer.move_cursor_to_position(TextPosition::new(1, 3));
+        assert_eq!(
+            buffer.lines().collect::<Vec<_>>(),
+            vec!["first", "second", "third", ""]
+        );
+        assert_eq!(buffer.num_lines(), 4);
+    }
+
+    #[test]
+    fn move_cursor_down() {
+        let mut buffer = Buffer::with_initial_capacity(TEST_CAPACITY);
+        buffer.insert_at_cursor("first\nab\nthird\n");
+        buffer.move_cursor_to_position(TextPosition::new(0, 4));
+        buffer.move_cursor_down();
+        assert_eq!(buffer.get(), ("first\nab", "\nthird\n"));
+        buffer.move_cursor_down();
+        assert_eq!(buffer.get(), ("first\nab\nth", "ird\n"));
+        buffer.move_cursor_down();
+        assert_eq!(buffer.get(), ("first\nab\nthird\n", ""));
+        buffer.move_cursor_down();
+        assert_eq!(buffer.get(), ("first\nab\nthird\n", ""));
+    }
+
+    #[test]
+    fn delete_all() {
+        let mut buffer = Buffer::from_str("Delete all of this").unwrap();
+        buffer.move_cursor(6);
+        buffer.delete_all();
+        assert_eq!(buffer.get(), ("", ""));
+        buffer.insert_at_cursor("new");
+        assert_eq!(buffer.get(), ("new", ""));
+    }
+
     #[test]
     fn complex_1() {
         let mut buffer = Buffer::with_initial_capacity(1000);
@@ -0,0 +1,320 @@
+/*
+    Copyright 2020, John Pothier
+    This file is part of Five.
+
+    Five is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    Five is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with Five.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+// Runs the build command of a project and collects the problems that the compiler reported. Cargo's
+// JSON messages are understood, as is the usual "path:line:column: severity: message" format of other
+// compilers. Their columns count characters, which are taken as UTF-16 code units like the columns of
+// language servers; they only differ for characters outside of the Basic Multilingual Plane.
+
+use crate::diagnostics::{Diagnostic, Severity};
+use crate::text_position::TextPosition;
+use serde_json::Value;
+use std::convert::TryFrom;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::mpsc;
+use std::thread;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Problem {
+    pub path: String,
+    pub diagnostic: Diagnostic,
+}
+
+fn severity_of_level(level: &str) -> Option<Severity> {
+    match level {
+        "error" | "fatal error" | "error: internal compiler error" => Some(Severity::Error),
+        "warning" => Some(Severity::Warning),
+        "note" | "info" => Some(Severity::Information),
+        "help" => Some(Severity::Hint),
+        _ => None,
+    }
+}
+
+// Paths in compiler output are usually relative to the folder the build ran in.
+fn resolve_path(root_path: &Path, path: &str) -> String {
+    let path = root_path.join(path);
+    std::fs::canonicalize(&path)
+        .unwrap_or(path)
+        .to_string_lossy()
+        .into_owned()
+}
+
+// The position of a 1-based line and column.
+fn position(line: u64, column: u64) -> TextPosition {
+    let index = |number: u64| usize::try_from(number.saturating_sub(1)).unwrap_or(usize::MAX);
+    TextPosition::new(index(line), index(column))
+}
+
+// A "compiler-message" of cargo, at its primary span. Messages without a span, such as the summary
+// of how many warnings were emitted, are left out.
+fn parse_cargo_message(root_path: &Path, message: &Value) -> Option<Problem> {
+    if message["reason"] != "compiler-message" {
+        return None;
+    }
+    let message = &message["message"];
+    let severity = severity_of_level(message["level"].as_str()?)?;
+    let span = message["spans"]
+        .as_array()?
+        .iter()
+        .find(|span| span["is_primary"] == true)?;
+    Some(Problem {
+        path: resolve_path(root_path, span["file_name"].as_str()?),
+        diagnostic: Diagnostic {
+            severity,
+            start: position(span["line_start"].as_u64()?, span["column_start"].as_u64()?),
+            end: position(span["line_end"].as_u64()?, span["column_end"].as_u64()?),
+            message: String::from(message["message"].as_str()?),
+        },
+    })
+}
+
+// A line like "src/main.c:3:5: error: expected ';'". The column is optional.
+fn parse_line(root_path: &Path, line: &str) -> Option<Problem> {
+    let (path, rest) = line.split_once(':')?;
+    let (line_number, rest) = rest.split_once(':')?;
+    let line_number: u64 = line_number.trim().parse().ok()?;
+    let (column, rest) = match rest.split_once(':') {
+        Some((column, after_column)) => match column.trim().parse() {
+            Ok(column) => (column, after_column),
+            Err(_) => (1, rest),
+        },
+        None => (1, rest),
+    };
+    let (level, message) = rest.split_once(':')?;
+    let severity = severity_of_level(level.trim())?;
+    let path = path.trim();
+    if path.is_empty() {
+        return None;
+    }
+    let start = position(line_number, column);
+    Some(Problem {
+        path: resolve_path(root_path, path),
+        diagnostic: Diagnostic {
+            severity,
+            start,
+            end: start,
+            message: String::from(message.trim()),
+        },
+    })
+}
+
+pub fn parse_output(root_path: &Path, output: &str) -> Vec<Problem> {
+    output
+        .lines()
+        .filter_map(|line| match serde_json::from_str::<Value>(line) {
+            Ok(message) => parse_cargo_message(root_path, &message),
+            Err(_) => parse_line(root_path, line),
+        })
+        .collect()
+}
+
+// Runs one build at a time in the background.
+pub struct Builder {
+    command: String,
+    root_path: PathBuf,
+    receiver: Option<mpsc::Receiver<Result<Vec<Problem>, String>>>,
+}
+
+impl Builder {
+    pub fn new(command: String, root_path: PathBuf) -> Builder {
+        Builder {
+            command,
+            root_path,
+            receiver: None,
+        }
+    }
+
+    pub fn command(&self) -> &str {
+        &self.command
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.receiver.is_some()
+    }
+
+    // The problems are collected from both the standard output and the standard error of the
+    // command. A command that fails without reporting any problem is an error.
+    pub fn start(&mut self) {
+        let (sender, receiver) = mpsc::channel();
+        let command = self.command.clone();
+        let root_path = self.root_path.clone();
+        thread::spawn(move || {
+            let result = match Command::new("sh")
+                .arg("-c")
+                .arg(&command)
+                .current_dir(&root_path)
+                .output()
+            {
+                Err(err) => Err(format!("Could not run {}: {}", command, err)),
+                Ok(output) => {
+                    let stdout = String::from_utf8_lossy(&output.stdout);
+                    let stderr = String::from_utf8_lossy(&output.stderr);
+                    let mut problems = parse_output(&root_path, &stdout);
+                    problems.extend(parse_output(&root_path, &stderr));
+                    if problems.is_empty() && !output.status.success() {
+                        let last_line = stderr.lines().rev().find(|line| !line.trim().is_empty());
+                        Err(format!(
+                            "{} failed: {}",
+                            command,
+                            last_line.unwrap_or("no output")
+                        ))
+                    } else {
+                        Ok(problems)
+                    }
+                }
+            };
+            // Five may have exited in the meantime
+            let _ = sender.send(result);
+        });
+        self.receiver = Some(receiver);
+    }
+
+    // The result of the build, once it has finished.
+    pub fn poll(&mut self) -> Option<Result<Vec<Problem>, String>> {
+        let result = match self.receiver.as_ref()?.try_recv() {
+            Ok(result) => result,
+            Err(mpsc::TryRecvError::Empty) => return None,
+            Err(mpsc::TryRecvError::Disconnected) => Err(String::from("The build stopped")),
+        };
+        self.receiver = None;
+        Some(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::build::*;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn parse_cargo_messages() {
+        let output = concat!(
+            r#"{"reason":"compiler-artifact","target":{"name":"five"}}"#,
+            "\n",
+            r#"{"reason":"compiler-message","message":{"level":"warning","message":"unused variable: `x`","#,
+            r#""spans":[{"file_name":"src/main.rs","is_primary":true,"line_start":4,"line_end":4,"#,
+            r#""column_start":9,"column_end":10}]}}"#,
+            "\n",
+            r#"{"reason":"compiler-message","message":{"level":"error","message":"mismatched types","#,
+            r#""spans":[{"file_name":"/lib/std.rs","is_primary":false,"line_start":1,"line_end":1,"#,
+            r#""column_start":1,"column_end":2},{"file_name":"src/lib.rs","is_primary":true,"#,
+            r#""line_start":10,"line_end":12,"column_start":5,"column_end":1}]}}"#,
+            "\n",
+            r#"{"reason":"compiler-message","message":{"level":"warning","message":"1 warning emitted","spans":[]}}"#,
+            "\n",
+            r#"{"reason":"build-finished","success":false}"#,
+        );
+        assert_eq!(
+            parse_output(Path::new("/five"), output),
+            vec![
+                Problem {
+                    path: String::from("/five/src/main.rs"),
+                    diagnostic: Diagnostic {
+                        severity: Severity::Warning,
+                        start: TextPosition::new(3, 8),
+                        end: TextPosition::new(3, 9),
+                        message: String::from("unused variable: `x`"),
+                    },
+                },
+                Problem {
+                    path: String::from("/five/src/lib.rs"),
+                    diagnostic: Diagnostic {
+                        severity: Severity::Error,
+                        start: TextPosition::new(9, 4),
+                        end: TextPosition::new(11, 0),
+                        message: String::from("mismatched types"),
+                    },
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_compiler_lines() {
+        let output = "gcc -c main.c\n\
+                      main.c:3:5: error: expected ';' before 'return'\n\
+                      main.c: In function 'main':\n\
+                      /src/util.h:12: warning: unused parameter\n\
+                      make: *** [Makefile:2: main.o] Error 1\n";
+        let problems = parse_output(Path::new("/project"), output);
+        assert_eq!(
+            problems,
+            vec![
+                Problem {
+                    path: String::from("/project/main.c"),
+                    diagnostic: Diagnostic {
+                        severity: Severity::Error,
+                        start: TextPosition::new(2, 4),
+                        end: TextPosition::new(2, 4),
+                        message: String::from("expected ';' before 'return'"),
+                    },
+                },
+                Problem {
+                    path: String::from("/src/util.h"),
+                    diagnostic: Diagnostic {
+                        severity: Severity::Warning,
+                        start: TextPosition::new(11, 0),
+                        end: TextPosition::new(11, 0),
+                        message: String::from("unused parameter"),
+                    },
+                },
+            ]
+        );
+    }
+
+    fn wait_for_build(builder: &mut Builder) -> Result<Vec<Problem>, String> {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        loop {
+            if let Some(result) = builder.poll() {
+                return result;
+            }
+            assert!(Instant::now() < deadline, "the build did not finish");
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn run_build() {
+        let mut builder = Builder::new(
+            String::from("echo 'a.c:1:2: warning: careful' >&2; exit 1"),
+            PathBuf::from("/nonexistent-five-root"),
+        );
+        // The folder doesn't exist, so the command can't even start
+        builder.start();
+        assert!(wait_for_build(&mut builder).is_err());
+
+        let mut builder = Builder::new(
+            String::from("echo 'a.c:1:2: warning: careful' >&2; exit 1"),
+            std::env::temp_dir(),
+        );
+        builder.start();
+        assert!(builder.is_running());
+        let problems = wait_for_build(&mut builder).unwrap();
+        assert!(!builder.is_running());
+        assert_eq!(problems.len(), 1);
+        assert_eq!(problems[0].diagnostic.message, "careful");
+
+        let mut builder = Builder::new(String::from("echo oops >&2; false"), std::env::temp_dir());
+        builder.start();
+        assert_eq!(
+            wait_for_build(&mut builder),
+            Err(String::from("echo oops >&2; false failed: oops"))
+        );
+    }
+}
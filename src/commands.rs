@@ -0,0 +1,277 @@
+/*
+    Copyright 2020, John Pothier
+    This file is part of Five.
+
+    Five is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    Five is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with Five.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+// Everything that can be bound to a key. Components act on commands rather than on the keys
+// themselves, so that the keymap decides which keys do what.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Command {
+    Palette,
+    QuickOpen,
+    GoToLine,
+    GoToSymbol,
+    SearchSymbols,
+    CursorUp,
+    CursorDown,
+    CursorLeft,
+    CursorRight,
+    PageUp,
+    PageDown,
+    LineStart,
+    LineEnd,
+    GoToStart,
+    GoToEnd,
+    DeleteBackward,
+    Save,
+    LeaveEditor,
+    ToggleLineNumbers,
+    ToggleWrap,
+    NextEncoding,
+    ToggleLineEnding,
+    Open,
+    Close,
+    Expand,
+    Collapse,
+    GoUp,
+    ClearFilter,
+    ToggleTreeMode,
+    NextSortOrder,
+    ToggleDetails,
+    ToggleChangedOnly,
+    NewFile,
+    NewFolder,
+    Rename,
+    Duplicate,
+    Move,
+    Delete,
+    UndoFileOperation,
+    PreviousQuery,
+    NextQuery,
+    ClearHistory,
+    Hover,
+    GoToDefinition,
+    FindReferences,
+    Complete,
+    Build,
+    ShowProblems,
+    NextDiagnostic,
+    PreviousDiagnostic,
+}
+
+// The name that the keymap uses for each command, and what it does.
+pub const COMMANDS: &[(Command, &str, &str)] = &[
+    (Command::Palette, "command_palette", "List all commands"),
+    (Command::QuickOpen, "quick_open", "Find a file by name"),
+    (
+        Command::GoToLine,
+        "go_to_line",
+        "Go to a line of the open file",
+    ),
+    (
+        Command::GoToSymbol,
+        "go_to_symbol",
+        "Go to a definition in the open file",
+    ),
+    (
+        Command::SearchSymbols,
+        "search_symbols",
+        "Find a definition in any file",
+    ),
+    (Command::CursorUp, "cursor_up", "Move up"),
+    (Command::CursorDown, "cursor_down", "Move down"),
+    (Command::CursorLeft, "cursor_left", "Move left"),
+    (Command::CursorRight, "cursor_right", "Move right"),
+    (Command::PageUp, "page_up", "Move up a page"),
+    (Command::PageDown, "page_down", "Move down a page"),
+    (
+        Command::LineStart,
+        "line_start",
+        "Move to the start of the line",
+    ),
+    (Command::LineEnd, "line_end", "Move to the end of the line"),
+    (Command::GoToStart, "go_to_start", "Move to the first item"),
+    (Command::GoToEnd, "go_to_end", "Move to the last item"),
+    (
+        Command::DeleteBackward,
+        "delete_backward",
+        "Delete the character before the cursor",
+    ),
+    (Command::Save, "save", "Save the open file"),
+    (
+        Command::LeaveEditor,
+        "leave_editor",
+        "Go back to the file pane",
+    ),
+    (
+        Command::ToggleLineNumbers,
+        "toggle_line_numbers",
+        "Switch between absolute, relative and no line numbers",
+    ),
+    (
+        Command::ToggleWrap,
+        "toggle_wrap",
+        "Turn soft wrap on or off",
+    ),
+    (
+        Command::NextEncoding,
+        "next_encoding",
+        "Convert the open file to the next encoding",
+    ),
+    (
+        Command::ToggleLineEnding,
+        "toggle_line_ending",
+        "Switch the open file between LF and CRLF",
+    ),
+    (Command::Open, "open", "Open the selected item"),
+    (Command::Close, "close", "Close quick open"),
+    (Command::Expand, "expand", "Expand the selected folder"),
+    (
+        Command::Collapse,
+        "collapse",
+        "Collapse the selected folder",
+    ),
+    (
+        Command::GoUp,
+        "go_up",
+        "Go up to the parent folder, or shorten the filter",
+    ),
+    (
+        Command::ClearFilter,
+        "clear_filter",
+        "Clear the file tree filter",
+    ),
+    (
+        Command::ToggleTreeMode,
+        "toggle_tree_mode",
+        "Switch between the expandable and one-folder file tree",
+    ),
+    (
+        Command::NextSortOrder,
+        "next_sort_order",
+        "Sort files by the next order",
+    ),
+    (
+        Command::ToggleDetails,
+        "toggle_details",
+        "Show the next combination of file details",
+    ),
+    (
+        Command::ToggleChangedOnly,
+        "toggle_changed_only",
+        "Show only the files that git has changes for, or everything again",
+    ),
+    (Command::NewFile, "new_file", "Create a file"),
+    (Command::NewFolder, "new_folder", "Create a folder"),
+    (Command::Rename, "rename", "Rename the selected item"),
+    (
+        Command::Duplicate,
+        "duplicate",
+        "Duplicate the selected item",
+    ),
+    (
+        Command::Move,
+        "move",
+        "Move the selected item to another folder",
+    ),
+    (
+        Command::Delete,
+        "delete",
+        "Move the selected item to the trash",
+    ),
+    (
+        Command::UndoFileOperation,
+        "undo_file_operation",
+        "Undo the last file operation",
+    ),
+    (
+        Command::PreviousQuery,
+        "previous_query",
+        "Go back to an earlier quick open query",
+    ),
+    (
+        Command::NextQuery,
+        "next_query",
+        "Go forward to a later quick open query",
+    ),
+    (
+        Command::ClearHistory,
+        "clear_history",
+        "Forget the recently opened files and quick open queries",
+    ),
+    (
+        Command::Hover,
+        "hover",
+        "Show information about the symbol under the cursor",
+    ),
+    (
+        Command::GoToDefinition,
+        "go_to_definition",
+        "Go to the definition of the symbol under the cursor",
+    ),
+    (
+        Command::FindReferences,
+        "find_references",
+        "List the references to the symbol under the cursor",
+    ),
+    (
+        Command::Complete,
+        "complete",
+        "Suggest completions for the word before the cursor",
+    ),
+    (
+        Command::Build,
+        "build",
+        "Run the build command and list the problems it reports",
+    ),
+    (
+        Command::ShowProblems,
+        "show_problems",
+        "List the problems reported by the last build",
+    ),
+    (
+        Command::NextDiagnostic,
+        "next_diagnostic",
+        "Go to the next problem in the file",
+    ),
+    (
+        Command::PreviousDiagnostic,
+        "previous_diagnostic",
+        "Go to the previous problem in the file",
+    ),
+];
+
+impl Command {
+    pub fn from_name(name: &str) -> Option<Command> {
+        COMMANDS
+            .iter()
+            .find(|(_, command_name, _)| *command_name == name)
+            .map(|(command, _, _)| *command)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::commands::*;
+
+    #[test]
+    fn names_are_unique() {
+        for (command, name, _) in COMMANDS {
+            assert_eq!(Command::from_name(name), Some(*command));
+        }
+    }
+}
@@ -15,6 +15,7 @@
     You should have received a copy of the GNU General Public License
     along with Five.  If not, see <https://www.gnu.org/licenses/>.
 */
+use crate::commands::Command;
 use crate::event::Event;
 use crate::terminal::Rect;
 use std::io::Write;
@@ -39,5 +40,17 @@ pub trait Component {
 
     fn dispatch_event(&mut self, event: termion::event::Event) -> DispatchEventResult;
 
+    // Commands come from key chords in the keymap. Keys that aren't bound to a command are still
+    // dispatched as events, which is how text gets typed.
+    fn dispatch_command(&mut self, _command: Command) -> DispatchEventResult {
+        DispatchEventResult::empty()
+    }
+
+    // While a component is prompting for input, every key goes to it as an event instead of being
+    // looked up in the keymap.
+    fn is_prompting(&self) -> bool {
+        false
+    }
+
     fn dispatch_events(&mut self, events: &[Event]);
 }
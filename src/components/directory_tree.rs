@@ -0,0 +1,1524 @@
+/*
+    Copyright 2020, John Pothier
+    This file is part of Five.
+
+    Five is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    Five is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with Five.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use crate::commands::Command;
+use crate::components::component::{Component, DispatchEventResult};
+use crate::components::scrolling_list::ScrollingList;
+use crate::event::Event;
+use crate::file_operations::FileOperation;
+use crate::git_status::{GitState, GitStatus};
+use crate::indexer::index::{FileIndexEntry, FileTreeFolder, FileTreeNode, FileType, Index};
+use crate::painting_utils::{paint_empty_lines, paint_left_and_right_text, paint_truncated_text};
+use crate::session::TreeState;
+use crate::terminal::Rect;
+use crate::theme::{StyleName, Theme};
+use chrono::{DateTime, Datelike, Local};
+use std::cell::Cell;
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::io::Write;
+use std::path::Path;
+use std::time::SystemTime;
+use termion::event::Key;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TreeMode {
+    // Folders expand and collapse in place, showing their contents indented below them
+    Expandable,
+    // Only the contents of one folder are shown at a time
+    DrillDown,
+}
+
+// Folders always come first, in alphabetical order. The sort order is for the files after them.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SortOrder {
+    Name,
+    // Most recently modified first
+    Modified,
+    // Largest first
+    Size,
+    Extension,
+}
+
+impl SortOrder {
+    fn next(self) -> SortOrder {
+        match self {
+            SortOrder::Name => SortOrder::Modified,
+            SortOrder::Modified => SortOrder::Size,
+            SortOrder::Size => SortOrder::Extension,
+            SortOrder::Extension => SortOrder::Name,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            SortOrder::Name => "name",
+            SortOrder::Modified => "date",
+            SortOrder::Size => "size",
+            SortOrder::Extension => "type",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<SortOrder> {
+        let mut sort_order = SortOrder::Name;
+        loop {
+            if sort_order.name() == name {
+                return Some(sort_order);
+            }
+            sort_order = sort_order.next();
+            if sort_order == SortOrder::Name {
+                return None;
+            }
+        }
+    }
+}
+
+// Shown to the right of file names.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum DetailColumns {
+    None,
+    Size,
+    Modified,
+    SizeAndModified,
+}
+
+impl DetailColumns {
+    fn next(self) -> DetailColumns {
+        match self {
+            DetailColumns::None => DetailColumns::Size,
+            DetailColumns::Size => DetailColumns::Modified,
+            DetailColumns::Modified => DetailColumns::SizeAndModified,
+            DetailColumns::SizeAndModified => DetailColumns::None,
+        }
+    }
+}
+
+const SIZE_COLUMN_WIDTH: usize = 6;
+const MODIFIED_COLUMN_WIDTH: usize = 7;
+
+// Hides the items of one folder whose names don't contain the query.
+struct Filter {
+    folder_path: String,
+    query: String,
+}
+
+enum PromptKind {
+    // Creates the item in the folder at the path
+    NewFile(String),
+    NewFolder(String),
+    // The rest act on the item at the path
+    Rename(String),
+    Duplicate(String),
+    Move(String),
+    Delete(String),
+}
+
+struct Prompt {
+    kind: PromptKind,
+    input: String,
+}
+
+struct TreeRow<'a> {
+    node: &'a FileTreeNode,
+    depth: usize,
+}
+
+// Everything is tracked by path rather than by position, so that it survives the index being refreshed.
+pub struct DirectoryTreeComponent {
+    mode: TreeMode,
+    tree: Option<FileTreeNode>,
+    selected_path: Option<String>,
+    expanded_folders: HashSet<String>,
+    // The folder shown in drill-down mode
+    current_folder_path: Option<String>,
+    list: ScrollingList,
+    sort_order: SortOrder,
+    detail_columns: DetailColumns,
+    filter: Option<Filter>,
+    git_status: GitStatus,
+    // Hides files and folders that git has no changes for
+    changed_only: bool,
+    prompt: Option<Prompt>,
+    // Shown in place of the breadcrumb until the next key press, e.g. when a file operation fails
+    message: Option<String>,
+    // File operations are only started from the keyboard while the file pane has focus
+    has_focus: bool,
+    painted_rect: Cell<Option<Rect>>,
+    needs_paint: Cell<bool>,
+    theme: Theme,
+}
+
+// Indices of up to `count` items on either side of the selected one, nearest first.
+pub fn neighbouring_indices(selected: usize, len: usize, count: usize) -> Vec<usize> {
+    (1..=count)
+        .flat_map(|distance| {
+            vec![
+                selected.checked_add(distance),
+                selected.checked_sub(distance),
+            ]
+        })
+        .flatten()
+        .filter(|index| *index < len)
+        .collect()
+}
+
+// The default name for a copy of the item, like "main copy.rs" for "main.rs".
+fn duplicate_name(name: &str) -> String {
+    let path = Path::new(name);
+    match (
+        path.file_stem().and_then(|stem| stem.to_str()),
+        path.extension().and_then(|extension| extension.to_str()),
+    ) {
+        (Some(stem), Some(extension)) => format!("{} copy.{}", stem, extension),
+        _ => format!("{} copy", name),
+    }
+}
+
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty() && name != "." && name != ".." && !name.contains('/')
+}
+
+fn sibling_path(path: &str, name: &str) -> String {
+    let parent = Path::new(path).parent().unwrap_or_else(|| Path::new("/"));
+    String::from(parent.join(name).to_str().unwrap())
+}
+
+fn compare_names(a: &str, b: &str) -> Ordering {
+    a.to_lowercase()
+        .cmp(&b.to_lowercase())
+        .then_with(|| a.cmp(b))
+}
+
+fn extension(name: &str) -> &str {
+    Path::new(name)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or("")
+}
+
+fn compare_nodes(sort_order: SortOrder, a: &FileTreeNode, b: &FileTreeNode) -> Ordering {
+    match (a, b) {
+        (FileTreeNode::Folder(a), FileTreeNode::Folder(b)) => {
+            compare_names(&a.folder_name, &b.folder_name)
+        }
+        (FileTreeNode::Folder(_), FileTreeNode::File(_)) => Ordering::Less,
+        (FileTreeNode::File(_), FileTreeNode::Folder(_)) => Ordering::Greater,
+        (FileTreeNode::File(a), FileTreeNode::File(b)) => {
+            let by_name = || compare_names(&a.file_name, &b.file_name);
+            match sort_order {
+                SortOrder::Name => by_name(),
+                SortOrder::Modified => b.metadata.modified.cmp(&a.metadata.modified),
+                SortOrder::Size => b.metadata.size.cmp(&a.metadata.size),
+                SortOrder::Extension => extension(&a.file_name)
+                    .to_lowercase()
+                    .cmp(&extension(&b.file_name).to_lowercase()),
+            }
+            .then_with(by_name)
+        }
+    }
+}
+
+// Like "812B", "4.0K" or "13M".
+fn format_size(size: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "K", "M", "G", "T"];
+    let mut value = size as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit + 1 < UNITS.len() {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{}B", size)
+    } else if value < 10.0 {
+        format!("{:.1}{}", value, UNITS[unit])
+    } else {
+        format!("{:.0}{}", value, UNITS[unit])
+    }
+}
+
+// Like "Mar  4" for this year, or just the year for older files.
+fn format_modified(modified: SystemTime) -> String {
+    let modified = DateTime::<Local>::from(modified);
+    if modified.year() == Local::now().year() {
+        modified.format("%b %e").to_string()
+    } else {
+        modified.format("%Y").to_string()
+    }
+}
+
+impl DirectoryTreeComponent {
+    pub fn new() -> DirectoryTreeComponent {
+        DirectoryTreeComponent {
+            mode: TreeMode::Expandable,
+            tree: None,
+            selected_path: None,
+            expanded_folders: HashSet::new(),
+            current_folder_path: None,
+            list: ScrollingList::new(),
+            sort_order: SortOrder::Name,
+            detail_columns: DetailColumns::None,
+            filter: None,
+            git_status: GitStatus::default(),
+            changed_only: false,
+            prompt: None,
+            message: None,
+            has_focus: true,
+            painted_rect: Cell::new(None),
+            needs_paint: Cell::new(true),
+            theme: Theme::new(),
+        }
+    }
+
+    pub fn set_theme(&mut self, theme: &Theme) {
+        self.theme = theme.clone();
+        self.needs_paint.set(true);
+    }
+
+    pub fn set_has_focus(&mut self, has_focus: bool) {
+        self.has_focus = has_focus;
+    }
+
+    pub fn show_message(&mut self, message: String) {
+        self.message = Some(message);
+        self.needs_paint.set(true);
+    }
+
+    pub fn set_git_status(&mut self, git_status: GitStatus) {
+        self.git_status = git_status;
+        self.needs_paint.set(true);
+    }
+
+    fn is_changed(&self, node: &FileTreeNode) -> bool {
+        self.git_status
+            .state(node.path())
+            .is_some_and(GitState::is_changed)
+    }
+
+    // Expands the folders containing changes as well, so that they can all be seen at once.
+    fn toggle_changed_only(&mut self) {
+        self.changed_only = !self.changed_only;
+        if !self.changed_only || self.mode != TreeMode::Expandable {
+            return;
+        }
+        if let Some(tree) = &self.tree {
+            let changed_folders = self
+                .git_status
+                .changed_folders()
+                .filter(|path| tree.path_to(path).is_some())
+                .map(String::from);
+            self.expanded_folders.extend(changed_folders);
+        }
+    }
+
+    // Selects the item, revealing it if it is inside a collapsed folder or outside the shown folder.
+    pub fn select_path(&mut self, path: &str) {
+        let parent_paths: Vec<String> = match self.tree.as_ref().and_then(|tree| tree.path_to(path))
+        {
+            None => return,
+            Some(mut nodes) => {
+                nodes.pop();
+                nodes.iter().map(|node| String::from(node.path())).collect()
+            }
+        };
+        match self.mode {
+            TreeMode::Expandable => self.expanded_folders.extend(parent_paths),
+            TreeMode::DrillDown => self.current_folder_path = parent_paths.last().cloned(),
+        }
+        self.selected_path = Some(String::from(path));
+        self.scroll_to_selection();
+        self.needs_paint.set(true);
+    }
+
+    // Reveals the folder and shows what is in it: expanded, or as the current folder in drill-down
+    // mode.
+    pub fn open_folder(&mut self, path: &str) {
+        self.select_path(path);
+        if self.selected_path.as_deref() != Some(path) {
+            return;
+        }
+        match self.mode {
+            TreeMode::Expandable => self.set_expanded(String::from(path), true),
+            TreeMode::DrillDown => {
+                self.current_folder_path = Some(String::from(path));
+                self.selected_path = None;
+                self.filter = None;
+            }
+        }
+        self.scroll_to_selection();
+        self.needs_paint.set(true);
+    }
+
+    pub fn update_index(&mut self, index: Index) {
+        self.tree = Some(index.tree);
+        self.drop_missing_paths();
+    }
+
+    pub fn state(&self) -> TreeState {
+        let mut expanded_folders: Vec<String> = self.expanded_folders.iter().cloned().collect();
+        expanded_folders.sort();
+        TreeState {
+            mode: self.mode,
+            sort_order: self.sort_order,
+            selected_path: self.selected_path.clone(),
+            current_folder_path: self.current_folder_path.clone(),
+            expanded_folders,
+        }
+    }
+
+    // Paths that no longer exist are dropped, as when the index is refreshed.
+    pub fn restore_state(&mut self, state: TreeState) {
+        self.mode = state.mode;
+        self.sort_order = state.sort_order;
+        self.selected_path = state.selected_path;
+        self.current_folder_path = state.current_folder_path;
+        self.expanded_folders = state.expanded_folders.into_iter().collect();
+        self.drop_missing_paths();
+        self.scroll_to_selection();
+    }
+
+    // Drops whatever no longer exists, falling back to the closest folder that does.
+    fn drop_missing_paths(&mut self) {
+        self.needs_paint.set(true);
+        let tree = match self.tree.as_ref() {
+            None => return,
+            Some(tree) => tree,
+        };
+        self.expanded_folders
+            .retain(|path| tree.path_to(path).is_some());
+        if let Some(current_folder_path) = &self.current_folder_path {
+            if tree.path_to(current_folder_path).is_none() {
+                self.current_folder_path = None;
+            }
+        }
+        if let Some(selected_path) = &self.selected_path {
+            if tree.path_to(selected_path).is_none() {
+                self.selected_path = None;
+            }
+        }
+    }
+
+    pub fn toggle_mode(&mut self) {
+        let parent_path = self
+            .selected_parent()
+            .map(|parent| String::from(parent.path()));
+        match self.mode {
+            TreeMode::Expandable => {
+                // Drill down to where the selection is, so it stays visible
+                self.mode = TreeMode::DrillDown;
+                self.current_folder_path = parent_path;
+            }
+            TreeMode::DrillDown => {
+                self.mode = TreeMode::Expandable;
+                if let (Some(tree), Some(current_folder_path)) =
+                    (&self.tree, &self.current_folder_path)
+                {
+                    for node in tree.path_to(current_folder_path).unwrap_or_default() {
+                        self.expanded_folders.insert(String::from(node.path()));
+                    }
+                }
+            }
+        }
+        self.needs_paint.set(true);
+    }
+
+    fn current_folder(&self) -> Option<&FileTreeNode> {
+        let tree = self.tree.as_ref()?;
+        match (&self.mode, &self.current_folder_path) {
+            (TreeMode::DrillDown, Some(path)) => tree.path_to(path)?.pop(),
+            _ => Some(tree),
+        }
+    }
+
+    fn rows(&self) -> Vec<TreeRow<'_>> {
+        let mut rows = Vec::new();
+        if let Some(FileTreeNode::Folder(folder)) = self.current_folder() {
+            self.add_rows(&mut rows, folder, 0);
+        }
+        rows
+    }
+
+    fn add_rows<'a>(&self, rows: &mut Vec<TreeRow<'a>>, folder: &'a FileTreeFolder, depth: usize) {
+        let mut children: Vec<&FileTreeNode> = match &self.filter {
+            Some(filter) if filter.folder_path == folder.path => {
+                let query = filter.query.to_lowercase();
+                folder
+                    .children
+                    .iter()
+                    .filter(|child| child.name().to_lowercase().contains(&query))
+                    .collect()
+            }
+            _ => folder.children.iter().collect(),
+        };
+        if self.changed_only {
+            children.retain(|child| self.is_changed(child));
+        }
+        children.sort_by(|a, b| compare_nodes(self.sort_order, a, b));
+
+        for node in children {
+            rows.push(TreeRow { node, depth });
+            if let (TreeMode::Expandable, FileTreeNode::Folder(folder)) = (&self.mode, node) {
+                if self.expanded_folders.contains(&folder.path) {
+                    self.add_rows(rows, folder, depth + 1);
+                }
+            }
+        }
+    }
+
+    fn selected_row_index(&self, rows: &[TreeRow]) -> Option<usize> {
+        let selected_path = self.selected_path.as_ref()?;
+        rows.iter().position(|row| row.node.path() == selected_path)
+    }
+
+    fn selected_node(&self) -> Option<&FileTreeNode> {
+        self.tree
+            .as_ref()?
+            .path_to(self.selected_path.as_ref()?)?
+            .pop()
+    }
+
+    // The folder containing the selection, or the current folder if nothing is selected.
+    fn selected_parent(&self) -> Option<&FileTreeNode> {
+        let tree = self.tree.as_ref()?;
+        match &self.selected_path {
+            None => self.current_folder(),
+            Some(selected_path) => {
+                let mut nodes = tree.path_to(selected_path)?;
+                nodes.pop();
+                nodes.pop()
+            }
+        }
+    }
+
+    fn select_row(&mut self, index: usize) {
+        let path = self
+            .rows()
+            .get(index)
+            .map(|row| String::from(row.node.path()));
+        if path.is_some() {
+            self.selected_path = path;
+        }
+    }
+
+    pub fn neighbouring_files(&self, count: usize) -> Vec<FileIndexEntry> {
+        let rows = self.rows();
+        match self.selected_row_index(&rows) {
+            None => vec![],
+            Some(selected_index) => neighbouring_indices(selected_index, rows.len(), count)
+                .into_iter()
+                .filter_map(|index| match rows[index].node {
+                    FileTreeNode::File(file_index_entry) => Some(file_index_entry.clone()),
+                    FileTreeNode::Folder(_) => None,
+                })
+                .collect(),
+        }
+    }
+
+    // Returns false if the command isn't for moving the selection.
+    fn navigate(&mut self, command: Command) -> bool {
+        let rows = self.rows();
+        let selected_index = self.selected_row_index(&rows);
+        match self.list.navigate(command, selected_index, rows.len()) {
+            None => false,
+            Some(next_index) => {
+                if let Some(next_index) = next_index {
+                    self.select_row(next_index);
+                }
+                true
+            }
+        }
+    }
+
+    // Finishes handling something that may have moved the selection.
+    fn selection_changed(&mut self, mut events: Vec<Event>) -> DispatchEventResult {
+        self.needs_paint.set(true);
+        self.scroll_to_selection();
+
+        // Opening a file already shows it
+        if events.is_empty() {
+            if let Some(node) = self.selected_node() {
+                events.push(Event::FileItemSelected(node.clone()))
+            }
+        }
+        DispatchEventResult {
+            handled: true,
+            events,
+        }
+    }
+
+    // Selects the row under the pointer. On a double click, also opens it and returns any resulting event.
+    fn click(&mut self, x: u16, y: u16) -> Option<Event> {
+        let rect = self.painted_rect.get()?;
+        // The first row is the header
+        if !rect.contains(x, y) || y == rect.top {
+            return None;
+        }
+        let num_rows = self.rows().len();
+        let (index, is_double_click) = self.list.click(usize::from(y - rect.top - 1), num_rows)?;
+        self.select_row(index);
+        if is_double_click {
+            self.open_selected_item()
+        } else {
+            None
+        }
+    }
+
+    fn scroll_to_selection(&mut self) {
+        let selected_index = self.selected_row_index(&self.rows());
+        match selected_index {
+            Some(selected_index) => self.list.scroll_to_item(selected_index),
+            None => self.list.reset(),
+        }
+    }
+
+    fn set_expanded(&mut self, path: String, expanded: bool) {
+        if expanded {
+            self.expanded_folders.insert(path);
+        } else {
+            self.expanded_folders.remove(&path);
+        }
+    }
+
+    // Expands the selected folder, or moves into it if it is already expanded.
+    fn expand_selected_folder(&mut self) {
+        let path = match self.selected_node() {
+            Some(FileTreeNode::Folder(folder)) => folder.path.clone(),
+            _ => return,
+        };
+        if self.expanded_folders.contains(&path) {
+            self.navigate(Command::CursorDown);
+        } else {
+            self.set_expanded(path, true);
+        }
+    }
+
+    // Collapses the selected folder, or moves to the parent folder if it is already collapsed.
+    fn collapse_selected_folder(&mut self) {
+        if let Some(FileTreeNode::Folder(folder)) = self.selected_node() {
+            if self.expanded_folders.contains(&folder.path) {
+                let path = folder.path.clone();
+                self.set_expanded(path, false);
+                return;
+            }
+        }
+        let is_root =
+            |node: &FileTreeNode| Some(node.path()) == self.tree.as_ref().map(|tree| tree.path());
+        if let Some(parent) = self.selected_parent() {
+            if !is_root(parent) {
+                self.selected_path = Some(String::from(parent.path()));
+            }
+        }
+    }
+
+    fn open_selected_item(&mut self) -> Option<Event> {
+        let path = match self.selected_node()? {
+            FileTreeNode::File(file_index_entry) => {
+                return Some(Event::FileItemOpened(file_index_entry.clone()))
+            }
+            FileTreeNode::Folder(folder) => folder.path.clone(),
+        };
+        match self.mode {
+            TreeMode::Expandable => {
+                let expanded = self.expanded_folders.contains(&path);
+                self.set_expanded(path, !expanded);
+            }
+            TreeMode::DrillDown => {
+                self.current_folder_path = Some(path);
+                self.selected_path = None;
+                self.filter = None;
+            }
+        }
+        None
+    }
+
+    fn leave_current_folder(&mut self) {
+        match self.mode {
+            TreeMode::Expandable => self.collapse_selected_folder(),
+            TreeMode::DrillDown => {
+                let parent_path = match (&self.tree, &self.current_folder_path) {
+                    (Some(tree), Some(current_folder_path)) => {
+                        let mut nodes = tree.path_to(current_folder_path).unwrap_or_default();
+                        nodes.pop();
+                        nodes.last().map(|parent| String::from(parent.path()))
+                    }
+                    _ => return,
+                };
+                self.current_folder_path = parent_path;
+                self.selected_path = None;
+                self.filter = None;
+            }
+        }
+    }
+
+    // Adds the character to the filter, starting one for the folder containing the selection if needed.
+    fn extend_filter(&mut self, c: char) {
+        if self.filter.is_none() {
+            let folder_path = match self.mode {
+                TreeMode::DrillDown => self.current_folder(),
+                TreeMode::Expandable => self.selected_parent(),
+            }
+            .map(|folder| String::from(folder.path()));
+            let folder_path = match folder_path {
+                None => return,
+                Some(folder_path) => folder_path,
+            };
+            self.filter = Some(Filter {
+                folder_path,
+                query: String::new(),
+            });
+        }
+        let filter = self.filter.as_mut().unwrap();
+        filter.query.push(c);
+
+        // Move the selection onto the first match if it was filtered out
+        let rows = self.rows();
+        if self.selected_row_index(&rows).is_none() {
+            let folder_path = &self.filter.as_ref().unwrap().folder_path;
+            let first_match = rows.iter().position(|row| {
+                Path::new(row.node.path()).parent() == Some(Path::new(folder_path))
+            });
+            if let Some(first_match) = first_match {
+                self.select_row(first_match);
+            }
+        }
+    }
+
+    // Returns false if there was no filter to shorten.
+    fn shorten_filter(&mut self) -> bool {
+        let filter = match self.filter.as_mut() {
+            None => return false,
+            Some(filter) => filter,
+        };
+        filter.query.pop();
+        if filter.query.is_empty() {
+            self.filter = None;
+        }
+        true
+    }
+
+    // New items go into the selected folder, or next to the selected file.
+    fn target_folder_path(&self) -> Option<String> {
+        match self.selected_node() {
+            Some(FileTreeNode::Folder(folder)) => Some(folder.path.clone()),
+            _ => self
+                .selected_parent()
+                .map(|parent| String::from(parent.path())),
+        }
+    }
+
+    fn relative_path(&self, path: &str) -> String {
+        let root_path = self.tree.as_ref().map(|tree| tree.path()).unwrap_or("/");
+        match Path::new(path).strip_prefix(root_path) {
+            Ok(relative_path) => String::from(relative_path.to_str().unwrap()),
+            Err(_) => String::from(path),
+        }
+    }
+
+    fn start_prompt(&mut self, command: Command) -> bool {
+        let selected_path = self.selected_path.clone();
+        let selected_name = self.selected_node().map(|node| String::from(node.name()));
+        let prompt = match (command, selected_path, selected_name) {
+            (Command::NewFile, _, _) => self.target_folder_path().map(|folder_path| Prompt {
+                kind: PromptKind::NewFile(folder_path),
+                input: String::new(),
+            }),
+            (Command::NewFolder, _, _) => self.target_folder_path().map(|folder_path| Prompt {
+                kind: PromptKind::NewFolder(folder_path),
+                input: String::new(),
+            }),
+            (Command::Rename, Some(path), Some(name)) => Some(Prompt {
+                kind: PromptKind::Rename(path),
+                input: name,
+            }),
+            (Command::Duplicate, Some(path), Some(name)) => Some(Prompt {
+                kind: PromptKind::Duplicate(path),
+                input: duplicate_name(&name),
+            }),
+            (Command::Move, Some(path), Some(_)) => {
+                let parent_path = Path::new(&path).parent().unwrap().to_str().unwrap();
+                let input = self.relative_path(parent_path);
+                Some(Prompt {
+                    kind: PromptKind::Move(path),
+                    input,
+                })
+            }
+            (Command::Delete, Some(path), Some(_)) => Some(Prompt {
+                kind: PromptKind::Delete(path),
+                input: String::new(),
+            }),
+            _ => return false,
+        };
+        self.prompt = prompt;
+        true
+    }
+
+    fn prompt_text(&self, prompt: &Prompt) -> String {
+        let name = |path: &str| {
+            let name = Path::new(path).file_name().and_then(|name| name.to_str());
+            String::from(name.unwrap_or(path))
+        };
+        match &prompt.kind {
+            PromptKind::NewFile(folder_path) => {
+                format!("New file in {}: {}", name(folder_path), prompt.input)
+            }
+            PromptKind::NewFolder(folder_path) => {
+                format!("New folder in {}: {}", name(folder_path), prompt.input)
+            }
+            PromptKind::Rename(_) => format!("Rename to: {}", prompt.input),
+            PromptKind::Duplicate(_) => format!("Duplicate as: {}", prompt.input),
+            PromptKind::Move(_) => format!("Move to folder: {}", prompt.input),
+            PromptKind::Delete(path) => format!("Delete {}? (y/n)", name(path)),
+        }
+    }
+
+    // The operation that the finished prompt asks for, or an explanation of what is wrong with the input.
+    fn prompt_operation(&self, prompt: &Prompt) -> Result<FileOperation, String> {
+        let name = prompt.input.trim();
+        let invalid_name = || Err(format!("\"{}\" is not a valid name", name));
+        match &prompt.kind {
+            PromptKind::NewFile(folder_path) | PromptKind::NewFolder(folder_path) => {
+                if !is_valid_name(name) {
+                    return invalid_name();
+                }
+                let path = String::from(Path::new(folder_path).join(name).to_str().unwrap());
+                match prompt.kind {
+                    PromptKind::NewFile(_) => Ok(FileOperation::CreateFile(path)),
+                    _ => Ok(FileOperation::CreateFolder(path)),
+                }
+            }
+            PromptKind::Rename(path) | PromptKind::Duplicate(path) => {
+                if !is_valid_name(name) {
+                    return invalid_name();
+                }
+                let new_path = sibling_path(path, name);
+                match prompt.kind {
+                    PromptKind::Rename(_) => Ok(FileOperation::Move(path.clone(), new_path)),
+                    _ => Ok(FileOperation::Duplicate(path.clone(), new_path)),
+                }
+            }
+            PromptKind::Move(path) => {
+                let tree = self.tree.as_ref().ok_or_else(String::new)?;
+                let folder_path = Path::new(tree.path()).join(name.trim_matches('/'));
+                let folder_path = folder_path.to_str().unwrap().trim_end_matches('/');
+                match tree.path_to(folder_path).and_then(|mut nodes| nodes.pop()) {
+                    Some(FileTreeNode::Folder(folder)) => {
+                        let name = Path::new(path).file_name().unwrap();
+                        let new_path = Path::new(&folder.path).join(name);
+                        Ok(FileOperation::Move(
+                            path.clone(),
+                            String::from(new_path.to_str().unwrap()),
+                        ))
+                    }
+                    _ => Err(format!("There is no folder \"{}\"", name)),
+                }
+            }
+            PromptKind::Delete(path) => Ok(FileOperation::Trash(path.clone())),
+        }
+    }
+
+    fn dispatch_prompt_event(&mut self, key: Key) -> Vec<Event> {
+        let prompt = match self.prompt.as_mut() {
+            None => return vec![],
+            Some(prompt) => prompt,
+        };
+        if let PromptKind::Delete(_) = prompt.kind {
+            let prompt = self.prompt.take().unwrap();
+            if key != Key::Char('y') {
+                return vec![];
+            }
+            let operation = self.prompt_operation(&prompt).unwrap();
+            // Keep a selection nearby, rather than losing it along with the deleted item
+            let rows = self.rows();
+            if let Some(selected_index) = self.selected_row_index(&rows) {
+                let next_index = if selected_index + 1 < rows.len() {
+                    selected_index + 1
+                } else {
+                    selected_index.saturating_sub(1)
+                };
+                self.select_row(next_index);
+            }
+            let mut events = vec![Event::FileOperationRequested(operation)];
+            if let Some(node) = self.selected_node() {
+                events.push(Event::FileItemSelected(node.clone()))
+            }
+            return events;
+        }
+
+        match key {
+            Key::Char('\n') => {
+                let prompt = self.prompt.take().unwrap();
+                match self.prompt_operation(&prompt) {
+                    Ok(operation) => return vec![Event::FileOperationRequested(operation)],
+                    Err(message) => self.message = Some(message),
+                }
+            }
+            Key::Char(c) => prompt.input.push(c),
+            Key::Backspace => {
+                prompt.input.pop();
+            }
+            Key::Esc => self.prompt = None,
+            _ => {}
+        }
+        vec![]
+    }
+
+    // The folders from the root down to the one containing the selection, like "five › src › components".
+    fn breadcrumb(&self) -> String {
+        let tree = match &self.tree {
+            None => return String::new(),
+            Some(tree) => tree,
+        };
+        let folder_path = match self.mode {
+            TreeMode::DrillDown => self.current_folder().map(|folder| folder.path()),
+            TreeMode::Expandable => self.selected_parent().map(|parent| parent.path()),
+        };
+        folder_path
+            .and_then(|folder_path| tree.path_to(folder_path))
+            .unwrap_or_default()
+            .iter()
+            .map(|node| node.name())
+            .collect::<Vec<&str>>()
+            .join(" › ")
+    }
+
+    // The detail columns for the node, right-aligned and with a leading space.
+    fn details(&self, node: &FileTreeNode) -> String {
+        let (size, modified) = match node {
+            FileTreeNode::Folder(_) => (String::new(), String::new()),
+            FileTreeNode::File(file_index_entry) => (
+                format_size(file_index_entry.metadata.size),
+                file_index_entry
+                    .metadata
+                    .modified
+                    .map(format_modified)
+                    .unwrap_or_default(),
+            ),
+        };
+        match self.detail_columns {
+            DetailColumns::None => String::new(),
+            DetailColumns::Size => format!("{:>1$}", size, SIZE_COLUMN_WIDTH),
+            DetailColumns::Modified => format!("{:>1$}", modified, MODIFIED_COLUMN_WIDTH),
+            DetailColumns::SizeAndModified => format!(
+                "{:>2$}{:>3$}",
+                size, modified, SIZE_COLUMN_WIDTH, MODIFIED_COLUMN_WIDTH
+            ),
+        }
+    }
+
+    fn paint_row<Writer: Write>(
+        &self,
+        stream: &mut Writer,
+        row: &TreeRow,
+        is_selected: bool,
+        width: u16,
+    ) -> std::io::Result<()> {
+        let mut guides = String::new();
+        for _ in 0..row.depth {
+            guides.push_str("│ ");
+        }
+        let marker = match (&self.mode, row.node) {
+            (TreeMode::Expandable, FileTreeNode::Folder(folder)) => {
+                if self.expanded_folders.contains(&folder.path) {
+                    "▾ "
+                } else {
+                    "▸ "
+                }
+            }
+            (TreeMode::Expandable, FileTreeNode::File(_)) => "  ",
+            (TreeMode::DrillDown, _) => "",
+        };
+        write!(stream, "{}{}", self.theme.style(StyleName::Dimmed), guides)?;
+        let indent_width = (row.depth * 2) as u16;
+
+        let git_state = self.git_status.state(row.node.path());
+        let style_name = match (row.node, git_state) {
+            _ if is_selected => StyleName::SelectedItem,
+            (_, Some(git_state)) => git_state.style_name(),
+            (FileTreeNode::File(file_index_entry), None) => {
+                let metadata = &file_index_entry.metadata;
+                if metadata.file_type == FileType::Symlink {
+                    StyleName::Symlink
+                } else if metadata.is_executable() {
+                    StyleName::Executable
+                } else {
+                    StyleName::File
+                }
+            }
+            (FileTreeNode::Folder(_), None) => StyleName::Folder,
+        };
+        write!(stream, "{}", self.theme.style(style_name))?;
+        let line = format!("{}{}", marker, row.node.name());
+        let details = self.details(row.node);
+        // A column of git states, as long as anything has one
+        let git_column = if self.git_status.is_empty() {
+            String::new()
+        } else {
+            format!(" {}", git_state.map_or(' ', GitState::letter))
+        };
+        let details_width = details.chars().count() + git_column.chars().count();
+        let name_width = width.saturating_sub(indent_width);
+        match name_width.checked_sub(details_width as u16) {
+            // Names get at least as much room as the details
+            Some(name_width) if name_width as usize >= details_width => {
+                paint_truncated_text(stream, &line, name_width)?;
+                if !is_selected {
+                    write!(stream, "{}", self.theme.style(StyleName::Dimmed))?;
+                }
+                write!(stream, "{}", details)?;
+                if let (Some(git_state), false) = (git_state, is_selected) {
+                    write!(stream, "{}", self.theme.style(git_state.style_name()))?;
+                }
+                write!(stream, "{}", git_column)?;
+            }
+            _ => paint_truncated_text(stream, &line, name_width)?,
+        }
+        write!(stream, "{}", termion::style::Reset)
+    }
+}
+
+impl Component for DirectoryTreeComponent {
+    fn needs_paint(&self) -> bool {
+        self.needs_paint.take()
+    }
+
+    fn paint<Writer: Write>(&self, stream: &mut Writer, rect: Rect) -> std::io::Result<()> {
+        self.painted_rect.set(Some(rect));
+        write!(stream, "{}", termion::cursor::Goto(rect.left, rect.top))?;
+        match (&self.prompt, &self.message) {
+            (Some(prompt), _) => {
+                write!(stream, "{}", self.theme.style(StyleName::Prompt))?;
+                paint_truncated_text(stream, &self.prompt_text(prompt), rect.width)?;
+            }
+            (None, Some(message)) => {
+                write!(stream, "{}", self.theme.style(StyleName::Error))?;
+                paint_truncated_text(stream, message, rect.width)?;
+            }
+            (None, None) => {
+                write!(stream, "{}", self.theme.style(StyleName::Title))?;
+                let left_text = match &self.filter {
+                    Some(filter) => format!("Filter: {}", filter.query),
+                    None => self.breadcrumb(),
+                };
+                // Name order is the norm, so only call out the others
+                let sort_text = match self.sort_order {
+                    SortOrder::Name => String::new(),
+                    sort_order => format!("by {}", sort_order.name()),
+                };
+                let right_text = match (self.changed_only, sort_text.is_empty()) {
+                    (false, _) => sort_text,
+                    (true, true) => String::from("changed"),
+                    (true, false) => format!("changed, {}", sort_text),
+                };
+                paint_left_and_right_text(stream, &left_text, &right_text, rect.width)?;
+            }
+        }
+
+        let rows = self.rows();
+        let selected_index = self.selected_row_index(&rows);
+        let num_rows = rect.height.saturating_sub(1);
+        let mut width = rect.width;
+        if self.list.needs_scrollbar(num_rows, rows.len()) {
+            width -= 1;
+            self.list.paint_scrollbar(
+                stream,
+                Rect {
+                    top: rect.top + 1,
+                    left: rect.left + width,
+                    width: 1,
+                    height: num_rows,
+                },
+                rows.len(),
+                &self.theme,
+            )?;
+        }
+
+        let mut row = 0;
+        for index in self.list.visible_items(num_rows, rows.len()) {
+            write!(
+                stream,
+                "{}",
+                termion::cursor::Goto(rect.left, rect.top + 1 + row)
+            )?;
+            self.paint_row(stream, &rows[index], selected_index == Some(index), width)?;
+            row += 1;
+        }
+        write!(stream, "{}", self.theme.style(StyleName::Text))?;
+        paint_empty_lines(
+            stream,
+            Rect {
+                top: rect.top + 1 + row,
+                left: rect.left,
+                width,
+                height: num_rows - row,
+            },
+        )?;
+        self.needs_paint.set(false);
+        Ok(())
+    }
+
+    fn dispatch_event(&mut self, event: termion::event::Event) -> DispatchEventResult {
+        let mut events = Vec::<Event>::new();
+        if let termion::event::Event::Key(key) = event {
+            if self.message.take().is_some() {
+                self.needs_paint.set(true);
+            }
+            if self.prompt.is_some() {
+                self.needs_paint.set(true);
+                return DispatchEventResult {
+                    handled: true,
+                    events: self.dispatch_prompt_event(key),
+                };
+            }
+        }
+        match event {
+            termion::event::Event::Key(Key::Char(c))
+                if c != '\n' && !c.is_control() && self.has_focus =>
+            {
+                self.extend_filter(c);
+                self.selection_changed(events)
+            }
+            termion::event::Event::Mouse(termion::event::MouseEvent::Press(
+                termion::event::MouseButton::Left,
+                x,
+                y,
+            )) => {
+                // Clicks outside of the rows are still taken, since they are meant for the tree
+                if let Some(event) = self.click(x, y) {
+                    events.push(event);
+                }
+                self.selection_changed(events)
+            }
+            termion::event::Event::Mouse(termion::event::MouseEvent::Press(
+                termion::event::MouseButton::WheelDown,
+                _,
+                _,
+            )) => {
+                self.list.scroll_by(1, self.rows().len());
+                self.needs_paint.set(true);
+                DispatchEventResult {
+                    handled: true,
+                    events,
+                }
+            }
+            termion::event::Event::Mouse(termion::event::MouseEvent::Press(
+                termion::event::MouseButton::WheelUp,
+                _,
+                _,
+            )) => {
+                self.list.scroll_by(-1, self.rows().len());
+                self.needs_paint.set(true);
+                DispatchEventResult {
+                    handled: true,
+                    events,
+                }
+            }
+            _ => DispatchEventResult::empty(),
+        }
+    }
+
+    fn dispatch_command(&mut self, command: Command) -> DispatchEventResult {
+        let mut events = Vec::<Event>::new();
+        if self.message.take().is_some() {
+            self.needs_paint.set(true);
+        }
+        // File operations only start from the focused tree, not from keys that the editor passed on
+        if self.has_focus && self.start_prompt(command) {
+            self.needs_paint.set(true);
+            return DispatchEventResult {
+                handled: true,
+                events,
+            };
+        }
+        let handled = match command {
+            Command::UndoFileOperation if self.has_focus => {
+                events.push(Event::FileOperationUndoRequested);
+                return DispatchEventResult {
+                    handled: true,
+                    events,
+                };
+            }
+            command if self.navigate(command) => true,
+            Command::Expand => match self.mode {
+                TreeMode::Expandable => {
+                    self.expand_selected_folder();
+                    true
+                }
+                TreeMode::DrillDown => false,
+            },
+            Command::Collapse => match self.mode {
+                TreeMode::Expandable => {
+                    self.collapse_selected_folder();
+                    true
+                }
+                TreeMode::DrillDown => false,
+            },
+            Command::GoUp => {
+                if !self.shorten_filter() {
+                    self.leave_current_folder();
+                }
+                true
+            }
+            Command::ClearFilter if self.filter.is_some() => {
+                self.filter = None;
+                true
+            }
+            Command::NextSortOrder => {
+                self.sort_order = self.sort_order.next();
+                true
+            }
+            Command::ToggleDetails => {
+                self.detail_columns = self.detail_columns.next();
+                true
+            }
+            Command::ToggleChangedOnly => {
+                self.toggle_changed_only();
+                true
+            }
+            Command::Open => {
+                if let Some(event) = self.open_selected_item() {
+                    events.push(event)
+                }
+                true
+            }
+            Command::ToggleTreeMode => {
+                self.toggle_mode();
+                true
+            }
+            _ => false,
+        };
+        if handled {
+            self.selection_changed(events)
+        } else {
+            DispatchEventResult::empty()
+        }
+    }
+
+    fn is_prompting(&self) -> bool {
+        self.prompt.is_some()
+    }
+
+    fn dispatch_events(&mut self, _: &[Event]) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::components::directory_tree::*;
+    use crate::indexer::index::{FileMetadata, FileTreeFolder};
+    use crate::keymap::{KeyContext, Keymap, KeymapMatch};
+    use std::path::Path;
+
+    fn file(path: &str) -> FileTreeNode {
+        FileTreeNode::File(FileIndexEntry::new(Path::new(path), FileMetadata::unknown()).unwrap())
+    }
+
+    fn folder(path: &str, children: Vec<FileTreeNode>) -> FileTreeNode {
+        FileTreeNode::Folder(FileTreeFolder {
+            children,
+            folder_name: String::from(Path::new(path).file_name().unwrap().to_str().unwrap()),
+            path: String::from(path),
+        })
+    }
+
+    fn test_index(extra_file: bool) -> Index {
+        let mut src_children = vec![
+            folder(
+                "/five/src/components",
+                vec![file("/five/src/components/root.rs")],
+            ),
+            file("/five/src/main.rs"),
+        ];
+        if extra_file {
+            src_children.push(file("/five/src/new.rs"));
+        }
+        Index::new(folder(
+            "/five",
+            vec![folder("/five/src", src_children), file("/five/README.md")],
+        ))
+    }
+
+    // Goes through the default keymap, like keys pressed in the running editor.
+    fn press(tree: &mut DirectoryTreeComponent, key: Key) -> DispatchEventResult {
+        if !tree.is_prompting() {
+            if let KeymapMatch::Command(command) = Keymap::new().lookup(KeyContext::Tree, &[key]) {
+                return tree.dispatch_command(command);
+            }
+        }
+        tree.dispatch_event(termion::event::Event::Key(key))
+    }
+
+    fn row_names(tree: &DirectoryTreeComponent) -> Vec<&str> {
+        tree.rows().iter().map(|row| row.node.name()).collect()
+    }
+
+    #[test]
+    fn neighbours_nearest_first() {
+        assert_eq!(neighbouring_indices(5, 10, 2), vec![6, 4, 7, 3]);
+        assert_eq!(neighbouring_indices(0, 10, 2), vec![1, 2]);
+        assert_eq!(neighbouring_indices(9, 10, 1), vec![8]);
+    }
+
+    #[test]
+    fn expand_and_collapse() {
+        let mut tree = DirectoryTreeComponent::new();
+        tree.update_index(test_index(false));
+        assert_eq!(row_names(&tree), vec!["src", "README.md"]);
+
+        press(&mut tree, Key::Down);
+        press(&mut tree, Key::Right);
+        assert_eq!(
+            row_names(&tree),
+            vec!["src", "components", "main.rs", "README.md"]
+        );
+        assert_eq!(tree.breadcrumb(), "five");
+
+        // Right on an expanded folder moves into it, left goes back to the parent
+        press(&mut tree, Key::Right);
+        assert_eq!(tree.selected_path.as_deref(), Some("/five/src/components"));
+        assert_eq!(tree.breadcrumb(), "five › src");
+        press(&mut tree, Key::Left);
+        assert_eq!(tree.selected_path.as_deref(), Some("/five/src"));
+        press(&mut tree, Key::Left);
+        assert_eq!(row_names(&tree), vec!["src", "README.md"]);
+    }
+
+    #[test]
+    fn keep_state_across_refreshes() {
+        let mut tree = DirectoryTreeComponent::new();
+        tree.update_index(test_index(false));
+        press(&mut tree, Key::Down);
+        press(&mut tree, Key::Char('\n'));
+        press(&mut tree, Key::Down);
+        press(&mut tree, Key::Down);
+        assert_eq!(tree.selected_path.as_deref(), Some("/five/src/main.rs"));
+
+        tree.update_index(test_index(true));
+        assert_eq!(
+            row_names(&tree),
+            vec!["src", "components", "main.rs", "new.rs", "README.md"]
+        );
+        assert_eq!(tree.selected_path.as_deref(), Some("/five/src/main.rs"));
+    }
+
+    #[test]
+    fn restore_saved_state() {
+        let mut tree = DirectoryTreeComponent::new();
+        tree.update_index(test_index(true));
+        press(&mut tree, Key::Down);
+        press(&mut tree, Key::Right);
+        press(&mut tree, Key::Down);
+        press(&mut tree, Key::Down);
+        press(&mut tree, Key::Down);
+        let state = tree.state();
+        assert_eq!(state.selected_path.as_deref(), Some("/five/src/new.rs"));
+
+        // Files that were deleted in the meantime are forgotten
+        let mut restored_tree = DirectoryTreeComponent::new();
+        restored_tree.update_index(test_index(false));
+        restored_tree.restore_state(state.clone());
+        assert_eq!(
+            row_names(&restored_tree),
+            vec!["src", "components", "main.rs", "README.md"]
+        );
+        assert_eq!(restored_tree.selected_path, None);
+
+        let mut restored_tree = DirectoryTreeComponent::new();
+        restored_tree.update_index(test_index(true));
+        restored_tree.restore_state(state.clone());
+        assert_eq!(restored_tree.state(), state);
+    }
+
+    fn requested_operations(tree: &mut DirectoryTreeComponent, keys: &[Key]) -> Vec<String> {
+        keys.iter()
+            .flat_map(|key| press(tree, *key).events)
+            .filter_map(|event| match event {
+                Event::FileOperationRequested(operation) => Some(format!("{:?}", operation)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn prompt_for_file_operations() {
+        let mut tree = DirectoryTreeComponent::new();
+        tree.update_index(test_index(false));
+        press(&mut tree, Key::Down);
+
+        // Renaming starts from the current name
+        let mut keys = vec![Key::F(2)];
+        keys.extend("rc".chars().map(|_| Key::Backspace));
+        keys.extend("ource\n".chars().map(Key::Char));
+        assert_eq!(
+            requested_operations(&mut tree, &keys),
+            vec!["Move(\"/five/src\", \"/five/source\")"]
+        );
+
+        let keys: Vec<Key> = vec![Key::Alt('n')]
+            .into_iter()
+            .chain("lib.rs\n".chars().map(Key::Char))
+            .collect();
+        assert_eq!(
+            requested_operations(&mut tree, &keys),
+            vec!["CreateFile(\"/five/src/lib.rs\")"]
+        );
+
+        let keys: Vec<Key> = vec![Key::Alt('m')]
+            .into_iter()
+            .chain("nowhere\n".chars().map(Key::Char))
+            .collect();
+        assert!(requested_operations(&mut tree, &keys).is_empty());
+        assert!(tree.message.is_some());
+
+        // Deleting asks first, then moves the selection on
+        assert!(requested_operations(&mut tree, &[Key::Delete, Key::Char('n')]).is_empty());
+        assert_eq!(
+            requested_operations(&mut tree, &[Key::Delete, Key::Char('y')]),
+            vec!["Trash(\"/five/src\")"]
+        );
+        assert_eq!(tree.selected_path.as_deref(), Some("/five/README.md"));
+    }
+
+    fn file_with_size(path: &str, size: u64) -> FileTreeNode {
+        let metadata = FileMetadata {
+            size,
+            ..FileMetadata::unknown()
+        };
+        FileTreeNode::File(FileIndexEntry::new(Path::new(path), metadata).unwrap())
+    }
+
+    #[test]
+    fn sort_folders_first() {
+        let mut tree = DirectoryTreeComponent::new();
+        tree.update_index(Index::new(folder(
+            "/five",
+            vec![
+                file_with_size("/five/b.rs", 10),
+                folder("/five/target", vec![]),
+                file_with_size("/five/README.md", 30),
+                file_with_size("/five/a.toml", 20),
+                folder("/five/Docs", vec![]),
+            ],
+        )));
+        assert_eq!(
+            row_names(&tree),
+            vec!["Docs", "target", "a.toml", "b.rs", "README.md"]
+        );
+        press(&mut tree, Key::Alt('s'));
+        press(&mut tree, Key::Alt('s'));
+        assert_eq!(tree.sort_order, SortOrder::Size);
+        assert_eq!(
+            row_names(&tree),
+            vec!["Docs", "target", "README.md", "a.toml", "b.rs"]
+        );
+        press(&mut tree, Key::Alt('s'));
+        assert_eq!(
+            row_names(&tree),
+            vec!["Docs", "target", "README.md", "b.rs", "a.toml"]
+        );
+    }
+
+    #[test]
+    fn filter_current_folder() {
+        let mut tree = DirectoryTreeComponent::new();
+        tree.update_index(test_index(false));
+        press(&mut tree, Key::Down);
+        press(&mut tree, Key::Right);
+
+        // Only the folder containing the selection is filtered
+        press(&mut tree, Key::Char('r'));
+        press(&mut tree, Key::Char('e'));
+        assert_eq!(row_names(&tree), vec!["README.md"]);
+        assert_eq!(tree.selected_path.as_deref(), Some("/five/README.md"));
+        press(&mut tree, Key::Backspace);
+        assert_eq!(
+            row_names(&tree),
+            vec!["src", "components", "main.rs", "README.md"]
+        );
+        press(&mut tree, Key::Esc);
+        assert!(tree.filter.is_none());
+
+        // Backspace goes back to leaving folders once the filter is gone
+        press(&mut tree, Key::Backspace);
+        assert_eq!(tree.selected_path.as_deref(), Some("/five/README.md"));
+    }
+
+    #[test]
+    fn format_details() {
+        assert_eq!(format_size(812), "812B");
+        assert_eq!(format_size(4096), "4.0K");
+        assert_eq!(format_size(13 * 1024 * 1024 + 1), "13M");
+    }
+
+    #[test]
+    fn name_duplicates() {
+        assert_eq!(duplicate_name("main.rs"), "main copy.rs");
+        assert_eq!(duplicate_name("src"), "src copy");
+        assert_eq!(duplicate_name(".gitignore"), ".gitignore copy");
+    }
+
+    #[test]
+    fn drill_down() {
+        let mut tree = DirectoryTreeComponent::new();
+        tree.update_index(test_index(false));
+        press(&mut tree, Key::Alt('t'));
+        press(&mut tree, Key::Down);
+        press(&mut tree, Key::Char('\n'));
+        assert_eq!(row_names(&tree), vec!["components", "main.rs"]);
+        assert_eq!(tree.breadcrumb(), "five › src");
+
+        // Switching back expands the folders on the way to the one that was shown
+        press(&mut tree, Key::Alt('t'));
+        assert_eq!(
+            row_names(&tree),
+            vec!["src", "components", "main.rs", "README.md"]
+        );
+
+        press(&mut tree, Key::Alt('t'));
+        press(&mut tree, Key::Backspace);
+        assert_eq!(row_names(&tree), vec!["src", "README.md"]);
+    }
+
+    #[test]
+    fn show_only_changed_files() {
+        let mut tree = DirectoryTreeComponent::new();
+        tree.update_index(test_index(true));
+        tree.set_git_status(GitStatus::parse(
+            Path::new("/five"),
+            "",
+            "1 .M N... 100644 100644 100644 587be6b 587be6b src/components/root.rs\0? src/new.rs\0! README.md\0",
+        ));
+        assert_eq!(tree.git_status.state("/five/src"), Some(GitState::Modified));
+
+        // Folders with changes are expanded to show them, and ignored files are left out
+        press(&mut tree, Key::Alt('c'));
+        assert_eq!(
+            row_names(&tree),
+            vec!["src", "components", "root.rs", "new.rs"]
+        );
+        press(&mut tree, Key::Alt('c'));
+        assert_eq!(
+            row_names(&tree),
+            vec![
+                "src",
+                "components",
+                "root.rs",
+                "main.rs",
+                "new.rs",
+                "README.md"
+            ]
+        );
+    }
+}
@@ -19,19 +19,27 @@
 use super::component::{Component, DispatchEventResult};
 use crate::event::Event;
 use crate::terminal::Rect;
+use crate::theme::{StyleName, Theme};
 use std::cell::Cell;
 use std::io::Write;
 
 pub struct DividerComponent {
     needs_paint: Cell<bool>,
+    theme: Theme,
 }
 
 impl DividerComponent {
     pub fn new() -> DividerComponent {
         DividerComponent {
             needs_paint: Cell::new(true),
+            theme: Theme::new(),
         }
     }
+
+    pub fn set_theme(&mut self, theme: &Theme) {
+        self.theme = theme.clone();
+        self.needs_paint.set(true);
+    }
 }
 
 impl Component for DividerComponent {
@@ -44,10 +52,10 @@ impl Component for DividerComponent {
                 stream,
                 "{}{} ",
                 termion::cursor::Goto(rect.left, row),
-                termion::color::Bg(termion::color::LightBlue)
+                self.theme.style(StyleName::Divider)
             )?;
         }
-        write!(stream, "{}", termion::color::Bg(termion::color::Reset))?;
+        write!(stream, "{}", termion::style::Reset)?;
         self.needs_paint.set(false);
         Ok(())
     }
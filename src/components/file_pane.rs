@@ -16,22 +16,67 @@
     along with Five.  If not, see <https://www.gnu.org/licenses/>.
 */
 
+use crate::commands::Command;
 use crate::components::component::{Component, DispatchEventResult};
+use crate::components::directory_tree::{neighbouring_indices, DirectoryTreeComponent};
+use crate::components::scrolling_list::ScrollingList;
+use crate::display_width;
+use crate::display_width::DEFAULT_TAB_WIDTH;
 use crate::event::Event;
-use crate::indexer::index::{FileIndexEntry, FileTreeFolder, FileTreeNode, Index};
-use crate::painting_utils::{paint_empty_lines, paint_truncated_text};
-use crate::quick_open::{get_quick_open_results, QuickOpenResult};
+use crate::file_history;
+use crate::file_history::{FileHistory, RecentFile};
+use crate::git_status::GitStatus;
+use crate::indexer::index::{FileIndexEntry, FileMetadata, FileTreeNode, Index};
+use crate::indexer::symbols::Symbol;
+use crate::keymap::KeyContext;
+use crate::painting_utils::{paint_empty_lines, paint_left_and_right_text, paint_truncated_text};
+use crate::quick_open::{
+    get_command_results, get_location_results, get_quick_open_results, get_symbol_results,
+    CommandResult, LocationResult, QuickOpenItem, QuickOpenResult,
+};
+use crate::session::{TreeState, MAX_QUERY_HISTORY};
 use crate::terminal::Rect;
+use crate::text_position::{split_position_suffix, TextPosition};
+use crate::theme::{StyleName, Theme};
 use std::cell::Cell;
 use std::cmp::min;
+use std::convert::TryFrom;
 use std::io::Write;
+use std::path::Path;
 use termion::event::Key;
 
+// What quick open lists, which is files and folders unless it was started for something else
+enum QuickOpenMode {
+    Files,
+    // The command palette
+    Commands(Vec<CommandResult>),
+    // The symbols of the open file, or of every file in the index
+    Symbols {
+        symbols: Vec<Symbol>,
+        in_open_file: bool,
+    },
+    // Places that the language server pointed to, such as the references to a symbol
+    Locations(Vec<LocationResult>),
+}
+
 struct QuickOpenComponent {
     search_query: String,
     index: Option<Index>,
+    mode: QuickOpenMode,
     results: Vec<QuickOpenResult>,
+    command_results: Vec<CommandResult>,
+    symbol_results: Vec<Symbol>,
+    location_results: Vec<LocationResult>,
     selected_item_index: Option<usize>,
+    position: Option<TextPosition>,
+    list: ScrollingList,
+    painted_rect: Cell<Option<Rect>>,
+    theme: Theme,
+    // The queries that files were opened with, most recent last
+    history: Vec<String>,
+    // Which of them is shown while going back through the history
+    history_index: Option<usize>,
+    file_history: FileHistory,
 }
 
 impl QuickOpenComponent {
@@ -39,380 +84,550 @@ impl QuickOpenComponent {
         QuickOpenComponent {
             search_query: String::new(),
             index: None,
+            mode: QuickOpenMode::Files,
             results: vec![],
+            command_results: vec![],
+            symbol_results: vec![],
+            location_results: vec![],
             selected_item_index: None,
+            position: None,
+            list: ScrollingList::new(),
+            painted_rect: Cell::new(None),
+            theme: Theme::new(),
+            history: vec![],
+            history_index: None,
+            file_history: FileHistory::default(),
         }
     }
-    fn update_quick_open_results(&mut self) {
-        match &self.index {
-            Some(index) => {
-                self.results = get_quick_open_results(&index, &self.search_query);
-                if self.results.len() > 0 {
-                    self.selected_item_index = Some(0)
-                } else {
-                    self.selected_item_index = None
-                }
-            }
-            None => {}
-        };
+    fn start(&mut self, mode: QuickOpenMode) {
+        // Only a query for files is kept, anything else starts over with an empty query
+        if !matches!(
+            (&self.mode, &mode),
+            (QuickOpenMode::Files, QuickOpenMode::Files)
+        ) {
+            self.search_query.clear();
+        }
+        self.mode = mode;
+        self.history_index = None;
+        self.update_quick_open_results();
     }
-}
 
-impl Component for QuickOpenComponent {
-    fn needs_paint(&self) -> bool {
-        true
+    fn num_results(&self) -> usize {
+        match self.mode {
+            QuickOpenMode::Files => self.results.len(),
+            QuickOpenMode::Commands(_) => self.command_results.len(),
+            QuickOpenMode::Symbols { .. } => self.symbol_results.len(),
+            QuickOpenMode::Locations(_) => self.location_results.len(),
+        }
     }
 
-    fn paint<Writer: Write>(&self, stream: &mut Writer, rect: Rect) -> std::io::Result<()> {
-        write!(stream, "{}", termion::cursor::Goto(rect.left, rect.top))?;
-        write!(
-            stream,
-            "{}{}",
-            termion::color::Bg(termion::color::Yellow),
-            termion::color::Fg(termion::color::Black)
-        )?;
-        paint_truncated_text(stream, &self.search_query, rect.width)?;
-
-        let mut row = rect.top + 1;
-
-        for (index, result) in self.results.iter().enumerate() {
-            if self.selected_item_index.is_some() && self.selected_item_index.unwrap() == index {
-                write!(
-                    stream,
-                    "{}{}",
-                    termion::color::Bg(termion::color::White),
-                    termion::color::Fg(termion::color::Black)
-                )?;
-            } else {
-                write!(
-                    stream,
-                    "{}{}",
-                    termion::color::Fg(termion::color::White),
-                    termion::color::Bg(termion::color::Reset)
-                )?;
+    fn update_quick_open_results(&mut self) {
+        match (&self.mode, &self.index) {
+            (QuickOpenMode::Commands(commands), _) => {
+                self.command_results = get_command_results(commands, &self.search_query);
+                self.results.clear();
+                self.position = None;
+            }
+            (QuickOpenMode::Symbols { symbols, .. }, _) => {
+                self.symbol_results = get_symbol_results(symbols, &self.search_query);
+                self.results.clear();
+                self.position = None;
+            }
+            (QuickOpenMode::Locations(locations), _) => {
+                self.location_results = get_location_results(locations, &self.search_query);
+                self.results.clear();
+                self.position = None;
+            }
+            (QuickOpenMode::Files, Some(index)) => {
+                let (query, position) = split_position_suffix(&self.search_query);
+                self.results =
+                    get_quick_open_results(index, query, &self.file_history, file_history::now());
+                self.position = position;
             }
+            (QuickOpenMode::Files, None) => return,
+        }
+        self.list.reset();
+        if self.num_results() > 0 {
+            self.selected_item_index = Some(0)
+        } else {
+            self.selected_item_index = None
+        }
+    }
 
-            write!(stream, "{}", termion::cursor::Goto(rect.left, row))?;
-            paint_truncated_text(stream, &result.file_name, rect.width)?;
-            if row >= rect.height {
-                break;
+    fn is_picking_symbol_in_open_file(&self) -> bool {
+        matches!(
+            self.mode,
+            QuickOpenMode::Symbols {
+                in_open_file: true,
+                ..
             }
+        )
+    }
 
-            row += 1;
+    // Selecting a symbol shows its definition, and opening it also moves the focus there.
+    fn symbol_events(&self, symbol: &Symbol, is_opened: bool) -> Vec<Event> {
+        let position = TextPosition::new(symbol.line, 0);
+        if self.is_picking_symbol_in_open_file() {
+            return if is_opened {
+                vec![Event::GoToPosition(position), Event::FileViewFocusRequested]
+            } else {
+                vec![Event::GoToPosition(position)]
+            };
         }
+        self.location_events(&symbol.path, position, is_opened)
+    }
 
-        write!(
-            stream,
-            "{}{}",
-            termion::color::Fg(termion::color::Reset),
-            termion::color::Bg(termion::color::Reset)
-        )?;
-        paint_empty_lines(
-            stream,
-            Rect {
-                top: row,
-                left: rect.left,
-                width: rect.width,
-                height: rect.height - row + 1,
-            },
-        )?;
+    // Locations can be in files outside of the index, such as the sources of libraries.
+    fn location_events(&self, path: &str, position: TextPosition, is_opened: bool) -> Vec<Event> {
+        let position = Event::GoToPosition(position);
+        let index_entry = self
+            .index
+            .as_ref()
+            .and_then(|index| index.files.iter().find(|entry| entry.path == path))
+            .cloned()
+            .or_else(|| FileIndexEntry::new(Path::new(path), FileMetadata::unknown()));
+        match (index_entry, is_opened) {
+            (None, _) => vec![],
+            (Some(index_entry), true) => vec![Event::FileItemOpened(index_entry), position],
+            (Some(index_entry), false) => vec![
+                Event::FileItemSelected(FileTreeNode::File(index_entry)),
+                position,
+            ],
+        }
+    }
 
-        Ok(())
+    // Selected commands aren't previewed, only files, folders, symbols and locations are.
+    fn selection_events(&self) -> Vec<Event> {
+        let mut events = vec![];
+        if let QuickOpenMode::Locations(_) = self.mode {
+            return match self
+                .selected_item_index
+                .and_then(|index| self.location_results.get(index))
+            {
+                None => events,
+                Some(location) => self.location_events(&location.path, location.position, false),
+            };
+        }
+        if let QuickOpenMode::Symbols { .. } = self.mode {
+            return match self
+                .selected_item_index
+                .and_then(|index| self.symbol_results.get(index))
+            {
+                None => events,
+                Some(symbol) => self.symbol_events(symbol, false),
+            };
+        }
+        let result = match self
+            .selected_item_index
+            .and_then(|index| self.results.get(index))
+        {
+            None => return events,
+            Some(result) => result,
+        };
+        match &result.item {
+            QuickOpenItem::File(index_entry) => {
+                events.push(Event::FileItemSelected(FileTreeNode::File(
+                    index_entry.clone(),
+                )));
+                if let Some(position) = self.position {
+                    events.push(Event::GoToPosition(position));
+                }
+            }
+            QuickOpenItem::Folder(path) => {
+                let folder = self
+                    .index
+                    .as_ref()
+                    .and_then(|index| index.tree.path_to(path))
+                    .and_then(|nodes| nodes.last().cloned().cloned());
+                if let Some(folder) = folder {
+                    events.push(Event::FileItemSelected(folder));
+                }
+            }
+        }
+        events
     }
 
-    fn dispatch_event(&mut self, event: termion::event::Event) -> DispatchEventResult {
-        let mut events = Vec::<Event>::new();
-        let handled = match event {
-            termion::event::Event::Key(key) => match key {
-                Key::Char(c) => {
-                    self.search_query.push(c);
-                    self.update_quick_open_results();
-                    true
+    fn open_selected_item(&mut self) -> Vec<Event> {
+        let mut events = vec![];
+        if let Some(selected_index) = self.selected_item_index {
+            match self.mode {
+                QuickOpenMode::Files => {}
+                QuickOpenMode::Commands(_) => {
+                    let command = self.command_results[selected_index].command;
+                    return vec![Event::CommandRequested(command)];
                 }
-                Key::Backspace => {
-                    self.search_query.pop();
-                    self.update_quick_open_results();
-                    true
+                QuickOpenMode::Symbols { .. } => {
+                    return self.symbol_events(&self.symbol_results[selected_index], true)
                 }
-                Key::Down => {
-                    let next_item_index = match self.selected_item_index {
-                        None => 0usize,
-                        Some(index) => index + 1usize,
-                    };
-                    if next_item_index < self.results.len() {
-                        self.selected_item_index = Some(next_item_index);
-                    };
-                    true
+                QuickOpenMode::Locations(_) => {
+                    let location = &self.location_results[selected_index];
+                    return self.location_events(&location.path, location.position, true);
                 }
-                Key::Up => {
-                    let maybe_next_item_index = match self.selected_item_index {
-                        None => None,
-                        Some(index) => {
-                            if index > 0 {
-                                Some(index - 1usize)
-                            } else {
-                                None
-                            }
-                        }
-                    };
-                    if let Some(next_item_index) = maybe_next_item_index {
-                        self.selected_item_index = Some(next_item_index);
-                    };
-                    true
+            }
+            self.add_to_history();
+            match &self.results[selected_index].item {
+                QuickOpenItem::File(index_entry) => {
+                    events.push(Event::FileItemOpened(index_entry.clone()));
+                    if let Some(position) = self.position {
+                        events.push(Event::GoToPosition(position));
+                    }
                 }
-                _ => false,
-            },
-            _ => false,
-        };
-        if handled {
-            if let Some(selected_index) = self.selected_item_index {
-                events.push(Event::FileItemSelected(FileTreeNode::File(
-                    self.results[selected_index].clone(),
-                )));
+                QuickOpenItem::Folder(path) => events.push(Event::FolderOpened(path.clone())),
             }
         }
-        DispatchEventResult {
-            handled: handled,
-            events: events,
-        }
+        events
     }
 
-    fn dispatch_events(&mut self, _: &[Event]) {}
-}
-
-struct FileTreeCache {
-    node_stack: Vec<FileTreeNode>,
-}
-
-impl FileTreeCache {
-    pub fn new(file_tree: &FileTreeNode) -> FileTreeCache {
-        FileTreeCache {
-            node_stack: vec![file_tree.clone()],
+    fn add_to_history(&mut self) {
+        let query = self.search_query.trim();
+        if query.is_empty() {
+            return;
+        }
+        let query = String::from(query);
+        self.history.retain(|earlier_query| *earlier_query != query);
+        self.history.push(query);
+        if self.history.len() > MAX_QUERY_HISTORY {
+            self.history.remove(0);
         }
+        self.history_index = None;
     }
-}
-
-struct DirectoryTreeComponent {
-    selected_item_index: Option<usize>,
-    needs_paint: Cell<bool>,
-    file_tree_cache: Option<FileTreeCache>,
-}
 
-impl DirectoryTreeComponent {
-    fn update_index(&mut self, index: Index) {
-        self.needs_paint.set(true);
+    // Replaces the query with an earlier or later one from the history. Going forward past the
+    // most recent query clears it.
+    fn go_through_history(&mut self, backward: bool) {
+        let history_index = match (self.history_index, backward) {
+            (None, true) => self.history.len().checked_sub(1),
+            (None, false) => return,
+            (Some(index), true) => Some(index.saturating_sub(1)),
+            (Some(index), false) if index + 1 < self.history.len() => Some(index + 1),
+            (Some(_), false) => None,
+        };
+        self.history_index = history_index;
+        self.search_query = match history_index {
+            Some(index) => self.history[index].clone(),
+            None => String::new(),
+        };
+        self.update_quick_open_results();
+    }
 
-        match self.file_tree_cache {
-            None => self.file_tree_cache = Some(FileTreeCache::new(&index.tree)),
-            _ => {}
+    // Selects the result under the pointer. Returns whether it was a double click, or None if there is
+    // no result there.
+    fn click(&mut self, x: u16, y: u16) -> Option<bool> {
+        let rect = self.painted_rect.get()?;
+        // The first row is the query
+        if !rect.contains(x, y) || y == rect.top {
+            return None;
         }
+        let (index, is_double_click) = self
+            .list
+            .click(usize::from(y - rect.top - 1), self.num_results())?;
+        self.selected_item_index = Some(index);
+        Some(is_double_click)
     }
 
-    fn num_current_items(&self) -> usize {
-        match &self.file_tree_cache {
-            None => 0,
-            Some(file_tree_cache) => match file_tree_cache.node_stack.last().unwrap() {
-                FileTreeNode::File(_) => 1,
-                FileTreeNode::Folder(file_tree_folder) => file_tree_folder.children.len(),
-            },
+    // The name, then the details, dimmed unless the result is selected.
+    fn paint_name_and_details<Writer: Write>(
+        &self,
+        stream: &mut Writer,
+        (name, style_name): (&str, StyleName),
+        details: &str,
+        is_selected: bool,
+        width: u16,
+    ) -> std::io::Result<()> {
+        let name_width = min(
+            display_width::width(name, DEFAULT_TAB_WIDTH),
+            usize::from(width),
+        );
+        let name_width = u16::try_from(name_width).unwrap();
+        if !is_selected {
+            write!(stream, "{}", self.theme.style(style_name))?;
         }
+        paint_truncated_text(stream, name, name_width)?;
+        if !is_selected {
+            write!(stream, "{}", self.theme.style(StyleName::Dimmed))?;
+        }
+        paint_truncated_text(stream, &format!(" {}", details), width - name_width)
     }
 
-    fn file_tree_node_at_index(&self, index: usize) -> Option<&FileTreeNode> {
-        match &self.file_tree_cache {
-            None => None,
-            Some(file_tree_cache) => match file_tree_cache.node_stack.last().unwrap() {
-                FileTreeNode::File(_) => None,
-                FileTreeNode::Folder(file_tree_folder) => file_tree_folder.children.get(index),
-            },
-        }
+    // Files and folders have the folder they are in next to them.
+    fn paint_result<Writer: Write>(
+        &self,
+        stream: &mut Writer,
+        result: &QuickOpenResult,
+        is_selected: bool,
+        width: u16,
+    ) -> std::io::Result<()> {
+        let (name, style_name) = match result.item {
+            QuickOpenItem::File(_) => (result.name.clone(), StyleName::File),
+            QuickOpenItem::Folder(_) => (format!("{}/", result.name), StyleName::Folder),
+        };
+        self.paint_name_and_details(
+            stream,
+            (&name, style_name),
+            &result.parent,
+            is_selected,
+            width,
+        )
     }
 
-    fn open_selected_item(&mut self) -> Option<Event> {
-        let next_current_node = match self.selected_item_index {
-            None => None,
-            Some(selected_index) => match self.file_tree_node_at_index(selected_index) {
-                None => None,
-                Some(file_tree_node) => match file_tree_node {
-                    FileTreeNode::File(file_index_entry) => {
-                        Some(FileTreeNode::File(file_index_entry.clone()))
-                    }
-                    FileTreeNode::Folder(file_tree_folder) => {
-                        Some(FileTreeNode::Folder(file_tree_folder.clone()))
-                    }
-                },
-            },
+    // Symbols have their kind and line next to them, and the file too unless it is the open one.
+    fn paint_symbol<Writer: Write>(
+        &self,
+        stream: &mut Writer,
+        symbol: &Symbol,
+        is_selected: bool,
+        width: u16,
+    ) -> std::io::Result<()> {
+        let details = match (&self.index, self.is_picking_symbol_in_open_file()) {
+            (Some(index), false) => format!(
+                "{} {}:{}",
+                symbol.kind.name(),
+                index.relative_path(&symbol.path),
+                symbol.line + 1
+            ),
+            _ => format!("{} {}", symbol.kind.name(), symbol.line + 1),
         };
+        self.paint_name_and_details(
+            stream,
+            (&symbol.name, StyleName::Text),
+            &details,
+            is_selected,
+            width,
+        )
+    }
 
-        match next_current_node {
-            None => None,
-            Some(next_current_node) => {
-                if let FileTreeNode::File(file_index_entry) = next_current_node {
-                    Some(Event::FileItemOpened(file_index_entry))
-                } else {
-                    self.push_node_stack(next_current_node);
-                    None
-                }
-            }
-        }
+    // Locations show their line, or their problem in the color of its severity, with the file and
+    // line number next to it.
+    fn paint_location<Writer: Write>(
+        &self,
+        stream: &mut Writer,
+        location: &LocationResult,
+        is_selected: bool,
+        width: u16,
+    ) -> std::io::Result<()> {
+        let path = match &self.index {
+            Some(index) => index.relative_path(&location.path),
+            None => &location.path,
+        };
+        let style_name = location
+            .severity
+            .map_or(StyleName::Text, |severity| severity.style_name());
+        self.paint_name_and_details(
+            stream,
+            (location.text.trim(), style_name),
+            &format!("{}:{}", path, location.position.line + 1),
+            is_selected,
+            width,
+        )
     }
 
-    fn push_node_stack(&mut self, next_current_node: FileTreeNode) {
-        if let Some(file_tree_cache) = &mut self.file_tree_cache {
-            file_tree_cache.node_stack.push(next_current_node);
-            self.selected_item_index = None
+    fn neighbouring_files(&self, count: usize) -> Vec<FileIndexEntry> {
+        match self.selected_item_index {
+            None => vec![],
+            Some(selected_index) => neighbouring_indices(selected_index, self.results.len(), count)
+                .into_iter()
+                .filter_map(|index| match &self.results[index].item {
+                    QuickOpenItem::File(index_entry) => Some(index_entry.clone()),
+                    QuickOpenItem::Folder(_) => None,
+                })
+                .collect(),
         }
     }
+}
 
-    fn pop_node_stack(&mut self) -> bool {
-        if let Some(file_tree_cache) = &mut self.file_tree_cache {
-            if file_tree_cache.node_stack.len() > 1 {
-                file_tree_cache.node_stack.pop();
-                self.selected_item_index = None;
-            }
+impl Component for QuickOpenComponent {
+    fn needs_paint(&self) -> bool {
+        true
+    }
 
-            true
-        } else {
-            false
+    fn paint<Writer: Write>(&self, stream: &mut Writer, rect: Rect) -> std::io::Result<()> {
+        self.painted_rect.set(Some(rect));
+        write!(
+            stream,
+            "{}{}",
+            termion::cursor::Goto(rect.left, rect.top),
+            self.theme.style(StyleName::Prompt)
+        )?;
+        let position_text = match self.selected_item_index {
+            Some(selected_index) => format!("{}/{}", selected_index + 1, self.num_results()),
+            None => String::new(),
+        };
+        paint_left_and_right_text(stream, &self.search_query, &position_text, rect.width)?;
+
+        let num_rows = rect.height.saturating_sub(1);
+        let mut width = rect.width;
+        if self.list.needs_scrollbar(num_rows, self.num_results()) {
+            width -= 1;
+            self.list.paint_scrollbar(
+                stream,
+                Rect {
+                    top: rect.top + 1,
+                    left: rect.left + width,
+                    width: 1,
+                    height: num_rows,
+                },
+                self.num_results(),
+                &self.theme,
+            )?;
         }
-    }
 
-    fn paint_directory<Writer: Write>(
-        &self,
-        stream: &mut Writer,
-        directory: &FileTreeFolder,
-        rect: Rect,
-    ) -> std::io::Result<()> {
-        let mut row = rect.top;
-        for (index, node) in directory.children.iter().enumerate() {
-            write!(stream, "{}", termion::cursor::Goto(rect.left, row))?;
-            if self.selected_item_index.is_some() && self.selected_item_index.unwrap() == index {
-                write!(
-                    stream,
-                    "{}{}",
-                    termion::color::Bg(termion::color::White),
-                    termion::color::Fg(termion::color::Black)
-                )?;
+        let mut row = 0;
+        for index in self.list.visible_items(num_rows, self.num_results()) {
+            let is_selected = self.selected_item_index == Some(index);
+            let style_name = if is_selected {
+                StyleName::SelectedItem
             } else {
-                match node {
-                    FileTreeNode::File(_) => {
-                        write!(stream, "{}", termion::color::Fg(termion::color::White))?;
-                    }
-                    FileTreeNode::Folder(_) => {
-                        write!(stream, "{}", termion::color::Fg(termion::color::Green))?;
-                    }
-                }
-            }
-            let line = match node {
-                FileTreeNode::File(file_index_entry) => &file_index_entry.file_name,
-                FileTreeNode::Folder(file_tree_folder) => &file_tree_folder.folder_name,
+                StyleName::Text
             };
-            paint_truncated_text(stream, line, rect.width)?;
+            write!(stream, "{}", self.theme.style(style_name))?;
+
             write!(
                 stream,
-                "{}{}",
-                termion::color::Fg(termion::color::Reset),
-                termion::color::Bg(termion::color::Reset)
+                "{}",
+                termion::cursor::Goto(rect.left, rect.top + 1 + row)
             )?;
+            match self.mode {
+                QuickOpenMode::Commands(_) => {
+                    // Long descriptions are cut short so that the key binding stays visible
+                    let result = &self.command_results[index];
+                    let binding = result.binding.as_deref().unwrap_or("");
+                    match u16::try_from(binding.len() + 1) {
+                        Ok(binding_width) if binding_width > 1 && binding_width < width => {
+                            paint_truncated_text(
+                                stream,
+                                result.description,
+                                width - binding_width,
+                            )?;
+                            write!(stream, " {}", binding)?;
+                        }
+                        _ => paint_truncated_text(stream, result.description, width)?,
+                    }
+                }
+                QuickOpenMode::Symbols { .. } => {
+                    self.paint_symbol(stream, &self.symbol_results[index], is_selected, width)?
+                }
+                QuickOpenMode::Locations(_) => {
+                    self.paint_location(stream, &self.location_results[index], is_selected, width)?
+                }
+                QuickOpenMode::Files => {
+                    self.paint_result(stream, &self.results[index], is_selected, width)?
+                }
+            }
             row += 1;
         }
+
+        write!(stream, "{}", termion::style::Reset)?;
         paint_empty_lines(
             stream,
             Rect {
-                top: row,
+                top: rect.top + 1 + row,
                 left: rect.left,
-                width: rect.width,
-                height: rect.height + 1 - min(row, rect.height + 1),
+                width,
+                height: num_rows - row,
             },
         )?;
-        self.needs_paint.set(false);
-        Ok(())
-    }
-}
 
-impl Component for DirectoryTreeComponent {
-    fn needs_paint(&self) -> bool {
-        self.needs_paint.take()
-    }
-    fn paint<Writer: Write>(&self, stream: &mut Writer, rect: Rect) -> std::io::Result<()> {
-        if let Some(file_tree_cache) = &self.file_tree_cache {
-            assert!(file_tree_cache.node_stack.len() > 0);
-            if let FileTreeNode::Folder(file_tree_folder) =
-                file_tree_cache.node_stack.last().unwrap()
-            {
-                self.paint_directory(stream, &file_tree_folder, rect)
-            } else {
-                // TODO: single file support
-                Ok(())
-            }
-        } else {
-            Ok(())
-        }
+        Ok(())
     }
 
     fn dispatch_event(&mut self, event: termion::event::Event) -> DispatchEventResult {
         let mut events = Vec::<Event>::new();
         let handled = match event {
-            termion::event::Event::Key(key) => match key {
-                Key::Down => {
-                    let next_item_index = match self.selected_item_index {
-                        None => 0usize,
-                        Some(index) => index + 1usize,
-                    };
-                    if next_item_index < self.num_current_items() {
-                        self.selected_item_index = Some(next_item_index)
-                    };
-                    true
-                }
-                Key::Up => {
-                    let maybe_next_item_index = match self.selected_item_index {
-                        None => None,
-                        Some(index) => {
-                            if index > 0 {
-                                Some(index - 1usize)
-                            } else {
-                                None
-                            }
-                        }
-                    };
-                    if let Some(next_item_index) = maybe_next_item_index {
-                        self.selected_item_index = Some(next_item_index)
-                    };
-                    true
+            termion::event::Event::Key(Key::Char(c)) => {
+                self.search_query.push(c);
+                self.history_index = None;
+                self.update_quick_open_results();
+                true
+            }
+            termion::event::Event::Key(Key::Backspace) => {
+                self.search_query.pop();
+                self.history_index = None;
+                self.update_quick_open_results();
+                true
+            }
+            termion::event::Event::Mouse(termion::event::MouseEvent::Press(
+                termion::event::MouseButton::Left,
+                x,
+                y,
+            )) => match self.click(x, y) {
+                Some(true) => {
+                    return DispatchEventResult {
+                        handled: true,
+                        events: self.open_selected_item(),
+                    }
                 }
-                Key::Backspace => self.pop_node_stack(),
-                Key::Char(c) => match c {
-                    '\n' => {
-                        if let Some(event) = self.open_selected_item() {
-                            events.push(event)
-                        }
-                        true
+                Some(false) => true,
+                None => {
+                    return DispatchEventResult {
+                        handled: true,
+                        events,
                     }
-                    _ => false,
-                },
-                _ => false,
+                }
             },
+            termion::event::Event::Mouse(termion::event::MouseEvent::Press(
+                termion::event::MouseButton::WheelDown,
+                _,
+                _,
+            )) => {
+                self.list.scroll_by(1, self.num_results());
+                return DispatchEventResult {
+                    handled: true,
+                    events,
+                };
+            }
+            termion::event::Event::Mouse(termion::event::MouseEvent::Press(
+                termion::event::MouseButton::WheelUp,
+                _,
+                _,
+            )) => {
+                self.list.scroll_by(-1, self.num_results());
+                return DispatchEventResult {
+                    handled: true,
+                    events,
+                };
+            }
             _ => false,
         };
         if handled {
-            self.needs_paint.set(true);
+            events = self.selection_events();
+        }
+        DispatchEventResult { handled, events }
+    }
 
-            let event = if let Some(selected_index) = self.selected_item_index {
-                if let Some(file_tree_node) = self.file_tree_node_at_index(selected_index) {
-                    Some(Event::FileItemSelected(file_tree_node.clone()))
-                } else {
-                    None
+    fn dispatch_command(&mut self, command: Command) -> DispatchEventResult {
+        match command {
+            Command::Open => {
+                return DispatchEventResult {
+                    handled: true,
+                    events: self.open_selected_item(),
                 }
-            } else {
-                None
-            };
-            if let Some(event) = event {
-                events.push(event)
             }
-        };
-        DispatchEventResult {
-            handled: handled,
-            events: events,
+            Command::PreviousQuery | Command::NextQuery
+                if matches!(self.mode, QuickOpenMode::Files) =>
+            {
+                self.go_through_history(command == Command::PreviousQuery);
+                return DispatchEventResult {
+                    handled: true,
+                    events: self.selection_events(),
+                };
+            }
+            _ => {}
+        }
+        match self
+            .list
+            .navigate(command, self.selected_item_index, self.num_results())
+        {
+            Some(next_item_index) => {
+                self.selected_item_index = next_item_index;
+                if let Some(next_item_index) = next_item_index {
+                    self.list.scroll_to_item(next_item_index);
+                }
+                DispatchEventResult {
+                    handled: true,
+                    events: self.selection_events(),
+                }
+            }
+            None => DispatchEventResult::empty(),
         }
     }
 
@@ -433,11 +648,7 @@ pub struct FilePaneComponent {
 impl FilePaneComponent {
     pub fn new() -> FilePaneComponent {
         FilePaneComponent {
-            directory_tree: DirectoryTreeComponent {
-                selected_item_index: None,
-                needs_paint: Cell::new(true),
-                file_tree_cache: None,
-            },
+            directory_tree: DirectoryTreeComponent::new(),
             quick_open: QuickOpenComponent::new(),
             mode: FilePaneMode::DirectoryTree,
         }
@@ -445,12 +656,117 @@ impl FilePaneComponent {
 
     pub fn start_quick_open(&mut self) {
         self.mode = FilePaneMode::QuickOpen;
+        self.quick_open.start(QuickOpenMode::Files);
+    }
+
+    pub fn start_command_palette(&mut self, commands: Vec<CommandResult>) {
+        self.mode = FilePaneMode::QuickOpen;
+        self.quick_open.start(QuickOpenMode::Commands(commands));
+    }
+
+    // Lists the symbols of the open file, or of all files, to jump to their definition.
+    pub fn start_symbol_search(&mut self, symbols: Vec<Symbol>, in_open_file: bool) {
+        self.mode = FilePaneMode::QuickOpen;
+        self.quick_open.start(QuickOpenMode::Symbols {
+            symbols,
+            in_open_file,
+        });
+    }
+
+    // Lists places in files, such as the references to a symbol or the problems found by a build, to
+    // jump to them.
+    pub fn show_locations(&mut self, locations: Vec<LocationResult>) {
+        self.mode = FilePaneMode::QuickOpen;
+        self.quick_open.start(QuickOpenMode::Locations(locations));
+    }
+
+    pub fn close_quick_open(&mut self) {
+        self.mode = FilePaneMode::DirectoryTree;
     }
 
     pub fn update_index(&mut self, index: Index) {
         self.quick_open.index = Some(index.clone());
         self.directory_tree.update_index(index);
     }
+
+    pub fn set_has_focus(&mut self, has_focus: bool) {
+        self.directory_tree.set_has_focus(has_focus);
+    }
+
+    pub fn set_theme(&mut self, theme: &Theme) {
+        self.directory_tree.set_theme(theme);
+        self.quick_open.theme = theme.clone();
+    }
+
+    pub fn show_message(&mut self, message: String) {
+        self.directory_tree.show_message(message);
+    }
+
+    pub fn set_git_status(&mut self, git_status: GitStatus) {
+        self.directory_tree.set_git_status(git_status);
+    }
+
+    pub fn select_path(&mut self, path: &str) {
+        self.mode = FilePaneMode::DirectoryTree;
+        self.directory_tree.select_path(path);
+    }
+
+    pub fn open_folder(&mut self, path: &str) {
+        self.mode = FilePaneMode::DirectoryTree;
+        self.directory_tree.open_folder(path);
+    }
+
+    pub fn tree_state(&self) -> TreeState {
+        self.directory_tree.state()
+    }
+
+    pub fn restore_tree_state(&mut self, state: TreeState) {
+        self.directory_tree.restore_state(state);
+    }
+
+    pub fn quick_open_history(&self) -> Vec<String> {
+        self.quick_open.history.clone()
+    }
+
+    pub fn set_quick_open_history(&mut self, history: Vec<String>) {
+        self.quick_open.history = history;
+        self.quick_open.history_index = None;
+    }
+
+    pub fn record_file_opened(&mut self, path: &str) {
+        self.quick_open
+            .file_history
+            .record_open(path, file_history::now());
+    }
+
+    pub fn recent_files(&self) -> Vec<RecentFile> {
+        self.quick_open.file_history.files().to_vec()
+    }
+
+    pub fn set_recent_files(&mut self, files: Vec<RecentFile>) {
+        self.quick_open.file_history = FileHistory::new(files);
+    }
+
+    // Forgets the recently opened files and the quick open queries.
+    pub fn clear_history(&mut self) {
+        self.quick_open.file_history.clear();
+        self.set_quick_open_history(vec![]);
+    }
+
+    pub fn key_context(&self) -> KeyContext {
+        match self.mode {
+            FilePaneMode::DirectoryTree => KeyContext::Tree,
+            FilePaneMode::QuickOpen => KeyContext::QuickOpen,
+        }
+    }
+
+    // The files around the selected item, which are likely to be previewed next.
+    pub fn neighbouring_files(&self, count: usize) -> Vec<FileIndexEntry> {
+        match self.mode {
+            FilePaneMode::DirectoryTree => self.directory_tree.neighbouring_files(count),
+            FilePaneMode::QuickOpen => self.quick_open.neighbouring_files(count),
+        }
+    }
 }
 
 impl Component for FilePaneComponent {
@@ -464,29 +780,39 @@ impl Component for FilePaneComponent {
         }
     }
     fn dispatch_event(&mut self, event: termion::event::Event) -> DispatchEventResult {
-        match event {
-            termion::event::Event::Key(key) => match key {
-                Key::Esc => match self.mode {
-                    FilePaneMode::QuickOpen => {
-                        self.mode = FilePaneMode::DirectoryTree;
-                        return DispatchEventResult {
-                            handled: true,
-                            events: vec![],
-                        };
-                    }
-                    _ => {}
-                },
-                _ => {}
-            },
-            _ => {}
-        }
-
         match self.mode {
             FilePaneMode::DirectoryTree => self.directory_tree.dispatch_event(event),
             FilePaneMode::QuickOpen => self.quick_open.dispatch_event(event),
         }
     }
 
+    fn dispatch_command(&mut self, command: Command) -> DispatchEventResult {
+        match self.mode {
+            FilePaneMode::DirectoryTree => self.directory_tree.dispatch_command(command),
+            FilePaneMode::QuickOpen if command == Command::Close => {
+                self.mode = FilePaneMode::DirectoryTree;
+                // The symbols of the open file are picked from the file view, so closing goes back
+                let events = if self.quick_open.is_picking_symbol_in_open_file() {
+                    vec![Event::FileViewFocusRequested]
+                } else {
+                    vec![]
+                };
+                DispatchEventResult {
+                    handled: true,
+                    events,
+                }
+            }
+            FilePaneMode::QuickOpen => self.quick_open.dispatch_command(command),
+        }
+    }
+
+    fn is_prompting(&self) -> bool {
+        match self.mode {
+            FilePaneMode::DirectoryTree => self.directory_tree.is_prompting(),
+            FilePaneMode::QuickOpen => false,
+        }
+    }
+
     fn dispatch_events(&mut self, events: &[Event]) {
         match self.mode {
             FilePaneMode::DirectoryTree => self.directory_tree.dispatch_events(events),
@@ -17,224 +17,1357 @@
 */
 
 use super::component::{Component, DispatchEventResult};
+use super::hex_view::{HexData, HexViewComponent};
+use super::large_file_view::LargeFileViewComponent;
 use crate::buffer::Buffer;
+use crate::commands::Command;
+use crate::diagnostics::Diagnostic;
+use crate::display_width::{byte_index_at_column, displayed_grapheme, width, DEFAULT_TAB_WIDTH};
 use crate::event::Event;
-use crate::painting_utils::{paint_empty_lines, paint_truncated_text};
-use crate::terminal::Rect;
+use crate::language::Language;
+use crate::large_file::LargeFile;
+use crate::lsp::{CompletionItem, LspRequest, TextEdit};
+use crate::painting_utils::{
+    paint_empty_lines, paint_left_and_right_text, paint_text_columns, paint_truncated_text,
+};
+use crate::session::{FileState, MAX_FILE_STATES};
+use crate::terminal::{Rect, SPACES};
+use crate::text_format::{LineEnding, TextFormat};
+use crate::text_layout::{
+    byte_index_of_grapheme, byte_index_of_utf16_column, grapheme_index_of_byte,
+    utf16_column_of_byte, wrap_line,
+};
+use crate::text_position::{parse_position, TextPosition};
+use crate::theme::{StyleName, Theme};
 use std::cell::Cell;
+use std::cmp::{max, min, Ordering, Reverse};
 use std::convert::TryFrom;
 use std::io::Write;
+use std::ops::Range;
+use std::str::FromStr;
 use termion;
+use termion::event::{MouseButton, MouseEvent};
 use unicode_segmentation::UnicodeSegmentation;
 
+#[derive(Clone, Copy, PartialEq)]
+pub enum LineNumbers {
+    Off,
+    Absolute,
+    // The cursor line shows its absolute number, all other lines show their distance to it
+    Relative,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum WrapMode {
+    // Long lines are cut off at the edge of the view, which scrolls horizontally to follow the cursor
+    NoWrap,
+    // Long lines continue on the next row, breaking at word boundaries where possible
+    SoftWrap,
+}
+
+// The most lines that a popup shows at once
+const MAX_POPUP_LINES: usize = 10;
+
+// Shown next to the cursor, with answers from the language server
+enum Popup {
+    Hover(Vec<String>),
+    // All the items the server suggested. Only those matching the word before the cursor are shown,
+    // and selected_index counts those.
+    Completion {
+        items: Vec<CompletionItem>,
+        selected_index: usize,
+    },
+}
+
+fn is_identifier_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+// The completions that could finish the word before the cursor.
+fn matching_completions<'a>(items: &'a [CompletionItem], word: &str) -> Vec<&'a CompletionItem> {
+    let word = word.to_lowercase();
+    items
+        .iter()
+        .filter(|item| item.insert_text.to_lowercase().starts_with(&word))
+        .collect()
+}
+
 pub struct FileViewComponent {
-    content: String,
-    num_content_lines: i64,
     file_path: String,
-    start_line: i64,
+    language: Language,
+    format: TextFormat,
+    start_line: usize,
+    start_column: usize,
     has_focus: bool,
     needs_paint: Cell<bool>,
     buffer: Buffer,
+    hex_view: Option<HexViewComponent>,
+    large_file_view: Option<LargeFileViewComponent>,
+    modified: bool,
+    line_numbers: LineNumbers,
+    wrap_mode: WrapMode,
+    tab_width: usize,
+    go_to_line_query: Option<String>,
+    // Where a mouse selection started. The selection runs from here to the cursor.
+    selection_anchor: Option<TextPosition>,
+    num_visible_lines: Cell<usize>,
+    text_width: Cell<usize>,
+    painted_rect: Cell<Option<Rect>>,
+    theme: Theme,
+    // Where the cursor was in the text files shown before, most recently shown last
+    file_states: Vec<FileState>,
+    // Only text files remember their position, not folders or the read-only views
+    remembers_position: bool,
+    // Sorted by position. Columns count UTF-16 code units, as the language server sent them.
+    diagnostics: Vec<Diagnostic>,
+    popup: Option<Popup>,
 }
 
 pub enum FileViewContent {
-    TextFile(String, String),
-    BinaryFile(String),
+    TextFile(String, Buffer, TextFormat),
+    BinaryFile(String, HexData),
+    LargeFile(String, LargeFile),
     Folder(String, Vec<String>),
 }
 
 impl FileViewComponent {
     pub fn new() -> FileViewComponent {
         FileViewComponent {
-            content: String::new(),
-            num_content_lines: 0,
             file_path: String::new(),
+            language: Language::PlainText,
+            format: TextFormat::new(),
             start_line: 0,
+            start_column: 0,
             has_focus: false,
             needs_paint: Cell::new(true),
             buffer: Buffer::new(),
+            hex_view: None,
+            large_file_view: None,
+            modified: false,
+            line_numbers: LineNumbers::Absolute,
+            wrap_mode: WrapMode::NoWrap,
+            tab_width: DEFAULT_TAB_WIDTH,
+            go_to_line_query: None,
+            selection_anchor: None,
+            num_visible_lines: Cell::new(0),
+            text_width: Cell::new(0),
+            painted_rect: Cell::new(None),
+            theme: Theme::new(),
+            file_states: vec![],
+            remembers_position: false,
+            diagnostics: vec![],
+            popup: None,
         }
     }
 
     pub fn set_content(&mut self, content: FileViewContent) {
-        self.buffer.delete_all();
-        match content {
-            FileViewContent::TextFile(path, content) => {
-                self.content = content;
-                self.num_content_lines = i64::try_from(self.content.lines().count()).unwrap();
+        self.remember_file_state();
+        self.hex_view = None;
+        self.large_file_view = None;
+        self.remembers_position = false;
+        self.buffer = match content {
+            FileViewContent::TextFile(path, buffer, format) => {
+                self.remembers_position = true;
+                self.language = Language::from_path(&path);
+                self.format = format;
+                self.file_path = path;
+                buffer
+            }
+            FileViewContent::BinaryFile(path, data) => {
+                let mut hex_view = HexViewComponent::new(path.clone(), data, &self.theme);
+                hex_view.set_has_focus(self.has_focus);
+                self.hex_view = Some(hex_view);
+                self.language = Language::PlainText;
+                self.format = TextFormat::new();
                 self.file_path = path;
+                Buffer::new()
             }
-            FileViewContent::BinaryFile(path) => {
-                self.content = String::from("<binary file>");
-                self.num_content_lines = 1;
+            FileViewContent::LargeFile(path, file) => {
+                let mut large_file_view =
+                    LargeFileViewComponent::new(path.clone(), file, self.tab_width, &self.theme);
+                large_file_view.set_has_focus(self.has_focus);
+                self.large_file_view = Some(large_file_view);
+                self.language = Language::PlainText;
+                self.format = TextFormat::new();
                 self.file_path = path;
+                Buffer::new()
             }
             FileViewContent::Folder(path, mut children) => {
-                self.num_content_lines = i64::try_from(children.len()).unwrap();
-                self.content = children
+                self.language = Language::PlainText;
+                self.format = TextFormat::new();
+                self.file_path = path;
+                let content = children
                     .iter_mut()
                     .map(|child| String::from("./") + child)
                     .collect::<Vec<String>>()
                     .join("\n");
-                self.file_path = path;
+                let Ok(buffer) = Buffer::from_str(&content);
+                buffer
             }
         };
 
-        // TODO: this is pretty inefficient. There should be an optimized method to initialize a buffer with
-        // content and put the cursor at the beginning instead of having to move cursor after initial insertion.
-        self.buffer.insert_at_cursor(&self.content);
-        self.buffer.move_cursor_to_beginning();
         self.start_line = 0;
+        self.start_column = 0;
+        self.selection_anchor = None;
+        self.modified = false;
+        self.diagnostics.clear();
+        self.popup = None;
         self.needs_paint.set(true);
+        self.restore_file_state();
+    }
+
+    fn remember_file_state(&mut self) {
+        if !self.remembers_position {
+            return;
+        }
+        let file_path = &self.file_path;
+        self.file_states
+            .retain(|file_state| file_state.path != *file_path);
+        // Files that were only glanced at would push out the ones that were worked on
+        let cursor = self.buffer.cursor_position();
+        if cursor == TextPosition::new(0, 0) && self.start_line == 0 {
+            return;
+        }
+        self.file_states.push(FileState {
+            path: self.file_path.clone(),
+            cursor,
+            start_line: self.start_line,
+        });
+        if self.file_states.len() > MAX_FILE_STATES {
+            self.file_states.remove(0);
+        }
+    }
+
+    fn restore_file_state(&mut self) {
+        if !self.remembers_position {
+            return;
+        }
+        if let Some(file_state) = self
+            .file_states
+            .iter()
+            .find(|file_state| file_state.path == self.file_path)
+        {
+            let start_line = file_state.start_line;
+            self.buffer.move_cursor_to_position(file_state.cursor);
+            self.start_line = min(start_line, self.buffer.cursor_position().line);
+            // Before the first paint, the size of the view isn't known yet
+            if self.num_visible_lines.get() > 0 {
+                self.scroll_to_cursor();
+            }
+        }
+    }
+
+    // Including the file shown now.
+    pub fn file_states(&mut self) -> Vec<FileState> {
+        self.remember_file_state();
+        self.file_states.clone()
+    }
+
+    pub fn set_file_states(&mut self, file_states: Vec<FileState>) {
+        self.file_states = file_states;
     }
 
     pub fn set_has_focus(&mut self, focused: bool) {
         self.has_focus = focused;
+        if let Some(hex_view) = &mut self.hex_view {
+            hex_view.set_has_focus(focused);
+        }
+        if let Some(large_file_view) = &mut self.large_file_view {
+            large_file_view.set_has_focus(focused);
+        }
+        self.needs_paint.set(true);
+    }
+
+    pub fn get_buffer(&self) -> (&Buffer, String, TextFormat) {
+        (&self.buffer, self.file_path.clone(), self.format)
+    }
+
+    // The buffer is always UTF-8, so converting only changes what is written on save.
+    fn convert_to_next_encoding(&mut self) {
+        self.format.encoding = self.format.encoding.next();
+        self.modified = true;
+        self.needs_paint.set(true);
+    }
+
+    // Likewise, the buffer always uses "\n" line endings.
+    fn toggle_line_ending(&mut self) {
+        self.format.line_ending = self.format.line_ending.toggled();
+        self.format.mixed_line_endings = false;
+        self.modified = true;
+        self.needs_paint.set(true);
+    }
+
+    pub fn set_theme(&mut self, theme: &Theme) {
+        self.theme = theme.clone();
+        if let Some(hex_view) = &mut self.hex_view {
+            hex_view.set_theme(theme);
+        }
+        if let Some(large_file_view) = &mut self.large_file_view {
+            large_file_view.set_theme(theme);
+        }
+        self.needs_paint.set(true);
+    }
+
+    pub fn set_line_numbers(&mut self, line_numbers: LineNumbers) {
+        self.line_numbers = line_numbers;
+        self.needs_paint.set(true);
+    }
+
+    pub fn set_wrap_mode(&mut self, wrap_mode: WrapMode) {
+        self.wrap_mode = wrap_mode;
+        self.start_column = 0;
+        self.needs_paint.set(true);
+    }
+
+    pub fn set_tab_width(&mut self, tab_width: usize) {
+        self.tab_width = tab_width;
+        self.needs_paint.set(true);
+    }
+
+    pub fn mark_saved(&mut self) {
+        self.modified = false;
+        // Saving wrote every line with the same ending
+        self.format.mixed_line_endings = false;
+        self.needs_paint.set(true);
+    }
+
+    pub fn set_diagnostics(&mut self, mut diagnostics: Vec<Diagnostic>) {
+        diagnostics.sort_by_key(|diagnostic| (diagnostic.start, diagnostic.severity));
+        self.diagnostics = diagnostics;
+        self.needs_paint.set(true);
+    }
+
+    // The most severe diagnostic that starts on the line.
+    fn line_diagnostic(&self, line_index: usize) -> Option<&Diagnostic> {
+        self.diagnostics
+            .iter()
+            .filter(|diagnostic| diagnostic.start.line == line_index)
+            .min_by_key(|diagnostic| diagnostic.severity)
+    }
+
+    // Language servers count columns in UTF-16 code units rather than graphemes.
+    fn lsp_position(&self, position: TextPosition) -> TextPosition {
+        let line = self.buffer.line(position.line).unwrap_or_default();
+        let byte_index = byte_index_of_grapheme(&line, position.column);
+        TextPosition::new(position.line, utf16_column_of_byte(&line, byte_index))
+    }
+
+    fn position_from_lsp(&self, position: TextPosition) -> TextPosition {
+        let line = self.buffer.line(position.line).unwrap_or_default();
+        let byte_index = byte_index_of_utf16_column(&line, position.column);
+        TextPosition::new(position.line, grapheme_index_of_byte(&line, byte_index))
+    }
+
+    pub fn go_to_lsp_position(&mut self, position: TextPosition) {
+        self.go_to_position(self.position_from_lsp(position));
+    }
+
+    // Moves the cursor to the next diagnostic after it, or the previous one before it, wrapping
+    // around at the ends of the file.
+    fn go_to_diagnostic(&mut self, forward: bool) {
+        let cursor_position = self.lsp_position(self.buffer.cursor_position());
+        let mut starts = self.diagnostics.iter().map(|diagnostic| diagnostic.start);
+        let target = if forward {
+            let first = self.diagnostics.first().map(|diagnostic| diagnostic.start);
+            starts.find(|start| *start > cursor_position).or(first)
+        } else {
+            let last = self.diagnostics.last().map(|diagnostic| diagnostic.start);
+            starts.rev().find(|start| *start < cursor_position).or(last)
+        };
+        if let Some(target) = target {
+            self.selection_anchor = None;
+            self.popup = None;
+            self.go_to_lsp_position(target);
+        }
+    }
+
+    fn language_server_request(&self, request: LspRequest) -> Event {
+        let position = self.lsp_position(self.buffer.cursor_position());
+        Event::LanguageServerRequested(request, position)
+    }
+
+    // Code blocks are shown without their fences, since there is no highlighting anyway.
+    pub fn show_hover(&mut self, text: &str) {
+        let text = text
+            .lines()
+            .filter(|line| !line.starts_with("```"))
+            .collect::<Vec<&str>>()
+            .join("\n");
+        let lines = text
+            .trim()
+            .lines()
+            .map(|line| line.replace('\t', "    "))
+            .collect();
+        self.popup = Some(Popup::Hover(lines));
+        self.needs_paint.set(true);
+    }
+
+    pub fn show_completions(&mut self, items: Vec<CompletionItem>) {
+        self.popup = Some(Popup::Completion {
+            items,
+            selected_index: 0,
+        });
+        self.filter_completions();
+    }
+
+    // Called when the word before the cursor changes. Closes the completions when none of them
+    // match anymore.
+    fn filter_completions(&mut self) {
+        let word = self.word_before_cursor();
+        match &mut self.popup {
+            Some(Popup::Completion {
+                items,
+                selected_index,
+            }) if !matching_completions(items, &word).is_empty() => *selected_index = 0,
+            Some(Popup::Completion { .. }) => self.popup = None,
+            _ => {}
+        }
+        self.needs_paint.set(true);
+    }
+
+    // Replaces the word before the cursor with the selected completion.
+    fn accept_completion(&mut self) -> Option<Event> {
+        let word = self.word_before_cursor();
+        let insert_text = match self.popup.take() {
+            Some(Popup::Completion {
+                items,
+                selected_index,
+            }) => matching_completions(&items, &word)
+                .get(selected_index)?
+                .insert_text
+                .clone(),
+            _ => return None,
+        };
+        let event = self.edit_at_cursor(word.graphemes(true).count(), &insert_text);
+        self.scroll_to_cursor();
+        self.needs_paint.set(true);
+        Some(event)
+    }
+
+    // The identifier that ends at the cursor, if any.
+    fn word_before_cursor(&self) -> String {
+        let position = self.buffer.cursor_position();
+        let line = self.buffer.line(position.line).unwrap_or_default();
+        let before_cursor = &line[..byte_index_of_grapheme(&line, position.column)];
+        let word_start = before_cursor
+            .char_indices()
+            .rev()
+            .take_while(|(_, c)| is_identifier_char(*c))
+            .last()
+            .map_or(before_cursor.len(), |(i, _)| i);
+        String::from(&before_cursor[word_start..])
+    }
+
+    // Replaces the graphemes before the cursor with the text, and returns the edit for the language
+    // server.
+    fn edit_at_cursor(&mut self, num_deleted_graphemes: usize, text: &str) -> Event {
+        let end = self.lsp_position(self.buffer.cursor_position());
+        if num_deleted_graphemes > 0 {
+            self.buffer.delete_at_cursor(num_deleted_graphemes);
+        }
+        let start = self.lsp_position(self.buffer.cursor_position());
+        self.buffer.insert_at_cursor(text);
+        self.modified = true;
+        Event::TextEdited(TextEdit {
+            start,
+            end,
+            text: String::from(text),
+        })
+    }
+
+    pub fn start_go_to_line(&mut self) {
+        if let Some(hex_view) = &mut self.hex_view {
+            hex_view.start_jump_to_offset();
+            return;
+        }
+        if let Some(large_file_view) = &mut self.large_file_view {
+            large_file_view.start_go_to_line();
+            return;
+        }
+        self.go_to_line_query = Some(String::new());
+        self.needs_paint.set(true);
+    }
+
+    fn toggle_line_numbers(&mut self) {
+        self.line_numbers = match self.line_numbers {
+            LineNumbers::Off => LineNumbers::Absolute,
+            LineNumbers::Absolute => LineNumbers::Relative,
+            LineNumbers::Relative => LineNumbers::Off,
+        };
+        self.needs_paint.set(true);
+    }
+
+    fn toggle_wrap_mode(&mut self) {
+        self.wrap_mode = match self.wrap_mode {
+            WrapMode::NoWrap => WrapMode::SoftWrap,
+            WrapMode::SoftWrap => WrapMode::NoWrap,
+        };
+        self.start_column = 0;
+        self.scroll_to_cursor();
+        self.needs_paint.set(true);
+    }
+
+    // Returns the byte ranges of the line that are painted on separate rows.
+    fn line_segments(&self, line: &str) -> Vec<Range<usize>> {
+        match self.wrap_mode {
+            WrapMode::NoWrap => std::iter::once(0..line.len()).collect(),
+            // The last column is reserved for the continuation indicator
+            WrapMode::SoftWrap => wrap_line(
+                line,
+                self.text_width.get().saturating_sub(1),
+                self.tab_width,
+            ),
+        }
+    }
+
+    fn go_to_position(&mut self, position: TextPosition) {
+        self.buffer.move_cursor_to_position(position);
+        let cursor_line = self.buffer.cursor_position().line;
+        let num_visible_lines = self.num_visible_lines.get();
+        if cursor_line < self.start_line || cursor_line >= self.start_line + num_visible_lines {
+            // Jumps put the target line in the middle of the view, like most editors do
+            self.start_line = cursor_line.saturating_sub(num_visible_lines / 2);
+        }
+        self.scroll_to_cursor();
+        self.needs_paint.set(true);
+    }
+
+    // Moves the cursor to the row above or below, keeping its visual column. When soft wrap is on,
+    // rows are visual lines rather than lines of the file.
+    fn move_cursor_vertically(&mut self, down: bool) {
+        let position = self.buffer.cursor_position();
+        let line = match self.buffer.line(position.line) {
+            Some(line) => line.into_owned(),
+            None => return,
+        };
+        let segments = self.line_segments(&line);
+        let cursor_byte_index = byte_index_of_grapheme(&line, position.column);
+        let segment_index = segments
+            .iter()
+            .position(|segment| cursor_byte_index < segment.end)
+            .unwrap_or(segments.len() - 1);
+        let column = width(
+            &line[segments[segment_index].start..cursor_byte_index],
+            self.tab_width,
+        );
+
+        let (target_line_index, target_segment_index) = if down {
+            if segment_index + 1 < segments.len() {
+                (position.line, segment_index + 1)
+            } else if position.line + 1 < self.buffer.num_lines() {
+                (position.line + 1, 0)
+            } else {
+                return;
+            }
+        } else if segment_index > 0 {
+            (position.line, segment_index - 1)
+        } else if position.line > 0 {
+            (position.line - 1, usize::MAX)
+        } else {
+            return;
+        };
+
+        let target_line = if target_line_index == position.line {
+            line
+        } else {
+            match self.buffer.line(target_line_index) {
+                Some(line) => line.into_owned(),
+                None => return,
+            }
+        };
+        let target_segments = self.line_segments(&target_line);
+        let target_segment =
+            target_segments[min(target_segment_index, target_segments.len() - 1)].clone();
+        let byte_index = self.byte_index_in_segment(&target_line, target_segment, column);
+        self.buffer.move_cursor_to_position(TextPosition::new(
+            target_line_index,
+            grapheme_index_of_byte(&target_line, byte_index),
+        ));
+    }
+
+    fn move_cursor(&mut self, command: Command) {
+        let position = self.buffer.cursor_position();
+        match command {
+            Command::CursorUp => self.move_cursor_vertically(false),
+            Command::CursorDown => self.move_cursor_vertically(true),
+            Command::CursorLeft => self.buffer.move_cursor(-1),
+            Command::CursorRight => self.buffer.move_cursor(1),
+            Command::PageUp | Command::PageDown => {
+                for _ in 0..max(self.num_visible_lines.get(), 1) {
+                    self.move_cursor_vertically(command == Command::PageDown);
+                }
+            }
+            Command::LineStart => self
+                .buffer
+                .move_cursor_to_position(TextPosition::new(position.line, 0)),
+            Command::LineEnd => self
+                .buffer
+                .move_cursor_to_position(TextPosition::new(position.line, usize::MAX)),
+            Command::GoToStart => self.buffer.move_cursor_to_beginning(),
+            Command::GoToEnd => self.buffer.move_cursor_to_end(),
+            _ => {}
+        }
+    }
+
+    // Returns the byte index of the grapheme at the given column of a segment of the line.
+    fn byte_index_in_segment(&self, line: &str, segment: Range<usize>, column: usize) -> usize {
+        let segment_text = &line[segment.clone()];
+        let byte_index = segment.start + byte_index_at_column(segment_text, column, self.tab_width);
+        // Only the last segment of a line can have the cursor after its last grapheme, otherwise
+        // the cursor would show up at the start of the next row.
+        if byte_index == segment.end && segment.end < line.len() {
+            segment.start
+                + segment_text
+                    .grapheme_indices(true)
+                    .next_back()
+                    .map_or(0, |(i, _)| i)
+        } else {
+            byte_index
+        }
+    }
+
+    // The text position painted at the given screen coordinates. Coordinates above or below the text
+    // area count as its first or last row.
+    fn position_at(&self, x: u16, y: u16) -> Option<TextPosition> {
+        let rect = self.painted_rect.get()?;
+        // One row for the header and one for the status bar
+        let num_text_rows = rect.height.saturating_sub(2);
+        if num_text_rows == 0 {
+            return None;
+        }
+        let row = usize::from(min(max(y, rect.top + 1), rect.top + num_text_rows) - rect.top - 1);
+        let text_left = rect.left + min(self.gutter_width(), rect.width);
+        let column = usize::from(x.saturating_sub(text_left));
+        let first_column = match self.wrap_mode {
+            WrapMode::NoWrap => self.start_column,
+            WrapMode::SoftWrap => 0,
+        };
+
+        let mut remaining_rows = row;
+        for (offset, line) in self.buffer.lines().skip(self.start_line).enumerate() {
+            let segments = self.line_segments(&line);
+            if remaining_rows < segments.len() {
+                let segment = segments[remaining_rows].clone();
+                let byte_index = self.byte_index_in_segment(&line, segment, first_column + column);
+                return Some(TextPosition::new(
+                    self.start_line + offset,
+                    grapheme_index_of_byte(&line, byte_index),
+                ));
+            }
+            remaining_rows -= segments.len();
+        }
+        // Below the last line
+        Some(TextPosition::new(usize::MAX, usize::MAX))
+    }
+
+    fn is_in_text_area(&self, y: u16) -> bool {
+        match self.painted_rect.get() {
+            None => false,
+            Some(rect) => y > rect.top && y + 1 < rect.top + rect.height,
+        }
+    }
+
+    // The start and end of the selected text, if any.
+    fn selection(&self) -> Option<(TextPosition, TextPosition)> {
+        let anchor = self.selection_anchor?;
+        let cursor_position = self.buffer.cursor_position();
+        match anchor.cmp(&cursor_position) {
+            Ordering::Less => Some((anchor, cursor_position)),
+            Ordering::Equal => None,
+            Ordering::Greater => Some((cursor_position, anchor)),
+        }
+    }
+
+    // Returns None if there was no selection to delete.
+    fn delete_selection(&mut self) -> Option<Event> {
+        let (start, end) = self.selection()?;
+        self.selection_anchor = None;
+        let num_graphemes = |line_index: usize| {
+            self.buffer
+                .line(line_index)
+                .map_or(0, |line| line.graphemes(true).count())
+        };
+        // Each line break is one more grapheme
+        let num_deleted_graphemes = if start.line == end.line {
+            end.column - start.column
+        } else {
+            (num_graphemes(start.line) - start.column + 1)
+                + (start.line + 1..end.line)
+                    .map(|line_index| num_graphemes(line_index) + 1)
+                    .sum::<usize>()
+                + end.column
+        };
+        self.buffer.move_cursor_to_position(end);
+        Some(self.edit_at_cursor(num_deleted_graphemes, ""))
+    }
+
+    fn dispatch_mouse_event(&mut self, mouse_event: MouseEvent) -> bool {
+        match mouse_event {
+            MouseEvent::Press(MouseButton::Left, x, y) => match self.position_at(x, y) {
+                // Clicks on the header and the status bar don't move the cursor
+                Some(_) if !self.is_in_text_area(y) => return false,
+                None => return false,
+                Some(position) => {
+                    self.popup = None;
+                    self.buffer.move_cursor_to_position(position);
+                    self.selection_anchor = Some(self.buffer.cursor_position());
+                }
+            },
+            MouseEvent::Hold(x, y) => match (self.selection_anchor, self.position_at(x, y)) {
+                (Some(_), Some(position)) => self.buffer.move_cursor_to_position(position),
+                _ => return false,
+            },
+            MouseEvent::Release(_, _) => {
+                if self.selection().is_none() {
+                    self.selection_anchor = None;
+                }
+                return true;
+            }
+            _ => return false,
+        }
+        self.scroll_to_cursor();
+        self.needs_paint.set(true);
+        true
+    }
+
+    fn scroll_to_cursor(&mut self) {
+        let cursor_position = self.buffer.cursor_position();
+        let num_visible_lines = max(self.num_visible_lines.get(), 1);
+        if cursor_position.line < self.start_line {
+            self.start_line = cursor_position.line;
+        }
+
+        let cursor_line = self.buffer.line(cursor_position.line).unwrap_or_default();
+        let cursor_byte_index = byte_index_of_grapheme(&cursor_line, cursor_position.column);
+        match self.wrap_mode {
+            WrapMode::NoWrap => {
+                if cursor_position.line >= self.start_line + num_visible_lines {
+                    self.start_line = cursor_position.line + 1 - num_visible_lines;
+                }
+
+                let text_width = max(self.text_width.get(), 1);
+                let column = width(&cursor_line[..cursor_byte_index], self.tab_width);
+                if column < self.start_column {
+                    self.start_column = column;
+                } else if column >= self.start_column + text_width {
+                    self.start_column = column + 1 - text_width;
+                }
+            }
+            WrapMode::SoftWrap => {
+                // Count the rows between the first painted line and the cursor, then drop lines
+                // from the top until the cursor row fits.
+                let mut num_rows_per_line: Vec<usize> = self
+                    .buffer
+                    .lines()
+                    .skip(self.start_line)
+                    .take(cursor_position.line - self.start_line)
+                    .map(|line| self.line_segments(&line).len())
+                    .collect();
+                let cursor_segment_index = self
+                    .line_segments(&cursor_line)
+                    .iter()
+                    .position(|segment| cursor_byte_index < segment.end)
+                    .unwrap_or(0);
+                num_rows_per_line.push(cursor_segment_index + 1);
+
+                let mut num_rows: usize = num_rows_per_line.iter().sum();
+                for num_line_rows in num_rows_per_line.iter() {
+                    if num_rows <= num_visible_lines || self.start_line == cursor_position.line {
+                        break;
+                    }
+                    num_rows -= num_line_rows;
+                    self.start_line += 1;
+                }
+            }
+        }
+    }
+
+    // Returns None if the event was not meant for the go-to-line prompt.
+    fn dispatch_go_to_line_event(&mut self, key: termion::event::Key) -> Option<()> {
+        let query = self.go_to_line_query.as_mut()?;
+        match key {
+            termion::event::Key::Char('\n') => {
+                let position = parse_position(query);
+                self.go_to_line_query = None;
+                if let Some(position) = position {
+                    self.go_to_position(position);
+                }
+            }
+            termion::event::Key::Char(c) => {
+                if c.is_ascii_digit() || c == ':' {
+                    query.push(c);
+                }
+            }
+            termion::event::Key::Backspace => {
+                query.pop();
+            }
+            termion::event::Key::Esc => {
+                self.go_to_line_query = None;
+            }
+            _ => return None,
+        }
         self.needs_paint.set(true);
+        Some(())
     }
 
-    pub fn get_buffer(&self) -> (&Buffer, String) {
-        return (&self.buffer, self.file_path.clone());
+    // Returns None if the key was not meant for the popup. The popup then closes, unless the key goes
+    // on typing the word that is being completed.
+    fn dispatch_popup_event(&mut self, key: termion::event::Key) -> Option<Vec<Event>> {
+        let word = self.word_before_cursor();
+        let popup = self.popup.as_mut()?;
+        self.needs_paint.set(true);
+        match (popup, key) {
+            (_, termion::event::Key::Esc) => self.popup = None,
+            (Popup::Completion { selected_index, .. }, termion::event::Key::Up) => {
+                *selected_index = selected_index.saturating_sub(1);
+            }
+            (
+                Popup::Completion {
+                    items,
+                    selected_index,
+                },
+                termion::event::Key::Down,
+            ) => {
+                if *selected_index + 1 < matching_completions(items, &word).len() {
+                    *selected_index += 1;
+                }
+            }
+            (Popup::Completion { .. }, termion::event::Key::Char('\n'))
+            | (Popup::Completion { .. }, termion::event::Key::Char('\t')) => {
+                return Some(self.accept_completion().into_iter().collect());
+            }
+            (Popup::Completion { .. }, termion::event::Key::Char(c)) if is_identifier_char(c) => {
+                return None
+            }
+            (Popup::Completion { .. }, termion::event::Key::Backspace) => return None,
+            _ => {
+                self.popup = None;
+                return None;
+            }
+        }
+        Some(vec![])
     }
 
     fn scroll_down(&mut self) {
-        if self.start_line < self.num_content_lines {
-            self.start_line = self.start_line + 1;
+        if self.start_line + 1 < self.buffer.num_lines() {
+            self.start_line += 1;
             self.needs_paint.set(true);
         }
     }
 
     fn scroll_up(&mut self) {
         if self.start_line > 0 {
-            self.start_line = self.start_line - 1;
+            self.start_line -= 1;
             self.needs_paint.set(true);
         }
     }
-}
 
-impl Component for FileViewComponent {
-    fn needs_paint(&self) -> bool {
-        self.needs_paint.take()
+    // Files with diagnostics get a column of signs before the line numbers.
+    fn sign_width(&self) -> u16 {
+        if self.diagnostics.is_empty() {
+            0
+        } else {
+            2
+        }
     }
-    fn paint<Writer: Write>(&self, stream: &mut Writer, rect: Rect) -> std::io::Result<()> {
-        write!(stream, "{}", termion::color::Fg(termion::color::Yellow))?;
-        write!(stream, "{}", termion::cursor::Goto(rect.left, rect.top),)?;
-        paint_truncated_text(stream, &self.file_path, rect.width)?;
 
-        write!(stream, "{}", termion::color::Fg(termion::color::White))?;
+    fn gutter_width(&self) -> u16 {
+        let number_width = match self.line_numbers {
+            LineNumbers::Off => 0,
+            _ => {
+                let num_digits = self.buffer.num_lines().to_string().len();
+                u16::try_from(num_digits).unwrap() + 1
+            }
+        };
+        self.sign_width() + number_width
+    }
 
-        let total_num_lines = rect.height as usize - 1;
-        let (before_cursor, after_cursor) = self.buffer.get();
+    fn paint_header<Writer: Write>(&self, stream: &mut Writer, rect: Rect) -> std::io::Result<()> {
+        write!(stream, "{}", termion::cursor::Goto(rect.left, rect.top))?;
+        match &self.go_to_line_query {
+            Some(query) => {
+                write!(stream, "{}", self.theme.style(StyleName::Prompt))?;
+                paint_truncated_text(stream, &format!("Go to line: {}", query), rect.width)
+            }
+            None => {
+                write!(stream, "{}", self.theme.style(StyleName::Title))?;
+                // LF is the norm, so only call out files that differ from it
+                let line_ending = match (self.format.mixed_line_endings, self.format.line_ending) {
+                    (true, line_ending) => format!("Mixed, saved as {} ", line_ending.name()),
+                    (false, LineEnding::Lf) => String::new(),
+                    (false, LineEnding::CrLf) => String::from("CRLF "),
+                };
+                paint_left_and_right_text(stream, &self.file_path, &line_ending, rect.width)
+            }
+        }
+    }
 
-        // This code is ugly because I'm trying to iterate over our lines iterators only once.
-        let num_lines_to_skip = self.start_line;
-        let mut current_line_index = 0i64;
-        let mut num_painted_lines = 0usize;
-        let mut last_before_cursor_line_length = 0usize;
+    fn paint_gutter<Writer: Write>(
+        &self,
+        stream: &mut Writer,
+        line_index: usize,
+        cursor_line: usize,
+        width: u16,
+    ) -> std::io::Result<()> {
+        let sign_width = min(self.sign_width(), width);
+        if sign_width > 0 {
+            match self.line_diagnostic(line_index) {
+                None => write!(stream, "{}", &SPACES[0..usize::from(sign_width)])?,
+                Some(diagnostic) => write!(
+                    stream,
+                    "{}{:<width$}",
+                    self.theme.style(diagnostic.severity.style_name()),
+                    diagnostic.severity.name()[..1].to_uppercase(),
+                    width = usize::from(sign_width)
+                )?,
+            }
+        }
+        let number = match self.line_numbers {
+            LineNumbers::Off => return Ok(()),
+            LineNumbers::Relative if line_index != cursor_line => {
+                max(line_index, cursor_line) - min(line_index, cursor_line)
+            }
+            _ => line_index + 1,
+        };
+        if line_index == cursor_line {
+            write!(stream, "{}", self.theme.style(StyleName::CurrentLineNumber))?;
+        } else {
+            write!(stream, "{}", self.theme.style(StyleName::LineNumber))?;
+        }
+        write!(
+            stream,
+            "{:>width$} ",
+            number,
+            width = usize::from(width - sign_width).saturating_sub(1)
+        )
+    }
 
-        let row_offset = |current_line_index: i64, num_lines_to_skip: i64| {
-            u16::try_from(current_line_index - num_lines_to_skip).unwrap()
+    fn paint_cursor<Writer: Write>(
+        &self,
+        stream: &mut Writer,
+        text_after_cursor: &str,
+        column: usize,
+        left: u16,
+        row: u16,
+    ) -> std::io::Result<()> {
+        let grapheme = match text_after_cursor.graphemes(true).next() {
+            None | Some("\t") => " ".into(),
+            Some(grapheme) => displayed_grapheme(grapheme, column, self.tab_width),
         };
+        write!(
+            stream,
+            "{}{}{}{}",
+            termion::cursor::Goto(left, row),
+            self.theme.style(StyleName::Cursor),
+            grapheme,
+            termion::style::Reset
+        )
+    }
 
-        let before_cursor_lines = before_cursor.lines();
-        let mut first = true;
-        for line in before_cursor_lines {
-            if !first {
-                current_line_index += 1;
-            }
+    // Paints the bytes of the line in the highlight range in the given style, as far as they fall into
+    // the segment painted on the row and into the visible columns.
+    fn paint_highlight<Writer: Write>(
+        &self,
+        stream: &mut Writer,
+        line: &str,
+        segment: Range<usize>,
+        highlight: Range<usize>,
+        style: &str,
+        row: u16,
+    ) -> std::io::Result<()> {
+        let rect = match self.painted_rect.get() {
+            None => return Ok(()),
+            Some(rect) => rect,
+        };
+        let highlight_start = max(highlight.start, segment.start);
+        let highlight_end = min(highlight.end, segment.end);
+        if highlight_start >= highlight_end {
+            return Ok(());
+        }
+        let first_column = match self.wrap_mode {
+            WrapMode::NoWrap => self.start_column,
+            WrapMode::SoftWrap => 0,
+        };
+        // Segments that continue on the next row end with the continuation indicator
+        let segment_width = if segment.end == line.len() {
+            self.text_width.get()
+        } else {
+            self.text_width.get().saturating_sub(1)
+        };
+        let text = &line[segment.start..highlight_end];
+        let start_column = max(
+            width(&line[segment.start..highlight_start], self.tab_width),
+            first_column,
+        );
+        let end_column = min(width(text, self.tab_width), first_column + segment_width);
+        if start_column >= end_column {
+            return Ok(());
+        }
+        let text_left = rect.left + min(self.gutter_width(), rect.width);
+        write!(
+            stream,
+            "{}{}",
+            termion::cursor::Goto(
+                text_left + u16::try_from(start_column - first_column).unwrap(),
+                row
+            ),
+            style
+        )?;
+        paint_text_columns(
+            stream,
+            text,
+            start_column,
+            u16::try_from(end_column - start_column).unwrap(),
+            self.tab_width,
+        )?;
+        write!(stream, "{}", termion::style::Reset)
+    }
 
-            first = false;
-            if current_line_index < num_lines_to_skip {
-                continue;
-            }
+    fn paint_status_bar<Writer: Write>(
+        &self,
+        stream: &mut Writer,
+        rect: Rect,
+    ) -> std::io::Result<()> {
+        let cursor_position = self.buffer.cursor_position();
+        let mut left_text = format!(
+            " {}:{}  {} lines",
+            cursor_position.line + 1,
+            cursor_position.column + 1,
+            self.buffer.num_lines()
+        );
+        if let Some(diagnostic) = self.line_diagnostic(cursor_position.line) {
+            left_text += &format!(
+                "  {}: {}",
+                diagnostic.severity.name(),
+                diagnostic.message.lines().next().unwrap_or_default()
+            );
+        }
+        let right_text = format!(
+            "{}{}  {}  {} ",
+            if self.modified { "[+]  " } else { "" },
+            self.format.encoding.name(),
+            self.format.line_ending.name(),
+            self.language.name()
+        );
+        write!(
+            stream,
+            "{}{}",
+            termion::cursor::Goto(rect.left, rect.top),
+            self.theme.style(StyleName::StatusBar)
+        )?;
+        paint_left_and_right_text(stream, &left_text, &right_text, rect.width)?;
+        write!(stream, "{}", termion::style::Reset)
+    }
 
-            let row_offset = row_offset(current_line_index, num_lines_to_skip);
-            write!(
-                stream,
-                "{}",
-                termion::cursor::Goto(rect.left, rect.top + 1 + row_offset)
-            )?;
-            paint_truncated_text(stream, line, rect.width)?;
+    // Paints the popup below the cursor, or above it if there is more room there. text_rect is
+    // where the lines of the file are painted.
+    fn paint_popup<Writer: Write>(
+        &self,
+        stream: &mut Writer,
+        popup: &Popup,
+        cursor_left: u16,
+        cursor_row: u16,
+        text_rect: Rect,
+    ) -> std::io::Result<()> {
+        let word = self.word_before_cursor();
+        let (lines, selected_index): (Vec<(&str, &str)>, Option<usize>) = match popup {
+            Popup::Hover(lines) => (lines.iter().map(|line| (line.as_str(), "")).collect(), None),
+            Popup::Completion {
+                items,
+                selected_index,
+            } => (
+                matching_completions(items, &word)
+                    .into_iter()
+                    .map(|item| (item.label.as_str(), item.detail.as_deref().unwrap_or("")))
+                    .collect(),
+                Some(*selected_index),
+            ),
+        };
 
-            num_painted_lines += 1;
-            last_before_cursor_line_length = line.graphemes(true).count();
+        let num_rows_below = text_rect.top + text_rect.height - cursor_row - 1;
+        let num_rows_above = cursor_row - text_rect.top;
+        let height = min(
+            min(lines.len(), MAX_POPUP_LINES),
+            usize::from(max(num_rows_below, num_rows_above)),
+        );
+        if height == 0 || text_rect.width < 2 {
+            return Ok(());
         }
+        let top = if height <= usize::from(num_rows_below) || num_rows_below >= num_rows_above {
+            cursor_row + 1
+        } else {
+            cursor_row - u16::try_from(height).unwrap()
+        };
+        // Keep the selected item in view
+        let first_index = selected_index.map_or(0, |index| (index + 1).saturating_sub(height));
+
+        // One column of padding on each side, and two between the label and the detail
+        let content_width = lines
+            .iter()
+            .map(|(text, detail)| {
+                let detail_width = width(detail, self.tab_width);
+                width(text, self.tab_width)
+                    + if detail_width > 0 {
+                        detail_width + 2
+                    } else {
+                        0
+                    }
+            })
+            .max()
+            .unwrap_or(0)
+            + 2;
+        let popup_width = min(
+            u16::try_from(content_width).unwrap_or(u16::MAX),
+            text_rect.width,
+        );
+        let left = max(
+            min(cursor_left, text_rect.left + text_rect.width - popup_width),
+            text_rect.left,
+        );
 
-        // Draw the cursor
-        let draw_cursor = self.has_focus
-            && current_line_index >= num_lines_to_skip
-            && last_before_cursor_line_length < rect.width as usize;
-        if draw_cursor {
-            let column_offset = u16::try_from(last_before_cursor_line_length).unwrap();
+        for (row_offset, (index, (text, detail))) in lines
+            .iter()
+            .enumerate()
+            .skip(first_index)
+            .take(height)
+            .enumerate()
+        {
+            let style = if selected_index == Some(index) {
+                StyleName::SelectedItem
+            } else {
+                StyleName::Popup
+            };
             write!(
                 stream,
-                "{}{} {}",
-                termion::cursor::Goto(
-                    rect.left + column_offset,
-                    rect.top + 1 + row_offset(current_line_index, num_lines_to_skip)
-                ),
-                termion::color::Bg(termion::color::White),
-                termion::color::Bg(termion::color::Reset)
+                "{}{} ",
+                termion::cursor::Goto(left, top + u16::try_from(row_offset).unwrap()),
+                self.theme.style(style)
             )?;
-            stream.flush().unwrap();
+            paint_left_and_right_text(stream, text, &format!("{} ", detail), popup_width - 1)?;
         }
+        write!(stream, "{}", termion::style::Reset)
+    }
+}
 
-        let after_cursor_lines = after_cursor.lines();
-        let first_column_offset = u16::try_from(last_before_cursor_line_length).unwrap();
-        let mut first = true;
-        for line in after_cursor_lines {
-            if !first {
-                current_line_index += 1;
-            }
+impl Component for FileViewComponent {
+    fn needs_paint(&self) -> bool {
+        let hex_view_needs_paint = match &self.hex_view {
+            Some(hex_view) => hex_view.needs_paint(),
+            None => false,
+        };
+        let large_file_view_needs_paint = match &self.large_file_view {
+            Some(large_file_view) => large_file_view.needs_paint(),
+            None => false,
+        };
+        self.needs_paint.take() || hex_view_needs_paint || large_file_view_needs_paint
+    }
+    fn paint<Writer: Write>(&self, stream: &mut Writer, rect: Rect) -> std::io::Result<()> {
+        if let Some(hex_view) = &self.hex_view {
+            self.needs_paint.set(false);
+            return hex_view.paint(stream, rect);
+        }
+        if let Some(large_file_view) = &self.large_file_view {
+            self.needs_paint.set(false);
+            return large_file_view.paint(stream, rect);
+        }
 
-            if current_line_index < num_lines_to_skip {
-                continue;
-            }
+        self.painted_rect.set(Some(rect));
+        self.paint_header(stream, rect)?;
 
-            let (column_offset, line_offset) = if first {
-                if draw_cursor {
-                    (first_column_offset + 1, 1usize)
-                } else {
-                    (first_column_offset, 0usize)
-                }
+        // One row for the header and one for the status bar
+        let num_text_rows = rect.height.saturating_sub(2);
+        self.num_visible_lines.set(usize::from(num_text_rows));
+
+        let gutter_width = min(self.gutter_width(), rect.width);
+        let text_left = rect.left + gutter_width;
+        let text_width = rect.width - gutter_width;
+        self.text_width.set(usize::from(text_width));
+        let cursor_position = self.buffer.cursor_position();
+        let selection = self.selection();
+        // Where the cursor was painted, which is where the popup goes
+        let mut cursor_cell = None;
+
+        let mut row_offset = 0u16;
+        let mut line_index = self.start_line;
+        let mut lines = self.buffer.lines().skip(self.start_line);
+        'rows: while row_offset < num_text_rows {
+            let line = match lines.next() {
+                Some(line) => line,
+                None => break,
+            };
+            let segments = self.line_segments(&line);
+            let cursor_byte_index = if self.has_focus && line_index == cursor_position.line {
+                Some(byte_index_of_grapheme(&line, cursor_position.column))
             } else {
-                (0u16, 0usize)
+                None
             };
 
-            write!(
-                stream,
-                "{}",
-                termion::cursor::Goto(
-                    rect.left + column_offset,
-                    rect.top + 1 + row_offset(current_line_index, num_lines_to_skip)
-                )
-            )?;
-            stream.flush().unwrap();
+            for (segment_index, segment) in segments.iter().enumerate() {
+                if row_offset >= num_text_rows {
+                    break 'rows;
+                }
 
-            let first_char_to_paint = line.grapheme_indices(true).nth(line_offset);
-            match first_char_to_paint {
-                Some((i, _)) => {
-                    paint_truncated_text(stream, &line[i..], rect.width - column_offset)?;
+                let row = rect.top + 1 + row_offset;
+                let is_last_segment = segment_index + 1 == segments.len();
+                write!(stream, "{}", termion::cursor::Goto(rect.left, row))?;
+                if segment_index == 0 {
+                    self.paint_gutter(stream, line_index, cursor_position.line, gutter_width)?;
+                } else {
+                    write!(stream, "{}", &SPACES[0..usize::from(gutter_width)])?;
                 }
-                None => {
-                    paint_truncated_text(stream, "", rect.width - column_offset)?;
+
+                write!(stream, "{}", self.theme.style(StyleName::Text))?;
+                let segment_text = &line[segment.clone()];
+                let first_column = match self.wrap_mode {
+                    WrapMode::NoWrap => self.start_column,
+                    WrapMode::SoftWrap => 0,
+                };
+                if is_last_segment {
+                    paint_text_columns(
+                        stream,
+                        segment_text,
+                        first_column,
+                        text_width,
+                        self.tab_width,
+                    )?;
+                } else {
+                    paint_text_columns(
+                        stream,
+                        segment_text,
+                        first_column,
+                        text_width.saturating_sub(1),
+                        self.tab_width,
+                    )?;
+                    write!(stream, "{}↩", self.theme.style(StyleName::Dimmed))?;
+                }
+
+                // Errors are painted last, so that they win over the warnings they overlap
+                let mut line_diagnostics: Vec<&Diagnostic> = self
+                    .diagnostics
+                    .iter()
+                    .filter(|diagnostic| {
+                        diagnostic.start.line <= line_index && line_index <= diagnostic.end.line
+                    })
+                    .collect();
+                line_diagnostics.sort_by_key(|diagnostic| Reverse(diagnostic.severity));
+                for diagnostic in line_diagnostics {
+                    let start = if line_index == diagnostic.start.line {
+                        byte_index_of_utf16_column(&line, diagnostic.start.column)
+                    } else {
+                        0
+                    };
+                    let mut end = if line_index == diagnostic.end.line {
+                        byte_index_of_utf16_column(&line, diagnostic.end.column)
+                    } else {
+                        line.len()
+                    };
+                    // Diagnostics that only point at a position underline the grapheme there
+                    if end <= start {
+                        end = line[start..]
+                            .grapheme_indices(true)
+                            .nth(1)
+                            .map_or(line.len(), |(i, _)| start + i);
+                    }
+                    let style = format!(
+                        "{}{}",
+                        self.theme.style(diagnostic.severity.style_name()),
+                        termion::style::Underline
+                    );
+                    self.paint_highlight(stream, &line, segment.clone(), start..end, &style, row)?;
+                }
+
+                if let Some((start, end)) = selection {
+                    if start.line <= line_index && line_index <= end.line {
+                        let selection_start = if line_index == start.line {
+                            byte_index_of_grapheme(&line, start.column)
+                        } else {
+                            0
+                        };
+                        let selection_end = if line_index == end.line {
+                            byte_index_of_grapheme(&line, end.column)
+                        } else {
+                            line.len()
+                        };
+                        self.paint_highlight(
+                            stream,
+                            &line,
+                            segment.clone(),
+                            selection_start..selection_end,
+                            &self.theme.style(StyleName::Selection),
+                            row,
+                        )?;
+                    }
+                }
+
+                let cursor_column = match cursor_byte_index {
+                    Some(cursor_byte_index)
+                        if segment.start <= cursor_byte_index
+                            && (cursor_byte_index < segment.end || is_last_segment) =>
+                    {
+                        let column = width(&line[segment.start..cursor_byte_index], self.tab_width);
+                        // Whitespace hanging off a wrapped row keeps the cursor on its last column
+                        match self.wrap_mode {
+                            WrapMode::NoWrap => Some(column),
+                            WrapMode::SoftWrap => {
+                                Some(min(column, usize::from(text_width.saturating_sub(1))))
+                            }
+                        }
+                    }
+                    _ => None,
+                };
+                if let Some(column) = cursor_column {
+                    if column >= first_column && column - first_column < usize::from(text_width) {
+                        let cursor_left = text_left + u16::try_from(column - first_column).unwrap();
+                        self.paint_cursor(
+                            stream,
+                            &line[cursor_byte_index.unwrap()..],
+                            column,
+                            cursor_left,
+                            row,
+                        )?;
+                        cursor_cell = Some((cursor_left, row));
+                    }
                 }
-            }
-            stream.flush().unwrap();
 
-            num_painted_lines += 1;
-            first = false;
+                row_offset += 1;
+            }
+            line_index += 1;
         }
 
-        if num_painted_lines < total_num_lines {
-            let row_offset = row_offset(current_line_index, num_lines_to_skip);
+        if row_offset < num_text_rows {
+            write!(stream, "{}", termion::style::Reset)?;
             paint_empty_lines(
                 stream,
                 Rect {
                     top: rect.top + 1 + row_offset,
                     left: rect.left,
                     width: rect.width,
-                    height: rect.height - row_offset + 1,
+                    height: num_text_rows - row_offset,
+                },
+            )?;
+        }
+
+        self.paint_status_bar(
+            stream,
+            Rect {
+                top: rect.top + rect.height - 1,
+                left: rect.left,
+                width: rect.width,
+                height: 1,
+            },
+        )?;
+
+        if let (Some(popup), Some((cursor_left, cursor_row))) = (&self.popup, cursor_cell) {
+            self.paint_popup(
+                stream,
+                popup,
+                cursor_left,
+                cursor_row,
+                Rect {
+                    top: rect.top + 1,
+                    left: text_left,
+                    width: text_width,
+                    height: num_text_rows,
                 },
             )?;
         }
@@ -244,73 +1377,141 @@ impl Component for FileViewComponent {
     }
 
     fn dispatch_event(&mut self, event: termion::event::Event) -> DispatchEventResult {
+        if let Some(hex_view) = &mut self.hex_view {
+            return hex_view.dispatch_event(event);
+        }
+        if let Some(large_file_view) = &mut self.large_file_view {
+            return large_file_view.dispatch_event(event);
+        }
+
         let mut events = Vec::<Event>::new();
+        if let termion::event::Event::Key(key) = event {
+            if self.dispatch_go_to_line_event(key).is_some() {
+                return DispatchEventResult {
+                    handled: true,
+                    events,
+                };
+            }
+            if let Some(events) = self.dispatch_popup_event(key) {
+                return DispatchEventResult {
+                    handled: true,
+                    events,
+                };
+            }
+        }
+
         let handled = match event {
-            termion::event::Event::Mouse(mouse_event) => match mouse_event {
-                termion::event::MouseEvent::Press(button, _, _) => match button {
-                    termion::event::MouseButton::WheelDown => {
-                        self.scroll_down();
-                        true
-                    }
-                    termion::event::MouseButton::WheelUp => {
-                        self.scroll_up();
-                        true
-                    }
-                    _ => false,
-                },
-                _ => false,
-            },
-            termion::event::Event::Key(key) => match key {
-                termion::event::Key::Down => {
-                    self.buffer.move_cursor_down();
-                    self.needs_paint.set(true);
-                    true
-                }
-                termion::event::Key::Left => {
-                    self.buffer.move_cursor_left(1);
-                    self.needs_paint.set(true);
-                    true
-                }
-                termion::event::Key::Right => {
-                    self.buffer.move_cursor_right(1);
-                    self.needs_paint.set(true);
-                    true
-                }
-                termion::event::Key::Up => {
-                    self.needs_paint.set(true);
-                    true
-                }
-                termion::event::Key::Char(c) => {
-                    self.buffer.insert_at_cursor(&c.to_string());
-                    self.needs_paint.set(true);
-                    true
-                }
-                termion::event::Key::Backspace => {
-                    self.buffer.delete_at_cursor(1);
-                    self.needs_paint.set(true);
-                    true
-                }
-                termion::event::Key::Esc => {
-                    events.push(Event::FileViewLostFocus);
-                    true
-                }
-                termion::event::Key::Ctrl(c) => {
-                    if c == 's' {
-                        events.push(Event::FileSaved);
-                        true
-                    } else {
-                        false
-                    }
-                }
-                _ => false,
-            },
+            termion::event::Event::Mouse(termion::event::MouseEvent::Press(
+                termion::event::MouseButton::WheelDown,
+                _,
+                _,
+            )) => {
+                self.scroll_down();
+                true
+            }
+            termion::event::Event::Mouse(termion::event::MouseEvent::Press(
+                termion::event::MouseButton::WheelUp,
+                _,
+                _,
+            )) => {
+                self.scroll_up();
+                true
+            }
+            termion::event::Event::Mouse(mouse_event) => self.dispatch_mouse_event(mouse_event),
+            termion::event::Event::Key(termion::event::Key::Char(c)) => {
+                // Typing replaces the selection
+                events.extend(self.delete_selection());
+                events.push(self.edit_at_cursor(0, &c.to_string()));
+                self.filter_completions();
+                self.scroll_to_cursor();
+                self.needs_paint.set(true);
+                true
+            }
             _ => false,
         };
+        DispatchEventResult { handled, events }
+    }
+
+    fn dispatch_command(&mut self, command: Command) -> DispatchEventResult {
+        if let Some(hex_view) = &mut self.hex_view {
+            return hex_view.dispatch_command(command);
+        }
+        if let Some(large_file_view) = &mut self.large_file_view {
+            return large_file_view.dispatch_command(command);
+        }
+
+        let mut events = Vec::<Event>::new();
+        match command {
+            Command::CursorUp
+            | Command::CursorDown
+            | Command::CursorLeft
+            | Command::CursorRight
+            | Command::PageUp
+            | Command::PageDown
+            | Command::LineStart
+            | Command::LineEnd
+            | Command::GoToStart
+            | Command::GoToEnd => {
+                self.selection_anchor = None;
+                self.popup = None;
+                self.move_cursor(command);
+                self.scroll_to_cursor();
+                self.needs_paint.set(true);
+            }
+            Command::DeleteBackward => {
+                let event = match self.delete_selection() {
+                    Some(event) => event,
+                    None => self.edit_at_cursor(1, ""),
+                };
+                events.push(event);
+                self.filter_completions();
+                self.scroll_to_cursor();
+                self.needs_paint.set(true);
+            }
+            Command::LeaveEditor => events.push(Event::FileViewLostFocus),
+            Command::Save => events.push(Event::FileSaved),
+            Command::ToggleLineNumbers => self.toggle_line_numbers(),
+            Command::ToggleWrap => self.toggle_wrap_mode(),
+            Command::NextEncoding => self.convert_to_next_encoding(),
+            Command::ToggleLineEnding => self.toggle_line_ending(),
+            Command::Hover => events.push(self.language_server_request(LspRequest::Hover)),
+            Command::GoToDefinition => {
+                events.push(self.language_server_request(LspRequest::Definition))
+            }
+            Command::FindReferences => {
+                events.push(self.language_server_request(LspRequest::References))
+            }
+            Command::Complete => events.push(self.language_server_request(LspRequest::Completion)),
+            Command::NextDiagnostic => self.go_to_diagnostic(true),
+            Command::PreviousDiagnostic => self.go_to_diagnostic(false),
+            _ => return DispatchEventResult::empty(),
+        }
         DispatchEventResult {
-            handled: handled,
-            events: events,
+            handled: true,
+            events,
         }
     }
 
-    fn dispatch_events(&mut self, _: &[Event]) {}
+    fn is_prompting(&self) -> bool {
+        match (&self.hex_view, &self.large_file_view) {
+            (Some(hex_view), _) => hex_view.is_prompting(),
+            (None, Some(large_file_view)) => large_file_view.is_prompting(),
+            (None, None) => self.go_to_line_query.is_some() || self.popup.is_some(),
+        }
+    }
+
+    fn dispatch_events(&mut self, events: &[Event]) {
+        for event in events {
+            match (event, &mut self.large_file_view) {
+                (Event::GoToPosition(position), Some(large_file_view)) => {
+                    large_file_view.go_to_line(position.line)
+                }
+                (Event::GoToPosition(position), None) => self.go_to_position(*position),
+                _ => {}
+            }
+        }
+        if let Some(large_file_view) = &mut self.large_file_view {
+            large_file_view.dispatch_events(events);
+        }
+    }
 }
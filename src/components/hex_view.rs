@@ -0,0 +1,621 @@
+/*
+    Copyright 2020, John Pothier
+    This file is part of Five.
+
+    Five is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    Five is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with Five.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use super::component::{Component, DispatchEventResult};
+use crate::commands::Command;
+use crate::event::Event;
+use crate::large_file::PagedFile;
+use crate::painting_utils::{paint_empty_lines, paint_left_and_right_text, paint_truncated_text};
+use crate::terminal::Rect;
+use crate::theme::{StyleName, Theme};
+use std::borrow::Cow;
+use std::cell::Cell;
+use std::cmp::{max, min};
+use std::io::Write;
+use std::ops::Range;
+use termion::event::Key;
+
+// "00000000  " in front of the bytes, and "  |" and "|" around the ASCII column
+const OFFSET_COLUMN_WIDTH: usize = 10;
+const ASCII_COLUMN_PADDING: usize = 3;
+// Each byte takes "xx " in the hex column and one character in the ASCII column
+const COLUMNS_PER_BYTE: usize = 4;
+const MAX_BYTES_PER_ROW: usize = 32;
+// Paged files are searched this many bytes at a time
+const SEARCH_CHUNK_SIZE: usize = 1024 * 1024;
+
+// Files above the large file threshold are read in pages instead of all at once.
+pub enum HexData {
+    Bytes(Vec<u8>),
+    Paged(PagedFile),
+    // The file could not be read, for this reason
+    Unreadable(String),
+}
+
+impl HexData {
+    fn len(&self) -> usize {
+        match self {
+            HexData::Bytes(data) => data.len(),
+            HexData::Paged(file) => file.size() as usize,
+            HexData::Unreadable(_) => 0,
+        }
+    }
+
+    fn read(&self, offset: usize, length: usize) -> Cow<'_, [u8]> {
+        match self {
+            HexData::Bytes(data) => {
+                let start = min(offset, data.len());
+                Cow::Borrowed(&data[start..min(start + length, data.len())])
+            }
+            HexData::Paged(file) => Cow::Owned(file.read(offset as u64, length)),
+            HexData::Unreadable(_) => Cow::Borrowed(&[]),
+        }
+    }
+
+    fn get(&self, offset: usize) -> Option<u8> {
+        self.read(offset, 1).first().copied()
+    }
+
+    fn find(&self, pattern: &[u8], start: usize) -> Option<usize> {
+        let file = match self {
+            HexData::Paged(file) => file,
+            _ => return find_bytes(&self.read(0, self.len()), pattern, start),
+        };
+        let len = self.len();
+        if pattern.is_empty() || pattern.len() > len {
+            return None;
+        }
+        // The chunks overlap by the length of the pattern, so that no match is split between two
+        let find_in = |range: Range<usize>| {
+            (range.start..range.end)
+                .step_by(SEARCH_CHUNK_SIZE)
+                .find_map(|chunk_start| {
+                    let chunk_end = min(chunk_start + SEARCH_CHUNK_SIZE, range.end);
+                    let chunk = file.read(
+                        chunk_start as u64,
+                        chunk_end - chunk_start + pattern.len() - 1,
+                    );
+                    chunk
+                        .windows(pattern.len())
+                        .position(|window| window == pattern)
+                        .map(|i| chunk_start + i)
+                })
+        };
+        let start = min(start, len);
+        find_in(start..len).or_else(|| find_in(0..start))
+    }
+}
+
+enum Prompt {
+    JumpToOffset(String),
+    Search(String),
+}
+
+pub struct HexViewComponent {
+    file_path: String,
+    data: HexData,
+    cursor_offset: usize,
+    start_row: usize,
+    has_focus: bool,
+    prompt: Option<Prompt>,
+    last_search: Option<Vec<u8>>,
+    last_match: Option<Range<usize>>,
+    message: Option<String>,
+    needs_paint: Cell<bool>,
+    bytes_per_row: Cell<usize>,
+    num_visible_rows: Cell<usize>,
+    theme: Theme,
+}
+
+fn bytes_per_row_for_width(width: usize) -> usize {
+    let available = width.saturating_sub(OFFSET_COLUMN_WIDTH + ASCII_COLUMN_PADDING);
+    let bytes_per_row = min(available / COLUMNS_PER_BYTE, MAX_BYTES_PER_ROW);
+    if bytes_per_row >= 8 {
+        bytes_per_row - bytes_per_row % 8
+    } else {
+        max(bytes_per_row, 1)
+    }
+}
+
+// Accepts decimal offsets, or hexadecimal offsets prefixed with "0x".
+pub fn parse_offset(text: &str) -> Option<usize> {
+    let text = text.trim();
+    match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => usize::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
+
+// Accepts hex bytes like "de ad be ef" or "deadbeef", or text in double quotes like "\"PNG\"".
+pub fn parse_byte_pattern(text: &str) -> Option<Vec<u8>> {
+    let text = text.trim();
+    if let Some(quoted) = text.strip_prefix('"') {
+        let quoted = quoted.strip_suffix('"').unwrap_or(quoted);
+        return if quoted.is_empty() {
+            None
+        } else {
+            Some(quoted.as_bytes().to_vec())
+        };
+    }
+
+    let digits: Vec<char> = text.chars().filter(|c| !c.is_whitespace()).collect();
+    if digits.is_empty() || !digits.len().is_multiple_of(2) {
+        return None;
+    }
+    digits
+        .chunks(2)
+        .map(|pair| {
+            let pair: String = pair.iter().collect();
+            u8::from_str_radix(&pair, 16).ok()
+        })
+        .collect()
+}
+
+// Finds the first occurrence of the pattern at or after `start`, wrapping around to the beginning.
+pub fn find_bytes(data: &[u8], pattern: &[u8], start: usize) -> Option<usize> {
+    if pattern.is_empty() || pattern.len() > data.len() {
+        return None;
+    }
+    let last_start = data.len() - pattern.len();
+    let matches_at = |i: &usize| &data[*i..*i + pattern.len()] == pattern;
+    (min(start, last_start + 1)..=last_start)
+        .find(matches_at)
+        .or_else(|| (0..min(start, last_start + 1)).find(matches_at))
+}
+
+impl HexViewComponent {
+    pub fn new(file_path: String, data: HexData, theme: &Theme) -> HexViewComponent {
+        HexViewComponent {
+            file_path,
+            data,
+            cursor_offset: 0,
+            start_row: 0,
+            has_focus: false,
+            prompt: None,
+            last_search: None,
+            last_match: None,
+            message: None,
+            needs_paint: Cell::new(true),
+            bytes_per_row: Cell::new(16),
+            num_visible_rows: Cell::new(0),
+            theme: theme.clone(),
+        }
+    }
+
+    pub fn set_theme(&mut self, theme: &Theme) {
+        self.theme = theme.clone();
+        self.needs_paint.set(true);
+    }
+
+    pub fn set_has_focus(&mut self, focused: bool) {
+        self.has_focus = focused;
+        self.needs_paint.set(true);
+    }
+
+    pub fn start_jump_to_offset(&mut self) {
+        self.prompt = Some(Prompt::JumpToOffset(String::new()));
+        self.needs_paint.set(true);
+    }
+
+    fn start_search(&mut self) {
+        self.prompt = Some(Prompt::Search(String::new()));
+        self.needs_paint.set(true);
+    }
+
+    fn num_rows(&self) -> usize {
+        let bytes_per_row = self.bytes_per_row.get();
+        self.data.len().div_ceil(bytes_per_row)
+    }
+
+    fn move_cursor_to(&mut self, offset: usize) {
+        self.cursor_offset = min(offset, self.data.len().saturating_sub(1));
+
+        let cursor_row = self.cursor_offset / self.bytes_per_row.get();
+        let num_visible_rows = max(self.num_visible_rows.get(), 1);
+        if cursor_row < self.start_row {
+            self.start_row = cursor_row;
+        } else if cursor_row >= self.start_row + num_visible_rows {
+            self.start_row = cursor_row + 1 - num_visible_rows;
+        }
+        self.needs_paint.set(true);
+    }
+
+    fn move_cursor(&mut self, offset: isize) {
+        let target = if offset < 0 {
+            self.cursor_offset.saturating_sub(offset.unsigned_abs())
+        } else {
+            self.cursor_offset + offset as usize
+        };
+        self.move_cursor_to(target);
+    }
+
+    fn search_next(&mut self) {
+        let pattern = match &self.last_search {
+            None => return,
+            Some(pattern) => pattern,
+        };
+        let start = match &self.last_match {
+            Some(last_match) => last_match.start + 1,
+            None => self.cursor_offset,
+        };
+        match self.data.find(pattern, start) {
+            Some(offset) => {
+                self.last_match = Some(offset..offset + pattern.len());
+                self.message = None;
+                self.move_cursor_to(offset);
+            }
+            None => {
+                self.last_match = None;
+                self.message = Some(String::from("Pattern not found"));
+            }
+        }
+        self.needs_paint.set(true);
+    }
+
+    fn confirm_prompt(&mut self) {
+        match self.prompt.take() {
+            Some(Prompt::JumpToOffset(query)) => match parse_offset(&query) {
+                Some(offset) => self.move_cursor_to(offset),
+                None => self.message = Some(String::from("Invalid offset")),
+            },
+            Some(Prompt::Search(query)) => match parse_byte_pattern(&query) {
+                Some(pattern) => {
+                    self.last_search = Some(pattern);
+                    self.last_match = None;
+                    self.search_next();
+                }
+                None => self.message = Some(String::from("Invalid byte pattern")),
+            },
+            None => {}
+        }
+    }
+
+    // Returns None if the event was not meant for the prompt.
+    fn dispatch_prompt_event(&mut self, key: Key) -> Option<()> {
+        let query = match self.prompt.as_mut()? {
+            Prompt::JumpToOffset(query) => query,
+            Prompt::Search(query) => query,
+        };
+        match key {
+            Key::Char('\n') => self.confirm_prompt(),
+            Key::Char(c) => query.push(c),
+            Key::Backspace => {
+                query.pop();
+            }
+            Key::Esc => self.prompt = None,
+            _ => return None,
+        }
+        self.needs_paint.set(true);
+        Some(())
+    }
+
+    fn paint_header<Writer: Write>(&self, stream: &mut Writer, rect: Rect) -> std::io::Result<()> {
+        write!(stream, "{}", termion::cursor::Goto(rect.left, rect.top))?;
+        let prompt = match &self.prompt {
+            Some(Prompt::JumpToOffset(query)) => Some(format!("Jump to offset: {}", query)),
+            Some(Prompt::Search(query)) => Some(format!("Find bytes: {}", query)),
+            None => None,
+        };
+        match prompt {
+            Some(prompt) => {
+                write!(stream, "{}", self.theme.style(StyleName::Prompt))?;
+                paint_truncated_text(stream, &prompt, rect.width)
+            }
+            None => {
+                write!(stream, "{}", self.theme.style(StyleName::Title))?;
+                paint_truncated_text(stream, &self.file_path, rect.width)
+            }
+        }
+    }
+
+    fn paint_row<Writer: Write>(
+        &self,
+        stream: &mut Writer,
+        row_offset: usize,
+        width: u16,
+    ) -> std::io::Result<()> {
+        let bytes_per_row = self.bytes_per_row.get();
+        let row_end = min(row_offset + bytes_per_row, self.data.len());
+        let row_data = self.data.read(row_offset, row_end - row_offset);
+        let style_for = |offset: usize| {
+            if self.has_focus && offset == self.cursor_offset {
+                Some(self.theme.style(StyleName::Cursor))
+            } else if self
+                .last_match
+                .as_ref()
+                .is_some_and(|last_match| last_match.contains(&offset))
+            {
+                Some(self.theme.style(StyleName::SearchMatch))
+            } else {
+                None
+            }
+        };
+        let reset = self.theme.style(StyleName::Text);
+
+        write!(
+            stream,
+            "{}{:08x}  {}",
+            self.theme.style(StyleName::Dimmed),
+            row_offset,
+            reset
+        )?;
+        for offset in row_offset..row_offset + bytes_per_row {
+            if offset >= row_end {
+                write!(stream, "   ")?;
+                continue;
+            }
+            match style_for(offset) {
+                Some(style) => write!(
+                    stream,
+                    "{}{:02x}{} ",
+                    style,
+                    row_data[offset - row_offset],
+                    reset
+                )?,
+                None => write!(stream, "{:02x} ", row_data[offset - row_offset])?,
+            }
+        }
+
+        write!(stream, " |")?;
+        for offset in row_offset..row_end {
+            let byte = row_data[offset - row_offset];
+            let c = if (0x20..0x7f).contains(&byte) {
+                byte as char
+            } else {
+                '.'
+            };
+            match style_for(offset) {
+                Some(style) => write!(stream, "{}{}{}", style, c, reset)?,
+                None => write!(stream, "{}", c)?,
+            }
+        }
+        write!(stream, "|")?;
+
+        let painted_width = OFFSET_COLUMN_WIDTH
+            + bytes_per_row * (COLUMNS_PER_BYTE - 1)
+            + ASCII_COLUMN_PADDING
+            + (row_end - row_offset);
+        paint_truncated_text(stream, "", width.saturating_sub(painted_width as u16))
+    }
+
+    fn paint_status_bar<Writer: Write>(
+        &self,
+        stream: &mut Writer,
+        rect: Rect,
+    ) -> std::io::Result<()> {
+        let left_text = match &self.message {
+            Some(message) => format!(" {}", message),
+            None => match (&self.data, self.data.get(self.cursor_offset)) {
+                (HexData::Unreadable(err), _) => format!(" Could not read the file: {}", err),
+                (_, Some(byte)) => format!(
+                    " 0x{:08x} ({})  byte 0x{:02x} ({})",
+                    self.cursor_offset, self.cursor_offset, byte, byte
+                ),
+                (_, None) => String::from(" empty file"),
+            },
+        };
+        let right_text = match &self.data {
+            HexData::Unreadable(_) => String::new(),
+            data => format!("{} bytes  binary ", data.len()),
+        };
+        write!(
+            stream,
+            "{}{}",
+            termion::cursor::Goto(rect.left, rect.top),
+            self.theme.style(StyleName::StatusBar)
+        )?;
+        paint_left_and_right_text(stream, &left_text, &right_text, rect.width)?;
+        write!(stream, "{}", termion::style::Reset)
+    }
+}
+
+impl Component for HexViewComponent {
+    fn needs_paint(&self) -> bool {
+        self.needs_paint.take()
+    }
+
+    fn paint<Writer: Write>(&self, stream: &mut Writer, rect: Rect) -> std::io::Result<()> {
+        self.paint_header(stream, rect)?;
+
+        let num_rows = rect.height.saturating_sub(2);
+        self.num_visible_rows.set(usize::from(num_rows));
+        self.bytes_per_row
+            .set(bytes_per_row_for_width(usize::from(rect.width)));
+
+        let mut row = 0;
+        while row < num_rows {
+            let row_index = self.start_row + usize::from(row);
+            if row_index >= self.num_rows() {
+                break;
+            }
+            write!(
+                stream,
+                "{}",
+                termion::cursor::Goto(rect.left, rect.top + 1 + row)
+            )?;
+            self.paint_row(stream, row_index * self.bytes_per_row.get(), rect.width)?;
+            row += 1;
+        }
+        write!(stream, "{}", termion::style::Reset)?;
+        paint_empty_lines(
+            stream,
+            Rect {
+                top: rect.top + 1 + row,
+                left: rect.left,
+                width: rect.width,
+                height: num_rows - row,
+            },
+        )?;
+
+        self.paint_status_bar(
+            stream,
+            Rect {
+                top: rect.top + rect.height - 1,
+                left: rect.left,
+                width: rect.width,
+                height: 1,
+            },
+        )?;
+        self.needs_paint.set(false);
+        Ok(())
+    }
+
+    fn dispatch_event(&mut self, event: termion::event::Event) -> DispatchEventResult {
+        let events = Vec::<Event>::new();
+        let handled = match event {
+            termion::event::Event::Key(key) => {
+                if self.dispatch_prompt_event(key).is_some() {
+                    return DispatchEventResult {
+                        handled: true,
+                        events,
+                    };
+                }
+
+                self.message = None;
+                match key {
+                    Key::Ctrl('f') => self.start_search(),
+                    Key::Char('n') => self.search_next(),
+                    _ => return DispatchEventResult::empty(),
+                }
+                true
+            }
+            termion::event::Event::Mouse(termion::event::MouseEvent::Press(
+                termion::event::MouseButton::WheelDown,
+                _,
+                _,
+            )) => {
+                if self.start_row + 1 < self.num_rows() {
+                    self.start_row += 1;
+                }
+                true
+            }
+            termion::event::Event::Mouse(termion::event::MouseEvent::Press(
+                termion::event::MouseButton::WheelUp,
+                _,
+                _,
+            )) => {
+                self.start_row = self.start_row.saturating_sub(1);
+                true
+            }
+            _ => false,
+        };
+        if handled {
+            self.needs_paint.set(true);
+        }
+        DispatchEventResult { handled, events }
+    }
+
+    // There are no lines to edit, so the start and end of a line are the start and end of the file.
+    fn dispatch_command(&mut self, command: Command) -> DispatchEventResult {
+        let mut events = Vec::<Event>::new();
+        let bytes_per_row = self.bytes_per_row.get() as isize;
+        let page_size = max(self.num_visible_rows.get(), 1) as isize * bytes_per_row;
+        self.message = None;
+        match command {
+            Command::CursorLeft => self.move_cursor(-1),
+            Command::CursorRight => self.move_cursor(1),
+            Command::CursorUp => self.move_cursor(-bytes_per_row),
+            Command::CursorDown => self.move_cursor(bytes_per_row),
+            Command::PageUp => self.move_cursor(-page_size),
+            Command::PageDown => self.move_cursor(page_size),
+            Command::LineStart | Command::GoToStart => self.move_cursor_to(0),
+            Command::LineEnd | Command::GoToEnd => self.move_cursor_to(self.data.len()),
+            Command::LeaveEditor => events.push(Event::FileViewLostFocus),
+            _ => return DispatchEventResult::empty(),
+        }
+        self.needs_paint.set(true);
+        DispatchEventResult {
+            handled: true,
+            events,
+        }
+    }
+
+    fn is_prompting(&self) -> bool {
+        self.prompt.is_some()
+    }
+
+    fn dispatch_events(&mut self, _: &[Event]) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::components::hex_view::*;
+
+    #[test]
+    fn parse_offsets() {
+        assert_eq!(parse_offset("42"), Some(42));
+        assert_eq!(parse_offset("0x1f"), Some(31));
+        assert_eq!(parse_offset("zz"), None);
+    }
+
+    #[test]
+    fn parse_byte_patterns() {
+        assert_eq!(
+            parse_byte_pattern("de ad BE ef"),
+            Some(vec![0xde, 0xad, 0xbe, 0xef])
+        );
+        assert_eq!(parse_byte_pattern("\"PNG\""), Some(b"PNG".to_vec()));
+        assert_eq!(parse_byte_pattern("abc"), None);
+        assert_eq!(parse_byte_pattern("zz"), None);
+    }
+
+    #[test]
+    fn find_wraps_around() {
+        let data = b"abcabc";
+        assert_eq!(find_bytes(data, b"bc", 0), Some(1));
+        assert_eq!(find_bytes(data, b"bc", 2), Some(4));
+        assert_eq!(find_bytes(data, b"bc", 5), Some(1));
+        assert_eq!(find_bytes(data, b"x", 0), None);
+    }
+
+    #[test]
+    fn find_in_paged_file() {
+        let mut content = vec![0u8; 2 * SEARCH_CHUNK_SIZE + 100];
+        // Across the boundary between the first two chunks, and near the end
+        content[SEARCH_CHUNK_SIZE - 2..SEARCH_CHUNK_SIZE + 2].copy_from_slice(b"five");
+        content[2 * SEARCH_CHUNK_SIZE + 50..][..4].copy_from_slice(b"five");
+        let path = std::env::temp_dir().join(format!("five-{}-hex-view", std::process::id()));
+        std::fs::write(&path, &content).unwrap();
+        let paged = HexData::Paged(PagedFile::open(&path).unwrap());
+        let bytes = HexData::Bytes(content.clone());
+
+        for start in [
+            0,
+            SEARCH_CHUNK_SIZE - 1,
+            SEARCH_CHUNK_SIZE + 10,
+            content.len(),
+        ] {
+            assert_eq!(paged.find(b"five", start), bytes.find(b"five", start));
+        }
+        assert_eq!(paged.find(b"five", 0), Some(SEARCH_CHUNK_SIZE - 2));
+        assert_eq!(
+            paged.find(b"five", SEARCH_CHUNK_SIZE),
+            Some(2 * SEARCH_CHUNK_SIZE + 50)
+        );
+        assert_eq!(paged.find(b"nine", 0), None);
+        assert_eq!(paged.read(SEARCH_CHUNK_SIZE - 2, 4), &b"five"[..]);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn bytes_per_row() {
+        assert_eq!(bytes_per_row_for_width(80), 16);
+        assert_eq!(bytes_per_row_for_width(40), 6);
+        assert_eq!(bytes_per_row_for_width(200), 32);
+    }
+}
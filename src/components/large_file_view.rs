@@ -0,0 +1,347 @@
+/*
+    Copyright 2020, John Pothier
+    This file is part of Five.
+
+    Five is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    Five is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with Five.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use super::component::{Component, DispatchEventResult};
+use crate::commands::Command;
+use crate::event::Event;
+use crate::language::Language;
+use crate::large_file::LargeFile;
+use crate::painting_utils::{
+    paint_empty_lines, paint_left_and_right_text, paint_text_columns, paint_truncated_text,
+};
+use crate::terminal::Rect;
+use crate::text_position::parse_position;
+use crate::theme::{StyleName, Theme};
+use std::cell::Cell;
+use std::cmp::{max, min};
+use std::convert::TryFrom;
+use std::io::Write;
+use termion::event::Key;
+
+const HORIZONTAL_SCROLL_STEP: usize = 8;
+
+// A read-only view of a file that is too large to edit. Only the visible lines are read from disk.
+pub struct LargeFileViewComponent {
+    file_path: String,
+    language: Language,
+    file: LargeFile,
+    cursor_line: usize,
+    start_line: usize,
+    start_column: usize,
+    tab_width: usize,
+    has_focus: bool,
+    go_to_line_query: Option<String>,
+    // A line that was asked for before the index reached it
+    pending_line: Option<usize>,
+    painted_num_lines: Cell<usize>,
+    needs_paint: Cell<bool>,
+    num_visible_lines: Cell<usize>,
+    theme: Theme,
+}
+
+impl LargeFileViewComponent {
+    pub fn new(
+        file_path: String,
+        file: LargeFile,
+        tab_width: usize,
+        theme: &Theme,
+    ) -> LargeFileViewComponent {
+        LargeFileViewComponent {
+            language: Language::from_path(&file_path),
+            file_path,
+            file,
+            cursor_line: 0,
+            start_line: 0,
+            start_column: 0,
+            tab_width,
+            has_focus: false,
+            go_to_line_query: None,
+            pending_line: None,
+            painted_num_lines: Cell::new(0),
+            needs_paint: Cell::new(true),
+            num_visible_lines: Cell::new(0),
+            theme: theme.clone(),
+        }
+    }
+
+    pub fn set_theme(&mut self, theme: &Theme) {
+        self.theme = theme.clone();
+        self.needs_paint.set(true);
+    }
+
+    pub fn set_has_focus(&mut self, focused: bool) {
+        self.has_focus = focused;
+        self.needs_paint.set(true);
+    }
+
+    pub fn start_go_to_line(&mut self) {
+        self.go_to_line_query = Some(String::new());
+        self.needs_paint.set(true);
+    }
+
+    // Lines past the end of the index built so far are gone to once the index reaches them.
+    pub fn go_to_line(&mut self, line: usize) {
+        let num_lines = self.file.num_lines();
+        self.pending_line = if line >= num_lines && !self.file.is_indexed() {
+            Some(line)
+        } else {
+            None
+        };
+        let last_line = num_lines.saturating_sub(1);
+        self.cursor_line = min(line, last_line);
+        let num_visible_lines = max(self.num_visible_lines.get(), 1);
+        if self.cursor_line < self.start_line
+            || self.cursor_line >= self.start_line + num_visible_lines
+        {
+            self.start_line = self.cursor_line.saturating_sub(num_visible_lines / 2);
+        }
+        self.needs_paint.set(true);
+    }
+
+    fn move_cursor(&mut self, lines: isize) {
+        let target = if lines < 0 {
+            self.cursor_line.saturating_sub(lines.unsigned_abs())
+        } else {
+            self.cursor_line + lines as usize
+        };
+        self.pending_line = None;
+        let last_line = self.file.num_lines().saturating_sub(1);
+        self.cursor_line = min(target, last_line);
+
+        let num_visible_lines = max(self.num_visible_lines.get(), 1);
+        if self.cursor_line < self.start_line {
+            self.start_line = self.cursor_line;
+        } else if self.cursor_line >= self.start_line + num_visible_lines {
+            self.start_line = self.cursor_line + 1 - num_visible_lines;
+        }
+        self.needs_paint.set(true);
+    }
+
+    fn gutter_width(&self) -> u16 {
+        let num_digits = max(self.file.num_lines(), 1).to_string().len();
+        u16::try_from(num_digits).unwrap() + 1
+    }
+
+    // Returns None if the event was not meant for the prompt.
+    fn dispatch_go_to_line_event(&mut self, key: Key) -> Option<()> {
+        let query = self.go_to_line_query.as_mut()?;
+        match key {
+            Key::Char('\n') => {
+                let position = parse_position(query);
+                self.go_to_line_query = None;
+                if let Some(position) = position {
+                    self.go_to_line(position.line);
+                }
+            }
+            Key::Char(c) => {
+                if c.is_ascii_digit() || c == ':' {
+                    query.push(c);
+                }
+            }
+            Key::Backspace => {
+                query.pop();
+            }
+            Key::Esc => self.go_to_line_query = None,
+            _ => return None,
+        }
+        self.needs_paint.set(true);
+        Some(())
+    }
+
+    fn paint_header<Writer: Write>(&self, stream: &mut Writer, rect: Rect) -> std::io::Result<()> {
+        write!(stream, "{}", termion::cursor::Goto(rect.left, rect.top))?;
+        match &self.go_to_line_query {
+            Some(query) => {
+                write!(stream, "{}", self.theme.style(StyleName::Prompt))?;
+                paint_truncated_text(stream, &format!("Go to line: {}", query), rect.width)
+            }
+            None => {
+                write!(stream, "{}", self.theme.style(StyleName::Title))?;
+                paint_left_and_right_text(stream, &self.file_path, "read-only ", rect.width)
+            }
+        }
+    }
+
+    fn paint_status_bar<Writer: Write>(
+        &self,
+        stream: &mut Writer,
+        rect: Rect,
+    ) -> std::io::Result<()> {
+        let left_text = format!(
+            " {}  {} lines{}",
+            self.cursor_line + 1,
+            self.file.num_lines(),
+            if self.file.is_indexed() {
+                ""
+            } else {
+                " (indexing...)"
+            }
+        );
+        let right_text = format!(
+            "{} MB  {}  {} ",
+            self.file.size() / (1024 * 1024),
+            self.file.encoding().name(),
+            self.language.name()
+        );
+        write!(
+            stream,
+            "{}{}",
+            termion::cursor::Goto(rect.left, rect.top),
+            self.theme.style(StyleName::StatusBar)
+        )?;
+        paint_left_and_right_text(stream, &left_text, &right_text, rect.width)?;
+        write!(stream, "{}", termion::style::Reset)
+    }
+}
+
+impl Component for LargeFileViewComponent {
+    fn needs_paint(&self) -> bool {
+        self.needs_paint.take()
+    }
+
+    fn paint<Writer: Write>(&self, stream: &mut Writer, rect: Rect) -> std::io::Result<()> {
+        self.paint_header(stream, rect)?;
+        self.painted_num_lines.set(self.file.num_lines());
+
+        let num_text_rows = rect.height.saturating_sub(2);
+        self.num_visible_lines.set(usize::from(num_text_rows));
+        let gutter_width = self.gutter_width();
+        let text_width = rect.width.saturating_sub(gutter_width);
+
+        let lines = self.file.lines(self.start_line, usize::from(num_text_rows));
+        let mut row = 0;
+        for (line_offset, line) in lines.iter().enumerate() {
+            let line_index = self.start_line + line_offset;
+            write!(
+                stream,
+                "{}",
+                termion::cursor::Goto(rect.left, rect.top + 1 + row)
+            )?;
+            if line_index == self.cursor_line && self.has_focus {
+                write!(stream, "{}", self.theme.style(StyleName::CurrentLineNumber))?;
+            } else {
+                write!(stream, "{}", self.theme.style(StyleName::LineNumber))?;
+            }
+            write!(
+                stream,
+                "{:>width$} {}",
+                line_index + 1,
+                self.theme.style(StyleName::Text),
+                width = usize::from(gutter_width) - 1
+            )?;
+            paint_text_columns(stream, line, self.start_column, text_width, self.tab_width)?;
+            row += 1;
+        }
+        write!(stream, "{}", termion::style::Reset)?;
+        paint_empty_lines(
+            stream,
+            Rect {
+                top: rect.top + 1 + row,
+                left: rect.left,
+                width: rect.width,
+                height: num_text_rows - row,
+            },
+        )?;
+
+        self.paint_status_bar(
+            stream,
+            Rect {
+                top: rect.top + rect.height - 1,
+                left: rect.left,
+                width: rect.width,
+                height: 1,
+            },
+        )?;
+        self.needs_paint.set(false);
+        Ok(())
+    }
+
+    fn dispatch_event(&mut self, event: termion::event::Event) -> DispatchEventResult {
+        let handled = match event {
+            termion::event::Event::Key(key) => self.dispatch_go_to_line_event(key).is_some(),
+            termion::event::Event::Mouse(termion::event::MouseEvent::Press(
+                termion::event::MouseButton::WheelDown,
+                _,
+                _,
+            )) => {
+                if self.start_line + 1 < self.file.num_lines() {
+                    self.start_line += 1;
+                }
+                true
+            }
+            termion::event::Event::Mouse(termion::event::MouseEvent::Press(
+                termion::event::MouseButton::WheelUp,
+                _,
+                _,
+            )) => {
+                self.start_line = self.start_line.saturating_sub(1);
+                true
+            }
+            _ => false,
+        };
+        if handled {
+            self.needs_paint.set(true);
+        }
+        DispatchEventResult {
+            handled,
+            events: vec![],
+        }
+    }
+
+    // Left and right scroll horizontally, since there is no cursor within the line.
+    fn dispatch_command(&mut self, command: Command) -> DispatchEventResult {
+        let mut events = Vec::<Event>::new();
+        let page_size = max(self.num_visible_lines.get(), 1) as isize;
+        match command {
+            Command::CursorUp => self.move_cursor(-1),
+            Command::CursorDown => self.move_cursor(1),
+            Command::PageUp => self.move_cursor(-page_size),
+            Command::PageDown => self.move_cursor(page_size),
+            Command::LineStart | Command::GoToStart => self.go_to_line(0),
+            Command::LineEnd | Command::GoToEnd => self.go_to_line(usize::MAX),
+            Command::CursorLeft => {
+                self.start_column = self.start_column.saturating_sub(HORIZONTAL_SCROLL_STEP)
+            }
+            Command::CursorRight => self.start_column += HORIZONTAL_SCROLL_STEP,
+            Command::LeaveEditor => events.push(Event::FileViewLostFocus),
+            _ => return DispatchEventResult::empty(),
+        }
+        self.needs_paint.set(true);
+        DispatchEventResult {
+            handled: true,
+            events,
+        }
+    }
+
+    fn is_prompting(&self) -> bool {
+        self.go_to_line_query.is_some()
+    }
+
+    fn dispatch_events(&mut self, events: &[Event]) {
+        for event in events {
+            if let Event::Tick = event {
+                if self.file.num_lines() != self.painted_num_lines.get() {
+                    self.needs_paint.set(true);
+                    if let Some(line) = self.pending_line {
+                        self.go_to_line(line);
+                    }
+                }
+            }
+        }
+    }
+}
@@ -17,7 +17,11 @@
 */
 
 pub mod component;
+pub mod directory_tree;
 pub mod divider;
 pub mod file_pane;
 pub mod file_view;
+pub mod hex_view;
+pub mod large_file_view;
 pub mod root;
+pub mod scrolling_list;
@@ -17,39 +17,185 @@
 */
 
 use crate::buffer::Buffer;
+use crate::build::{Builder, Problem};
+use crate::commands::{Command, COMMANDS};
 use crate::components::component::{Component, DispatchEventResult};
 use crate::components::divider::DividerComponent;
 use crate::components::file_pane::FilePaneComponent;
-use crate::components::file_view::{FileViewComponent, FileViewContent};
+use crate::components::file_view::{FileViewComponent, FileViewContent, LineNumbers, WrapMode};
+use crate::components::hex_view::HexData;
 use crate::event::Event;
+use crate::file_operations;
+use crate::file_operations::FileOperation;
+use crate::file_system::FileSystem;
+use crate::git_status::GitStatusReader;
 use crate::indexer::index::Indexer;
-use crate::indexer::index::{FileIndexEntry, FileTreeFolder, FileTreeNode};
-use crate::terminal::Rect;
+use crate::indexer::index::{FileIndexEntry, FileMetadata, FileTreeFolder, FileTreeNode};
+use crate::indexer::symbols::{extract_symbols, SymbolIndexer};
+use crate::keymap::{format_chord, KeyContext, Keymap, KeymapMatch};
+use crate::large_file::{LargeFile, PagedFile, DEFAULT_LARGE_FILE_THRESHOLD};
+use crate::lsp::{LanguageServers, Location, LspEvent};
+use crate::preview_cache::{PreviewCache, DEFAULT_MAX_CACHED_BYTES};
+use crate::quick_open::{CommandResult, LocationResult};
+use crate::session::Session;
+use crate::terminal::{mouse_position, Rect};
+use crate::text_format;
+use crate::text_format::TextFormat;
+use crate::text_layout::{byte_index_of_utf16_column, grapheme_index_of_byte};
+use crate::text_position::TextPosition;
+use crate::theme::Theme;
+use std::cell::Cell;
+use std::cmp::{max, min};
+use std::collections::HashMap;
+use std::convert::TryFrom;
 use std::io::Write;
 use std::path::Path;
-use termion::event::Key;
+use std::time::{Duration, Instant};
+use termion::event::{Key, MouseButton, MouseEvent};
 
+// Files that aren't cached are only read once the selection has stayed on them this long, so that
+// moving quickly through the file tree doesn't wait on every file along the way
+const PREVIEW_DELAY: Duration = Duration::from_millis(75);
+const NUM_PREFETCHED_NEIGHBOURS: usize = 3;
+const DEFAULT_FILE_PANE_WIDTH: u16 = 32;
+// Dragging the divider stops short of squeezing either side below these widths
+const MIN_FILE_PANE_WIDTH: u16 = 12;
+const MIN_FILE_VIEW_WIDTH: u16 = 20;
+
+fn clamp_file_pane_width(width: u16, rect: Rect) -> u16 {
+    max(
+        min(width, rect.width.saturating_sub(MIN_FILE_VIEW_WIDTH)),
+        MIN_FILE_PANE_WIDTH,
+    )
+}
+
+struct PendingPreview {
+    index_entry: FileIndexEntry,
+    position: Option<TextPosition>,
+    selected_at: Instant,
+}
+
+#[derive(Clone, Copy, PartialEq)]
 enum FocusedComponent {
     FilePane,
     FileView,
 }
 
+impl FocusedComponent {
+    fn other(self) -> FocusedComponent {
+        match self {
+            FocusedComponent::FilePane => FocusedComponent::FileView,
+            FocusedComponent::FileView => FocusedComponent::FilePane,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum MouseTarget {
+    FilePane,
+    Divider,
+    FileView,
+}
+
 pub struct RootComponent<'a> {
     indexer: &'a dyn Indexer,
+    file_system: &'a dyn FileSystem,
     file_pane: FilePaneComponent,
     file_view: FileViewComponent,
     divider: DividerComponent,
     focused_component: FocusedComponent,
+    large_file_threshold: u64,
+    preview_cache: PreviewCache,
+    pending_preview: Option<PendingPreview>,
+    painted_rect: Cell<Option<Rect>>,
+    // Set when the layout changes, so that everything is painted again
+    needs_full_paint: Cell<bool>,
+    file_pane_width: u16,
+    // Where the mouse button went down, which keeps getting the events until it is released
+    mouse_capture: Option<MouseTarget>,
+    // The operations that undo the file operations performed so far, most recent last
+    file_operation_undo_stack: Vec<FileOperation>,
+    keymap: Keymap,
+    // The keys of a chord typed so far
+    pending_keys: Vec<Key>,
+    // What had focus when the command palette opened
+    command_palette_focus: Option<FocusedComponent>,
+    // The file in the file view, unless it shows a folder
+    shown_file_path: Option<String>,
+    // Only for local directories
+    symbol_indexer: Option<SymbolIndexer>,
+    // Only for local directories
+    language_servers: Option<LanguageServers>,
+    // Only for local directories with a build command
+    builder: Option<Builder>,
+    // Found by the last build
+    problems: Vec<Problem>,
+    // Only for local directories
+    git_status_reader: Option<GitStatusReader>,
 }
 
 impl<'a> RootComponent<'a> {
-    pub fn new(indexer: &dyn Indexer) -> RootComponent {
+    pub fn new(indexer: &'a dyn Indexer, file_system: &'a dyn FileSystem) -> RootComponent<'a> {
         RootComponent {
-            indexer: indexer,
+            indexer,
+            file_system,
             file_pane: FilePaneComponent::new(),
             file_view: FileViewComponent::new(),
             divider: DividerComponent::new(),
             focused_component: FocusedComponent::FilePane,
+            large_file_threshold: DEFAULT_LARGE_FILE_THRESHOLD,
+            preview_cache: PreviewCache::new(DEFAULT_MAX_CACHED_BYTES),
+            pending_preview: None,
+            painted_rect: Cell::new(None),
+            needs_full_paint: Cell::new(false),
+            file_pane_width: DEFAULT_FILE_PANE_WIDTH,
+            mouse_capture: None,
+            file_operation_undo_stack: vec![],
+            keymap: Keymap::new(),
+            pending_keys: vec![],
+            command_palette_focus: None,
+            shown_file_path: None,
+            symbol_indexer: None,
+            language_servers: None,
+            builder: None,
+            problems: vec![],
+            git_status_reader: None,
+        }
+    }
+
+    pub fn session(&mut self) -> Session {
+        Session {
+            open_file: self.shown_file_path.clone(),
+            file_view_focused: self.focused_component == FocusedComponent::FileView,
+            files: self.file_view.file_states(),
+            tree: Some(self.file_pane.tree_state()),
+            quick_open_history: self.file_pane.quick_open_history(),
+            recent_files: self.file_pane.recent_files(),
+        }
+    }
+
+    // Expects the index to be there already, since files that are gone aren't restored.
+    pub fn restore_session(&mut self, session: Session) {
+        self.file_view.set_file_states(session.files);
+        self.file_pane
+            .set_quick_open_history(session.quick_open_history);
+        self.file_pane.set_recent_files(session.recent_files);
+        if let Some(tree) = session.tree {
+            self.file_pane.restore_tree_state(tree);
+        }
+        let index = self.indexer.get_index();
+        let open_file =
+            session
+                .open_file
+                .and_then(|path| match index?.tree.path_to(&path)?.last() {
+                    Some(FileTreeNode::File(index_entry)) => Some(index_entry.clone()),
+                    _ => None,
+                });
+        if let Some(index_entry) = open_file {
+            self.show_file_preview(&index_entry);
+            if session.file_view_focused {
+                self.focus_file_view();
+            }
         }
     }
 
@@ -63,81 +209,164 @@ impl<'a> RootComponent<'a> {
         }
     }
 
+    pub fn set_tab_width(&mut self, tab_width: usize) {
+        self.file_view.set_tab_width(tab_width);
+    }
+
+    pub fn set_theme(&mut self, theme: &Theme) {
+        self.file_pane.set_theme(theme);
+        self.file_view.set_theme(theme);
+        self.divider.set_theme(theme);
+        self.needs_full_paint.set(true);
+    }
+
+    pub fn set_file_pane_width(&mut self, width: u16) {
+        self.file_pane_width = width;
+        self.needs_full_paint.set(true);
+    }
+
+    pub fn set_line_numbers(&mut self, line_numbers: LineNumbers) {
+        self.file_view.set_line_numbers(line_numbers);
+    }
+
+    pub fn set_wrap_mode(&mut self, wrap_mode: WrapMode) {
+        self.file_view.set_wrap_mode(wrap_mode);
+    }
+
+    pub fn set_symbol_indexer(&mut self, symbol_indexer: SymbolIndexer) {
+        self.symbol_indexer = Some(symbol_indexer);
+    }
+
+    pub fn set_language_servers(&mut self, language_servers: LanguageServers) {
+        self.language_servers = Some(language_servers);
+    }
+
+    pub fn set_builder(&mut self, builder: Builder) {
+        self.builder = Some(builder);
+    }
+
+    pub fn set_git_status_reader(&mut self, mut git_status_reader: GitStatusReader) {
+        git_status_reader.refresh();
+        self.git_status_reader = Some(git_status_reader);
+    }
+
+    pub fn set_keymap(&mut self, keymap: Keymap) {
+        self.keymap = keymap;
+        self.pending_keys.clear();
+    }
+
+    // Files larger than this many bytes are opened read-only, without loading them into memory.
+    pub fn set_large_file_threshold(&mut self, large_file_threshold: u64) {
+        self.large_file_threshold = large_file_threshold;
+    }
+
     fn start_quick_open(&mut self) {
         self.update_index();
         self.file_pane.start_quick_open();
+        self.focus_file_pane();
     }
 
-    fn show_file_preview(&mut self, index_entry: &FileIndexEntry) {
-        let path = Path::new(&index_entry.path);
-        match std::fs::read_to_string(&path) {
-            Err(_) => {
-                // TODO: smart error handling for non-utf-8 strings
-                self.file_view
-                    .set_content(FileViewContent::BinaryFile(String::from(
-                        path.to_str().unwrap(),
-                    )));
-            }
-            Ok(content) => {
-                self.file_view.set_content(FileViewContent::TextFile(
-                    String::from(path.to_str().unwrap()),
-                    content,
-                ));
+    // The symbols come from the text being edited rather than the file, so they are never stale.
+    fn start_go_to_symbol(&mut self) {
+        let symbols = match &self.shown_file_path {
+            None => vec![],
+            Some(path) => {
+                let (buffer, _, _) = self.file_view.get_buffer();
+                let (left, right) = buffer.get();
+                extract_symbols(path, &[left, right].concat())
             }
+        };
+        if symbols.is_empty() {
+            self.file_pane
+                .show_message(String::from("No definitions in the open file"));
+            return;
         }
+        self.file_pane.start_symbol_search(symbols, true);
+        self.focus_file_pane();
     }
 
-    fn show_folder_preview(&mut self, folder: &FileTreeFolder) {
-        let children: Vec<String> = folder
-            .children
-            .iter()
-            .map(|child| match &child {
-                FileTreeNode::File(file_index_entry) => file_index_entry.file_name.clone(),
-                FileTreeNode::Folder(file_tree_folder) => file_tree_folder.folder_name.clone(),
-            })
-            .collect();
-        self.file_view
-            .set_content(FileViewContent::Folder(folder.path.clone(), children))
+    // Lists the symbols found so far, while the rest of the files are still being indexed.
+    fn start_symbol_search(&mut self) {
+        let symbols = match &self.symbol_indexer {
+            None => {
+                self.file_pane.show_message(String::from(
+                    "Definitions can only be searched in local directories",
+                ));
+                return;
+            }
+            Some(symbol_indexer) => symbol_indexer.symbols(),
+        };
+        self.update_index();
+        self.file_pane.start_symbol_search(symbols, false);
+        self.focus_file_pane();
     }
 
-    fn open_file(&mut self, index_entry: &FileIndexEntry) {
-        self.show_file_preview(index_entry);
+    fn focus_file_view(&mut self) {
         self.focused_component = FocusedComponent::FileView;
         self.file_view.set_has_focus(true);
+        self.file_pane.set_has_focus(false);
     }
 
-    fn save_file(&self, buffer: &Buffer, file_path: String) {
-        let (left, right) = buffer.get();
-        let maybe_handle = std::fs::OpenOptions::new().write(true).open(&file_path);
-        if let Err(err) = &maybe_handle {
-            println!("Error saving: {}", err);
-        }
+    fn focus_file_pane(&mut self) {
+        self.focused_component = FocusedComponent::FilePane;
+        self.file_view.set_has_focus(false);
+        self.file_pane.set_has_focus(true);
+    }
 
-        let mut handle = maybe_handle.unwrap();
-        if let Err(err) = handle.write(left.as_bytes()) {
-            // TODO: handle saving errors
-            println!("Error saving: {}", err);
+    fn start_go_to_line(&mut self) {
+        self.focus_file_view();
+        self.file_view.start_go_to_line();
+    }
+
+    // Selecting the file that is already shown keeps it as it is, with its unsaved edits, such as when
+    // picking a reference in it.
+    fn select_file(&mut self, index_entry: &FileIndexEntry) {
+        if self.shown_file_path.as_ref() == Some(&index_entry.path) {
+            self.pending_preview = None;
             return;
         }
+        let neighbours = self
+            .file_pane
+            .neighbouring_files(NUM_PREFETCHED_NEIGHBOURS)
+            .into_iter()
+            .map(|neighbour| neighbour.path)
+            .collect();
+        self.preview_cache.prefetch(neighbours);
 
-        if let Err(err) = handle.write(right.as_bytes()) {
-            // TODO: handle saving errors
-            println!("Error saving: {}", err);
-            return;
+        if self.preview_cache.get(&index_entry.path).is_some() {
+            self.show_file_preview(index_entry);
+        } else {
+            self.pending_preview = Some(PendingPreview {
+                index_entry: index_entry.clone(),
+                position: None,
+                selected_at: Instant::now(),
+            });
         }
     }
-}
 
-impl<'a> Component for RootComponent<'a> {
-    fn needs_paint(&self) -> bool {
-        self.file_view.needs_paint() || self.file_pane.needs_paint() || self.divider.needs_paint()
+    fn show_pending_preview(&mut self) {
+        let is_due = match &self.pending_preview {
+            None => false,
+            Some(pending_preview) => pending_preview.selected_at.elapsed() >= PREVIEW_DELAY,
+        };
+        if !is_due {
+            return;
+        }
+        let pending_preview = self.pending_preview.take().unwrap();
+        self.show_file_preview(&pending_preview.index_entry);
+        if let Some(position) = pending_preview.position {
+            self.file_view
+                .dispatch_events(&[Event::GoToPosition(position)]);
+        }
     }
-    fn paint<Writer: Write>(&self, stream: &mut Writer, rect: Rect) -> std::io::Result<()> {
+
+    // Returns the rects of the file pane, the divider and the file view.
+    fn layout(&self, rect: Rect) -> (Rect, Rect, Rect) {
         let margin = 1;
         let file_pane_rect = Rect {
             left: 1,
             top: 1,
-            width: 32,
+            width: clamp_file_pane_width(self.file_pane_width, rect),
             height: rect.height - margin,
         };
         let divider_rect = Rect {
@@ -152,61 +381,642 @@ impl<'a> Component for RootComponent<'a> {
             width: rect.width - file_pane_rect.width - 2 * margin,
             height: rect.height - margin,
         };
-        if self.file_pane.needs_paint() {
-            self.file_pane.paint(stream, file_pane_rect)?;
+        (file_pane_rect, divider_rect, file_view_rect)
+    }
+
+    fn mouse_target_at(&self, rect: Rect, x: u16, y: u16) -> Option<MouseTarget> {
+        let (file_pane_rect, _, file_view_rect) = self.layout(rect);
+        if file_pane_rect.contains(x, y) {
+            Some(MouseTarget::FilePane)
+        } else if file_view_rect.contains(x, y) {
+            Some(MouseTarget::FileView)
+        } else if x > file_pane_rect.left && x < file_view_rect.left {
+            // The margins on either side of the divider make it easier to grab
+            Some(MouseTarget::Divider)
+        } else {
+            None
         }
-        if self.file_view.needs_paint() {
-            self.file_view.paint(stream, file_view_rect)?;
+    }
+
+    fn drag_divider(&mut self, rect: Rect, x: u16) {
+        let (file_pane_rect, divider_rect, _) = self.layout(rect);
+        let width = i32::from(file_pane_rect.width) + i32::from(x) - i32::from(divider_rect.left);
+        let width = clamp_file_pane_width(u16::try_from(max(width, 0)).unwrap_or(u16::MAX), rect);
+        if width != file_pane_rect.width {
+            self.file_pane_width = width;
+            self.needs_full_paint.set(true);
         }
-        if self.divider.needs_paint() {
-            self.divider.paint(stream, divider_rect)?;
+    }
+
+    // Mouse events go to whatever is under the pointer, regardless of focus. Once a button is pressed,
+    // its drag and release go to the same place.
+    fn dispatch_mouse_event(
+        &mut self,
+        event: termion::event::Event,
+        mouse_event: MouseEvent,
+    ) -> DispatchEventResult {
+        let rect = match self.painted_rect.get() {
+            Some(rect) => rect,
+            None => return DispatchEventResult::empty(),
+        };
+        let (x, y) = mouse_position(&mouse_event);
+        let target = match mouse_event {
+            MouseEvent::Press(MouseButton::Left, _, _) => {
+                self.mouse_capture = self.mouse_target_at(rect, x, y);
+                self.mouse_capture
+            }
+            MouseEvent::Press(_, _, _) => self.mouse_target_at(rect, x, y),
+            MouseEvent::Hold(_, _) => self.mouse_capture,
+            MouseEvent::Release(_, _) => self.mouse_capture.take(),
+        };
+        let is_click = matches!(mouse_event, MouseEvent::Press(MouseButton::Left, _, _));
+        match target {
+            Some(MouseTarget::FilePane) => {
+                if is_click {
+                    self.focus_file_pane();
+                }
+                self.file_pane.dispatch_event(event)
+            }
+            Some(MouseTarget::FileView) => {
+                if is_click {
+                    self.focus_file_view();
+                }
+                self.file_view.dispatch_event(event)
+            }
+            Some(MouseTarget::Divider) => {
+                if let MouseEvent::Hold(_, _) = mouse_event {
+                    self.drag_divider(rect, x);
+                }
+                DispatchEventResult {
+                    handled: true,
+                    events: vec![],
+                }
+            }
+            None => DispatchEventResult::empty(),
         }
+    }
 
-        stream.flush()
+    // Commands from the palette apply to whatever had focus before the palette opened.
+    fn start_command_palette(&mut self) {
+        let (focused_context, _) = self.key_contexts();
+        let contexts = [focused_context, KeyContext::Global];
+        let commands = COMMANDS
+            .iter()
+            .map(|(command, name, description)| CommandResult {
+                command: *command,
+                name,
+                description,
+                binding: self
+                    .keymap
+                    .binding(&contexts, *command)
+                    .map(|keys| format_chord(&keys)),
+            })
+            .collect();
+        self.command_palette_focus = Some(self.focused_component);
+        self.file_pane.start_command_palette(commands);
+        self.focus_file_pane();
     }
 
-    fn dispatch_event(&mut self, event: termion::event::Event) -> DispatchEventResult {
-        // Swap dispatch priority depending on focus
+    fn run_command(&mut self, command: Command) {
+        self.file_pane.close_quick_open();
+        match self.command_palette_focus.take() {
+            Some(FocusedComponent::FileView) => self.focus_file_view(),
+            _ => self.focus_file_pane(),
+        }
+        let focused = self.focused_component;
+        let mut result = self.dispatch_command(command);
+        if !result.handled {
+            result = self.dispatch_command_to(focused, command);
+        }
+        if !result.handled {
+            result = self.dispatch_command_to(focused.other(), command);
+        }
+        self.dispatch_events(&result.events);
+    }
+
+    // The context of the focused component, and of the other one, which gets the keys that the
+    // focused component has no use for.
+    fn key_contexts(&self) -> (KeyContext, KeyContext) {
         match self.focused_component {
-            FocusedComponent::FilePane => {
-                let result = self.file_pane.dispatch_event(event.clone());
-                if result.handled {
-                    return result;
-                }
-                let result = self.file_view.dispatch_event(event.clone());
-                if result.handled {
-                    return result;
+            FocusedComponent::FilePane => (self.file_pane.key_context(), KeyContext::Editor),
+            FocusedComponent::FileView => (KeyContext::Editor, self.file_pane.key_context()),
+        }
+    }
+
+    fn dispatch_command_to(
+        &mut self,
+        component: FocusedComponent,
+        command: Command,
+    ) -> DispatchEventResult {
+        match component {
+            FocusedComponent::FilePane => self.file_pane.dispatch_command(command),
+            FocusedComponent::FileView => self.file_view.dispatch_command(command),
+        }
+    }
+
+    fn dispatch_event_to(
+        &mut self,
+        component: FocusedComponent,
+        event: termion::event::Event,
+    ) -> DispatchEventResult {
+        match component {
+            FocusedComponent::FilePane => self.file_pane.dispatch_event(event),
+            FocusedComponent::FileView => self.file_view.dispatch_event(event),
+        }
+    }
+
+    // Prompts take the keys they can use before the keymap does. Other keys are looked up in the
+    // keymap for the focused context, then the global context. Keys that aren't bound there are
+    // dispatched as they are, and finally looked up in the other component's context, so that for
+    // example the editor can still be saved while the file tree has focus.
+    fn dispatch_key(&mut self, key: Key) -> DispatchEventResult {
+        let is_prompting = match self.focused_component {
+            FocusedComponent::FilePane => self.file_pane.is_prompting(),
+            FocusedComponent::FileView => self.file_view.is_prompting(),
+        };
+        if is_prompting {
+            let result =
+                self.dispatch_event_to(self.focused_component, termion::event::Event::Key(key));
+            if result.handled {
+                self.pending_keys.clear();
+                return result;
+            }
+        }
+
+        self.pending_keys.push(key);
+        let keys = std::mem::take(&mut self.pending_keys);
+        let (focused_context, other_context) = self.key_contexts();
+        let focused = self.focused_component;
+        let other = focused.other();
+        let focused_match = self.keymap.lookup(focused_context, &keys);
+        let global_match = self.keymap.lookup(KeyContext::Global, &keys);
+        let other_match = self.keymap.lookup(other_context, &keys);
+
+        if let KeymapMatch::Command(command) = focused_match {
+            let result = self.dispatch_command_to(focused, command);
+            if result.handled {
+                return result;
+            }
+        }
+        if let KeymapMatch::Command(command) = global_match {
+            return self.dispatch_command(command);
+        }
+        let matches = [&focused_match, &global_match, &other_match];
+        if matches.contains(&&KeymapMatch::Prefix) {
+            self.pending_keys = keys;
+            return DispatchEventResult {
+                handled: true,
+                events: vec![],
+            };
+        }
+        if keys.len() > 1 {
+            // The chord didn't turn out to be bound to anything
+            return DispatchEventResult {
+                handled: true,
+                events: vec![],
+            };
+        }
+
+        let event = termion::event::Event::Key(key);
+        let result = self.dispatch_event_to(focused, event.clone());
+        if result.handled {
+            return result;
+        }
+        if let KeymapMatch::Command(command) = other_match {
+            let result = self.dispatch_command_to(other, command);
+            if result.handled {
+                return result;
+            }
+        }
+        self.dispatch_event_to(other, event)
+    }
+
+    // Tells the language server which file is shown, and shows what it reported about that file.
+    fn open_in_language_server(&mut self) {
+        let language_servers = match &mut self.language_servers {
+            None => return,
+            Some(language_servers) => language_servers,
+        };
+        let (buffer, file_path, _) = self.file_view.get_buffer();
+        let (left, right) = buffer.get();
+        if let Err(err) = language_servers.open(&file_path, &[left, right].concat()) {
+            self.file_pane.show_message(err);
+        }
+    }
+
+    // The shown file gets the diagnostics of its language server and the problems that the last
+    // build found in it.
+    fn update_diagnostics(&mut self) {
+        let path = match &self.shown_file_path {
+            None => return,
+            Some(path) => path,
+        };
+        let mut diagnostics = match &self.language_servers {
+            None => vec![],
+            Some(language_servers) => language_servers.diagnostics(path),
+        };
+        diagnostics.extend(
+            self.problems
+                .iter()
+                .filter(|problem| problem.path == *path)
+                .map(|problem| problem.diagnostic.clone()),
+        );
+        self.file_view.set_diagnostics(diagnostics);
+    }
+
+    fn start_build(&mut self) {
+        match &mut self.builder {
+            None => self.file_pane.show_message(String::from(
+                "Set build_command in the settings to build this directory",
+            )),
+            Some(builder) if builder.is_running() => self
+                .file_pane
+                .show_message(String::from("The build is still running")),
+            Some(builder) => {
+                builder.start();
+                let message = format!("Running {}", builder.command());
+                self.file_pane.show_message(message);
+            }
+        }
+    }
+
+    fn poll_builder(&mut self) {
+        let result = match &mut self.builder {
+            None => return,
+            Some(builder) => builder.poll(),
+        };
+        match result {
+            None => {}
+            Some(Err(err)) => self.file_pane.show_message(err),
+            Some(Ok(problems)) => {
+                self.problems = problems;
+                self.update_diagnostics();
+                if self.problems.is_empty() {
+                    self.file_pane
+                        .show_message(String::from("The build found no problems"));
+                } else {
+                    self.show_problems();
                 }
             }
-            FocusedComponent::FileView => {
-                let result = self.file_view.dispatch_event(event.clone());
-                if result.handled {
-                    return result;
+        }
+    }
+
+    fn refresh_git_status(&mut self) {
+        if let Some(git_status_reader) = &mut self.git_status_reader {
+            git_status_reader.refresh();
+        }
+    }
+
+    fn poll_git_status(&mut self) {
+        let git_status = match &mut self.git_status_reader {
+            None => return,
+            Some(git_status_reader) => git_status_reader.poll(),
+        };
+        if let Some(git_status) = git_status {
+            self.file_pane
+                .set_git_status(git_status.unwrap_or_default());
+        }
+    }
+
+    // Lists the problems of the last build, most severe first.
+    fn show_problems(&mut self) {
+        if self.problems.is_empty() {
+            self.file_pane
+                .show_message(String::from("The last build found no problems"));
+            return;
+        }
+        let mut problems = self.problems.clone();
+        problems.sort_by_key(|problem| problem.diagnostic.severity);
+        let locations = problems
+            .iter()
+            .map(|problem| Location {
+                path: problem.path.clone(),
+                position: problem.diagnostic.start,
+            })
+            .collect();
+        let results = self
+            .location_results(locations)
+            .into_iter()
+            .zip(problems)
+            .map(|(result, problem)| LocationResult {
+                text: String::from(
+                    problem
+                        .diagnostic
+                        .message
+                        .lines()
+                        .next()
+                        .unwrap_or_default(),
+                ),
+                severity: Some(problem.diagnostic.severity),
+                ..result
+            })
+            .collect();
+        self.update_index();
+        self.file_pane.show_locations(results);
+        self.focus_file_pane();
+    }
+
+    fn close_in_language_server(&mut self) {
+        if let Some(language_servers) = &mut self.language_servers {
+            language_servers.close();
+        }
+    }
+
+    fn poll_language_servers(&mut self) {
+        let events = match &mut self.language_servers {
+            None => return,
+            Some(language_servers) => language_servers.poll(),
+        };
+        for event in events {
+            match event {
+                LspEvent::Diagnostics(path, _) => {
+                    if self.shown_file_path.as_ref() == Some(&path) {
+                        self.update_diagnostics();
+                    }
                 }
-                let result = self.file_pane.dispatch_event(event.clone());
-                if result.handled {
-                    return result;
+                LspEvent::Hover(text) if text.trim().is_empty() => self
+                    .file_pane
+                    .show_message(String::from("No information about this symbol")),
+                LspEvent::Hover(text) => self.file_view.show_hover(&text),
+                LspEvent::Definition(locations) => match locations.as_slice() {
+                    [] => self
+                        .file_pane
+                        .show_message(String::from("No definition found")),
+                    [location] => self.go_to_location(location),
+                    _ => self.show_locations(locations),
+                },
+                LspEvent::References(locations) if locations.is_empty() => self
+                    .file_pane
+                    .show_message(String::from("No references found")),
+                LspEvent::References(locations) => self.show_locations(locations),
+                LspEvent::Completion(items) if items.is_empty() => {
+                    self.file_pane.show_message(String::from("No completions"))
                 }
+                LspEvent::Completion(items) => self.file_view.show_completions(items),
+                LspEvent::Error(message) => self.file_pane.show_message(message),
             }
         }
+    }
 
-        match event {
-            termion::event::Event::Key(key) => match key {
-                Key::Ctrl(c) => {
-                    if c == 'p' {
-                        self.start_quick_open();
-                        DispatchEventResult {
-                            handled: true,
-                            events: vec![],
-                        }
-                    } else {
-                        DispatchEventResult::empty()
-                    }
+    fn go_to_location(&mut self, location: &Location) {
+        if self.shown_file_path.as_ref() != Some(&location.path) {
+            let index_entry = self
+                .indexer
+                .get_index()
+                .and_then(|index| {
+                    index
+                        .files
+                        .into_iter()
+                        .find(|entry| entry.path == location.path)
+                })
+                .or_else(|| {
+                    FileIndexEntry::new(Path::new(&location.path), FileMetadata::unknown())
+                });
+            match index_entry {
+                None => return,
+                Some(index_entry) => self.open_file(&index_entry),
+            }
+        }
+        self.file_view.go_to_lsp_position(location.position);
+        self.focus_file_view();
+    }
+
+    fn show_locations(&mut self, locations: Vec<Location>) {
+        let results = self.location_results(locations);
+        self.update_index();
+        self.file_pane.show_locations(results);
+        self.focus_file_pane();
+    }
+
+    // The columns of locations count UTF-16 code units, which need the text of their line to be
+    // converted. Lines of the open file come from the buffer, since it may have unsaved edits.
+    fn location_results(&self, locations: Vec<Location>) -> Vec<LocationResult> {
+        let (buffer, file_path, _) = self.file_view.get_buffer();
+        let mut files: HashMap<String, Vec<String>> = HashMap::new();
+        locations
+            .into_iter()
+            .map(|location| {
+                let line = if location.path == file_path {
+                    buffer
+                        .line(location.position.line)
+                        .unwrap_or_default()
+                        .into_owned()
+                } else {
+                    let lines = files.entry(location.path.clone()).or_insert_with(|| {
+                        std::fs::read_to_string(&location.path)
+                            .unwrap_or_default()
+                            .lines()
+                            .map(String::from)
+                            .collect()
+                    });
+                    lines
+                        .get(location.position.line)
+                        .cloned()
+                        .unwrap_or_default()
+                };
+                let byte_index = byte_index_of_utf16_column(&line, location.position.column);
+                LocationResult {
+                    position: TextPosition::new(
+                        location.position.line,
+                        grapheme_index_of_byte(&line, byte_index),
+                    ),
+                    path: location.path,
+                    text: line,
+                    severity: None,
                 }
-                _ => DispatchEventResult::empty(),
+            })
+            .collect()
+    }
+
+    fn show_file_preview(&mut self, index_entry: &FileIndexEntry) {
+        self.pending_preview = None;
+        let path = Path::new(&index_entry.path);
+        let path_string = String::from(path.to_str().unwrap());
+        self.shown_file_path = Some(path_string.clone());
+        let is_large = std::fs::metadata(path)
+            .map(|metadata| metadata.len() > self.large_file_threshold)
+            .unwrap_or(false);
+        if is_large {
+            // Large files that can't be shown as text are paged through in the hex view
+            let content = match LargeFile::open(path) {
+                Some(file) => FileViewContent::LargeFile(path_string, file),
+                None => FileViewContent::BinaryFile(
+                    path_string,
+                    match PagedFile::open(path) {
+                        Ok(file) => HexData::Paged(file),
+                        Err(err) => HexData::Unreadable(err.to_string()),
+                    },
+                ),
+            };
+            self.file_view.set_content(content);
+            self.close_in_language_server();
+            return;
+        }
+        match self.preview_cache.load(&path_string) {
+            Err(err) => self.file_view.set_content(FileViewContent::BinaryFile(
+                path_string,
+                HexData::Unreadable(err.to_string()),
+            )),
+            Ok(data) => match text_format::decode(&data) {
+                Some((buffer, format)) => {
+                    self.file_view.set_content(FileViewContent::TextFile(
+                        path_string,
+                        buffer,
+                        format,
+                    ));
+                    self.open_in_language_server();
+                    self.update_diagnostics();
+                    return;
+                }
+                None => self.file_view.set_content(FileViewContent::BinaryFile(
+                    path_string,
+                    HexData::Bytes(data.to_vec()),
+                )),
             },
+        }
+        self.close_in_language_server();
+    }
 
-            _ => DispatchEventResult::empty(),
+    fn show_folder_preview(&mut self, folder: &FileTreeFolder) {
+        self.pending_preview = None;
+        self.shown_file_path = None;
+        let children: Vec<String> = folder
+            .children
+            .iter()
+            .map(|child| match &child {
+                FileTreeNode::File(file_index_entry) => file_index_entry.file_name.clone(),
+                FileTreeNode::Folder(file_tree_folder) => file_tree_folder.folder_name.clone(),
+            })
+            .collect();
+        self.file_view
+            .set_content(FileViewContent::Folder(folder.path.clone(), children));
+        self.close_in_language_server();
+    }
+
+    fn open_file(&mut self, index_entry: &FileIndexEntry) {
+        self.file_pane.record_file_opened(&index_entry.path);
+        if self.shown_file_path.as_ref() != Some(&index_entry.path) {
+            self.show_file_preview(index_entry);
+        }
+        self.focus_file_view();
+    }
+
+    // Returns the operation that undoes it, or None if it failed.
+    fn perform_file_operation(&mut self, operation: &FileOperation) -> Option<FileOperation> {
+        let mut index = self.indexer.get_index()?;
+        let undo_operation = match file_operations::perform(self.file_system, &mut index, operation)
+        {
+            Err(err) => {
+                self.file_pane.show_message(err.to_string());
+                return None;
+            }
+            Ok(undo_operation) => undo_operation,
+        };
+        if let FileOperation::Move(from, _) | FileOperation::Trash(from) = operation {
+            self.preview_cache.remove(from);
+        }
+        self.indexer.set_index(index.clone());
+        self.file_pane.update_index(index.clone());
+        self.refresh_git_status();
+
+        // Show where the item ended up, unless it was deleted and the selection has moved on
+        if let FileOperation::Trash(_) = operation {
+            return Some(undo_operation);
+        }
+        let path = operation.destination();
+        self.file_pane.select_path(path);
+        match index.tree.path_to(path).and_then(|mut nodes| nodes.pop()) {
+            Some(FileTreeNode::File(file_index_entry)) => self.select_file(file_index_entry),
+            Some(FileTreeNode::Folder(file_tree_folder)) => {
+                self.show_folder_preview(file_tree_folder)
+            }
+            None => {}
+        }
+        Some(undo_operation)
+    }
+
+    fn undo_file_operation(&mut self) {
+        match self.file_operation_undo_stack.pop() {
+            None => self.file_pane.show_message(String::from("Nothing to undo")),
+            Some(operation) => {
+                if self.perform_file_operation(&operation).is_none() {
+                    self.file_operation_undo_stack.push(operation);
+                }
+            }
+        }
+    }
+
+    fn save_file(
+        &self,
+        buffer: &Buffer,
+        file_path: String,
+        format: TextFormat,
+    ) -> std::io::Result<()> {
+        let mut handle = std::fs::OpenOptions::new()
+            .write(true)
+            .truncate(true)
+            .open(&file_path)?;
+        text_format::encode(&mut handle, buffer, format)
+    }
+}
+
+impl<'a> Component for RootComponent<'a> {
+    fn needs_paint(&self) -> bool {
+        self.needs_full_paint.get()
+            || self.file_view.needs_paint()
+            || self.file_pane.needs_paint()
+            || self.divider.needs_paint()
+    }
+    fn paint<Writer: Write>(&self, stream: &mut Writer, rect: Rect) -> std::io::Result<()> {
+        self.painted_rect.set(Some(rect));
+        let (file_pane_rect, divider_rect, file_view_rect) = self.layout(rect);
+        let paint_all = self.needs_full_paint.take();
+        if paint_all {
+            write!(stream, "{}", termion::clear::All)?;
+        }
+        if paint_all || self.file_pane.needs_paint() {
+            self.file_pane.paint(stream, file_pane_rect)?;
+        }
+        if paint_all || self.file_view.needs_paint() {
+            self.file_view.paint(stream, file_view_rect)?;
+        }
+        if paint_all || self.divider.needs_paint() {
+            self.divider.paint(stream, divider_rect)?;
+        }
+
+        stream.flush()
+    }
+
+    fn dispatch_event(&mut self, event: termion::event::Event) -> DispatchEventResult {
+        if let termion::event::Event::Mouse(mouse_event) = event.clone() {
+            return self.dispatch_mouse_event(event, mouse_event);
+        }
+
+        match event {
+            termion::event::Event::Key(key) => self.dispatch_key(key),
+            event => self.dispatch_event_to(self.focused_component, event),
+        }
+    }
+
+    fn dispatch_command(&mut self, command: Command) -> DispatchEventResult {
+        match command {
+            Command::Palette => self.start_command_palette(),
+            Command::QuickOpen => self.start_quick_open(),
+            Command::GoToLine => self.start_go_to_line(),
+            Command::GoToSymbol => self.start_go_to_symbol(),
+            Command::SearchSymbols => self.start_symbol_search(),
+            Command::Build => self.start_build(),
+            Command::ShowProblems => self.show_problems(),
+            Command::ClearHistory => {
+                self.file_pane.clear_history();
+                self.file_pane
+                    .show_message(String::from("Cleared the history"));
+            }
+            _ => return DispatchEventResult::empty(),
+        }
+        DispatchEventResult {
+            handled: true,
+            events: vec![],
         }
     }
 
@@ -214,27 +1024,140 @@ impl<'a> Component for RootComponent<'a> {
         for event in events {
             match event {
                 Event::FileItemSelected(file_tree_node) => match file_tree_node {
-                    FileTreeNode::File(file_index_entry) => {
-                        self.show_file_preview(file_index_entry)
-                    }
+                    FileTreeNode::File(file_index_entry) => self.select_file(file_index_entry),
                     FileTreeNode::Folder(file_tree_folder) => {
                         self.show_folder_preview(file_tree_folder)
                     }
                 },
                 Event::FileItemOpened(index_entry) => self.open_file(index_entry),
-                Event::FileViewLostFocus => {
-                    self.file_view.set_has_focus(false);
-                    self.focused_component = FocusedComponent::FilePane;
+                Event::FolderOpened(path) => {
+                    self.file_pane.open_folder(path);
+                    self.focus_file_pane();
                 }
+                Event::FileViewLostFocus => self.focus_file_pane(),
                 Event::FileSaved => {
-                    let (buffer, file_path) = self.file_view.get_buffer();
-                    self.save_file(buffer, file_path);
+                    let (buffer, file_path, format) = self.file_view.get_buffer();
+                    self.preview_cache.remove(&file_path);
+                    match self.save_file(buffer, file_path.clone(), format) {
+                        // TODO: handle saving errors
+                        Err(err) => println!("Error saving: {}", err),
+                        Ok(()) => {
+                            if let Some(symbol_indexer) = &self.symbol_indexer {
+                                let (left, right) = buffer.get();
+                                symbol_indexer.update_file(&file_path, &[left, right].concat());
+                            }
+                            if let Some(language_servers) = &mut self.language_servers {
+                                language_servers.save();
+                            }
+                            self.file_view.mark_saved();
+                            self.refresh_git_status();
+                        }
+                    }
+                }
+                Event::GoToPosition(position) => {
+                    if let Some(pending_preview) = &mut self.pending_preview {
+                        pending_preview.position = Some(*position);
+                    }
+                }
+                Event::FileOperationRequested(operation) => {
+                    if let Some(undo_operation) = self.perform_file_operation(operation) {
+                        self.file_operation_undo_stack.push(undo_operation);
+                    }
+                }
+                Event::FileOperationUndoRequested => self.undo_file_operation(),
+                Event::FileViewFocusRequested => {
+                    self.file_pane.close_quick_open();
+                    self.focus_file_view();
+                }
+                Event::TextEdited(edit) => {
+                    if let Some(language_servers) = &mut self.language_servers {
+                        let (buffer, _, _) = self.file_view.get_buffer();
+                        language_servers.change(edit, || {
+                            let (left, right) = buffer.get();
+                            [left, right].concat()
+                        });
+                    }
+                }
+                Event::LanguageServerRequested(request, position) => {
+                    let result = match &mut self.language_servers {
+                        None => Err(String::from(
+                            "Language servers only run for local directories",
+                        )),
+                        Some(language_servers) => language_servers.request(*request, *position),
+                    };
+                    if let Err(err) = result {
+                        self.file_pane.show_message(err);
+                    }
+                }
+                Event::CommandRequested(command) => self.run_command(*command),
+                Event::Tick => {
+                    self.show_pending_preview();
+                    self.poll_language_servers();
+                    self.poll_builder();
+                    self.poll_git_status();
                 }
             }
         }
 
         self.file_pane.dispatch_events(events);
-        self.file_view.dispatch_events(events);
+        if self.pending_preview.is_some() {
+            // The position is for the file that is about to be shown, not the one still in view
+            let events: Vec<Event> = events
+                .iter()
+                .filter(|event| !matches!(event, Event::GoToPosition(_)))
+                .cloned()
+                .collect();
+            self.file_view.dispatch_events(&events);
+        } else {
+            self.file_view.dispatch_events(events);
+        }
         self.divider.dispatch_events(events);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::components::root::*;
+    use crate::file_system::LocalFileSystem;
+    use crate::indexer::ignore::IgnorePatterns;
+    use crate::indexer::local_index::LocalIndexer;
+
+    #[test]
+    fn go_to_position_in_pending_preview() {
+        let folder = std::env::temp_dir().join(format!("five-{}-root", std::process::id()));
+        std::fs::create_dir_all(&folder).unwrap();
+        let entry = |name: &str| {
+            let path = folder.join(name);
+            std::fs::write(&path, "first\nsecond\nthird\n").unwrap();
+            FileIndexEntry::new(&path, FileMetadata::unknown()).unwrap()
+        };
+        let (a, b) = (entry("a.txt"), entry("b.txt"));
+        let indexer = LocalIndexer::new(folder.clone(), IgnorePatterns::default());
+        let file_system = LocalFileSystem::new(None);
+        let mut root = RootComponent::new(&indexer, &file_system);
+
+        root.show_file_preview(&a);
+        root.dispatch_events(&[Event::GoToPosition(TextPosition::new(1, 2))]);
+        root.dispatch_events(&[
+            Event::FileItemSelected(FileTreeNode::File(b.clone())),
+            Event::GoToPosition(TextPosition::new(2, 3)),
+        ]);
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while root.shown_file_path.as_ref() != Some(&b.path) {
+            assert!(Instant::now() < deadline, "the preview was not shown");
+            std::thread::sleep(PREVIEW_DELAY);
+            root.dispatch_events(&[Event::Tick]);
+        }
+
+        let file_states = root.file_view.file_states();
+        let cursor = |path: &str| {
+            file_states
+                .iter()
+                .find(|file_state| file_state.path == path)
+                .map(|file_state| file_state.cursor)
+        };
+        assert_eq!(cursor(&a.path), Some(TextPosition::new(1, 2)));
+        assert_eq!(cursor(&b.path), Some(TextPosition::new(2, 3)));
+        std::fs::remove_dir_all(&folder).unwrap();
+    }
+}
@@ -0,0 +1,252 @@
+/*
+    Copyright 2020, John Pothier
+    This file is part of Five.
+
+    Five is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    Five is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with Five.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+// Scrolling and keyboard navigation for lists with a selected item, like the directory tree and quick open.
+
+use crate::commands::Command;
+use crate::terminal::Rect;
+use crate::theme::{StyleName, Theme};
+use std::cell::Cell;
+use std::cmp::{max, min};
+use std::io::Write;
+use std::ops::Range;
+use std::time::{Duration, Instant};
+
+// Two clicks on the same item within this time count as a double click
+const DOUBLE_CLICK_INTERVAL: Duration = Duration::from_millis(400);
+
+pub struct ScrollingList {
+    first_visible_item: usize,
+    num_visible_items: Cell<usize>,
+    last_click: Option<(usize, Instant)>,
+}
+
+// The rows of the scrollbar track covered by the thumb.
+pub fn scrollbar_thumb(
+    num_items: usize,
+    first_visible_item: usize,
+    num_visible_items: usize,
+    track_height: usize,
+) -> Range<usize> {
+    if num_items <= num_visible_items || track_height == 0 {
+        return 0..track_height;
+    }
+    let thumb_height = max(track_height * num_visible_items / num_items, 1);
+    let max_first_item = num_items - num_visible_items;
+    let thumb_start =
+        (track_height - thumb_height) * min(first_visible_item, max_first_item) / max_first_item;
+    thumb_start..thumb_start + thumb_height
+}
+
+impl ScrollingList {
+    pub fn new() -> ScrollingList {
+        ScrollingList {
+            first_visible_item: 0,
+            num_visible_items: Cell::new(0),
+            last_click: None,
+        }
+    }
+
+    fn page_size(&self) -> usize {
+        max(self.num_visible_items.get(), 1)
+    }
+
+    pub fn reset(&mut self) {
+        self.first_visible_item = 0;
+    }
+
+    // Scrolls as little as possible to bring the item into view.
+    pub fn scroll_to_item(&mut self, index: usize) {
+        if index < self.first_visible_item {
+            self.first_visible_item = index;
+        } else if index >= self.first_visible_item + self.page_size() {
+            self.first_visible_item = index + 1 - self.page_size();
+        }
+    }
+
+    pub fn scroll_by(&mut self, num_items: isize, list_length: usize) {
+        let max_first_item = list_length.saturating_sub(self.page_size());
+        self.first_visible_item = if num_items < 0 {
+            self.first_visible_item
+                .saturating_sub(num_items.unsigned_abs())
+        } else {
+            min(self.first_visible_item + num_items as usize, max_first_item)
+        };
+    }
+
+    // Returns the item to select after the command, or None if the command isn't for navigation.
+    // Moving up with nothing selected keeps nothing selected.
+    pub fn navigate(
+        &self,
+        command: Command,
+        selected_item: Option<usize>,
+        list_length: usize,
+    ) -> Option<Option<usize>> {
+        let last_item = list_length.checked_sub(1);
+        let target = match (command, selected_item) {
+            (Command::CursorDown, None) => Some(0),
+            (Command::CursorDown, Some(index)) => Some(index + 1),
+            (Command::CursorUp, None) => None,
+            (Command::CursorUp, Some(index)) => Some(index.saturating_sub(1)),
+            (Command::PageDown, _) => {
+                Some(selected_item.map_or(0, |index| index + self.page_size()))
+            }
+            (Command::PageUp, _) => {
+                Some(selected_item.unwrap_or(0).saturating_sub(self.page_size()))
+            }
+            (Command::GoToStart, _) => Some(0),
+            (Command::GoToEnd, _) => last_item,
+            _ => return None,
+        };
+        match (target, last_item) {
+            (Some(target), Some(last_item)) => Some(Some(min(target, last_item))),
+            _ => Some(selected_item),
+        }
+    }
+
+    // Returns the item shown in the given row of the list area, and whether the click on it is the second
+    // of a double click.
+    pub fn click(&mut self, row: usize, list_length: usize) -> Option<(usize, bool)> {
+        let first = min(
+            self.first_visible_item,
+            list_length.saturating_sub(self.num_visible_items.get()),
+        );
+        let index = first + row;
+        if row >= self.num_visible_items.get() || index >= list_length {
+            return None;
+        }
+        let now = Instant::now();
+        let is_double_click = match self.last_click {
+            Some((last_index, last_time)) => {
+                last_index == index && now.duration_since(last_time) < DOUBLE_CLICK_INTERVAL
+            }
+            None => false,
+        };
+        // A third click starts over rather than making another double click
+        self.last_click = if is_double_click {
+            None
+        } else {
+            Some((index, now))
+        };
+        Some((index, is_double_click))
+    }
+
+    // Returns the items to paint in a list area of the given height.
+    pub fn visible_items(&self, num_rows: u16, list_length: usize) -> Range<usize> {
+        let num_rows = usize::from(num_rows);
+        self.num_visible_items.set(num_rows);
+        // The list may have gotten shorter since it was scrolled
+        let first = min(
+            self.first_visible_item,
+            list_length.saturating_sub(num_rows),
+        );
+        first..min(first + num_rows, list_length)
+    }
+
+    pub fn needs_scrollbar(&self, num_rows: u16, list_length: usize) -> bool {
+        list_length > usize::from(num_rows)
+    }
+
+    pub fn paint_scrollbar<Writer: Write>(
+        &self,
+        stream: &mut Writer,
+        rect: Rect,
+        list_length: usize,
+        theme: &Theme,
+    ) -> std::io::Result<()> {
+        let first_item = self.visible_items(rect.height, list_length).start;
+        let thumb = scrollbar_thumb(
+            list_length,
+            first_item,
+            usize::from(rect.height),
+            usize::from(rect.height),
+        );
+        for row in 0..rect.height {
+            let is_thumb = thumb.contains(&usize::from(row));
+            write!(
+                stream,
+                "{}{}{}",
+                termion::cursor::Goto(rect.left, rect.top + row),
+                if is_thumb {
+                    theme.style(StyleName::ScrollbarThumb)
+                } else {
+                    theme.style(StyleName::ScrollbarTrack)
+                },
+                if is_thumb { "┃" } else { "│" }
+            )?;
+        }
+        write!(stream, "{}", termion::style::Reset)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::components::scrolling_list::*;
+
+    #[test]
+    fn thumb_covers_visible_fraction() {
+        assert_eq!(scrollbar_thumb(10, 0, 20, 20), 0..20);
+        assert_eq!(scrollbar_thumb(100, 0, 10, 10), 0..1);
+        assert_eq!(scrollbar_thumb(100, 90, 10, 10), 9..10);
+        assert_eq!(scrollbar_thumb(40, 10, 20, 20), 5..15);
+    }
+
+    #[test]
+    fn navigate_commands() {
+        let list = ScrollingList::new();
+        list.visible_items(5, 20);
+        assert_eq!(list.navigate(Command::CursorDown, None, 20), Some(Some(0)));
+        assert_eq!(list.navigate(Command::CursorUp, None, 20), Some(None));
+        assert_eq!(list.navigate(Command::CursorUp, Some(0), 20), Some(Some(0)));
+        assert_eq!(list.navigate(Command::PageDown, Some(3), 20), Some(Some(8)));
+        assert_eq!(
+            list.navigate(Command::PageDown, Some(18), 20),
+            Some(Some(19))
+        );
+        assert_eq!(list.navigate(Command::PageUp, Some(3), 20), Some(Some(0)));
+        assert_eq!(list.navigate(Command::GoToEnd, None, 20), Some(Some(19)));
+        assert_eq!(list.navigate(Command::CursorDown, None, 0), Some(None));
+        assert_eq!(list.navigate(Command::CursorLeft, Some(3), 20), None);
+    }
+
+    #[test]
+    fn double_click() {
+        let mut list = ScrollingList::new();
+        list.visible_items(5, 20);
+        list.scroll_by(10, 20);
+        assert_eq!(list.click(2, 20), Some((12, false)));
+        assert_eq!(list.click(2, 20), Some((12, true)));
+        assert_eq!(list.click(2, 20), Some((12, false)));
+        assert_eq!(list.click(3, 20), Some((13, false)));
+        assert_eq!(list.click(5, 20), None);
+        assert_eq!(list.click(4, 3), None);
+    }
+
+    #[test]
+    fn follow_selection() {
+        let mut list = ScrollingList::new();
+        assert_eq!(list.visible_items(5, 20), 0..5);
+        list.scroll_to_item(7);
+        assert_eq!(list.visible_items(5, 20), 3..8);
+        list.scroll_to_item(1);
+        assert_eq!(list.visible_items(5, 20), 1..6);
+        list.scroll_by(100, 20);
+        assert_eq!(list.visible_items(5, 20), 15..20);
+        assert_eq!(list.visible_items(5, 8), 3..8);
+    }
+}
@@ -0,0 +1,141 @@
+/*
+    Copyright 2020, John Pothier
+    This file is part of Five.
+
+    Five is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    Five is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with Five.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+// Everything that needs to know how wide text is on screen goes through here, so that painting and
+// cursor placement always agree. Widths are measured in terminal columns, and since tabs advance
+// to the next tab stop, the width of a grapheme can depend on the column it starts at.
+
+use std::borrow::Cow;
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthChar;
+
+pub const DEFAULT_TAB_WIDTH: usize = 4;
+
+const ZERO_WIDTH_JOINER: char = '\u{200d}';
+const EMOJI_PRESENTATION_SELECTOR: char = '\u{fe0f}';
+
+fn is_escaped(c: char) -> bool {
+    c != '\t' && c.is_control()
+}
+
+// Control characters are shown as caret notation (^@ through ^_, and ^? for DEL), or as their code
+// point in angle brackets for the C1 range, so that they can't mess with the terminal.
+fn escape(c: char) -> String {
+    match c as u32 {
+        code @ 0x00..=0x1f => format!("^{}", char::from(b'@' + code as u8)),
+        0x7f => String::from("^?"),
+        code => format!("<{:02X}>", code),
+    }
+}
+
+pub fn grapheme_width(grapheme: &str, column: usize, tab_width: usize) -> usize {
+    let mut chars = grapheme.chars();
+    let first = match chars.next() {
+        None => return 0,
+        Some(first) => first,
+    };
+
+    if first == '\t' {
+        let tab_width = std::cmp::max(tab_width, 1);
+        return tab_width - column % tab_width;
+    }
+    if is_escaped(first) {
+        return escape(first).len();
+    }
+
+    // A grapheme cluster occupies a single cell (or two for wide characters), no matter how many
+    // combining marks or joined emoji it is made of.
+    let width = UnicodeWidthChar::width(first).unwrap_or(0);
+    let is_emoji_sequence = grapheme
+        .chars()
+        .any(|c| c == ZERO_WIDTH_JOINER || c == EMOJI_PRESENTATION_SELECTOR);
+    if is_emoji_sequence {
+        2
+    } else {
+        width
+    }
+}
+
+// Returns what should be written to the terminal for the grapheme.
+pub fn displayed_grapheme(grapheme: &str, column: usize, tab_width: usize) -> Cow<'_, str> {
+    match grapheme.chars().next() {
+        Some('\t') => Cow::Owned(" ".repeat(grapheme_width(grapheme, column, tab_width))),
+        Some(c) if is_escaped(c) => Cow::Owned(escape(c)),
+        _ => Cow::Borrowed(grapheme),
+    }
+}
+
+pub fn width(text: &str, tab_width: usize) -> usize {
+    text.graphemes(true).fold(0, |column, grapheme| {
+        column + grapheme_width(grapheme, column, tab_width)
+    })
+}
+
+// Returns the byte index of the grapheme that covers the given column, or the length of the text
+// if the text is not that wide.
+pub fn byte_index_at_column(text: &str, column: usize, tab_width: usize) -> usize {
+    let mut current_column = 0;
+    for (i, grapheme) in text.grapheme_indices(true) {
+        current_column += grapheme_width(grapheme, current_column, tab_width);
+        if current_column > column {
+            return i;
+        }
+    }
+    text.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::display_width::*;
+
+    #[test]
+    fn ascii() {
+        assert_eq!(width("hello", DEFAULT_TAB_WIDTH), 5);
+    }
+
+    #[test]
+    fn wide_characters() {
+        assert_eq!(width("日本語", DEFAULT_TAB_WIDTH), 6);
+        assert_eq!(width("😎", DEFAULT_TAB_WIDTH), 2);
+        assert_eq!(byte_index_at_column("日本語", 3, DEFAULT_TAB_WIDTH), 3);
+    }
+
+    #[test]
+    fn combining_and_joined_graphemes() {
+        assert_eq!(width("a̐", DEFAULT_TAB_WIDTH), 1);
+        assert_eq!(width("👨‍👩‍👧", DEFAULT_TAB_WIDTH), 2);
+    }
+
+    #[test]
+    fn tabs_align_to_tab_stops() {
+        assert_eq!(width("\t", 4), 4);
+        assert_eq!(width("ab\t", 4), 4);
+        assert_eq!(width("abcd\tx", 4), 9);
+        assert_eq!(width("ab\t", 8), 8);
+        assert_eq!(displayed_grapheme("\t", 1, 4), "   ");
+    }
+
+    #[test]
+    fn control_characters() {
+        assert_eq!(displayed_grapheme("\u{0}", 0, 4), "^@");
+        assert_eq!(displayed_grapheme("\u{1b}", 0, 4), "^[");
+        assert_eq!(displayed_grapheme("\u{7f}", 0, 4), "^?");
+        assert_eq!(displayed_grapheme("\u{85}", 0, 4), "<85>");
+        assert_eq!(width("a\u{1b}b", 4), 4);
+    }
+}
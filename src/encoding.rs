@@ -0,0 +1,283 @@
+/*
+    Copyright 2020, John Pothier
+    This file is part of Five.
+
+    Five is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    Five is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with Five.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+// Text files are decoded into UTF-8 when they are loaded, and encoded back into their original
+// encoding when they are saved.
+
+use std::borrow::Cow;
+
+// Like git, only look at the start of the file to decide whether it is text
+const SNIFF_LENGTH: usize = 8000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Utf8,
+    Utf8WithBom,
+    Utf16Le,
+    Utf16Be,
+    Latin1,
+    Windows1252,
+}
+
+pub const ALL_ENCODINGS: [Encoding; 6] = [
+    Encoding::Utf8,
+    Encoding::Utf8WithBom,
+    Encoding::Utf16Le,
+    Encoding::Utf16Be,
+    Encoding::Latin1,
+    Encoding::Windows1252,
+];
+
+// Windows-1252 differs from Latin-1 only in 0x80..=0x9F. Undefined bytes map to the C1 control
+// character with the same value, like Latin-1.
+const WINDOWS_1252_HIGH: [char; 32] = [
+    '€', '\u{81}', '‚', 'ƒ', '„', '…', '†', '‡', 'ˆ', '‰', 'Š', '‹', 'Œ', '\u{8d}', 'Ž', '\u{8f}',
+    '\u{90}', '‘', '’', '“', '”', '•', '–', '—', '˜', '™', 'š', '›', 'œ', '\u{9d}', 'ž', 'Ÿ',
+];
+
+impl Encoding {
+    pub fn name(self) -> &'static str {
+        match self {
+            Encoding::Utf8 => "UTF-8",
+            Encoding::Utf8WithBom => "UTF-8 with BOM",
+            Encoding::Utf16Le => "UTF-16 LE",
+            Encoding::Utf16Be => "UTF-16 BE",
+            Encoding::Latin1 => "ISO-8859-1",
+            Encoding::Windows1252 => "Windows-1252",
+        }
+    }
+
+    pub fn bom(self) -> &'static [u8] {
+        match self {
+            Encoding::Utf8WithBom => &[0xef, 0xbb, 0xbf],
+            Encoding::Utf16Le => &[0xff, 0xfe],
+            Encoding::Utf16Be => &[0xfe, 0xff],
+            _ => &[],
+        }
+    }
+
+    pub fn next(self) -> Encoding {
+        let index = ALL_ENCODINGS.iter().position(|e| *e == self).unwrap();
+        ALL_ENCODINGS[(index + 1) % ALL_ENCODINGS.len()]
+    }
+}
+
+fn is_suspicious_control(c: u32) -> bool {
+    match c {
+        0x09 | 0x0a | 0x0d | 0x0c | 0x1b => false,
+        c => c < 0x20 || c == 0x7f,
+    }
+}
+
+// Text has no NUL characters and only a few other control characters.
+fn has_binary_characters(characters: &[u32]) -> bool {
+    let num_control = characters
+        .iter()
+        .filter(|c| is_suspicious_control(**c))
+        .count();
+    characters.contains(&0) || num_control * 10 > characters.len()
+}
+
+fn looks_binary(sample: &[u8]) -> bool {
+    let characters: Vec<u32> = sample.iter().map(|b| u32::from(*b)).collect();
+    has_binary_characters(&characters)
+}
+
+// Binary files are full of zero bytes too, so the sample only counts as UTF-16 if it also decodes
+// into valid text. `is_truncated` allows for a surrogate pair cut in half at the end of the sample.
+fn detect_utf16_without_bom(sample: &[u8], is_truncated: bool) -> Option<Encoding> {
+    let num_pairs = sample.len() / 2;
+    if num_pairs == 0 {
+        return None;
+    }
+    let num_even_zeros = sample.iter().step_by(2).filter(|b| **b == 0).count();
+    let num_odd_zeros = sample
+        .iter()
+        .skip(1)
+        .step_by(2)
+        .filter(|b| **b == 0)
+        .count();
+    // Mostly-ASCII text in UTF-16 has a zero in every other byte
+    let (encoding, to_u16): (Encoding, fn([u8; 2]) -> u16) =
+        if num_odd_zeros * 10 > num_pairs * 4 && num_even_zeros * 10 < num_pairs {
+            (Encoding::Utf16Le, u16::from_le_bytes)
+        } else if num_even_zeros * 10 > num_pairs * 4 && num_odd_zeros * 10 < num_pairs {
+            (Encoding::Utf16Be, u16::from_be_bytes)
+        } else {
+            return None;
+        };
+
+    let mut units: Vec<u16> = sample
+        .chunks_exact(2)
+        .map(|chunk| to_u16([chunk[0], chunk[1]]))
+        .collect();
+    if is_truncated
+        && units
+            .last()
+            .is_some_and(|unit| (0xd800..0xdc00).contains(unit))
+    {
+        units.pop();
+    }
+    let mut characters = Vec::with_capacity(units.len());
+    for c in char::decode_utf16(units) {
+        characters.push(u32::from(c.ok()?));
+    }
+    if has_binary_characters(&characters) {
+        None
+    } else {
+        Some(encoding)
+    }
+}
+
+// Returns None for binary files.
+pub fn detect(data: &[u8]) -> Option<Encoding> {
+    if data.starts_with(Encoding::Utf8WithBom.bom()) {
+        return Some(Encoding::Utf8WithBom);
+    }
+    if data.starts_with(Encoding::Utf16Le.bom()) {
+        return Some(Encoding::Utf16Le);
+    }
+    if data.starts_with(Encoding::Utf16Be.bom()) {
+        return Some(Encoding::Utf16Be);
+    }
+
+    let sample = &data[..std::cmp::min(data.len(), SNIFF_LENGTH)];
+    if looks_binary(sample) {
+        return detect_utf16_without_bom(sample, sample.len() < data.len());
+    }
+
+    if std::str::from_utf8(data).is_ok() {
+        Some(Encoding::Utf8)
+    } else if data.iter().any(|b| (0x80..=0x9f).contains(b)) {
+        // These are control characters in Latin-1, but printable in Windows-1252
+        Some(Encoding::Windows1252)
+    } else {
+        Some(Encoding::Latin1)
+    }
+}
+
+fn decode_utf16(data: &[u8], to_u16: fn([u8; 2]) -> u16) -> String {
+    let units: Vec<u16> = data
+        .chunks(2)
+        .map(|chunk| match chunk {
+            [a, b] => to_u16([*a, *b]),
+            _ => 0xfffd,
+        })
+        .collect();
+    String::from_utf16_lossy(&units)
+}
+
+pub fn decode(data: &[u8], encoding: Encoding) -> String {
+    let data = data.strip_prefix(encoding.bom()).unwrap_or(data);
+    match encoding {
+        Encoding::Utf8 | Encoding::Utf8WithBom => String::from_utf8_lossy(data).into_owned(),
+        Encoding::Utf16Le => decode_utf16(data, u16::from_le_bytes),
+        Encoding::Utf16Be => decode_utf16(data, u16::from_be_bytes),
+        Encoding::Latin1 => data.iter().map(|b| char::from(*b)).collect(),
+        Encoding::Windows1252 => data
+            .iter()
+            .map(|b| match b {
+                0x80..=0x9f => WINDOWS_1252_HIGH[usize::from(b - 0x80)],
+                _ => char::from(*b),
+            })
+            .collect(),
+    }
+}
+
+// Encodes text without the BOM. Characters that can't be represented become '?'.
+pub fn encode(text: &str, encoding: Encoding) -> Cow<'_, [u8]> {
+    match encoding {
+        Encoding::Utf8 | Encoding::Utf8WithBom => Cow::Borrowed(text.as_bytes()),
+        Encoding::Utf16Le => Cow::Owned(text.encode_utf16().flat_map(u16::to_le_bytes).collect()),
+        Encoding::Utf16Be => Cow::Owned(text.encode_utf16().flat_map(u16::to_be_bytes).collect()),
+        Encoding::Latin1 => Cow::Owned(
+            text.chars()
+                .map(|c| if (c as u32) < 0x100 { c as u8 } else { b'?' })
+                .collect(),
+        ),
+        Encoding::Windows1252 => Cow::Owned(
+            text.chars()
+                .map(|c| match WINDOWS_1252_HIGH.iter().position(|h| *h == c) {
+                    Some(i) => 0x80 + i as u8,
+                    None if (c as u32) < 0x100 => c as u8,
+                    None => b'?',
+                })
+                .collect(),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::encoding::*;
+
+    #[test]
+    fn detect_utf8() {
+        assert_eq!(detect("héllo".as_bytes()), Some(Encoding::Utf8));
+        assert_eq!(detect(b"\xef\xbb\xbfhi"), Some(Encoding::Utf8WithBom));
+    }
+
+    #[test]
+    fn detect_utf16() {
+        assert_eq!(detect(b"\xff\xfeh\x00i\x00"), Some(Encoding::Utf16Le));
+        assert_eq!(detect(b"\xfe\xff\x00h\x00i"), Some(Encoding::Utf16Be));
+        assert_eq!(
+            detect(b"h\x00e\x00l\x00l\x00o\x00"),
+            Some(Encoding::Utf16Le)
+        );
+    }
+
+    #[test]
+    fn detect_single_byte_encodings() {
+        assert_eq!(detect(b"caf\xe9"), Some(Encoding::Latin1));
+        assert_eq!(detect(b"\x93quoted\x94"), Some(Encoding::Windows1252));
+    }
+
+    #[test]
+    fn detect_binary() {
+        assert_eq!(detect(b"\x7fELF\x02\x01\x01\x00\x00\x00"), None);
+        assert_eq!(detect(b"\x01\x02\x03\x04\x05"), None);
+        // Zero bytes in every other position, but not text
+        assert_eq!(detect(b"\x01\x00\x02\x00\x03\x00\x04\x00"), None);
+        assert_eq!(detect(b"h\x00i\x00\x00\xdcx\x00"), None);
+
+        // The executable running this test is a real binary
+        let executable = std::fs::read(std::env::current_exe().unwrap()).unwrap();
+        assert_eq!(detect(&executable), None);
+        let mut elf_header = b"\x7fELF\x02\x01\x01\x00".to_vec();
+        elf_header.extend_from_slice(&[0; 8]);
+        elf_header.extend_from_slice(b"\x03\x00\x3e\x00\x01\x00\x00\x00\x60\x10\x00\x00");
+        elf_header.extend_from_slice(&[0; 4]);
+        elf_header.extend_from_slice(b"\x40\x00\x00\x00\x00\x00\x00\x00");
+        assert_eq!(detect(&elf_header), None);
+    }
+
+    #[test]
+    fn round_trip() {
+        let text = "“Café” costs 5€";
+        for encoding in ALL_ENCODINGS.iter() {
+            if *encoding == Encoding::Latin1 {
+                continue;
+            }
+            let mut data = encoding.bom().to_vec();
+            data.extend_from_slice(&encode(text, *encoding));
+            assert_eq!(decode(&data, *encoding), text, "{}", encoding.name());
+        }
+        assert_eq!(&*encode(text, Encoding::Latin1), b"?Caf\xe9? costs 5?");
+    }
+}
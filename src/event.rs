@@ -16,12 +16,32 @@
     along with Five.  If not, see <https://www.gnu.org/licenses/>.
 */
 
+use crate::commands::Command;
+use crate::file_operations::FileOperation;
 use crate::indexer::index::{FileIndexEntry, FileTreeNode};
+use crate::lsp::{LspRequest, TextEdit};
+use crate::text_position::TextPosition;
 
 #[derive(Clone)]
 pub enum Event {
     FileItemSelected(FileTreeNode),
     FileItemOpened(FileIndexEntry),
+    // Shows the folder in the file tree
+    FolderOpened(String),
     FileViewLostFocus,
     FileSaved,
+    GoToPosition(TextPosition),
+    // Edits of the open file, for the language server
+    TextEdited(TextEdit),
+    // Asks the language server about a position in the open file, with a UTF-16 column
+    LanguageServerRequested(LspRequest, TextPosition),
+    FileOperationRequested(FileOperation),
+    // Undoes the most recent file operation
+    FileOperationUndoRequested,
+    // Closes quick open and focuses the file view, after picking a symbol of the open file
+    FileViewFocusRequested,
+    // Runs a command picked from the command palette
+    CommandRequested(Command),
+    // Sent periodically, so components can pick up the results of background work and delayed actions
+    Tick,
 }
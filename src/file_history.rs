@@ -0,0 +1,161 @@
+/*
+    Copyright 2020, John Pothier
+    This file is part of Five.
+
+    Five is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    Five is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with Five.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Remembers how often and how recently files were opened, to rank them in quick open. Files that
+// are opened often and were opened recently come first ("frecency").
+
+// The least useful files are forgotten beyond this many
+pub const MAX_RECENT_FILES: usize = 500;
+
+const HOUR: u64 = 60 * 60;
+const DAY: u64 = 24 * HOUR;
+const WEEK: u64 = 7 * DAY;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct RecentFile {
+    pub path: String,
+    pub open_count: u32,
+    // Seconds since the epoch
+    pub last_opened: u64,
+}
+
+impl RecentFile {
+    // Each open counts for more the more recent the last one was.
+    fn frecency(&self, now: u64) -> f64 {
+        let age = now.saturating_sub(self.last_opened);
+        let weight = if age < HOUR {
+            4.0
+        } else if age < DAY {
+            2.0
+        } else if age < WEEK {
+            0.5
+        } else {
+            0.25
+        };
+        f64::from(self.open_count) * weight
+    }
+}
+
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct FileHistory {
+    // Most recently opened first
+    files: Vec<RecentFile>,
+}
+
+impl FileHistory {
+    pub fn new(mut files: Vec<RecentFile>) -> FileHistory {
+        files.sort_by_key(|file| std::cmp::Reverse(file.last_opened));
+        FileHistory { files }
+    }
+
+    pub fn files(&self) -> &[RecentFile] {
+        &self.files
+    }
+
+    pub fn record_open(&mut self, path: &str, now: u64) {
+        let open_count = match self.files.iter().position(|file| file.path == path) {
+            Some(position) => self.files.remove(position).open_count,
+            None => 0,
+        };
+        self.files.insert(
+            0,
+            RecentFile {
+                path: String::from(path),
+                open_count: open_count.saturating_add(1),
+                last_opened: now,
+            },
+        );
+        if self.files.len() > MAX_RECENT_FILES {
+            let (index, _) = self
+                .files
+                .iter()
+                .enumerate()
+                .min_by(|(_, a), (_, b)| a.frecency(now).total_cmp(&b.frecency(now)))
+                .unwrap();
+            self.files.remove(index);
+        }
+    }
+
+    // By path. Files that were never opened aren't there.
+    pub fn frecencies(&self, now: u64) -> HashMap<&str, f64> {
+        self.files
+            .iter()
+            .map(|file| (file.path.as_str(), file.frecency(now)))
+            .collect()
+    }
+
+    pub fn clear(&mut self) {
+        self.files.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::file_history::*;
+
+    #[test]
+    fn rank_by_frequency_and_recency() {
+        let now = 100 * WEEK;
+        let mut history = FileHistory::default();
+        history.record_open("/old_favourite.rs", now - 2 * WEEK);
+        history.record_open("/old_favourite.rs", now - 2 * WEEK);
+        history.record_open("/old_favourite.rs", now - 2 * WEEK);
+        history.record_open("/yesterday.rs", now - DAY - 1);
+        history.record_open("/just_now.rs", now - 10);
+
+        let paths: Vec<&str> = history.files().iter().map(|file| &*file.path).collect();
+        assert_eq!(
+            paths,
+            vec!["/just_now.rs", "/yesterday.rs", "/old_favourite.rs"]
+        );
+        let frecencies = history.frecencies(now);
+        assert_eq!(frecencies["/just_now.rs"], 4.0);
+        assert_eq!(frecencies["/old_favourite.rs"], 0.75);
+        assert_eq!(frecencies["/yesterday.rs"], 0.5);
+        assert_eq!(frecencies.get("/never.rs"), None);
+
+        history.clear();
+        assert!(history.files().is_empty());
+    }
+
+    #[test]
+    fn forget_the_least_useful_files() {
+        let now = 100 * WEEK;
+        let mut history = FileHistory::default();
+        history.record_open("/favourite.rs", now - 2 * WEEK);
+        history.record_open("/favourite.rs", now - 2 * WEEK);
+        history.record_open("/once.rs", now - 2 * WEEK);
+        for i in 0..MAX_RECENT_FILES - 1 {
+            history.record_open(&format!("/{}.rs", i), now);
+        }
+        assert_eq!(history.files().len(), MAX_RECENT_FILES);
+        let frecencies = history.frecencies(now);
+        assert_eq!(frecencies.get("/once.rs"), None);
+        assert_eq!(frecencies["/favourite.rs"], 0.5);
+    }
+}
@@ -0,0 +1,288 @@
+/*
+    Copyright 2020, John Pothier
+    This file is part of Five.
+
+    Five is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    Five is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with Five.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+// Creating, renaming, copying and deleting items from the file pane. Each operation changes the file
+// system and then the index in place, so the file tree doesn't have to be reindexed.
+
+use crate::file_system::FileSystem;
+use crate::indexer::index::{FileIndexEntry, FileMetadata, FileTreeFolder, FileTreeNode, Index};
+use std::io;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Clone)]
+pub enum FileOperation {
+    CreateFile(String),
+    CreateFolder(String),
+    // Used for both renaming and moving
+    Move(String, String),
+    Duplicate(String, String),
+    Trash(String),
+    // Undoes a Trash, putting the node back where it was
+    Restore {
+        trash_path: String,
+        node: FileTreeNode,
+    },
+}
+
+impl FileOperation {
+    // Where the item ends up after the operation.
+    pub fn destination(&self) -> &str {
+        match self {
+            FileOperation::CreateFile(path) | FileOperation::CreateFolder(path) => path,
+            FileOperation::Move(_, to) | FileOperation::Duplicate(_, to) => to,
+            FileOperation::Trash(path) => path,
+            FileOperation::Restore { node, .. } => node.path(),
+        }
+    }
+}
+
+fn error(message: String) -> io::Error {
+    io::Error::other(message)
+}
+
+fn check_absent(index: &Index, path: &str) -> io::Result<()> {
+    if index.contains(path) {
+        return Err(error(format!("{} already exists", path)));
+    }
+    let parent = Path::new(path).parent().and_then(|parent| parent.to_str());
+    match parent {
+        Some(parent) if index.is_folder(parent) => Ok(()),
+        _ => Err(error(format!("{} is not in a known folder", path))),
+    }
+}
+
+fn find_node(index: &Index, path: &str) -> io::Result<FileTreeNode> {
+    match index.tree.path_to(path).and_then(|mut nodes| nodes.pop()) {
+        Some(node) if node.path() != index.tree.path() => Ok(node.clone()),
+        Some(_) => Err(error(String::from("The root folder can't be changed"))),
+        None => Err(error(format!("{} doesn't exist", path))),
+    }
+}
+
+fn moved_node(node: &FileTreeNode, path: &str) -> io::Result<FileTreeNode> {
+    node.moved_to(path)
+        .ok_or_else(|| error(format!("{} is not a valid path", path)))
+}
+
+// Deleted items are moved to the trash folder of the root, on the same host as the root, where they are
+// kept after exiting too. Five never empties it.
+fn trash_path(
+    file_system: &dyn FileSystem,
+    index: &Index,
+    node: &FileTreeNode,
+) -> io::Result<String> {
+    // Prefixed with the time, so deleting several items with the same name keeps them all
+    let time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_nanos())
+        .unwrap_or(0);
+    let name = format!("{}-{}", time, node.name());
+    Ok(String::from(
+        Path::new(&file_system.trash_folder(index.tree.path())?)
+            .join(name)
+            .to_str()
+            .unwrap(),
+    ))
+}
+
+// Performs the operation and updates the index to match, returning the operation that undoes it.
+pub fn perform(
+    file_system: &dyn FileSystem,
+    index: &mut Index,
+    operation: &FileOperation,
+) -> io::Result<FileOperation> {
+    match operation {
+        FileOperation::CreateFile(path) => {
+            check_absent(index, path)?;
+            let metadata = FileMetadata {
+                modified: Some(SystemTime::now()),
+                ..FileMetadata::unknown()
+            };
+            let entry = FileIndexEntry::new(Path::new(path), metadata)
+                .ok_or_else(|| error(format!("{} is not a valid path", path)))?;
+            file_system.create_file(path)?;
+            index.add_node(FileTreeNode::File(entry));
+            Ok(FileOperation::Trash(path.clone()))
+        }
+        FileOperation::CreateFolder(path) => {
+            check_absent(index, path)?;
+            let folder_name = Path::new(path)
+                .file_name()
+                .and_then(|name| name.to_str())
+                .ok_or_else(|| error(format!("{} is not a valid path", path)))?;
+            let folder = FileTreeFolder {
+                children: vec![],
+                folder_name: String::from(folder_name),
+                path: path.clone(),
+            };
+            file_system.create_folder(path)?;
+            index.add_node(FileTreeNode::Folder(folder));
+            Ok(FileOperation::Trash(path.clone()))
+        }
+        FileOperation::Move(from, to) => {
+            let node = find_node(index, from)?;
+            if Path::new(to).starts_with(from) {
+                return Err(error(format!("{} can't be moved into itself", from)));
+            }
+            check_absent(index, to)?;
+            let moved = moved_node(&node, to)?;
+            file_system.rename(from, to)?;
+            index.remove_node(from);
+            index.add_node(moved);
+            Ok(FileOperation::Move(to.clone(), from.clone()))
+        }
+        FileOperation::Duplicate(from, to) => {
+            let node = find_node(index, from)?;
+            check_absent(index, to)?;
+            let copy = moved_node(&node, to)?;
+            file_system.copy(from, to)?;
+            index.add_node(copy);
+            Ok(FileOperation::Trash(to.clone()))
+        }
+        FileOperation::Trash(path) => {
+            let node = find_node(index, path)?;
+            let trash_path = trash_path(file_system, index, &node)?;
+            file_system
+                .create_folder(Path::new(&trash_path).parent().unwrap().to_str().unwrap())?;
+            file_system.rename(path, &trash_path)?;
+            index.remove_node(path);
+            Ok(FileOperation::Restore { trash_path, node })
+        }
+        FileOperation::Restore { trash_path, node } => {
+            check_absent(index, node.path())?;
+            file_system.rename(trash_path, node.path())?;
+            index.add_node(node.clone());
+            Ok(FileOperation::Trash(String::from(node.path())))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::file_operations::*;
+    use crate::file_system::LocalFileSystem;
+    use crate::indexer::ignore::IgnorePatterns;
+    use crate::indexer::index::Indexer;
+    use crate::indexer::local_index::LocalIndexer;
+    use std::path::PathBuf;
+
+    fn test_folder(name: &str) -> PathBuf {
+        let folder = std::env::temp_dir().join(format!("five-{}-{}", std::process::id(), name));
+        std::fs::create_dir_all(folder.join("src")).unwrap();
+        std::fs::write(folder.join("src").join("main.rs"), "fn main() {}").unwrap();
+        std::fs::canonicalize(folder).unwrap()
+    }
+
+    fn index_folder(folder: &Path) -> Index {
+        let indexer = LocalIndexer::new(folder.to_path_buf(), IgnorePatterns::default());
+        loop {
+            if let Some(index) = indexer.get_index() {
+                return index;
+            }
+        }
+    }
+
+    fn file_names(index: &Index) -> Vec<String> {
+        let mut names: Vec<String> = index.files.iter().map(|file| file.path.clone()).collect();
+        names.sort();
+        names
+    }
+
+    fn path(folder: &Path, relative_path: &str) -> String {
+        String::from(folder.join(relative_path).to_str().unwrap())
+    }
+
+    #[test]
+    fn keep_index_in_sync() {
+        let folder = test_folder("file-operations");
+        let file_system = LocalFileSystem::new(None);
+        let mut index = index_folder(&folder);
+        let operations = vec![
+            FileOperation::CreateFolder(path(&folder, "docs")),
+            FileOperation::CreateFile(path(&folder, "docs/notes.md")),
+            FileOperation::Duplicate(path(&folder, "src"), path(&folder, "src copy")),
+            FileOperation::Move(path(&folder, "src copy"), path(&folder, "docs/old")),
+            FileOperation::Move(path(&folder, "docs/notes.md"), path(&folder, "NOTES.md")),
+        ];
+        for operation in &operations {
+            perform(&file_system, &mut index, operation).unwrap();
+        }
+
+        // The updated index looks the same as a freshly built one
+        let expected = vec![
+            path(&folder, "NOTES.md"),
+            path(&folder, "docs/old/main.rs"),
+            path(&folder, "src/main.rs"),
+        ];
+        assert_eq!(file_names(&index), expected);
+        assert_eq!(file_names(&index_folder(&folder)), expected);
+        assert!(index.is_folder(&path(&folder, "docs/old")));
+
+        // Nothing gets replaced
+        let clash = FileOperation::Move(path(&folder, "NOTES.md"), path(&folder, "src/main.rs"));
+        assert!(perform(&file_system, &mut index, &clash).is_err());
+        assert_eq!(file_names(&index), expected);
+        std::fs::remove_dir_all(&folder).unwrap();
+    }
+
+    #[test]
+    fn undo_delete() {
+        let folder = test_folder("file-operations-trash");
+        let state_dir = folder.with_file_name(format!(
+            "five-{}-file-operations-trash-state",
+            std::process::id()
+        ));
+        let file_system = LocalFileSystem::new(Some(state_dir.clone()));
+        let mut index = index_folder(&folder);
+        let undo = perform(
+            &file_system,
+            &mut index,
+            &FileOperation::Trash(path(&folder, "src")),
+        )
+        .unwrap();
+        assert!(index.files.is_empty());
+        // The trash is kept outside of the root
+        assert_eq!(std::fs::read_dir(&folder).unwrap().count(), 0);
+        let trash_folder = file_system.trash_folder(index.tree.path()).unwrap();
+        assert!(Path::new(&trash_folder).starts_with(&state_dir));
+        assert_eq!(std::fs::read_dir(&trash_folder).unwrap().count(), 1);
+
+        perform(&file_system, &mut index, &undo).unwrap();
+        assert_eq!(file_names(&index), vec![path(&folder, "src/main.rs")]);
+        assert_eq!(
+            std::fs::read_to_string(folder.join("src/main.rs")).unwrap(),
+            "fn main() {}"
+        );
+        assert_eq!(std::fs::read_dir(&trash_folder).unwrap().count(), 0);
+        std::fs::remove_dir_all(&folder).unwrap();
+        std::fs::remove_dir_all(&state_dir).unwrap();
+    }
+
+    #[test]
+    fn keep_items_without_a_trash_folder() {
+        let folder = test_folder("file-operations-no-trash");
+        let file_system = LocalFileSystem::new(None);
+        let mut index = index_folder(&folder);
+        let trash = FileOperation::Trash(path(&folder, "src"));
+        assert!(perform(&file_system, &mut index, &trash).is_err());
+        assert_eq!(file_names(&index), vec![path(&folder, "src/main.rs")]);
+        assert!(folder.join("src/main.rs").exists());
+        std::fs::remove_dir_all(&folder).unwrap();
+    }
+}
@@ -0,0 +1,281 @@
+/*
+    Copyright 2020, John Pothier
+    This file is part of Five.
+
+    Five is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    Five is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with Five.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+// Changes to files and folders, made either on this machine or on the remote host of an --ssh session.
+// None of the operations replace something that already exists at the destination.
+
+use crate::session;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+pub trait FileSystem {
+    fn create_file(&self, path: &str) -> io::Result<()>;
+    // Also creates any missing parent folders, and succeeds if the folder is already there.
+    fn create_folder(&self, path: &str) -> io::Result<()>;
+    fn rename(&self, from: &str, to: &str) -> io::Result<()>;
+    // Folders are copied with everything inside them. Symbolic links are copied as links.
+    fn copy(&self, from: &str, to: &str) -> io::Result<()>;
+    // Deletes the file, or the folder with everything inside it. Succeeds if nothing is there.
+    fn delete(&self, path: &str) -> io::Result<()>;
+    // Where items deleted from under the root are moved to. It is in the state folder of Five rather
+    // than under the root, so that the trash shows up neither in the file tree nor in git status.
+    fn trash_folder(&self, root_path: &str) -> io::Result<String>;
+}
+
+fn already_exists(path: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::AlreadyExists,
+        format!("{} already exists", path),
+    )
+}
+
+pub struct LocalFileSystem {
+    // Usually session::state_dir(), which is None without a home folder
+    state_dir: Option<PathBuf>,
+}
+
+impl LocalFileSystem {
+    pub fn new(state_dir: Option<PathBuf>) -> LocalFileSystem {
+        LocalFileSystem { state_dir }
+    }
+}
+
+fn copy_recursively(from: &Path, to: &Path) -> io::Result<()> {
+    // Not following links, which could point outside of the folder or back into it
+    let file_type = std::fs::symlink_metadata(from)?.file_type();
+    if file_type.is_symlink() {
+        return std::os::unix::fs::symlink(std::fs::read_link(from)?, to);
+    }
+    if !file_type.is_dir() {
+        return std::fs::copy(from, to).map(|_| ());
+    }
+    std::fs::create_dir(to)?;
+    for entry in std::fs::read_dir(from)? {
+        let entry = entry?;
+        copy_recursively(&entry.path(), &to.join(entry.file_name()))?;
+    }
+    Ok(())
+}
+
+impl FileSystem for LocalFileSystem {
+    fn create_file(&self, path: &str) -> io::Result<()> {
+        std::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(path)
+            .map(|_| ())
+    }
+
+    fn create_folder(&self, path: &str) -> io::Result<()> {
+        std::fs::create_dir_all(path)
+    }
+
+    fn rename(&self, from: &str, to: &str) -> io::Result<()> {
+        // fs::rename would silently replace an existing file
+        if Path::new(to).exists() {
+            return Err(already_exists(to));
+        }
+        match std::fs::rename(from, to) {
+            // Such as into the trash, when the state folder is on another file system
+            Err(err) if err.kind() == io::ErrorKind::CrossesDevices => {
+                if let Err(err) = copy_recursively(Path::new(from), Path::new(to)) {
+                    let _ = self.delete(to);
+                    return Err(err);
+                }
+                self.delete(from)
+            }
+            result => result,
+        }
+    }
+
+    fn copy(&self, from: &str, to: &str) -> io::Result<()> {
+        if Path::new(to).exists() {
+            return Err(already_exists(to));
+        }
+        copy_recursively(Path::new(from), Path::new(to))
+    }
+
+    fn delete(&self, path: &str) -> io::Result<()> {
+        let result = match std::fs::symlink_metadata(path) {
+            Ok(metadata) if metadata.is_dir() => std::fs::remove_dir_all(path),
+            Ok(_) => std::fs::remove_file(path),
+            Err(err) => Err(err),
+        };
+        match result {
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+            result => result,
+        }
+    }
+
+    fn trash_folder(&self, root_path: &str) -> io::Result<String> {
+        let state_dir = self.state_dir.as_ref().ok_or_else(|| {
+            io::Error::other("There is no folder to keep the trash in, since HOME is not set")
+        })?;
+        Ok(String::from(
+            session::trash_folder(state_dir, root_path)
+                .to_str()
+                .unwrap(),
+        ))
+    }
+}
+
+pub struct SshFileSystem {
+    ssh_args: Vec<String>,
+}
+
+// Quotes the argument for the remote shell, which would otherwise split it on spaces and expand it.
+pub fn shell_quote(argument: &str) -> String {
+    format!("'{}'", argument.replace('\'', "'\\''"))
+}
+
+impl SshFileSystem {
+    pub fn new(ssh_args: &[String]) -> SshFileSystem {
+        SshFileSystem {
+            ssh_args: ssh_args.to_vec(),
+        }
+    }
+
+    // Returns what the command printed.
+    fn output(&self, command: String) -> io::Result<String> {
+        let output = Command::new("ssh")
+            .args(&self.ssh_args)
+            .arg(command)
+            .output()?;
+        if output.status.success() {
+            return Ok(String::from_utf8_lossy(&output.stdout).into_owned());
+        }
+        let message = String::from_utf8_lossy(&output.stderr).trim().to_string();
+        Err(io::Error::other(if message.is_empty() {
+            String::from("The command failed on the remote host")
+        } else {
+            message
+        }))
+    }
+
+    fn run(&self, command: String) -> io::Result<()> {
+        self.output(command).map(|_| ())
+    }
+
+    // Runs the command only if nothing exists at the destination yet.
+    fn run_if_absent(&self, destination: &str, command: String) -> io::Result<()> {
+        self.run(format!(
+            "if [ -e {0} ]; then echo {1} >&2; exit 1; fi; {2}",
+            shell_quote(destination),
+            shell_quote(&already_exists(destination).to_string()),
+            command
+        ))
+    }
+}
+
+impl FileSystem for SshFileSystem {
+    fn create_file(&self, path: &str) -> io::Result<()> {
+        self.run_if_absent(path, format!("touch {}", shell_quote(path)))
+    }
+
+    fn create_folder(&self, path: &str) -> io::Result<()> {
+        self.run(format!("mkdir -p {}", shell_quote(path)))
+    }
+
+    fn rename(&self, from: &str, to: &str) -> io::Result<()> {
+        self.run_if_absent(to, format!("mv {} {}", shell_quote(from), shell_quote(to)))
+    }
+
+    fn copy(&self, from: &str, to: &str) -> io::Result<()> {
+        self.run_if_absent(
+            to,
+            format!("cp -RP {} {}", shell_quote(from), shell_quote(to)),
+        )
+    }
+
+    fn delete(&self, path: &str) -> io::Result<()> {
+        self.run(format!("rm -rf -- {}", shell_quote(path)))
+    }
+
+    // The same folder as session::state_dir, but for the remote user.
+    fn trash_folder(&self, root_path: &str) -> io::Result<String> {
+        let state_home = self.output(String::from(
+            "printf '%s' \"${XDG_STATE_HOME:-$HOME/.local/state}\"",
+        ))?;
+        let state_dir = Path::new(&state_home).join("five");
+        Ok(String::from(
+            session::trash_folder(&state_dir, root_path)
+                .to_str()
+                .unwrap(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::file_system::*;
+
+    #[test]
+    fn quote_for_the_remote_shell() {
+        assert_eq!(shell_quote("/a b/c"), "'/a b/c'");
+        assert_eq!(shell_quote("it's"), "'it'\\''s'");
+    }
+
+    #[test]
+    fn never_replace_existing_files() {
+        let folder = std::env::temp_dir().join(format!("five-{}-file-system", std::process::id()));
+        std::fs::create_dir_all(&folder).unwrap();
+        let a = folder.join("a");
+        let b = folder.join("b");
+        std::fs::write(&a, "a").unwrap();
+        std::fs::write(&b, "b").unwrap();
+        let file_system = LocalFileSystem::new(None);
+        let (a, b) = (a.to_str().unwrap(), b.to_str().unwrap());
+
+        assert!(file_system.create_file(a).is_err());
+        assert!(file_system.rename(a, b).is_err());
+        assert!(file_system.copy(a, b).is_err());
+        assert_eq!(std::fs::read_to_string(b).unwrap(), "b");
+        std::fs::remove_dir_all(&folder).unwrap();
+    }
+
+    #[test]
+    fn copy_links_as_links() {
+        let folder =
+            std::env::temp_dir().join(format!("five-{}-file-system-links", std::process::id()));
+        std::fs::create_dir_all(folder.join("a")).unwrap();
+        std::fs::write(folder.join("a").join("file"), "file").unwrap();
+        // Pointing back at the parent, which would never end if the link were followed
+        std::os::unix::fs::symlink("..", folder.join("a").join("parent")).unwrap();
+        let file_system = LocalFileSystem::new(None);
+
+        let (a, b) = (folder.join("a"), folder.join("b"));
+        file_system
+            .copy(a.to_str().unwrap(), b.to_str().unwrap())
+            .unwrap();
+        assert_eq!(std::fs::read_to_string(b.join("file")).unwrap(), "file");
+        let link = std::fs::symlink_metadata(b.join("parent")).unwrap();
+        assert!(link.file_type().is_symlink());
+        assert_eq!(
+            std::fs::read_link(b.join("parent")).unwrap(),
+            Path::new("..")
+        );
+
+        file_system.delete(b.to_str().unwrap()).unwrap();
+        assert!(!b.exists());
+        // The link was deleted, not what it points to
+        assert!(a.join("file").exists());
+        assert!(file_system.delete(b.to_str().unwrap()).is_ok());
+        std::fs::remove_dir_all(&folder).unwrap();
+    }
+}
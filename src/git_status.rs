@@ -0,0 +1,350 @@
+/*
+    Copyright 2020, John Pothier
+    This file is part of Five.
+
+    Five is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    Five is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with Five.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+// Reads the state of the files in a git repository from "git status --porcelain=v2", to decorate them
+// in the file tree. Folders take on the most notable state of what they contain.
+
+use crate::theme::StyleName;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::mpsc;
+use std::thread;
+
+// Ordered from least to most notable, which is the state that folders take on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum GitState {
+    Ignored,
+    Untracked,
+    Added,
+    Modified,
+    Conflicted,
+}
+
+impl GitState {
+    // Shown next to the names in the file tree
+    pub fn letter(self) -> char {
+        match self {
+            GitState::Ignored => '!',
+            GitState::Untracked => '?',
+            GitState::Added => 'A',
+            GitState::Modified => 'M',
+            GitState::Conflicted => 'C',
+        }
+    }
+
+    pub fn is_changed(self) -> bool {
+        self != GitState::Ignored
+    }
+
+    pub fn style_name(self) -> StyleName {
+        match self {
+            GitState::Ignored => StyleName::GitIgnored,
+            GitState::Untracked => StyleName::GitUntracked,
+            GitState::Added => StyleName::GitAdded,
+            GitState::Modified => StyleName::GitModified,
+            GitState::Conflicted => StyleName::GitConflicted,
+        }
+    }
+}
+
+// The state of a changed entry, from its XY field such as ".M" or "A.".
+fn state_of_change(xy: &str) -> GitState {
+    if xy.starts_with('A') || xy.starts_with('R') || xy.starts_with('C') {
+        GitState::Added
+    } else {
+        GitState::Modified
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct GitStatus {
+    // By absolute path, without the trailing slash of untracked and ignored folders
+    entries: HashMap<String, GitState>,
+    // The folders containing changes
+    folders: HashMap<String, GitState>,
+}
+
+impl GitStatus {
+    // The output is the NUL-separated "-z" form, with paths relative to the top level of the
+    // repository. The prefix is the path of the root from there, as given by --show-prefix.
+    pub fn parse(root_path: &Path, prefix: &str, output: &str) -> GitStatus {
+        let mut status = GitStatus::default();
+        let mut records = output.split('\0');
+        while let Some(record) = records.next() {
+            let (state, path) = match record.chars().next() {
+                Some('1') => match record.splitn(9, ' ').collect::<Vec<&str>>()[..] {
+                    [_, xy, _, _, _, _, _, _, path] => (state_of_change(xy), path),
+                    _ => continue,
+                },
+                Some('2') => {
+                    // Renames and copies are followed by the path they came from
+                    records.next();
+                    match record.splitn(10, ' ').collect::<Vec<&str>>()[..] {
+                        [_, xy, _, _, _, _, _, _, _, path] => (state_of_change(xy), path),
+                        _ => continue,
+                    }
+                }
+                Some('u') => match record.splitn(11, ' ').nth(10) {
+                    Some(path) => (GitState::Conflicted, path),
+                    None => continue,
+                },
+                Some('?') => (GitState::Untracked, &record[2.min(record.len())..]),
+                Some('!') => (GitState::Ignored, &record[2.min(record.len())..]),
+                _ => continue,
+            };
+            let path = match path.strip_prefix(prefix) {
+                Some("") => root_path.to_path_buf(),
+                Some(relative_path) => root_path.join(relative_path.trim_end_matches('/')),
+                // An untracked or ignored folder that contains the root
+                None if path.ends_with('/') && prefix.starts_with(path) => root_path.to_path_buf(),
+                // Paths outside the root can't be shown
+                None => continue,
+            };
+            status.add(root_path, &path, state);
+        }
+        status
+    }
+
+    fn add(&mut self, root_path: &Path, path: &Path, state: GitState) {
+        self.entries
+            .insert(path.to_string_lossy().into_owned(), state);
+        if !state.is_changed() {
+            return;
+        }
+        for folder in path.ancestors().skip(1) {
+            if !folder.starts_with(root_path) {
+                break;
+            }
+            let folder_state = self
+                .folders
+                .entry(folder.to_string_lossy().into_owned())
+                .or_insert(state);
+            *folder_state = (*folder_state).max(state);
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn changed_folders(&self) -> impl Iterator<Item = &str> {
+        self.folders.keys().map(String::as_str)
+    }
+
+    // Everything inside an untracked or ignored folder shares its state.
+    pub fn state(&self, path: &str) -> Option<GitState> {
+        if let Some(state) = self.entries.get(path).or_else(|| self.folders.get(path)) {
+            return Some(*state);
+        }
+        Path::new(path)
+            .ancestors()
+            .skip(1)
+            .find_map(|folder| self.entries.get(folder.to_str()?))
+            .copied()
+    }
+}
+
+// Reads the status in the background, once at a time. A refresh asked for while one is running
+// starts when it finishes, so that the result includes the latest changes.
+pub struct GitStatusReader {
+    root_path: PathBuf,
+    receiver: Option<mpsc::Receiver<Option<GitStatus>>>,
+    needs_refresh: bool,
+}
+
+impl GitStatusReader {
+    pub fn new(root_path: PathBuf) -> GitStatusReader {
+        GitStatusReader {
+            root_path,
+            receiver: None,
+            needs_refresh: false,
+        }
+    }
+
+    pub fn refresh(&mut self) {
+        if self.receiver.is_some() {
+            self.needs_refresh = true;
+            return;
+        }
+        let (sender, receiver) = mpsc::channel();
+        let root_path = self.root_path.clone();
+        thread::spawn(move || {
+            // Folders outside of a repository, or without git installed, have no status
+            let git = |args: &[&str]| {
+                Command::new("git")
+                    .args(args)
+                    .current_dir(&root_path)
+                    .output()
+                    .ok()
+                    .filter(|output| output.status.success())
+                    .map(|output| String::from_utf8_lossy(&output.stdout).into_owned())
+            };
+            let status = git(&["rev-parse", "--show-prefix"]).and_then(|prefix| {
+                let output = git(&["status", "--porcelain=v2", "-z", "--ignored", "--", "."])?;
+                Some(GitStatus::parse(&root_path, prefix.trim_end(), &output))
+            });
+            // Five may have exited in the meantime
+            let _ = sender.send(status);
+        });
+        self.receiver = Some(receiver);
+    }
+
+    // The new status, once it has been read. None means that the root isn't in a repository.
+    pub fn poll(&mut self) -> Option<Option<GitStatus>> {
+        let status = match self.receiver.as_ref()?.try_recv() {
+            Ok(status) => status,
+            Err(mpsc::TryRecvError::Empty) => return None,
+            Err(mpsc::TryRecvError::Disconnected) => None,
+        };
+        self.receiver = None;
+        if self.needs_refresh {
+            self.needs_refresh = false;
+            self.refresh();
+        }
+        Some(status)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::git_status::*;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn parse_status() {
+        let output = concat!(
+            "1 .M N... 100644 100644 100644 587be6b 587be6b src/main.rs\0",
+            "1 A. N... 000000 100644 100644 0000000 5e9519c src/new file.rs\0",
+            "2 R. N... 100644 100644 100644 587be6b 587be6b R100 docs/b.md\0docs/a.md\0",
+            "u UU N... 100644 100644 100644 100644 1111111 2222222 3333333 README.md\0",
+            "? scratch/\0",
+            "! target/\0",
+        );
+        let status = GitStatus::parse(Path::new("/five"), "", output);
+        assert_eq!(status.state("/five/src/main.rs"), Some(GitState::Modified));
+        assert_eq!(status.state("/five/src/new file.rs"), Some(GitState::Added));
+        assert_eq!(status.state("/five/src"), Some(GitState::Modified));
+        assert_eq!(status.state("/five/docs/b.md"), Some(GitState::Added));
+        assert_eq!(status.state("/five/docs/a.md"), None);
+        assert_eq!(status.state("/five/README.md"), Some(GitState::Conflicted));
+        assert_eq!(status.state("/five"), Some(GitState::Conflicted));
+        assert_eq!(status.state("/five/scratch"), Some(GitState::Untracked));
+        assert_eq!(
+            status.state("/five/scratch/notes.txt"),
+            Some(GitState::Untracked)
+        );
+        assert_eq!(status.state("/five/target/debug"), Some(GitState::Ignored));
+        assert_eq!(status.state("/five/Cargo.toml"), None);
+    }
+
+    #[test]
+    fn parse_status_of_subfolder() {
+        let output = concat!(
+            "1 .M N... 100644 100644 100644 587be6b 587be6b sub/main.rs\0",
+            "1 .M N... 100644 100644 100644 587be6b 587be6b other/main.rs\0",
+        );
+        let status = GitStatus::parse(Path::new("/five/sub"), "sub/", output);
+        assert_eq!(status.state("/five/sub/main.rs"), Some(GitState::Modified));
+        assert_eq!(status.state("/five/sub/sub/main.rs"), None);
+        assert_eq!(status.state("/five/sub/other/main.rs"), None);
+        assert_eq!(status.state("/five/sub"), Some(GitState::Modified));
+
+        // An untracked root is listed as the folder itself, or one that contains it
+        for output in &["? sub/\0", "? sub/inner/\0"] {
+            let status = GitStatus::parse(Path::new("/five/sub/inner"), "sub/inner/", output);
+            assert_eq!(
+                status.state("/five/sub/inner/notes.txt"),
+                Some(GitState::Untracked)
+            );
+        }
+    }
+
+    #[test]
+    fn read_status() {
+        let root_path =
+            std::env::temp_dir().join(format!("five-git-status-{}", std::process::id()));
+        std::fs::create_dir_all(&root_path).unwrap();
+        let git = |args: &[&str]| {
+            Command::new("git")
+                .args(args)
+                .current_dir(&root_path)
+                .output()
+                .map(|output| output.status.success())
+                .unwrap_or(false)
+        };
+        let wait_for_status = |reader: &mut GitStatusReader| {
+            let deadline = Instant::now() + Duration::from_secs(10);
+            loop {
+                if let Some(status) = reader.poll() {
+                    return status;
+                }
+                assert!(Instant::now() < deadline, "the status was not read");
+                thread::sleep(Duration::from_millis(10));
+            }
+        };
+        let mut reader = GitStatusReader::new(root_path.clone());
+        // Not a repository yet, unless the temporary folder is inside one
+        if !git(&["rev-parse", "--git-dir"]) {
+            reader.refresh();
+            assert_eq!(wait_for_status(&mut reader), None);
+        }
+        // Without git there is nothing more to check
+        if git(&["init", "-q", "."]) {
+            std::fs::write(root_path.join("notes.txt"), "notes").unwrap();
+            reader.refresh();
+            let status = wait_for_status(&mut reader).unwrap();
+            let path = root_path.join("notes.txt");
+            assert_eq!(
+                status.state(path.to_str().unwrap()),
+                Some(GitState::Untracked)
+            );
+
+            // A root inside the repository gets the paths relative to itself
+            let sub_path = root_path.join("sub");
+            std::fs::create_dir_all(&sub_path).unwrap();
+            std::fs::write(sub_path.join("main.rs"), "fn main() {}").unwrap();
+            assert!(git(&["add", "sub/main.rs"]));
+            std::fs::write(sub_path.join("new.rs"), "").unwrap();
+            let mut reader = GitStatusReader::new(sub_path.clone());
+            reader.refresh();
+            let status = wait_for_status(&mut reader).unwrap();
+            let path = sub_path.join("main.rs");
+            assert_eq!(status.state(path.to_str().unwrap()), Some(GitState::Added));
+            let path = sub_path.join("new.rs");
+            assert_eq!(
+                status.state(path.to_str().unwrap()),
+                Some(GitState::Untracked)
+            );
+
+            // Git lists a root that is entirely untracked as a single folder
+            let fresh_path = sub_path.join("fresh");
+            std::fs::create_dir_all(&fresh_path).unwrap();
+            std::fs::write(fresh_path.join("notes.txt"), "notes").unwrap();
+            let mut reader = GitStatusReader::new(fresh_path.clone());
+            reader.refresh();
+            let status = wait_for_status(&mut reader).unwrap();
+            let path = fresh_path.join("notes.txt");
+            assert_eq!(
+                status.state(path.to_str().unwrap()),
+                Some(GitState::Untracked)
+            );
+        }
+        std::fs::remove_dir_all(&root_path).unwrap();
+    }
+}
@@ -0,0 +1,117 @@
+/*
+    Copyright 2020, John Pothier
+    This file is part of Five.
+
+    Five is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    Five is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with Five.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use std::path::{Component, Path};
+
+// Glob patterns for files and folders that are left out of the index. A pattern that contains a
+// '/' is matched against the whole path relative to the root; any other pattern is matched
+// against each file or folder name along the path. '*' matches any run of characters other than
+// '/', '?' matches any single one.
+#[derive(Clone, Debug, Default)]
+pub struct IgnorePatterns {
+    patterns: Vec<String>,
+}
+
+fn glob_match(pattern: &[char], text: &[char]) -> bool {
+    match pattern.split_first() {
+        None => text.is_empty(),
+        Some(('*', rest)) => (0..=text.len())
+            .any(|skip| !text[..skip].contains(&'/') && glob_match(rest, &text[skip..])),
+        Some(('?', rest)) => match text.split_first() {
+            Some((c, text_rest)) => *c != '/' && glob_match(rest, text_rest),
+            None => false,
+        },
+        Some((c, rest)) => match text.split_first() {
+            Some((t, text_rest)) => c == t && glob_match(rest, text_rest),
+            None => false,
+        },
+    }
+}
+
+impl IgnorePatterns {
+    pub fn new(patterns: Vec<String>) -> IgnorePatterns {
+        IgnorePatterns {
+            patterns: patterns
+                .into_iter()
+                .map(|pattern| pattern.trim_matches('/').to_string())
+                .filter(|pattern| !pattern.is_empty())
+                .collect(),
+        }
+    }
+
+    // The path is relative to the root of the tree. Everything inside an ignored folder is ignored.
+    pub fn is_ignored(&self, relative_path: &Path) -> bool {
+        if self.patterns.is_empty() {
+            return false;
+        }
+        let names: Vec<Vec<char>> = relative_path
+            .components()
+            .filter_map(|component| match component {
+                Component::Normal(name) => Some(name.to_string_lossy().chars().collect()),
+                _ => None,
+            })
+            .collect();
+        self.patterns.iter().any(|pattern| {
+            let pattern: Vec<char> = pattern.chars().collect();
+            if pattern.contains(&'/') {
+                // Any leading part of the path, so that the contents of a matching folder go too
+                (1..=names.len()).any(|length| {
+                    let prefix: Vec<char> = names[..length].join(&'/');
+                    glob_match(&pattern, &prefix)
+                })
+            } else {
+                names.iter().any(|name| glob_match(&pattern, name))
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn patterns(patterns: &[&str]) -> IgnorePatterns {
+        IgnorePatterns::new(patterns.iter().map(|pattern| pattern.to_string()).collect())
+    }
+
+    #[test]
+    fn match_names() {
+        let ignore = patterns(&["target", "*.o", ".git"]);
+        assert!(ignore.is_ignored(Path::new("target")));
+        assert!(ignore.is_ignored(Path::new("target/debug/five")));
+        assert!(ignore.is_ignored(Path::new("sub/target")));
+        assert!(ignore.is_ignored(Path::new("src/main.o")));
+        assert!(ignore.is_ignored(Path::new(".git/HEAD")));
+        assert!(!ignore.is_ignored(Path::new("targets")));
+        assert!(!ignore.is_ignored(Path::new("src/main.rs")));
+        assert!(!ignore.is_ignored(Path::new(".gitignore")));
+    }
+
+    #[test]
+    fn match_paths() {
+        let ignore = patterns(&["docs/build/", "src/*.tmp", "a?c/x"]);
+        assert!(ignore.is_ignored(Path::new("docs/build")));
+        assert!(ignore.is_ignored(Path::new("docs/build/index.html")));
+        assert!(!ignore.is_ignored(Path::new("other/docs/build")));
+        assert!(ignore.is_ignored(Path::new("src/a.tmp")));
+        assert!(!ignore.is_ignored(Path::new("src/sub/a.tmp")));
+        assert!(ignore.is_ignored(Path::new("abc/x")));
+        assert!(!ignore.is_ignored(Path::new("ab/c/x")));
+        assert!(!patterns(&[]).is_ignored(Path::new("anything")));
+    }
+}
@@ -16,18 +16,72 @@
     along with Five.  If not, see <https://www.gnu.org/licenses/>.
 */
 
-use std::error::Error;
-use std::path::PathBuf;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::time::SystemTime;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FileType {
+    Regular,
+    Symlink,
+    // Sockets, devices and the like
+    Other,
+}
+
+#[derive(Debug, Clone)]
+pub struct FileMetadata {
+    pub file_type: FileType,
+    pub size: u64,
+    pub modified: Option<SystemTime>,
+    // The Unix permission bits, like 0o644
+    pub permissions: Option<u32>,
+}
+
+impl FileMetadata {
+    // For files that we know nothing about yet.
+    pub fn unknown() -> FileMetadata {
+        FileMetadata {
+            file_type: FileType::Regular,
+            size: 0,
+            modified: None,
+            permissions: None,
+        }
+    }
+
+    pub fn is_executable(&self) -> bool {
+        self.permissions
+            .map(|permissions| permissions & 0o111 != 0)
+            .unwrap_or(false)
+    }
+}
+
+impl From<&std::fs::Metadata> for FileMetadata {
+    fn from(metadata: &std::fs::Metadata) -> Self {
+        let file_type = if metadata.file_type().is_symlink() {
+            FileType::Symlink
+        } else if metadata.is_file() {
+            FileType::Regular
+        } else {
+            FileType::Other
+        };
+        FileMetadata {
+            file_type,
+            size: metadata.len(),
+            modified: metadata.modified().ok(),
+            permissions: Some(metadata.permissions().mode() & 0o7777),
+        }
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct FileIndexEntry {
     pub path: String,
     pub file_name: String,
-    pub normalized_filename: String,
+    pub metadata: FileMetadata,
 }
 
 impl FileIndexEntry {
-    pub fn new(path: &PathBuf) -> Option<FileIndexEntry> {
+    pub fn new(path: &Path, metadata: FileMetadata) -> Option<FileIndexEntry> {
         let path_string = if let Some(path) = path.to_str() {
             String::from(path)
         } else {
@@ -42,11 +96,10 @@ impl FileIndexEntry {
         } else {
             return None;
         };
-        let normalized_filename = file_name.to_lowercase();
         Some(FileIndexEntry {
             path: path_string,
             file_name,
-            normalized_filename,
+            metadata,
         })
     }
 }
@@ -65,6 +118,100 @@ pub enum FileTreeNode {
 }
 
 impl FileTreeNode {
+    pub fn path(&self) -> &str {
+        match self {
+            FileTreeNode::File(file_index_entry) => &file_index_entry.path,
+            FileTreeNode::Folder(file_tree_folder) => &file_tree_folder.path,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            FileTreeNode::File(file_index_entry) => &file_index_entry.file_name,
+            FileTreeNode::Folder(file_tree_folder) => &file_tree_folder.folder_name,
+        }
+    }
+
+    // Returns the nodes from this one down to the node with the given path, or None if there is no such
+    // node.
+    pub fn path_to(&self, path: &str) -> Option<Vec<&FileTreeNode>> {
+        if self.path() == path {
+            return Some(vec![self]);
+        }
+        match self {
+            FileTreeNode::File(_) => None,
+            FileTreeNode::Folder(file_tree_folder) => {
+                let mut nodes = file_tree_folder
+                    .children
+                    .iter()
+                    .filter(|child| match child {
+                        FileTreeNode::File(_) => child.path() == path,
+                        FileTreeNode::Folder(_) => Path::new(path).starts_with(child.path()),
+                    })
+                    .find_map(|child| child.path_to(path))?;
+                nodes.insert(0, self);
+                Some(nodes)
+            }
+        }
+    }
+
+    // A copy of this node and everything below it, as if it lived at the given path.
+    pub fn moved_to(&self, path: &str) -> Option<FileTreeNode> {
+        match self {
+            FileTreeNode::File(file_index_entry) => Some(FileTreeNode::File(FileIndexEntry::new(
+                Path::new(path),
+                file_index_entry.metadata.clone(),
+            )?)),
+            FileTreeNode::Folder(file_tree_folder) => {
+                let children = file_tree_folder
+                    .children
+                    .iter()
+                    .map(|child| child.moved_to(Path::new(path).join(child.name()).to_str()?))
+                    .collect::<Option<Vec<FileTreeNode>>>()?;
+                Some(FileTreeNode::Folder(FileTreeFolder {
+                    children,
+                    folder_name: String::from(Path::new(path).file_name()?.to_str()?),
+                    path: String::from(path),
+                }))
+            }
+        }
+    }
+
+    fn folder_mut(&mut self, path: &str) -> Option<&mut FileTreeFolder> {
+        match self {
+            FileTreeNode::File(_) => None,
+            FileTreeNode::Folder(file_tree_folder) => {
+                if file_tree_folder.path == path {
+                    return Some(file_tree_folder);
+                }
+                file_tree_folder
+                    .children
+                    .iter_mut()
+                    .filter(|child| Path::new(path).starts_with(child.path()))
+                    .find_map(|child| child.folder_mut(path))
+            }
+        }
+    }
+
+    // The paths of the folders below this one.
+    fn all_folders(&self) -> Vec<String> {
+        match self {
+            FileTreeNode::File(_) => vec![],
+            FileTreeNode::Folder(file_tree_folder) => file_tree_folder
+                .children
+                .iter()
+                .flat_map(|child| match child {
+                    FileTreeNode::File(_) => vec![],
+                    FileTreeNode::Folder(folder) => {
+                        let mut folders = vec![folder.path.clone()];
+                        folders.extend(child.all_folders());
+                        folders
+                    }
+                })
+                .collect(),
+        }
+    }
+
     fn all_files(&self) -> Vec<FileIndexEntry> {
         match self {
             FileTreeNode::File(file_index_entry) => vec![file_index_entry.clone()],
@@ -81,16 +228,72 @@ impl FileTreeNode {
 pub struct Index {
     pub tree: FileTreeNode,
     pub files: Vec<FileIndexEntry>,
+    // The paths of all folders except the root
+    pub folders: Vec<String>,
 }
 
 impl Index {
     pub fn new(root_node: FileTreeNode) -> Index {
         let files = root_node.all_files();
+        let folders = root_node.all_folders();
         Index {
             tree: root_node,
-            files: files,
+            files,
+            folders,
+        }
+    }
+
+    // The path relative to the root, without a leading separator.
+    pub fn relative_path<'a>(&self, path: &'a str) -> &'a str {
+        match Path::new(path).strip_prefix(self.tree.path()) {
+            Ok(relative_path) => relative_path.to_str().unwrap_or(path),
+            Err(_) => path,
         }
     }
+
+    pub fn contains(&self, path: &str) -> bool {
+        self.tree.path_to(path).is_some()
+    }
+
+    pub fn is_folder(&self, path: &str) -> bool {
+        matches!(
+            self.tree.path_to(path).and_then(|mut nodes| nodes.pop()),
+            Some(FileTreeNode::Folder(_))
+        )
+    }
+
+    // Adds the node to the folder it belongs in, which must already be in the index.
+    pub fn add_node(&mut self, node: FileTreeNode) -> bool {
+        let parent_path = match Path::new(node.path())
+            .parent()
+            .and_then(|parent| parent.to_str())
+        {
+            None => return false,
+            Some(parent_path) => String::from(parent_path),
+        };
+        match self.tree.folder_mut(&parent_path) {
+            None => false,
+            Some(parent) => {
+                parent.children.push(node);
+                self.files = self.tree.all_files();
+                self.folders = self.tree.all_folders();
+                true
+            }
+        }
+    }
+
+    pub fn remove_node(&mut self, path: &str) -> Option<FileTreeNode> {
+        let parent_path = Path::new(path).parent()?.to_str()?;
+        let parent = self.tree.folder_mut(parent_path)?;
+        let position = parent
+            .children
+            .iter()
+            .position(|child| child.path() == path)?;
+        let node = parent.children.remove(position);
+        self.files = self.tree.all_files();
+        self.folders = self.tree.all_folders();
+        Some(node)
+    }
 }
 
 #[derive(Debug)]
@@ -114,10 +317,12 @@ impl std::fmt::Display for IndexError {
 
 impl From<std::io::Error> for IndexError {
     fn from(error: std::io::Error) -> Self {
-        IndexError::new(error.description())
+        IndexError::new(&error.to_string())
     }
 }
 
 pub trait Indexer {
     fn get_index(&self) -> Option<Index>;
+    // Replaces the index, for changes that Five made itself and already knows about.
+    fn set_index(&self, index: Index);
 }
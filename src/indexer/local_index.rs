@@ -1,6 +1,6 @@
+use crate::indexer::ignore::IgnorePatterns;
 use crate::indexer::index::*;
 use std::fs::read_dir;
-use std::mem;
 use std::ops::{Deref, DerefMut};
 use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
@@ -8,40 +8,38 @@ use std::thread;
 
 struct BackgroundThreadState {
     cwd: PathBuf,
+    ignore: IgnorePatterns,
     index: Arc<Mutex<Option<Index>>>,
 }
 
-fn get_node_for_dir(dir: &Path) -> Result<FileTreeNode, IndexError> {
+// Ignored folders are skipped entirely rather than filtered out afterwards, so that huge build
+// output folders don't slow down indexing.
+fn get_node_for_dir(
+    dir: &Path,
+    root: &Path,
+    ignore: &IgnorePatterns,
+) -> Result<FileTreeNode, IndexError> {
     let mut children: Vec<FileTreeNode> = Vec::new();
     for entry in read_dir(dir)? {
         let entry = entry?;
         let metadata = entry.metadata()?;
         let path = entry.path();
+        if ignore.is_ignored(path.strip_prefix(root).unwrap_or(&path)) {
+            continue;
+        }
         if metadata.is_dir() {
-            children.push(get_node_for_dir(path.as_path())?);
+            children.push(get_node_for_dir(path.as_path(), root, ignore)?);
             continue;
         }
 
-        let file_name = match path.file_name() {
-            Some(file_name) => match file_name.to_str() {
-                Some(file_name) => Some(String::from(file_name)),
-                None => None,
-            },
-            None => None,
-        };
-        if file_name.is_none() {
+        let file_index_entry = FileIndexEntry::new(&path, FileMetadata::from(&metadata));
+        if file_index_entry.is_none() {
             return Err(IndexError::new("Could not get file name"));
         }
-        let normalized_filename = file_name.as_ref().unwrap().to_lowercase();
-        let file_index_entry = FileIndexEntry {
-            path: String::from(path.to_str().unwrap()),
-            file_name: file_name.unwrap(),
-            normalized_filename: normalized_filename,
-        };
-        children.push(FileTreeNode::File(file_index_entry));
+        children.push(FileTreeNode::File(file_index_entry.unwrap()));
     }
     Ok(FileTreeNode::Folder(FileTreeFolder {
-        children: children,
+        children,
         folder_name: String::from(dir.file_name().unwrap().to_str().unwrap()),
         path: String::from(dir.to_str().unwrap()),
     }))
@@ -50,11 +48,13 @@ fn get_node_for_dir(dir: &Path) -> Result<FileTreeNode, IndexError> {
 impl BackgroundThreadState {
     fn run(&mut self) {
         let initial_dir = std::fs::canonicalize(&self.cwd).unwrap();
-        let root_node = get_node_for_dir(initial_dir.as_path()).expect("Could not index!");
+        let root_node =
+            get_node_for_dir(initial_dir.as_path(), initial_dir.as_path(), &self.ignore)
+                .expect("Could not index!");
         match self.index.lock() {
             Err(_) => {}
             Ok(mut index) => {
-                mem::replace(index.deref_mut(), Some(Index::new(root_node)));
+                index.deref_mut().replace(Index::new(root_node));
             }
         }
     }
@@ -68,15 +68,16 @@ pub struct LocalIndexer {
 }
 
 impl LocalIndexer {
-    pub fn new(cwd: PathBuf) -> LocalIndexer {
+    pub fn new(cwd: PathBuf, ignore: IgnorePatterns) -> LocalIndexer {
         let index = Arc::new(Mutex::new(None));
         let mut background_thread_state = BackgroundThreadState {
-            cwd: cwd,
+            cwd,
+            ignore,
             index: Arc::clone(&index),
         };
         LocalIndexer {
             thread: Some(thread::spawn(move || background_thread_state.run())),
-            index: index,
+            index,
         }
     }
 }
@@ -98,4 +99,10 @@ impl Indexer for LocalIndexer {
             Ok(index) => index.deref().clone(),
         }
     }
+
+    fn set_index(&self, index: Index) {
+        if let Ok(mut current_index) = self.index.lock() {
+            current_index.deref_mut().replace(index);
+        }
+    }
 }
@@ -1,3 +1,5 @@
+pub mod ignore;
 pub mod index;
 pub mod local_index;
 pub mod ssh_index;
+pub mod symbols;
@@ -1,10 +1,12 @@
+use crate::file_system::shell_quote;
+use crate::indexer::ignore::IgnorePatterns;
 use crate::indexer::index::*;
-use std::mem;
 use std::ops::{Deref, DerefMut};
 use std::path::PathBuf;
 use std::process::Command;
 use std::sync::{Arc, Mutex};
 use std::thread;
+use std::time::{Duration, UNIX_EPOCH};
 
 pub struct SshConfig {
     ssh_args: Vec<String>,
@@ -32,42 +34,75 @@ impl SshConfig {
             directory_path,
         }
     }
+
+    pub fn ssh_args(&self) -> &[String] {
+        &self.ssh_args
+    }
+
+    // Identifies the remote directory, e.g. for remembering its session.
+    pub fn target(&self) -> String {
+        format!(
+            "ssh {}:{}",
+            self.ssh_args.join(" "),
+            self.directory_path.display()
+        )
+    }
 }
 
 #[derive(Debug)]
 enum FindOutput {
-    File(PathBuf),
+    File(PathBuf, FileMetadata),
     Folder(PathBuf),
 }
 
-fn parse_find_line(line: &str) -> Option<FindOutput> {
-    // Example:
-    //    658744      4 -rw-r--r--   1 root     root          148 Aug 17  2015 ./.profile
-    const FILE_ATTRIBUTES_COLUMN_INDEX: usize = 2;
+// The fields of FIND_FORMAT: the type, the permission bits in octal, the size, the modification time in
+// seconds since the epoch and the path, which comes last because it may contain spaces.
+const FIND_FORMAT: &str = "%y %m %s %T@ %p\\n";
+// -printf only exists in GNU find. Elsewhere (e.g. on macOS or the BSDs), stat prints the same fields,
+// except for the type, which is the first character of the symbolic permissions instead.
+const STAT_FORMAT: &str = "%Sp %Lp %z %m %N";
 
-    let mut columns = line.split_ascii_whitespace();
-
-    let file_attributes_column = columns.nth(FILE_ATTRIBUTES_COLUMN_INDEX);
-    if file_attributes_column.is_none() {
-        return None;
-    }
-
-    let path_column = columns.last();
-    if path_column.is_none() {
-        return None;
-    }
+// Lists everything under the root with FIND_FORMAT, or STAT_FORMAT if find is not GNU find.
+fn find_command(root_path: &str) -> String {
+    format!(
+        "if find {0} -maxdepth 0 -printf '' >/dev/null 2>&1; then find {0} -printf {1}; else find {0} -exec stat -f {2} {{}} +; fi",
+        shell_quote(root_path),
+        shell_quote(FIND_FORMAT),
+        shell_quote(STAT_FORMAT)
+    )
+}
 
-    let path_string = path_column.unwrap();
-    let path = PathBuf::from(path_string);
+fn parse_find_line(line: &str) -> Option<FindOutput> {
+    // Examples, from FIND_FORMAT and STAT_FORMAT:
+    //    f 644 148 1439812800.0000000000 ./.profile
+    //    -rw-r--r-- 644 148 1439812800 ./.profile
+    let mut columns = line.splitn(5, ' ');
+    let file_type = columns.next()?;
+    let permissions = u32::from_str_radix(columns.next()?, 8).ok();
+    let size = columns.next()?.parse::<u64>().ok()?;
+    let modified = columns
+        .next()?
+        .parse::<f64>()
+        .ok()
+        .filter(|seconds| *seconds >= 0.0)
+        .map(|seconds| UNIX_EPOCH + Duration::from_secs_f64(seconds));
+    let path = PathBuf::from(columns.next()?);
 
-    match file_attributes_column.unwrap().chars().nth(0) {
-        None => return None,
-        Some(c) => match c {
-            'd' => Some(FindOutput::Folder(path)),
-            '-' => Some(FindOutput::File(path)),
-            _ => None,
+    let file_type = match file_type.chars().next()? {
+        'd' => return Some(FindOutput::Folder(path)),
+        'f' | '-' => FileType::Regular,
+        'l' => FileType::Symlink,
+        _ => FileType::Other,
+    };
+    Some(FindOutput::File(
+        path,
+        FileMetadata {
+            file_type,
+            size,
+            modified,
+            permissions,
         },
-    }
+    ))
 }
 
 // Assumption: When we encounter a directory in the FindOutput list, all following entries are the
@@ -75,7 +110,7 @@ fn parse_find_line(line: &str) -> Option<FindOutput> {
 fn get_file_tree_node(
     find_output: &[FindOutput],
 ) -> Result<(&[FindOutput], FileTreeNode), IndexError> {
-    let current_item = find_output.get(0);
+    let current_item = find_output.first();
     if current_item.is_none() {
         return Err(IndexError::new("No directory found"));
     }
@@ -104,7 +139,7 @@ fn get_file_tree_node(
             let should_continue = |slice: &[FindOutput]| match slice.first() {
                 None => false,
                 Some(find_output) => match find_output {
-                    FindOutput::File(_) => true,
+                    FindOutput::File(_, _) => true,
                     FindOutput::Folder(path) => path.starts_with(folder_path),
                 },
             };
@@ -122,43 +157,52 @@ fn get_file_tree_node(
             });
             Ok((next_slice, node))
         }
-        FindOutput::File(path) => match FileIndexEntry::new(path) {
+        FindOutput::File(path, metadata) => match FileIndexEntry::new(path, metadata.clone()) {
             Some(file_index_entry) => Ok((&find_output[1..], FileTreeNode::File(file_index_entry))),
             None => Err(IndexError::new("Could not create FileIndexEntry")),
         },
     }
 }
 
-fn retrieve_index(config: &SshConfig) -> Result<Index, IndexError> {
+fn retrieve_index(config: &SshConfig, ignore: &IgnorePatterns) -> Result<Index, IndexError> {
     let mut args = config.ssh_args.clone();
-    args.push(format!(
-        "find {} -ls",
-        config.directory_path.to_str().unwrap()
-    ));
+    args.push(find_command(config.directory_path.to_str().unwrap()));
     let output = Command::new("ssh").args(&args).output();
     let output_string = String::from_utf8(output.unwrap().stdout).unwrap();
-    let find_output: Vec<FindOutput> = output_string.lines().filter_map(parse_find_line).collect();
+    let find_output: Vec<FindOutput> = output_string
+        .lines()
+        .filter_map(parse_find_line)
+        .filter(|output| {
+            let path = match output {
+                FindOutput::File(path, _) | FindOutput::Folder(path) => path,
+            };
+            !ignore.is_ignored(path.strip_prefix(&config.directory_path).unwrap_or(path))
+        })
+        .collect();
 
     Ok(Index::new(get_file_tree_node(&find_output)?.1))
 }
 
 struct BackgroundThreadState {
     config: SshConfig,
+    ignore: IgnorePatterns,
     index: Arc<Mutex<Option<Index>>>,
 }
 
 impl BackgroundThreadState {
     fn run(&mut self) {
-        let retrieved_index = retrieve_index(&self.config);
-        if retrieved_index.is_err() {
-            println!("Could not retrieve index: {}", retrieved_index.unwrap_err());
-            return;
-        }
+        let retrieved_index = match retrieve_index(&self.config, &self.ignore) {
+            Err(err) => {
+                println!("Could not retrieve index: {}", err);
+                return;
+            }
+            Ok(retrieved_index) => retrieved_index,
+        };
 
         match self.index.lock() {
             Err(_) => {}
             Ok(mut index) => {
-                mem::replace(index.deref_mut(), Some(retrieved_index.unwrap()));
+                index.deref_mut().replace(retrieved_index);
             }
         }
     }
@@ -170,15 +214,16 @@ pub struct SshIndexer {
 }
 
 impl SshIndexer {
-    pub fn new(config: SshConfig) -> SshIndexer {
+    pub fn new(config: SshConfig, ignore: IgnorePatterns) -> SshIndexer {
         let index = Arc::new(Mutex::new(None));
         let mut background_thread_state = BackgroundThreadState {
-            config: config,
+            config,
+            ignore,
             index: Arc::clone(&index),
         };
         SshIndexer {
             thread: Some(thread::spawn(move || background_thread_state.run())),
-            index: index,
+            index,
         }
     }
 }
@@ -200,4 +245,91 @@ impl Indexer for SshIndexer {
             Ok(index) => index.deref().clone(),
         }
     }
+
+    fn set_index(&self, index: Index) {
+        if let Ok(mut current_index) = self.index.lock() {
+            current_index.deref_mut().replace(index);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::indexer::ssh_index::*;
+
+    #[test]
+    fn parse_find_output() {
+        match parse_find_line("f 755 148 1439812800.5000000000 /home/me/my script.sh") {
+            Some(FindOutput::File(path, metadata)) => {
+                assert_eq!(path, PathBuf::from("/home/me/my script.sh"));
+                assert_eq!(metadata.file_type, FileType::Regular);
+                assert_eq!(metadata.size, 148);
+                assert_eq!(metadata.permissions, Some(0o755));
+                assert_eq!(
+                    metadata.modified,
+                    Some(UNIX_EPOCH + Duration::from_millis(1_439_812_800_500))
+                );
+            }
+            output => panic!("Unexpected output {:?}", output),
+        }
+        assert!(matches!(
+            parse_find_line("d 755 4096 1439812800.0000000000 /home/me"),
+            Some(FindOutput::Folder(_))
+        ));
+        assert!(parse_find_line("find: '/root': Permission denied").is_none());
+    }
+
+    #[test]
+    fn parse_stat_output() {
+        match parse_find_line("-rwxr-xr-x 755 148 1439812800 /home/me/my script.sh") {
+            Some(FindOutput::File(path, metadata)) => {
+                assert_eq!(path, PathBuf::from("/home/me/my script.sh"));
+                assert_eq!(metadata.file_type, FileType::Regular);
+                assert_eq!(metadata.size, 148);
+                assert_eq!(metadata.permissions, Some(0o755));
+                assert_eq!(
+                    metadata.modified,
+                    Some(UNIX_EPOCH + Duration::from_secs(1_439_812_800))
+                );
+            }
+            output => panic!("Unexpected output {:?}", output),
+        }
+        assert!(matches!(
+            parse_find_line("drwxr-xr-x 755 4096 1439812800 /home/me"),
+            Some(FindOutput::Folder(_))
+        ));
+        assert!(matches!(
+            parse_find_line("lrwxr-xr-x 755 7 1439812800 /home/me/link"),
+            Some(FindOutput::File(
+                _,
+                FileMetadata {
+                    file_type: FileType::Symlink,
+                    ..
+                }
+            ))
+        ));
+    }
+
+    #[test]
+    fn quote_find_command() {
+        // Run by the local shell, like the remote one would
+        let root = std::env::temp_dir().join(format!("five-{}-ssh-index it's", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(root.join("$HOME"), "").unwrap();
+        let output = Command::new("sh")
+            .arg("-c")
+            .arg(find_command(root.to_str().unwrap()))
+            .output()
+            .unwrap();
+        let paths: Vec<PathBuf> = String::from_utf8(output.stdout)
+            .unwrap()
+            .lines()
+            .filter_map(parse_find_line)
+            .map(|output| match output {
+                FindOutput::File(path, _) | FindOutput::Folder(path) => path,
+            })
+            .collect();
+        assert_eq!(paths, vec![root.clone(), root.join("$HOME")]);
+        std::fs::remove_dir_all(&root).unwrap();
+    }
 }
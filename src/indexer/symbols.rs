@@ -0,0 +1,487 @@
+/*
+    Copyright 2020, John Pothier
+    This file is part of Five.
+
+    Five is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    Five is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with Five.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use crate::language::Language;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+// Definitions found by looking at the start of each line, which is much cheaper than parsing and
+// good enough to jump around a project. Files in languages that have no rules here have no symbols.
+
+// Larger files are most likely generated, and not worth the time
+const MAX_FILE_SIZE: u64 = 1024 * 1024;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SymbolKind {
+    Function,
+    Struct,
+    Enum,
+    Trait,
+    Impl,
+    Module,
+    Type,
+    Constant,
+    Macro,
+    Class,
+    Interface,
+    Heading,
+}
+
+impl SymbolKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            SymbolKind::Function => "fn",
+            SymbolKind::Struct => "struct",
+            SymbolKind::Enum => "enum",
+            SymbolKind::Trait => "trait",
+            SymbolKind::Impl => "impl",
+            SymbolKind::Module => "mod",
+            SymbolKind::Type => "type",
+            SymbolKind::Constant => "const",
+            SymbolKind::Macro => "macro",
+            SymbolKind::Class => "class",
+            SymbolKind::Interface => "interface",
+            SymbolKind::Heading => "heading",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Symbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    pub path: String,
+    // Zero-based
+    pub line: usize,
+}
+
+// Strips any of the words from the start of the text, as many times as they appear.
+fn strip_modifiers<'a>(mut text: &'a str, modifiers: &[&str]) -> &'a str {
+    loop {
+        let stripped = modifiers.iter().find_map(|modifier| {
+            let rest = text.strip_prefix(modifier)?;
+            // "pub(crate)" and the like are followed by their restriction instead of a space
+            if *modifier == "pub" && rest.starts_with('(') {
+                return Some(rest[rest.find(')')? + 1..].trim_start());
+            }
+            if rest.starts_with(char::is_whitespace) {
+                Some(rest.trim_start())
+            } else {
+                None
+            }
+        });
+        match stripped {
+            Some(rest) => text = rest,
+            None => return text,
+        }
+    }
+}
+
+fn identifier(text: &str) -> Option<&str> {
+    let end = text
+        .find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '$'))
+        .unwrap_or(text.len());
+    let identifier = &text[..end];
+    match identifier.chars().next() {
+        Some(c) if !c.is_ascii_digit() => Some(identifier),
+        _ => None,
+    }
+}
+
+// The identifier after the keyword, if the text starts with the keyword and whitespace.
+fn after_keyword<'a>(text: &'a str, keyword: &str) -> Option<&'a str> {
+    let rest = text.strip_prefix(keyword)?;
+    if !rest.starts_with(char::is_whitespace) {
+        return None;
+    }
+    identifier(rest.trim_start())
+}
+
+fn find_keyword<'a>(
+    text: &'a str,
+    keywords: &[(&str, SymbolKind)],
+) -> Option<(&'a str, SymbolKind)> {
+    keywords
+        .iter()
+        .find_map(|(keyword, kind)| Some((after_keyword(text, keyword)?, *kind)))
+}
+
+// "impl<T: Clone> Display for Wrapper<T> {" is named "Display for Wrapper<T>".
+fn rust_impl_name(text: &str) -> Option<String> {
+    let mut rest = text.strip_prefix("impl")?;
+    if rest.starts_with('<') {
+        let mut depth = 0;
+        let end = rest.find(|c| {
+            match c {
+                '<' => depth += 1,
+                '>' => depth -= 1,
+                _ => {}
+            }
+            depth == 0
+        })?;
+        rest = &rest[end + 1..];
+    } else if !rest.starts_with(char::is_whitespace) {
+        return None;
+    }
+    let end = [rest.find('{'), rest.find(" where")]
+        .iter()
+        .flatten()
+        .copied()
+        .min()
+        .unwrap_or(rest.len());
+    let name = rest[..end]
+        .split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ");
+    if name.is_empty() {
+        None
+    } else {
+        Some(name)
+    }
+}
+
+fn rust_symbol(line: &str) -> Option<(String, SymbolKind)> {
+    let text = strip_modifiers(
+        line,
+        &["pub", "async", "unsafe", "default", "extern", "\"C\""],
+    );
+    if let Some(name) = rust_impl_name(text) {
+        return Some((name, SymbolKind::Impl));
+    }
+    // "const fn" is a function, "const NAME" is a constant
+    let text = match text.strip_prefix("const") {
+        Some(rest) if rest.trim_start().starts_with("fn ") => rest.trim_start(),
+        _ => text,
+    };
+    let text = strip_modifiers(text, &["async", "unsafe", "extern", "\"C\""]);
+    let (name, kind) = find_keyword(
+        text,
+        &[
+            ("fn", SymbolKind::Function),
+            ("struct", SymbolKind::Struct),
+            ("enum", SymbolKind::Enum),
+            ("union", SymbolKind::Struct),
+            ("trait", SymbolKind::Trait),
+            ("mod", SymbolKind::Module),
+            ("type", SymbolKind::Type),
+            ("const", SymbolKind::Constant),
+            ("static mut", SymbolKind::Constant),
+            ("static", SymbolKind::Constant),
+            ("macro_rules!", SymbolKind::Macro),
+        ],
+    )?;
+    if name == "_" {
+        return None;
+    }
+    Some((String::from(name), kind))
+}
+
+fn python_symbol(line: &str) -> Option<(String, SymbolKind)> {
+    let text = strip_modifiers(line, &["async"]);
+    let (name, kind) = find_keyword(
+        text,
+        &[("def", SymbolKind::Function), ("class", SymbolKind::Class)],
+    )?;
+    Some((String::from(name), kind))
+}
+
+fn javascript_symbol(line: &str) -> Option<(String, SymbolKind)> {
+    let text = strip_modifiers(line, &["export", "default", "declare", "abstract", "async"]);
+    if let Some((name, kind)) = find_keyword(
+        text,
+        &[
+            ("function", SymbolKind::Function),
+            ("function*", SymbolKind::Function),
+            ("class", SymbolKind::Class),
+            ("interface", SymbolKind::Interface),
+            ("type", SymbolKind::Type),
+            ("enum", SymbolKind::Enum),
+            ("namespace", SymbolKind::Module),
+        ],
+    ) {
+        return Some((String::from(name), kind));
+    }
+    // Arrow functions assigned to a variable: "const add = (a, b) => a + b"
+    let (name, _) = find_keyword(
+        text,
+        &[
+            ("const", SymbolKind::Function),
+            ("let", SymbolKind::Function),
+            ("var", SymbolKind::Function),
+        ],
+    )?;
+    if text.contains("=>") || text.contains("= function") {
+        Some((String::from(name), SymbolKind::Function))
+    } else {
+        None
+    }
+}
+
+fn go_symbol(line: &str) -> Option<(String, SymbolKind)> {
+    if let Some(rest) = line.strip_prefix("func ") {
+        // Methods have their receiver before the name: "func (v *View) Paint()"
+        let rest = match rest.strip_prefix('(') {
+            Some(receiver) => receiver[receiver.find(')')? + 1..].trim_start(),
+            None => rest,
+        };
+        return Some((String::from(identifier(rest)?), SymbolKind::Function));
+    }
+    let name = after_keyword(line, "type")?;
+    let definition = line["type".len()..].trim_start()[name.len()..].trim_start();
+    let kind = if definition.starts_with("struct") {
+        SymbolKind::Struct
+    } else if definition.starts_with("interface") {
+        SymbolKind::Interface
+    } else {
+        SymbolKind::Type
+    };
+    Some((String::from(name), kind))
+}
+
+fn markdown_symbol(line: &str) -> Option<(String, SymbolKind)> {
+    let title = line.trim_start_matches('#');
+    if title.len() == line.len() || !title.starts_with(' ') {
+        return None;
+    }
+    let title = title.trim();
+    if title.is_empty() {
+        None
+    } else {
+        Some((String::from(title), SymbolKind::Heading))
+    }
+}
+
+pub fn has_symbols(language: Language) -> bool {
+    matches!(
+        language,
+        Language::Rust
+            | Language::Python
+            | Language::JavaScript
+            | Language::TypeScript
+            | Language::Go
+            | Language::Markdown
+    )
+}
+
+pub fn extract_symbols(path: &str, text: &str) -> Vec<Symbol> {
+    let language = Language::from_path(path);
+    let find_symbol: fn(&str) -> Option<(String, SymbolKind)> = match language {
+        Language::Rust => rust_symbol,
+        Language::Python => python_symbol,
+        Language::JavaScript | Language::TypeScript => javascript_symbol,
+        Language::Go => go_symbol,
+        Language::Markdown => markdown_symbol,
+        _ => return vec![],
+    };
+    // Markdown headings must start the line; everything else may be indented, like methods
+    let is_markdown = language == Language::Markdown;
+    text.lines()
+        .enumerate()
+        .filter_map(|(line_index, line)| {
+            let line = if is_markdown { line } else { line.trim_start() };
+            let (name, kind) = find_symbol(line)?;
+            Some(Symbol {
+                name,
+                kind,
+                path: String::from(path),
+                line: line_index,
+            })
+        })
+        .collect()
+}
+
+// Extracts the symbols of the files in the background, as the file index does.
+pub struct SymbolIndexer {
+    thread: Option<thread::JoinHandle<()>>,
+    // By path
+    symbols: Arc<Mutex<HashMap<String, Vec<Symbol>>>>,
+    // Tells the thread to give up, so that quitting doesn't wait for it
+    stop: Arc<AtomicBool>,
+}
+
+impl SymbolIndexer {
+    pub fn new(paths: Vec<String>) -> SymbolIndexer {
+        let paths: Vec<String> = paths
+            .into_iter()
+            .filter(|path| has_symbols(Language::from_path(path)))
+            .collect();
+        let symbols = Arc::new(Mutex::new(HashMap::new()));
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_symbols = Arc::clone(&symbols);
+        let thread_stop = Arc::clone(&stop);
+        let thread = thread::spawn(move || {
+            for path in paths {
+                if thread_stop.load(Ordering::Relaxed) {
+                    return;
+                }
+                let file_symbols = match std::fs::metadata(&path) {
+                    Ok(metadata) if metadata.len() <= MAX_FILE_SIZE => {
+                        match std::fs::read_to_string(&path) {
+                            Ok(text) => extract_symbols(&path, &text),
+                            Err(_) => vec![],
+                        }
+                    }
+                    _ => vec![],
+                };
+                if let Ok(mut symbols) = thread_symbols.lock() {
+                    // Files saved in the meantime are already up to date
+                    symbols.entry(path).or_insert(file_symbols);
+                }
+            }
+        });
+        SymbolIndexer {
+            thread: Some(thread),
+            symbols,
+            stop,
+        }
+    }
+
+    // All symbols found so far, ordered by file and line.
+    pub fn symbols(&self) -> Vec<Symbol> {
+        let symbols = match self.symbols.lock() {
+            Err(_) => return vec![],
+            Ok(symbols) => symbols,
+        };
+        let mut paths: Vec<&String> = symbols.keys().collect();
+        paths.sort();
+        paths
+            .into_iter()
+            .flat_map(|path| symbols[path].iter().cloned())
+            .collect()
+    }
+
+    // For files that were just saved.
+    pub fn update_file(&self, path: &str, text: &str) {
+        if let Ok(mut symbols) = self.symbols.lock() {
+            symbols.insert(String::from(path), extract_symbols(path, text));
+        }
+    }
+}
+
+impl Drop for SymbolIndexer {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        self.thread
+            .take()
+            .unwrap()
+            .join()
+            .expect("Could not join thread");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::indexer::symbols::*;
+
+    fn symbols(path: &str, text: &str) -> Vec<(String, &'static str, usize)> {
+        extract_symbols(path, text)
+            .into_iter()
+            .map(|symbol| (symbol.name, symbol.kind.name(), symbol.line))
+            .collect()
+    }
+
+    fn symbol(name: &str, kind: &'static str, line: usize) -> (String, &'static str, usize) {
+        (String::from(name), kind, line)
+    }
+
+    #[test]
+    fn extract_rust_symbols() {
+        let text = r#"
+pub(crate) mod theme;
+pub struct Theme {
+    styles: HashMap<StyleName, Style>,
+}
+impl<'a> Component for RootComponent<'a> {
+    pub const fn new() -> Theme {
+    async unsafe fn paint<Writer: Write>(&self) {}
+}
+impl Theme where T: Clone {
+const DEFAULT_THEME_NAME: &str = "default";
+pub static mut COUNT: usize = 0;
+macro_rules! try_paint {
+// fn commented_out() {}
+let function = 1;
+enum Color { Red }
+pub(in crate::components) trait Paint {}
+"#;
+        assert_eq!(
+            symbols("theme.rs", text),
+            vec![
+                symbol("theme", "mod", 1),
+                symbol("Theme", "struct", 2),
+                symbol("Component for RootComponent<'a>", "impl", 5),
+                symbol("new", "fn", 6),
+                symbol("paint", "fn", 7),
+                symbol("Theme", "impl", 9),
+                symbol("DEFAULT_THEME_NAME", "const", 10),
+                symbol("COUNT", "const", 11),
+                symbol("try_paint", "macro", 12),
+                symbol("Color", "enum", 15),
+                symbol("Paint", "trait", 16),
+            ]
+        );
+    }
+
+    #[test]
+    fn extract_other_symbols() {
+        assert_eq!(
+            symbols(
+                "app.py",
+                "class App:\n    async def run(self):\n    define = 1\n"
+            ),
+            vec![symbol("App", "class", 0), symbol("run", "fn", 1)]
+        );
+        assert_eq!(
+            symbols(
+                "app.ts",
+                "export default class App {}\nexport interface Props {}\nconst add = (a, b) => a + b;\nconst x = 1;\nasync function* lines() {}\n"
+            ),
+            vec![
+                symbol("App", "class", 0),
+                symbol("Props", "interface", 1),
+                symbol("add", "fn", 2),
+                symbol("lines", "fn", 4),
+            ]
+        );
+        assert_eq!(
+            symbols(
+                "view.go",
+                "type View struct {\nfunc (v *View) Paint() {\nfunc main() {\ntype ID int\n"
+            ),
+            vec![
+                symbol("View", "struct", 0),
+                symbol("Paint", "fn", 1),
+                symbol("main", "fn", 2),
+                symbol("ID", "type", 3),
+            ]
+        );
+        assert_eq!(
+            symbols(
+                "README.md",
+                "# Five\ntext\n## Quick open\n#hashtag\n    # code\n"
+            ),
+            vec![
+                symbol("Five", "heading", 0),
+                symbol("Quick open", "heading", 2)
+            ]
+        );
+        assert!(symbols("notes.txt", "fn main() {}").is_empty());
+    }
+}
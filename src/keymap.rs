@@ -0,0 +1,431 @@
+/*
+    Copyright 2020, John Pothier
+    This file is part of Five.
+
+    Five is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    Five is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with Five.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use crate::commands::Command;
+use crate::settings::config_dir;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use termion::event::Key;
+
+// Where a key was pressed. Bindings in the focused context take precedence over global ones.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum KeyContext {
+    Global,
+    Editor,
+    Tree,
+    QuickOpen,
+}
+
+const KEY_CONTEXTS: &[(KeyContext, &str)] = &[
+    (KeyContext::Global, "global"),
+    (KeyContext::Editor, "editor"),
+    (KeyContext::Tree, "tree"),
+    (KeyContext::QuickOpen, "quick_open"),
+];
+
+// Binding a chord to this removes the default binding
+const UNBOUND_COMMAND_NAME: &str = "none";
+
+#[derive(Debug, PartialEq)]
+pub enum KeymapMatch {
+    Command(Command),
+    // The keys are the start of a longer chord, so wait for the next key
+    Prefix,
+    None,
+}
+
+pub struct Keymap {
+    bindings: HashMap<KeyContext, HashMap<Vec<Key>, Command>>,
+}
+
+const DEFAULT_BINDINGS: &[(KeyContext, Key, Command)] = &[
+    (KeyContext::Global, Key::F(1), Command::Palette),
+    (KeyContext::Global, Key::Ctrl('p'), Command::QuickOpen),
+    (KeyContext::Global, Key::Ctrl('g'), Command::GoToLine),
+    (KeyContext::Global, Key::Ctrl('r'), Command::GoToSymbol),
+    (KeyContext::Global, Key::Ctrl('t'), Command::SearchSymbols),
+    (KeyContext::Global, Key::F(5), Command::Build),
+    (KeyContext::Global, Key::F(6), Command::ShowProblems),
+    (KeyContext::Editor, Key::Up, Command::CursorUp),
+    (KeyContext::Editor, Key::Down, Command::CursorDown),
+    (KeyContext::Editor, Key::Left, Command::CursorLeft),
+    (KeyContext::Editor, Key::Right, Command::CursorRight),
+    (KeyContext::Editor, Key::PageUp, Command::PageUp),
+    (KeyContext::Editor, Key::PageDown, Command::PageDown),
+    (KeyContext::Editor, Key::Home, Command::LineStart),
+    (KeyContext::Editor, Key::End, Command::LineEnd),
+    (KeyContext::Editor, Key::Backspace, Command::DeleteBackward),
+    (KeyContext::Editor, Key::Esc, Command::LeaveEditor),
+    (KeyContext::Editor, Key::Ctrl('s'), Command::Save),
+    (
+        KeyContext::Editor,
+        Key::Ctrl('l'),
+        Command::ToggleLineNumbers,
+    ),
+    (KeyContext::Editor, Key::Alt('z'), Command::ToggleWrap),
+    (KeyContext::Editor, Key::Alt('e'), Command::NextEncoding),
+    (KeyContext::Editor, Key::Alt('l'), Command::ToggleLineEnding),
+    (KeyContext::Editor, Key::Alt('h'), Command::Hover),
+    (KeyContext::Editor, Key::F(12), Command::GoToDefinition),
+    (KeyContext::Editor, Key::Alt('r'), Command::FindReferences),
+    (KeyContext::Editor, Key::Null, Command::Complete),
+    (KeyContext::Editor, Key::F(8), Command::NextDiagnostic),
+    (KeyContext::Editor, Key::F(7), Command::PreviousDiagnostic),
+    (KeyContext::Tree, Key::Up, Command::CursorUp),
+    (KeyContext::Tree, Key::Down, Command::CursorDown),
+    (KeyContext::Tree, Key::PageUp, Command::PageUp),
+    (KeyContext::Tree, Key::PageDown, Command::PageDown),
+    (KeyContext::Tree, Key::Home, Command::GoToStart),
+    (KeyContext::Tree, Key::End, Command::GoToEnd),
+    (KeyContext::Tree, Key::Right, Command::Expand),
+    (KeyContext::Tree, Key::Left, Command::Collapse),
+    (KeyContext::Tree, Key::Char('\n'), Command::Open),
+    (KeyContext::Tree, Key::Backspace, Command::GoUp),
+    (KeyContext::Tree, Key::Esc, Command::ClearFilter),
+    (KeyContext::Tree, Key::Alt('t'), Command::ToggleTreeMode),
+    (KeyContext::Tree, Key::Alt('s'), Command::NextSortOrder),
+    (KeyContext::Tree, Key::Alt('i'), Command::ToggleDetails),
+    (KeyContext::Tree, Key::Alt('c'), Command::ToggleChangedOnly),
+    (KeyContext::Tree, Key::Alt('n'), Command::NewFile),
+    (KeyContext::Tree, Key::Alt('N'), Command::NewFolder),
+    (KeyContext::Tree, Key::F(2), Command::Rename),
+    (KeyContext::Tree, Key::Alt('d'), Command::Duplicate),
+    (KeyContext::Tree, Key::Alt('m'), Command::Move),
+    (KeyContext::Tree, Key::Delete, Command::Delete),
+    (KeyContext::Tree, Key::Alt('u'), Command::UndoFileOperation),
+    (KeyContext::QuickOpen, Key::Up, Command::CursorUp),
+    (KeyContext::QuickOpen, Key::Down, Command::CursorDown),
+    (KeyContext::QuickOpen, Key::PageUp, Command::PageUp),
+    (KeyContext::QuickOpen, Key::PageDown, Command::PageDown),
+    (KeyContext::QuickOpen, Key::Home, Command::GoToStart),
+    (KeyContext::QuickOpen, Key::End, Command::GoToEnd),
+    (KeyContext::QuickOpen, Key::Char('\n'), Command::Open),
+    (KeyContext::QuickOpen, Key::Esc, Command::Close),
+    (KeyContext::QuickOpen, Key::Alt('p'), Command::PreviousQuery),
+    (KeyContext::QuickOpen, Key::Alt('n'), Command::NextQuery),
+];
+
+const NAMED_KEYS: &[(&str, Key)] = &[
+    ("enter", Key::Char('\n')),
+    ("tab", Key::Char('\t')),
+    ("space", Key::Char(' ')),
+    ("esc", Key::Esc),
+    ("backspace", Key::Backspace),
+    ("delete", Key::Delete),
+    ("insert", Key::Insert),
+    ("up", Key::Up),
+    ("down", Key::Down),
+    ("left", Key::Left),
+    ("right", Key::Right),
+    ("home", Key::Home),
+    ("end", Key::End),
+    ("pageup", Key::PageUp),
+    ("pagedown", Key::PageDown),
+    ("shift+tab", Key::BackTab),
+    // What terminals send for Ctrl+Space
+    ("ctrl+space", Key::Null),
+];
+
+// Parses a key such as "ctrl+s", "alt+shift+n", "f2" or "pagedown".
+pub fn parse_key(text: &str) -> Option<Key> {
+    let text = text.to_lowercase();
+    if let Some((_, key)) = NAMED_KEYS.iter().find(|(name, _)| *name == text) {
+        return Some(*key);
+    }
+    if let Some(number) = text.strip_prefix('f') {
+        if let Ok(number) = number.parse::<u8>() {
+            return if (1..=12).contains(&number) {
+                Some(Key::F(number))
+            } else {
+                None
+            };
+        }
+    }
+
+    let mut parts: Vec<&str> = text.split('+').collect();
+    let key_name = parts.pop()?;
+    let mut key = match NAMED_KEYS.iter().find(|(name, _)| *name == key_name) {
+        Some((_, Key::Char(c))) => *c,
+        Some(_) => return None,
+        None => {
+            let mut chars = key_name.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => c,
+                _ => return None,
+            }
+        }
+    };
+    let (mut ctrl, mut alt) = (false, false);
+    for modifier in parts {
+        match modifier {
+            "ctrl" => ctrl = true,
+            "alt" => alt = true,
+            "shift" => key = key.to_ascii_uppercase(),
+            _ => return None,
+        }
+    }
+    // The terminal can't tell Ctrl+Alt chords apart from other keys
+    match (ctrl, alt) {
+        (true, true) => None,
+        (true, false) => Some(Key::Ctrl(key.to_ascii_lowercase())),
+        (false, true) => Some(Key::Alt(key)),
+        (false, false) => Some(Key::Char(key)),
+    }
+}
+
+// Writes the key the way that parse_key reads it.
+pub fn format_key(key: Key) -> String {
+    if let Some((name, _)) = NAMED_KEYS.iter().find(|(_, named_key)| *named_key == key) {
+        return String::from(*name);
+    }
+    let char_name = |c: char| match NAMED_KEYS
+        .iter()
+        .find(|(_, named_key)| *named_key == Key::Char(c))
+    {
+        Some((name, _)) => String::from(*name),
+        None if c.is_ascii_uppercase() => format!("shift+{}", c.to_ascii_lowercase()),
+        None => c.to_string(),
+    };
+    match key {
+        Key::F(number) => format!("f{}", number),
+        Key::Ctrl(c) => format!("ctrl+{}", char_name(c)),
+        Key::Alt(c) => format!("alt+{}", char_name(c)),
+        Key::Char(c) => char_name(c),
+        _ => String::from("?"),
+    }
+}
+
+pub fn format_chord(keys: &[Key]) -> String {
+    let keys: Vec<String> = keys.iter().map(|key| format_key(*key)).collect();
+    keys.join(" ")
+}
+
+// Parses space-separated keys, such as "ctrl+x ctrl+s".
+pub fn parse_chord(text: &str) -> Option<Vec<Key>> {
+    let keys: Option<Vec<Key>> = text.split_whitespace().map(parse_key).collect();
+    keys.filter(|keys| !keys.is_empty())
+}
+
+pub fn default_keymap_path() -> Option<PathBuf> {
+    Some(config_dir()?.join("keymap.toml"))
+}
+
+impl Keymap {
+    pub fn new() -> Keymap {
+        let mut keymap = Keymap {
+            bindings: HashMap::new(),
+        };
+        for (context, key, command) in DEFAULT_BINDINGS {
+            keymap.bind(*context, vec![*key], Some(*command));
+        }
+        keymap
+    }
+
+    // Starts from the default bindings and applies the ones in the file, if it exists.
+    pub fn load(path: &Path) -> Result<Keymap, String> {
+        let mut keymap = Keymap::new();
+        let text = match std::fs::read_to_string(path) {
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(keymap),
+            Err(err) => return Err(format!("{}: {}", path.display(), err)),
+            Ok(text) => text,
+        };
+        keymap
+            .apply(&text)
+            .map_err(|err| format!("{}: {}", path.display(), err))?;
+        Ok(keymap)
+    }
+
+    // Applies bindings from a TOML document with one table per context, such as
+    //
+    //     [editor]
+    //     "ctrl+x ctrl+s" = "save"
+    //     "ctrl+s" = "none"
+    pub fn apply(&mut self, text: &str) -> Result<(), String> {
+        let table: toml::Table = text
+            .parse()
+            .map_err(|err: toml::de::Error| err.to_string())?;
+        for (context_name, bindings) in table {
+            let context = KEY_CONTEXTS
+                .iter()
+                .find(|(_, name)| *name == context_name)
+                .map(|(context, _)| *context)
+                .ok_or_else(|| format!("unknown context \"{}\"", context_name))?;
+            let bindings = bindings
+                .as_table()
+                .ok_or_else(|| format!("\"{}\" should be a table of bindings", context_name))?;
+            for (chord_text, command_name) in bindings {
+                let keys = parse_chord(chord_text)
+                    .ok_or_else(|| format!("unknown key \"{}\"", chord_text))?;
+                let command_name = command_name.as_str().ok_or_else(|| {
+                    format!("\"{}\" should be bound to a command name", chord_text)
+                })?;
+                let command = if command_name == UNBOUND_COMMAND_NAME {
+                    None
+                } else {
+                    Some(
+                        Command::from_name(command_name)
+                            .ok_or_else(|| format!("unknown command \"{}\"", command_name))?,
+                    )
+                };
+                self.bind(context, keys, command);
+            }
+        }
+        Ok(())
+    }
+
+    fn bind(&mut self, context: KeyContext, keys: Vec<Key>, command: Option<Command>) {
+        let bindings = self.bindings.entry(context).or_default();
+        match command {
+            None => bindings.remove(&keys),
+            Some(command) => bindings.insert(keys, command),
+        };
+    }
+
+    // The shortest chord bound to the command in the first of the contexts that has one.
+    pub fn binding(&self, contexts: &[KeyContext], command: Command) -> Option<Vec<Key>> {
+        contexts.iter().find_map(|context| {
+            self.bindings
+                .get(context)?
+                .iter()
+                .filter(|(_, bound_command)| **bound_command == command)
+                .map(|(keys, _)| keys)
+                .min_by_key(|keys| (keys.len(), format_chord(keys)))
+                .cloned()
+        })
+    }
+
+    pub fn lookup(&self, context: KeyContext, keys: &[Key]) -> KeymapMatch {
+        let bindings = match self.bindings.get(&context) {
+            None => return KeymapMatch::None,
+            Some(bindings) => bindings,
+        };
+        if let Some(command) = bindings.get(keys) {
+            return KeymapMatch::Command(*command);
+        }
+        let is_prefix = bindings
+            .keys()
+            .any(|chord| chord.len() > keys.len() && chord.starts_with(keys));
+        if is_prefix {
+            KeymapMatch::Prefix
+        } else {
+            KeymapMatch::None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::commands::Command;
+    use crate::keymap::*;
+
+    #[test]
+    fn parse_keys() {
+        assert_eq!(parse_key("ctrl+s"), Some(Key::Ctrl('s')));
+        assert_eq!(parse_key("Ctrl+Shift+S"), Some(Key::Ctrl('s')));
+        assert_eq!(parse_key("alt+shift+n"), Some(Key::Alt('N')));
+        assert_eq!(parse_key("f2"), Some(Key::F(2)));
+        assert_eq!(parse_key("f13"), None);
+        assert_eq!(parse_key("enter"), Some(Key::Char('\n')));
+        assert_eq!(parse_key("alt+space"), Some(Key::Alt(' ')));
+        assert_eq!(parse_key("ctrl+alt+x"), None);
+        assert_eq!(parse_key("hyper+x"), None);
+        assert_eq!(parse_key("ctrl+up"), None);
+        assert_eq!(
+            parse_chord("ctrl+x  ctrl+s"),
+            Some(vec![Key::Ctrl('x'), Key::Ctrl('s')])
+        );
+        assert_eq!(parse_chord(""), None);
+    }
+
+    #[test]
+    fn format_keys() {
+        for text in [
+            "ctrl+s",
+            "alt+shift+n",
+            "f2",
+            "enter",
+            "alt+space",
+            "ctrl+space",
+            "pagedown",
+            "x",
+        ] {
+            assert_eq!(format_key(parse_key(text).unwrap()), text);
+        }
+        assert_eq!(
+            format_chord(&[Key::Ctrl('x'), Key::Char('S')]),
+            "ctrl+x shift+s"
+        );
+    }
+
+    #[test]
+    fn find_bindings() {
+        let mut keymap = Keymap::new();
+        keymap
+            .apply("[editor]\n\"ctrl+x ctrl+s\" = \"save\"\n\"f1\" = \"quick_open\"")
+            .unwrap();
+        let contexts = [KeyContext::Editor, KeyContext::Global];
+        assert_eq!(
+            keymap.binding(&contexts, Command::Save),
+            Some(vec![Key::Ctrl('s')])
+        );
+        assert_eq!(
+            keymap.binding(&contexts, Command::QuickOpen),
+            Some(vec![Key::F(1)])
+        );
+        assert_eq!(keymap.binding(&contexts, Command::Expand), None);
+    }
+
+    #[test]
+    fn override_defaults() {
+        let mut keymap = Keymap::new();
+        keymap
+            .apply(
+                r#"
+                [editor]
+                "ctrl+x ctrl+s" = "save"
+                "ctrl+s" = "none"
+                "#,
+            )
+            .unwrap();
+        assert_eq!(
+            keymap.lookup(KeyContext::Editor, &[Key::Ctrl('x')]),
+            KeymapMatch::Prefix
+        );
+        assert_eq!(
+            keymap.lookup(KeyContext::Editor, &[Key::Ctrl('x'), Key::Ctrl('s')]),
+            KeymapMatch::Command(Command::Save)
+        );
+        assert_eq!(
+            keymap.lookup(KeyContext::Editor, &[Key::Ctrl('s')]),
+            KeymapMatch::None
+        );
+        assert_eq!(
+            keymap.lookup(KeyContext::Editor, &[Key::Up]),
+            KeymapMatch::Command(Command::CursorUp)
+        );
+    }
+
+    #[test]
+    fn report_errors() {
+        let mut keymap = Keymap::new();
+        assert!(keymap.apply("[sidebar]\n\"x\" = \"save\"").is_err());
+        assert!(keymap.apply("[editor]\n\"ctrl+\" = \"save\"").is_err());
+        assert!(keymap.apply("[editor]\n\"ctrl+s\" = \"sav\"").is_err());
+        assert!(keymap.apply("[editor]\n\"ctrl+s\" = 1").is_err());
+    }
+}
@@ -0,0 +1,84 @@
+/*
+    Copyright 2020, John Pothier
+    This file is part of Five.
+
+    Five is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    Five is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with Five.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Language {
+    Rust,
+    C,
+    Cpp,
+    Python,
+    JavaScript,
+    TypeScript,
+    Go,
+    Java,
+    Shell,
+    Markdown,
+    Toml,
+    Json,
+    Yaml,
+    PlainText,
+}
+
+impl Language {
+    pub fn from_path(path: &str) -> Language {
+        let extension = match Path::new(path)
+            .extension()
+            .and_then(|extension| extension.to_str())
+        {
+            Some(extension) => extension.to_lowercase(),
+            None => return Language::PlainText,
+        };
+        match extension.as_str() {
+            "rs" => Language::Rust,
+            "c" | "h" => Language::C,
+            "cc" | "cpp" | "cxx" | "hh" | "hpp" | "hxx" => Language::Cpp,
+            "py" => Language::Python,
+            "js" | "jsx" | "mjs" => Language::JavaScript,
+            "ts" | "tsx" => Language::TypeScript,
+            "go" => Language::Go,
+            "java" => Language::Java,
+            "sh" | "bash" | "zsh" => Language::Shell,
+            "md" | "markdown" => Language::Markdown,
+            "toml" => Language::Toml,
+            "json" => Language::Json,
+            "yml" | "yaml" => Language::Yaml,
+            _ => Language::PlainText,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Language::Rust => "Rust",
+            Language::C => "C",
+            Language::Cpp => "C++",
+            Language::Python => "Python",
+            Language::JavaScript => "JavaScript",
+            Language::TypeScript => "TypeScript",
+            Language::Go => "Go",
+            Language::Java => "Java",
+            Language::Shell => "Shell",
+            Language::Markdown => "Markdown",
+            Language::Toml => "TOML",
+            Language::Json => "JSON",
+            Language::Yaml => "YAML",
+            Language::PlainText => "Plain Text",
+        }
+    }
+}
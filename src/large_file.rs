@@ -0,0 +1,412 @@
+/*
+    Copyright 2020, John Pothier
+    This file is part of Five.
+
+    Five is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    Five is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with Five.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+// Read-only access to files too large to load into a Buffer. The file is read in pages on demand, and a
+// sparse index of line offsets is built by a background thread so any line can be found quickly.
+// Binary files are only read in pages, for the hex view.
+
+use crate::encoding;
+use crate::encoding::Encoding;
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::fs::File;
+use std::io;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+pub const DEFAULT_LARGE_FILE_THRESHOLD: u64 = 32 * 1024 * 1024;
+
+const PAGE_SIZE: usize = 64 * 1024;
+const MAX_CACHED_PAGES: usize = 64;
+const INDEXER_CHUNK_SIZE: usize = 1024 * 1024;
+// The index records the line number and offset of a line start at least every LINES_PER_CHECKPOINT lines
+// and CHECKPOINT_BYTES bytes, and right after every line longer than MAX_LINE_LENGTH, so that finding a
+// line never means scanning more than CHECKPOINT_BYTES
+const LINES_PER_CHECKPOINT: usize = 1024;
+const CHECKPOINT_BYTES: u64 = 256 * 1024;
+// Longer lines are cut off, so that a file without newlines can't be read into memory all at once. The
+// rest of such a line is skipped by jumping to the checkpoint after it.
+const MAX_LINE_LENGTH: usize = 64 * 1024;
+
+struct LineIndex {
+    checkpoints: Vec<(usize, u64)>,
+    num_newlines: usize,
+    complete: bool,
+}
+
+// Reads a file in pages on demand, keeping the most recently used ones.
+pub struct PagedFile {
+    file: RefCell<File>,
+    size: u64,
+    pages: RefCell<HashMap<u64, Rc<Vec<u8>>>>,
+    page_order: RefCell<VecDeque<u64>>,
+}
+
+impl PagedFile {
+    pub fn open(path: &Path) -> io::Result<PagedFile> {
+        let file = File::open(path)?;
+        let size = file.metadata()?.len();
+        Ok(PagedFile {
+            file: RefCell::new(file),
+            size,
+            pages: RefCell::new(HashMap::new()),
+            page_order: RefCell::new(VecDeque::new()),
+        })
+    }
+
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    fn page(&self, page_index: u64) -> Option<Rc<Vec<u8>>> {
+        if let Some(page) = self.pages.borrow().get(&page_index) {
+            return Some(Rc::clone(page));
+        }
+
+        let mut data = Vec::with_capacity(PAGE_SIZE);
+        let mut file = self.file.borrow_mut();
+        file.seek(SeekFrom::Start(page_index * PAGE_SIZE as u64))
+            .ok()?;
+        (&mut *file)
+            .take(PAGE_SIZE as u64)
+            .read_to_end(&mut data)
+            .ok()?;
+        let page = Rc::new(data);
+
+        let mut page_order = self.page_order.borrow_mut();
+        let mut pages = self.pages.borrow_mut();
+        if page_order.len() >= MAX_CACHED_PAGES {
+            if let Some(oldest) = page_order.pop_front() {
+                pages.remove(&oldest);
+            }
+        }
+        page_order.push_back(page_index);
+        pages.insert(page_index, Rc::clone(&page));
+        Some(page)
+    }
+
+    // Returns fewer bytes than asked for at the end of the file, or if it can't be read.
+    pub fn read(&self, offset: u64, length: usize) -> Vec<u8> {
+        let mut data = Vec::with_capacity(length);
+        let mut offset = offset;
+        while data.len() < length && offset < self.size {
+            let page = match self.page(offset / PAGE_SIZE as u64) {
+                Some(page) => page,
+                None => break,
+            };
+            let page_start = (offset % PAGE_SIZE as u64) as usize;
+            if page_start >= page.len() {
+                break;
+            }
+            let page_end = std::cmp::min(page.len(), page_start + length - data.len());
+            data.extend_from_slice(&page[page_start..page_end]);
+            offset += (page_end - page_start) as u64;
+        }
+        data
+    }
+}
+
+pub struct LargeFile {
+    file: PagedFile,
+    encoding: Encoding,
+    line_index: Arc<Mutex<LineIndex>>,
+    cancelled: Arc<AtomicBool>,
+}
+
+// The encoding is detected from the start of the file only. Multi-byte encodings are not supported,
+// since lines are found by looking for '\n' bytes.
+fn detect_encoding(sample: &[u8]) -> Option<Encoding> {
+    match encoding::detect(sample)? {
+        Encoding::Utf16Le | Encoding::Utf16Be => None,
+        // The sample may end in the middle of a character
+        Encoding::Latin1 | Encoding::Windows1252
+            if std::str::from_utf8(sample).is_err_and(|error| error.error_len().is_none()) =>
+        {
+            Some(Encoding::Utf8)
+        }
+        encoding => Some(encoding),
+    }
+}
+
+fn build_line_index(path: PathBuf, line_index: Arc<Mutex<LineIndex>>, cancelled: Arc<AtomicBool>) {
+    let mut file = match File::open(path) {
+        Ok(file) => file,
+        Err(_) => return,
+    };
+    let mut chunk = vec![0; INDEXER_CHUNK_SIZE];
+    let mut offset: u64 = 0;
+    let mut num_newlines = 0;
+    let mut checkpoints = Vec::new();
+    let (mut checkpoint_line, mut checkpoint_offset) = (0, 0);
+    let mut line_length = 0;
+    while !cancelled.load(Ordering::Relaxed) {
+        let length = match file.read(&mut chunk) {
+            Ok(0) | Err(_) => break,
+            Ok(length) => length,
+        };
+        for (i, byte) in chunk[..length].iter().enumerate() {
+            if *byte == b'\n' {
+                num_newlines += 1;
+                let line_start = offset + i as u64 + 1;
+                if num_newlines - checkpoint_line >= LINES_PER_CHECKPOINT
+                    || line_start - checkpoint_offset >= CHECKPOINT_BYTES
+                    || line_length > MAX_LINE_LENGTH
+                {
+                    checkpoints.push((num_newlines, line_start));
+                    checkpoint_line = num_newlines;
+                    checkpoint_offset = line_start;
+                }
+                line_length = 0;
+            } else {
+                line_length += 1;
+            }
+        }
+        offset += length as u64;
+
+        let mut line_index = line_index.lock().unwrap();
+        line_index.checkpoints.append(&mut checkpoints);
+        line_index.num_newlines = num_newlines;
+    }
+    line_index.lock().unwrap().complete = true;
+}
+
+impl LargeFile {
+    // Returns None if the file can't be read or doesn't look like a text file this can display.
+    pub fn open(path: &Path) -> Option<LargeFile> {
+        let file = PagedFile::open(path).ok()?;
+        let encoding = detect_encoding(&file.read(0, PAGE_SIZE))?;
+
+        let line_index = Arc::new(Mutex::new(LineIndex {
+            checkpoints: vec![(0, 0)],
+            num_newlines: 0,
+            complete: false,
+        }));
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let thread_path = path.to_path_buf();
+        let thread_line_index = Arc::clone(&line_index);
+        let thread_cancelled = Arc::clone(&cancelled);
+        thread::spawn(move || build_line_index(thread_path, thread_line_index, thread_cancelled));
+
+        Some(LargeFile {
+            file,
+            encoding,
+            line_index,
+            cancelled,
+        })
+    }
+
+    pub fn size(&self) -> u64 {
+        self.file.size()
+    }
+
+    pub fn encoding(&self) -> Encoding {
+        self.encoding
+    }
+
+    pub fn is_indexed(&self) -> bool {
+        self.line_index.lock().unwrap().complete
+    }
+
+    // Only counts the lines indexed so far until is_indexed() returns true.
+    pub fn num_lines(&self) -> usize {
+        let line_index = self.line_index.lock().unwrap();
+        if line_index.complete {
+            line_index.num_newlines + 1
+        } else {
+            line_index.num_newlines
+        }
+    }
+
+    fn decode_line(&self, line: &[u8]) -> String {
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        encoding::decode(line, self.encoding)
+    }
+
+    // Returns up to `count` lines starting at `first_line`. Lines that haven't been indexed yet are not
+    // returned.
+    pub fn lines(&self, first_line: usize, count: usize) -> Vec<String> {
+        let num_lines = self.num_lines();
+        let count = std::cmp::min(count, num_lines.saturating_sub(first_line));
+        let (mut line, mut offset) = {
+            let line_index = self.line_index.lock().unwrap();
+            let checkpoints = &line_index.checkpoints;
+            checkpoints[checkpoints.partition_point(|(line, _)| *line <= first_line) - 1]
+        };
+
+        let mut lines = Vec::with_capacity(count);
+        let mut current_line = Vec::new();
+        'pages: while offset < self.size() && lines.len() < count {
+            let page = match self.file.page(offset / PAGE_SIZE as u64) {
+                Some(page) if !page.is_empty() => page,
+                _ => break,
+            };
+            let page_start = (offset % PAGE_SIZE as u64) as usize;
+            for byte in &page[page_start..] {
+                if line < first_line {
+                    offset += 1;
+                    if *byte == b'\n' {
+                        line += 1;
+                    }
+                    continue;
+                }
+                if *byte != b'\n' && current_line.len() < MAX_LINE_LENGTH {
+                    offset += 1;
+                    current_line.push(*byte);
+                    continue;
+                }
+                lines.push(self.decode_line(&current_line));
+                current_line.clear();
+                line += 1;
+                if lines.len() == count {
+                    break 'pages;
+                }
+                if *byte == b'\n' {
+                    offset += 1;
+                } else {
+                    // The line was cut off, so its end is only known from the index
+                    match self.checkpoint_at(line) {
+                        Some(line_start) => {
+                            offset = line_start;
+                            continue 'pages;
+                        }
+                        None => return lines,
+                    }
+                }
+            }
+        }
+        // The last line isn't followed by a newline
+        if lines.len() < count && line >= first_line {
+            lines.push(self.decode_line(&current_line));
+        }
+        lines
+    }
+
+    fn checkpoint_at(&self, line: usize) -> Option<u64> {
+        let line_index = self.line_index.lock().unwrap();
+        let checkpoints = &line_index.checkpoints;
+        checkpoints
+            .binary_search_by_key(&line, |(line, _)| *line)
+            .ok()
+            .map(|index| checkpoints[index].1)
+    }
+}
+
+impl Drop for LargeFile {
+    fn drop(&mut self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::large_file::*;
+    use std::io::Write;
+
+    fn write_temp_file(name: &str, content: &[u8]) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("five-{}-large-file-{}", std::process::id(), name));
+        File::create(&path).unwrap().write_all(content).unwrap();
+        path
+    }
+
+    fn open_indexed(path: &Path) -> LargeFile {
+        let large_file = LargeFile::open(path).unwrap();
+        while !large_file.is_indexed() {
+            thread::yield_now();
+        }
+        large_file
+    }
+
+    #[test]
+    fn read_lines_across_pages_and_checkpoints() {
+        let content: String = (0..10000).map(|i| format!("line {}\n", i)).collect();
+        let path = write_temp_file("lines", content.as_bytes());
+        let large_file = open_indexed(&path);
+
+        assert_eq!(large_file.num_lines(), 10001);
+        assert_eq!(large_file.lines(0, 2), vec!["line 0", "line 1"]);
+        assert_eq!(large_file.lines(1023, 2), vec!["line 1023", "line 1024"]);
+        assert_eq!(large_file.lines(9999, 5), vec!["line 9999", ""]);
+        assert_eq!(large_file.lines(20000, 5), Vec::<String>::new());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn cut_off_long_lines() {
+        let long_line = "x".repeat(3 * 1024 * 1024);
+        let short_lines: String = (0..2000).map(|i| format!("{}\n", i)).collect();
+        let content = format!("short\n{}\n{}{}", long_line, short_lines, long_line);
+        let path = write_temp_file("long-lines", content.as_bytes());
+        let large_file = open_indexed(&path);
+        let cut_off = "x".repeat(MAX_LINE_LENGTH);
+
+        assert_eq!(large_file.num_lines(), 2003);
+        assert_eq!(large_file.lines(0, 4), vec!["short", &cut_off, "0", "1"]);
+        assert_eq!(large_file.lines(1, 2), vec![&cut_off, "0"]);
+        assert_eq!(large_file.lines(2001, 5), vec!["1999", &cut_off]);
+        assert_eq!(
+            large_file.checkpoint_at(2),
+            Some("short\n".len() as u64 + long_line.len() as u64 + 1)
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn strip_carriage_returns() {
+        let path = write_temp_file("crlf", b"one\r\ntwo");
+        let large_file = open_indexed(&path);
+        assert_eq!(large_file.lines(0, 10), vec!["one", "two"]);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn read_across_pages() {
+        let content: Vec<u8> = (0..3 * PAGE_SIZE).map(|i| (i % 251) as u8).collect();
+        let path = write_temp_file("pages", &content);
+        let file = PagedFile::open(&path).unwrap();
+        let offset = PAGE_SIZE - 10;
+        assert_eq!(
+            file.read(offset as u64, PAGE_SIZE + 20),
+            &content[offset..][..PAGE_SIZE + 20]
+        );
+        assert_eq!(
+            file.read(content.len() as u64 - 5, 100),
+            &content[content.len() - 5..]
+        );
+        assert!(file.read(content.len() as u64, 1).is_empty());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn reject_binary_files() {
+        let path = write_temp_file("binary", b"\x00\x01\x02\x03");
+        assert!(LargeFile::open(&path).is_none());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn detect_utf8_cut_off_in_the_middle_of_a_character() {
+        assert_eq!(detect_encoding("aé".as_bytes()), Some(Encoding::Utf8));
+        assert_eq!(detect_encoding(&"aé".as_bytes()[..2]), Some(Encoding::Utf8));
+        assert_eq!(detect_encoding(b"a\xe9a"), Some(Encoding::Latin1));
+    }
+}
@@ -0,0 +1,445 @@
+/*
+    Copyright 2020, John Pothier
+    This file is part of Five.
+
+    Five is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    Five is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with Five.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use crate::diagnostics::{Diagnostic, Severity};
+use crate::lsp::transport::{read_message, write_message};
+use crate::lsp::{
+    path_to_uri, uri_to_path, CompletionItem, Location, LspEvent, LspRequest, ServerConfig,
+    TextEdit,
+};
+use crate::text_position::TextPosition;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::io::BufReader;
+use std::path::Path;
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc;
+use std::thread;
+
+// Values of the textDocumentSync capability
+const SYNC_NONE: u64 = 0;
+const SYNC_FULL: u64 = 1;
+
+// What an unanswered request asked for.
+enum PendingRequest {
+    Initialize,
+    Request(LspRequest),
+}
+
+// A language server running as a child process, talking JSON-RPC over its stdin and stdout. Its
+// messages are read on a separate thread and picked up by poll. Messages to it are written on another
+// thread, since a server that is busy and not reading would otherwise block the UI.
+pub struct LspClient {
+    process: Child,
+    // None once the client is dropped, which lets the writer thread finish
+    outgoing_messages: Option<mpsc::Sender<Value>>,
+    writer_thread: Option<thread::JoinHandle<()>>,
+    messages: mpsc::Receiver<Value>,
+    reader_thread: Option<thread::JoinHandle<()>>,
+    next_id: u64,
+    pending_requests: HashMap<u64, PendingRequest>,
+    // Messages held back until the server has answered the initialize request
+    queued_messages: Option<Vec<Value>>,
+    sync_kind: u64,
+    has_exited: bool,
+}
+
+fn position_to_json(position: TextPosition) -> Value {
+    json!({"line": position.line, "character": position.column})
+}
+
+fn parse_position(value: &Value) -> Option<TextPosition> {
+    Some(TextPosition::new(
+        usize::try_from(value["line"].as_u64()?).ok()?,
+        usize::try_from(value["character"].as_u64()?).ok()?,
+    ))
+}
+
+// Either a Location or a LocationLink.
+fn parse_location(value: &Value) -> Option<Location> {
+    let (uri, range) = match value.get("targetUri") {
+        Some(uri) => (uri, &value["targetSelectionRange"]),
+        None => (&value["uri"], &value["range"]),
+    };
+    Some(Location {
+        path: uri_to_path(uri.as_str()?)?,
+        position: parse_position(&range["start"])?,
+    })
+}
+
+fn parse_locations(value: &Value) -> Vec<Location> {
+    match value {
+        Value::Array(values) => values.iter().filter_map(parse_location).collect(),
+        Value::Object(_) => parse_location(value).into_iter().collect(),
+        _ => vec![],
+    }
+}
+
+// Hover contents can be a string, a MarkupContent, a MarkedString with a language, or a list of
+// those.
+fn hover_text(contents: &Value) -> String {
+    match contents {
+        Value::String(text) => text.clone(),
+        Value::Array(values) => values
+            .iter()
+            .map(hover_text)
+            .filter(|text| !text.is_empty())
+            .collect::<Vec<String>>()
+            .join("\n\n"),
+        Value::Object(_) => contents["value"].as_str().unwrap_or_default().to_string(),
+        _ => String::new(),
+    }
+}
+
+// The result is either a list of items or a CompletionList.
+fn parse_completion_items(result: &Value) -> Vec<CompletionItem> {
+    let items = match result.get("items") {
+        Some(items) => items,
+        None => result,
+    };
+    let items = match items.as_array() {
+        None => return vec![],
+        Some(items) => items,
+    };
+    items
+        .iter()
+        .filter_map(|item| {
+            let label = item["label"].as_str()?;
+            let insert_text = item["textEdit"]["newText"]
+                .as_str()
+                .or_else(|| item["insertText"].as_str())
+                .unwrap_or(label);
+            Some(CompletionItem {
+                label: String::from(label),
+                insert_text: String::from(insert_text),
+                detail: item["detail"].as_str().map(String::from),
+            })
+        })
+        .collect()
+}
+
+fn parse_diagnostics(params: &Value) -> Option<LspEvent> {
+    let path = uri_to_path(params["uri"].as_str()?)?;
+    let diagnostics = params["diagnostics"]
+        .as_array()?
+        .iter()
+        .filter_map(|diagnostic| {
+            Some(Diagnostic {
+                severity: match diagnostic["severity"].as_u64() {
+                    Some(2) => Severity::Warning,
+                    Some(3) => Severity::Information,
+                    Some(4) => Severity::Hint,
+                    _ => Severity::Error,
+                },
+                start: parse_position(&diagnostic["range"]["start"])?,
+                end: parse_position(&diagnostic["range"]["end"])?,
+                message: String::from(diagnostic["message"].as_str()?),
+            })
+        })
+        .collect();
+    Some(LspEvent::Diagnostics(path, diagnostics))
+}
+
+// The capability is either a number or an object with a "change" number.
+fn parse_sync_kind(capabilities: &Value) -> u64 {
+    let sync = &capabilities["textDocumentSync"];
+    sync.as_u64()
+        .or_else(|| sync["change"].as_u64())
+        .unwrap_or(SYNC_NONE)
+}
+
+impl LspClient {
+    pub fn start(config: &ServerConfig, root_path: &Path) -> std::io::Result<LspClient> {
+        let mut process = Command::new(&config.command)
+            .args(&config.args)
+            .current_dir(root_path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            // Anything the server logs would end up on the screen
+            .stderr(Stdio::null())
+            .spawn()?;
+        let mut stdin = process.stdin.take().unwrap();
+        let stdout = process.stdout.take().unwrap();
+        let (outgoing_messages, receiver) = mpsc::channel::<Value>();
+        let writer_thread = thread::spawn(move || {
+            for message in receiver {
+                if write_message(&mut stdin, &message).is_err() {
+                    return;
+                }
+            }
+        });
+        let (sender, messages) = mpsc::channel();
+        let reader_thread = thread::spawn(move || {
+            let mut reader = BufReader::new(stdout);
+            while let Ok(Some(message)) = read_message(&mut reader) {
+                if sender.send(message).is_err() {
+                    return;
+                }
+            }
+        });
+
+        let mut client = LspClient {
+            process,
+            outgoing_messages: Some(outgoing_messages),
+            writer_thread: Some(writer_thread),
+            messages,
+            reader_thread: Some(reader_thread),
+            next_id: 1,
+            pending_requests: HashMap::new(),
+            queued_messages: Some(vec![]),
+            sync_kind: SYNC_NONE,
+            has_exited: false,
+        };
+        let root_uri = path_to_uri(&root_path.to_string_lossy());
+        client.send_request_message(
+            PendingRequest::Initialize,
+            "initialize",
+            json!({
+                "processId": std::process::id(),
+                "rootUri": root_uri,
+                "workspaceFolders": [{"uri": root_uri, "name": "root"}],
+                "capabilities": {
+                    "textDocument": {
+                        "synchronization": {"didSave": true},
+                        "hover": {"contentFormat": ["plaintext", "markdown"]},
+                        "completion": {"completionItem": {"snippetSupport": false}},
+                        "publishDiagnostics": {},
+                        "definition": {},
+                        "references": {},
+                    },
+                },
+            }),
+        );
+        Ok(client)
+    }
+
+    pub fn has_exited(&self) -> bool {
+        self.has_exited
+    }
+
+    // A server that has gone away is noticed by poll, so errors are ignored here.
+    fn write(&mut self, message: Value) {
+        if let Some(outgoing_messages) = &self.outgoing_messages {
+            let _ = outgoing_messages.send(message);
+        }
+    }
+
+    fn send(&mut self, message: Value) {
+        match &mut self.queued_messages {
+            Some(queued_messages) => queued_messages.push(message),
+            None => self.write(message),
+        }
+    }
+
+    fn send_notification(&mut self, method: &str, params: Value) {
+        self.send(json!({"jsonrpc": "2.0", "method": method, "params": params}));
+    }
+
+    fn send_request_message(&mut self, request: PendingRequest, method: &str, params: Value) {
+        let id = self.next_id;
+        self.next_id += 1;
+        let message = json!({"jsonrpc": "2.0", "id": id, "method": method, "params": params});
+        // Everything else waits for the answer to initialize
+        if let PendingRequest::Initialize = request {
+            self.write(message);
+        } else {
+            self.send(message);
+        }
+        self.pending_requests.insert(id, request);
+    }
+
+    pub fn open_document(&mut self, uri: &str, language_id: &str, version: i64, text: &str) {
+        self.send_notification(
+            "textDocument/didOpen",
+            json!({
+                "textDocument": {
+                    "uri": uri,
+                    "languageId": language_id,
+                    "version": version,
+                    "text": text,
+                },
+            }),
+        );
+    }
+
+    // Before the server has said how it wants edits, they are folded into the document it is about
+    // to be sent.
+    pub fn change_document<GetText: FnOnce() -> String>(
+        &mut self,
+        uri: &str,
+        version: i64,
+        edit: &TextEdit,
+        text: GetText,
+    ) {
+        if let Some(queued_messages) = &mut self.queued_messages {
+            let queued_open = queued_messages.iter_mut().rev().find(|message| {
+                message["method"] == "textDocument/didOpen"
+                    && message["params"]["textDocument"]["uri"] == uri
+            });
+            if let Some(message) = queued_open {
+                let document = &mut message["params"]["textDocument"];
+                document["text"] = Value::from(text());
+                document["version"] = Value::from(version);
+            }
+            return;
+        }
+        let change = match self.sync_kind {
+            SYNC_NONE => return,
+            SYNC_FULL => json!({"text": text()}),
+            _ => json!({
+                "range": {
+                    "start": position_to_json(edit.start),
+                    "end": position_to_json(edit.end),
+                },
+                "text": edit.text,
+            }),
+        };
+        self.send_notification(
+            "textDocument/didChange",
+            json!({
+                "textDocument": {"uri": uri, "version": version},
+                "contentChanges": [change],
+            }),
+        );
+    }
+
+    pub fn save_document(&mut self, uri: &str) {
+        self.send_notification(
+            "textDocument/didSave",
+            json!({"textDocument": {"uri": uri}}),
+        );
+    }
+
+    pub fn close_document(&mut self, uri: &str) {
+        self.send_notification(
+            "textDocument/didClose",
+            json!({"textDocument": {"uri": uri}}),
+        );
+    }
+
+    pub fn send_request(&mut self, request: LspRequest, uri: &str, position: TextPosition) {
+        let mut params = json!({
+            "textDocument": {"uri": uri},
+            "position": position_to_json(position),
+        });
+        let method = match request {
+            LspRequest::Hover => "textDocument/hover",
+            LspRequest::Definition => "textDocument/definition",
+            LspRequest::References => {
+                params["context"] = json!({"includeDeclaration": true});
+                "textDocument/references"
+            }
+            LspRequest::Completion => "textDocument/completion",
+        };
+        self.send_request_message(PendingRequest::Request(request), method, params);
+    }
+
+    // Handles the messages that have arrived, and returns what the rest of Five should know about.
+    pub fn poll(&mut self) -> Vec<LspEvent> {
+        let mut events = vec![];
+        loop {
+            match self.messages.try_recv() {
+                Ok(message) => events.extend(self.handle_message(&message)),
+                Err(mpsc::TryRecvError::Empty) => break,
+                Err(mpsc::TryRecvError::Disconnected) => {
+                    self.has_exited = true;
+                    break;
+                }
+            }
+        }
+        events
+    }
+
+    fn handle_message(&mut self, message: &Value) -> Option<LspEvent> {
+        let method = message["method"].as_str();
+        match (message.get("id"), method) {
+            // Requests from the server, which get an empty answer, as Five has no settings to offer
+            (Some(id), Some(method)) => {
+                let result = match method {
+                    "workspace/configuration" => {
+                        let num_items = message["params"]["items"]
+                            .as_array()
+                            .map_or(0, |items| items.len());
+                        Value::from(vec![Value::Null; num_items])
+                    }
+                    _ => Value::Null,
+                };
+                self.write(json!({"jsonrpc": "2.0", "id": id, "result": result}));
+                None
+            }
+            (Some(id), None) => {
+                let request = self.pending_requests.remove(&id.as_u64()?)?;
+                if let Some(error) = message.get("error") {
+                    let error_message = error["message"].as_str().unwrap_or("unknown error");
+                    return Some(LspEvent::Error(format!(
+                        "Language server error: {}",
+                        error_message
+                    )));
+                }
+                let result = &message["result"];
+                match request {
+                    PendingRequest::Initialize => {
+                        self.sync_kind = parse_sync_kind(&result["capabilities"]);
+                        self.write(json!({
+                            "jsonrpc": "2.0",
+                            "method": "initialized",
+                            "params": {},
+                        }));
+                        for queued_message in self.queued_messages.take().unwrap_or_default() {
+                            self.write(queued_message);
+                        }
+                        None
+                    }
+                    PendingRequest::Request(LspRequest::Hover) => {
+                        Some(LspEvent::Hover(hover_text(&result["contents"])))
+                    }
+                    PendingRequest::Request(LspRequest::Definition) => {
+                        Some(LspEvent::Definition(parse_locations(result)))
+                    }
+                    PendingRequest::Request(LspRequest::References) => {
+                        Some(LspEvent::References(parse_locations(result)))
+                    }
+                    PendingRequest::Request(LspRequest::Completion) => {
+                        Some(LspEvent::Completion(parse_completion_items(result)))
+                    }
+                }
+            }
+            (None, Some("textDocument/publishDiagnostics")) => {
+                parse_diagnostics(&message["params"])
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Drop for LspClient {
+    // Servers are sent exit, but killed right away instead of being given time to read it. Waiting only
+    // reaps the killed process, and killing it also unblocks the threads, which may be stuck on the
+    // pipes.
+    fn drop(&mut self) {
+        self.write(json!({"jsonrpc": "2.0", "method": "exit"}));
+        self.outgoing_messages = None;
+        let _ = self.process.kill();
+        let _ = self.process.wait();
+        let threads = self.writer_thread.take().into_iter();
+        for thread in threads.chain(self.reader_thread.take()) {
+            let _ = thread.join();
+        }
+    }
+}
@@ -0,0 +1,458 @@
+/*
+    Copyright 2020, John Pothier
+    This file is part of Five.
+
+    Five is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    Five is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with Five.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+pub mod client;
+pub mod transport;
+
+use crate::diagnostics::Diagnostic;
+use crate::language::Language;
+use crate::text_position::TextPosition;
+use client::LspClient;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+
+// Positions exchanged with language servers count columns in UTF-16 code units rather than
+// graphemes. The file view converts them, since it has the text.
+
+// The protocol's name for each language, which is also what its server is called in the settings.
+pub const LANGUAGE_IDS: &[(Language, &str)] = &[
+    (Language::Rust, "rust"),
+    (Language::C, "c"),
+    (Language::Cpp, "cpp"),
+    (Language::Python, "python"),
+    (Language::JavaScript, "javascript"),
+    (Language::TypeScript, "typescript"),
+    (Language::Go, "go"),
+    (Language::Java, "java"),
+    (Language::Shell, "shellscript"),
+    (Language::Markdown, "markdown"),
+    (Language::Toml, "toml"),
+    (Language::Json, "json"),
+    (Language::Yaml, "yaml"),
+];
+
+pub fn language_id(language: Language) -> Option<&'static str> {
+    LANGUAGE_IDS
+        .iter()
+        .find(|(id_language, _)| *id_language == language)
+        .map(|(_, id)| *id)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ServerConfig {
+    pub command: String,
+    pub args: Vec<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LspRequest {
+    Hover,
+    Definition,
+    References,
+    Completion,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Location {
+    pub path: String,
+    pub position: TextPosition,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CompletionItem {
+    pub label: String,
+    // Replaces the word before the cursor
+    pub insert_text: String,
+    pub detail: Option<String>,
+}
+
+// Replaces the text from start to end, as they were before the edit.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TextEdit {
+    pub start: TextPosition,
+    pub end: TextPosition,
+    pub text: String,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum LspEvent {
+    Diagnostics(String, Vec<Diagnostic>),
+    Hover(String),
+    Definition(Vec<Location>),
+    References(Vec<Location>),
+    Completion(Vec<CompletionItem>),
+    Error(String),
+}
+
+fn is_unreserved(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || b"-._~/".contains(&byte)
+}
+
+pub fn path_to_uri(path: &str) -> String {
+    let mut uri = String::from("file://");
+    for byte in path.bytes() {
+        if is_unreserved(byte) {
+            uri.push(char::from(byte));
+        } else {
+            uri.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    uri
+}
+
+// Only file URIs have a path.
+pub fn uri_to_path(uri: &str) -> Option<String> {
+    let encoded = uri.strip_prefix("file://")?.as_bytes();
+    let mut path = Vec::with_capacity(encoded.len());
+    let mut i = 0;
+    while i < encoded.len() {
+        let decoded = match encoded[i] {
+            b'%' => encoded
+                .get(i + 1..i + 3)
+                .and_then(|hex| std::str::from_utf8(hex).ok())
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok()),
+            _ => None,
+        };
+        match decoded {
+            Some(byte) => {
+                path.push(byte);
+                i += 3;
+            }
+            None => {
+                path.push(encoded[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8(path).ok()
+}
+
+// The file in the file view, as the language server knows it.
+struct OpenDocument {
+    uri: String,
+    language_id: &'static str,
+    version: i64,
+}
+
+// Starts the configured server for a language the first time a file in that language is shown,
+// and keeps it running until Five exits.
+pub struct LanguageServers {
+    root_path: PathBuf,
+    // By language id
+    configs: HashMap<String, ServerConfig>,
+    clients: HashMap<&'static str, LspClient>,
+    // Languages whose server could not be started or has stopped, which aren't tried again
+    failed: HashSet<&'static str>,
+    // Only the file in the file view is open, since unsaved edits are dropped when another file is
+    // shown
+    document: Option<OpenDocument>,
+    // By path
+    diagnostics: HashMap<String, Vec<Diagnostic>>,
+}
+
+impl LanguageServers {
+    // The configs come from the global settings only, since starting a server runs its command.
+    pub fn new(root_path: PathBuf, configs: HashMap<String, ServerConfig>) -> LanguageServers {
+        LanguageServers {
+            root_path,
+            configs,
+            clients: HashMap::new(),
+            failed: HashSet::new(),
+            document: None,
+            diagnostics: HashMap::new(),
+        }
+    }
+
+    // Returns an error if the server for the language of the file could not be started.
+    pub fn open(&mut self, path: &str, text: &str) -> Result<(), String> {
+        self.close();
+        let language_id = match language_id(Language::from_path(path)) {
+            Some(language_id) if !self.failed.contains(language_id) => language_id,
+            _ => return Ok(()),
+        };
+        if !self.clients.contains_key(language_id) {
+            let config = match self.configs.get(language_id) {
+                None => return Ok(()),
+                Some(config) => config,
+            };
+            match LspClient::start(config, &self.root_path) {
+                Err(err) => {
+                    self.failed.insert(language_id);
+                    return Err(format!("Could not start {}: {}", config.command, err));
+                }
+                Ok(client) => self.clients.insert(language_id, client),
+            };
+        }
+        let document = OpenDocument {
+            uri: path_to_uri(path),
+            language_id,
+            version: 1,
+        };
+        self.clients.get_mut(language_id).unwrap().open_document(
+            &document.uri,
+            language_id,
+            document.version,
+            text,
+        );
+        self.document = Some(document);
+        Ok(())
+    }
+
+    pub fn close(&mut self) {
+        if let Some(document) = self.document.take() {
+            if let Some(client) = self.clients.get_mut(document.language_id) {
+                client.close_document(&document.uri);
+            }
+        }
+    }
+
+    // Servers that want the whole text after each edit get it from `text`.
+    pub fn change<GetText: FnOnce() -> String>(&mut self, edit: &TextEdit, text: GetText) {
+        if let Some(document) = &mut self.document {
+            if let Some(client) = self.clients.get_mut(document.language_id) {
+                document.version += 1;
+                client.change_document(&document.uri, document.version, edit, text);
+            }
+        }
+    }
+
+    pub fn save(&mut self) {
+        if let Some(document) = &self.document {
+            if let Some(client) = self.clients.get_mut(document.language_id) {
+                client.save_document(&document.uri);
+            }
+        }
+    }
+
+    // Asks about a position in the open file. The answer comes later from poll. Returns an error if
+    // no server handles the file.
+    pub fn request(&mut self, request: LspRequest, position: TextPosition) -> Result<(), String> {
+        let clients = &mut self.clients;
+        let document = self.document.as_ref();
+        let client = document.and_then(|document| clients.get_mut(document.language_id));
+        match (document, client) {
+            (Some(document), Some(client)) => {
+                client.send_request(request, &document.uri, position);
+                Ok(())
+            }
+            _ => Err(String::from("No language server for this file")),
+        }
+    }
+
+    pub fn diagnostics(&self, path: &str) -> Vec<Diagnostic> {
+        self.diagnostics.get(path).cloned().unwrap_or_default()
+    }
+
+    // Answers and diagnostics that have arrived since the last call.
+    pub fn poll(&mut self) -> Vec<LspEvent> {
+        let mut events = vec![];
+        let mut stopped = vec![];
+        for (language_id, client) in self.clients.iter_mut() {
+            events.extend(client.poll());
+            if client.has_exited() {
+                stopped.push(*language_id);
+            }
+        }
+        for language_id in stopped {
+            self.clients.remove(language_id);
+            self.failed.insert(language_id);
+            events.push(LspEvent::Error(format!(
+                "The {} language server stopped",
+                language_id
+            )));
+        }
+        for event in &events {
+            if let LspEvent::Diagnostics(path, diagnostics) = event {
+                self.diagnostics.insert(path.clone(), diagnostics.clone());
+            }
+        }
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::diagnostics::Severity;
+    use crate::lsp::*;
+    use std::collections::VecDeque;
+    use std::time::{Duration, Instant};
+
+    // The fake server is an example, which `cargo test` builds into the examples folder next to
+    // the deps folder of the test binary. Only testing some of the targets, such as with --bin, leaves it
+    // out.
+    fn fake_server_config() -> Option<ServerConfig> {
+        let path = std::env::current_exe()
+            .ok()?
+            .parent()?
+            .parent()?
+            .join("examples")
+            .join("fake_language_server");
+        if !path.exists() {
+            eprintln!(
+                "Skipped: the fake language server was not built, run cargo build --examples"
+            );
+            return None;
+        }
+        Some(ServerConfig {
+            command: path.to_string_lossy().into_owned(),
+            args: vec![],
+        })
+    }
+
+    struct Events {
+        events: VecDeque<LspEvent>,
+    }
+
+    impl Events {
+        fn next(&mut self, servers: &mut LanguageServers) -> LspEvent {
+            let start = Instant::now();
+            while self.events.is_empty() {
+                assert!(start.elapsed() < Duration::from_secs(5), "no answer");
+                std::thread::sleep(Duration::from_millis(5));
+                self.events.extend(servers.poll());
+            }
+            self.events.pop_front().unwrap()
+        }
+    }
+
+    fn edit(line: usize, column: usize, text: &str) -> TextEdit {
+        TextEdit {
+            start: TextPosition::new(line, column),
+            end: TextPosition::new(line, column),
+            text: String::from(text),
+        }
+    }
+
+    fn diagnostic(severity: Severity, line: usize, column: usize, word: &str) -> Diagnostic {
+        Diagnostic {
+            severity,
+            start: TextPosition::new(line, column),
+            end: TextPosition::new(line, column + word.len()),
+            message: format!("found {}", word),
+        }
+    }
+
+    #[test]
+    fn talk_to_server() {
+        let config = match fake_server_config() {
+            Some(config) => config,
+            None => return,
+        };
+        let root_path = std::env::temp_dir().join(format!("five-lsp-{}", std::process::id()));
+        std::fs::create_dir_all(&root_path).unwrap();
+        let path = root_path.join("main.rs").to_string_lossy().into_owned();
+        let mut configs = HashMap::new();
+        configs.insert(String::from("rust"), config);
+        let mut servers = LanguageServers::new(root_path.clone(), configs);
+        let mut events = Events {
+            events: VecDeque::new(),
+        };
+
+        // The first edit is made before the server has started
+        servers
+            .open(&path, "fn main() {}\nfn helper() {}\n")
+            .unwrap();
+        servers.change(&edit(1, 0, "warning "), || {
+            String::from("fn main() {}\nwarning fn helper() {}\n")
+        });
+        let warning = diagnostic(Severity::Warning, 1, 0, "warning");
+        assert_eq!(
+            events.next(&mut servers),
+            LspEvent::Diagnostics(path.clone(), vec![warning.clone()])
+        );
+        servers.change(&edit(0, 12, " error"), || unreachable!());
+        let error = diagnostic(Severity::Error, 0, 13, "error");
+        assert_eq!(
+            events.next(&mut servers),
+            LspEvent::Diagnostics(path.clone(), vec![error.clone(), warning.clone()])
+        );
+        assert_eq!(servers.diagnostics(&path), vec![error, warning]);
+
+        servers
+            .request(LspRequest::Hover, TextPosition::new(0, 4))
+            .unwrap();
+        assert_eq!(
+            events.next(&mut servers),
+            LspEvent::Hover(String::from("`main`"))
+        );
+        servers
+            .request(LspRequest::Definition, TextPosition::new(1, 12))
+            .unwrap();
+        let definition = Location {
+            path: path.clone(),
+            position: TextPosition::new(1, 11),
+        };
+        assert_eq!(
+            events.next(&mut servers),
+            LspEvent::Definition(vec![definition.clone()])
+        );
+        servers
+            .request(LspRequest::References, TextPosition::new(1, 12))
+            .unwrap();
+        assert_eq!(
+            events.next(&mut servers),
+            LspEvent::References(vec![definition])
+        );
+        servers
+            .request(LspRequest::Completion, TextPosition::new(1, 0))
+            .unwrap();
+        match events.next(&mut servers) {
+            LspEvent::Completion(items) => assert!(items
+                .iter()
+                .any(|item| { item.label == "helper" && item.insert_text == "helper" })),
+            event => panic!("unexpected {:?}", event),
+        }
+
+        // Files without a server
+        servers
+            .open(&root_path.join("notes.txt").to_string_lossy(), "")
+            .unwrap();
+        assert!(servers
+            .request(LspRequest::Hover, TextPosition::new(0, 0))
+            .is_err());
+        std::fs::remove_dir_all(&root_path).unwrap();
+    }
+
+    #[test]
+    fn report_missing_server() {
+        let mut configs = HashMap::new();
+        configs.insert(
+            String::from("rust"),
+            ServerConfig {
+                command: String::from("/nonexistent/language-server"),
+                args: vec![],
+            },
+        );
+        let mut servers = LanguageServers::new(std::env::temp_dir(), configs);
+        assert!(servers.open("/main.rs", "").is_err());
+        // Only once
+        assert!(servers.open("/main.rs", "").is_ok());
+    }
+
+    #[test]
+    fn convert_uris() {
+        assert_eq!(path_to_uri("/five/src/main.rs"), "file:///five/src/main.rs");
+        assert_eq!(path_to_uri("/a b/é%.rs"), "file:///a%20b/%C3%A9%25.rs");
+        assert_eq!(
+            uri_to_path("file:///a%20b/%C3%A9%25.rs"),
+            Some(String::from("/a b/é%.rs"))
+        );
+        assert_eq!(uri_to_path("file:///100%"), Some(String::from("/100%")));
+        assert_eq!(uri_to_path("untitled:1"), None);
+    }
+}
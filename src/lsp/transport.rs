@@ -0,0 +1,88 @@
+/*
+    Copyright 2020, John Pothier
+    This file is part of Five.
+
+    Five is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    Five is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with Five.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+// Language server messages are JSON, each preceded by a header giving its length in bytes:
+//
+//     Content-Length: 52\r\n
+//     \r\n
+//     {"jsonrpc":"2.0","method":"initialized","params":{}}
+
+use serde_json::Value;
+use std::io::{BufRead, Error, ErrorKind, Write};
+
+// Returns None at the end of the stream.
+pub fn read_message<Reader: BufRead>(reader: &mut Reader) -> std::io::Result<Option<Value>> {
+    let mut content_length = None;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        // Other headers, like Content-Type, are ignored
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                content_length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+    let content_length = content_length
+        .ok_or_else(|| Error::new(ErrorKind::InvalidData, "missing Content-Length header"))?;
+    let mut content = vec![0; content_length];
+    reader.read_exact(&mut content)?;
+    serde_json::from_slice(&content)
+        .map(Some)
+        .map_err(|err| Error::new(ErrorKind::InvalidData, err))
+}
+
+pub fn write_message<Writer: Write>(writer: &mut Writer, message: &Value) -> std::io::Result<()> {
+    let content = message.to_string();
+    write!(
+        writer,
+        "Content-Length: {}\r\n\r\n{}",
+        content.len(),
+        content
+    )?;
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lsp::transport::*;
+    use serde_json::json;
+    use std::io::BufReader;
+
+    #[test]
+    fn read_and_write_messages() {
+        let mut stream = vec![];
+        let first = json!({"jsonrpc": "2.0", "method": "initialized", "params": {}});
+        let second = json!({"jsonrpc": "2.0", "id": 1, "result": "é"});
+        write_message(&mut stream, &first).unwrap();
+        stream.extend(b"Content-Type: application/vscode-jsonrpc; charset=utf-8\r\n");
+        write_message(&mut stream, &second).unwrap();
+
+        let mut reader = BufReader::new(&stream[..]);
+        assert_eq!(read_message(&mut reader).unwrap(), Some(first));
+        assert_eq!(read_message(&mut reader).unwrap(), Some(second));
+        assert_eq!(read_message(&mut reader).unwrap(), None);
+        assert!(read_message(&mut BufReader::new(&b"\r\n{}"[..])).is_err());
+    }
+}
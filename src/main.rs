@@ -19,7 +19,10 @@
 use components::component::Component;
 use std::convert::TryFrom;
 use std::io::{stdin, Write};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
 use structopt::StructOpt;
 use termion::event::Event;
 use termion::event::Key;
@@ -28,20 +31,85 @@ use termion::input::TermRead;
 use termion::raw::IntoRawMode;
 
 mod buffer;
+mod build;
+mod commands;
 mod components;
+mod diagnostics;
+mod display_width;
+mod encoding;
 mod event;
+mod file_history;
+mod file_operations;
+mod file_system;
+mod git_status;
 mod indexer;
+mod keymap;
+mod language;
+mod large_file;
+mod lsp;
 mod painting_utils;
+mod preview_cache;
 mod quick_open;
+mod session;
+mod settings;
 mod terminal;
+mod text_format;
+mod text_layout;
+mod text_position;
+mod theme;
 
+use build::Builder;
+use file_system::{FileSystem, LocalFileSystem, SshFileSystem};
+use git_status::GitStatusReader;
 use indexer::index::Indexer;
+use indexer::index::{FileIndexEntry, FileMetadata};
 use indexer::local_index::LocalIndexer;
 use indexer::ssh_index::SshConfig;
 use indexer::ssh_index::SshIndexer;
+use indexer::symbols::SymbolIndexer;
+use keymap::Keymap;
+use lsp::LanguageServers;
+use session::Session;
+use settings::Settings;
+use text_position::{split_position_suffix, TextPosition};
+
+struct InitialFile {
+    path: PathBuf,
+    position: Option<TextPosition>,
+}
 
 struct LocalConfig {
     directory_path: PathBuf,
+    initial_file: Option<InitialFile>,
+}
+
+impl LocalConfig {
+    // Accepts a directory, a file, or a file with a ":line[:column]" suffix. Files are opened in
+    // a tree rooted at their parent directory.
+    fn new(argument: &str) -> LocalConfig {
+        let (path, position) = if Path::new(argument).exists() {
+            (PathBuf::from(argument), None)
+        } else {
+            let (path, position) = split_position_suffix(argument);
+            (PathBuf::from(path), position)
+        };
+
+        if !path.is_file() {
+            return LocalConfig {
+                directory_path: path,
+                initial_file: None,
+            };
+        }
+
+        let directory_path = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+        LocalConfig {
+            directory_path,
+            initial_file: Some(InitialFile { path, position }),
+        }
+    }
 }
 
 enum LocationConfig {
@@ -51,8 +119,14 @@ enum LocationConfig {
 
 struct Config {
     location_config: LocationConfig,
+    tab_width: usize,
+    large_file_threshold: u64,
+    keymap: Keymap,
+    settings: Settings,
 }
 
+const TICK_INTERVAL: Duration = Duration::from_millis(25);
+
 fn run(config: Config) {
     let stdin = stdin();
     let mut stdout = MouseTerminal::from(std::io::stdout().into_raw_mode().unwrap());
@@ -69,47 +143,137 @@ fn run(config: Config) {
         height: terminal_height,
     };
 
-    let indexer: Box<dyn Indexer> = match config.location_config {
-        LocationConfig::Local(local_config) => {
-            Box::new(LocalIndexer::new(local_config.directory_path))
-        }
-        LocationConfig::Remote(ssh_config) => Box::new(SshIndexer::new(ssh_config)),
+    let mut initial_file: Option<InitialFile> = None;
+    let session_root = match &config.location_config {
+        LocationConfig::Local(local_config) => std::fs::canonicalize(&local_config.directory_path)
+            .unwrap_or_else(|_| local_config.directory_path.clone())
+            .to_string_lossy()
+            .into_owned(),
+        LocationConfig::Remote(ssh_config) => ssh_config.target(),
     };
-    let mut root_component = components::root::RootComponent::new(&*indexer);
+    let session_path = session::session_path(&session_root);
+    let is_local = matches!(config.location_config, LocationConfig::Local(_));
+    let (indexer, file_system): (Box<dyn Indexer>, Box<dyn FileSystem>) =
+        match config.location_config {
+            LocationConfig::Local(local_config) => {
+                initial_file = local_config.initial_file;
+                (
+                    Box::new(LocalIndexer::new(
+                        local_config.directory_path,
+                        config.settings.ignore_patterns(),
+                    )),
+                    Box::new(LocalFileSystem::new(session::state_dir())),
+                )
+            }
+            LocationConfig::Remote(ssh_config) => {
+                let file_system = SshFileSystem::new(ssh_config.ssh_args());
+                (
+                    Box::new(SshIndexer::new(
+                        ssh_config,
+                        config.settings.ignore_patterns(),
+                    )),
+                    Box::new(file_system),
+                )
+            }
+        };
+    let mut root_component = components::root::RootComponent::new(&*indexer, &*file_system);
+    root_component.set_tab_width(config.tab_width);
+    root_component.set_large_file_threshold(config.large_file_threshold);
+    root_component.set_keymap(config.keymap);
+    // Checked when the settings were loaded
+    root_component.set_theme(&config.settings.theme().unwrap());
+    if let Some(width) = config.settings.file_pane_width {
+        root_component.set_file_pane_width(width);
+    }
+    if let Some(line_numbers) = config.settings.line_numbers {
+        root_component.set_line_numbers(line_numbers);
+    }
+    if let Some(wrap_mode) = config.settings.wrap_mode {
+        root_component.set_wrap_mode(wrap_mode);
+    }
 
     // TODO: actually make this asynchronous
     while indexer.get_index().is_none() {}
     root_component.update_index();
+    // Reading every file over ssh would take too long
+    if is_local {
+        let paths = indexer
+            .get_index()
+            .unwrap()
+            .files
+            .into_iter()
+            .map(|index_entry| index_entry.path)
+            .collect();
+        root_component.set_symbol_indexer(SymbolIndexer::new(paths));
+        root_component.set_language_servers(LanguageServers::new(
+            PathBuf::from(&session_root),
+            config.settings.language_servers.clone(),
+        ));
+        if let Some(build_command) = &config.settings.build_command {
+            root_component.set_builder(Builder::new(
+                build_command.clone(),
+                PathBuf::from(&session_root),
+            ));
+        }
+        root_component.set_git_status_reader(GitStatusReader::new(PathBuf::from(&session_root)));
+    }
+    if let Some(session_path) = &session_path {
+        root_component.restore_session(Session::load(session_path));
+    }
 
     root_component.paint(&mut stdout, root_rect).unwrap();
 
-    for c in stdin.events() {
-        if c.is_err() {
-            continue;
+    if let Some(initial_file) = initial_file {
+        let index_entry = std::fs::canonicalize(&initial_file.path)
+            .ok()
+            .and_then(|path| FileIndexEntry::new(&path, FileMetadata::unknown()));
+        if let Some(index_entry) = index_entry {
+            let mut events = vec![event::Event::FileItemOpened(index_entry)];
+            if let Some(position) = initial_file.position {
+                events.push(event::Event::GoToPosition(position));
+            }
+            root_component.dispatch_events(&events);
+            root_component.paint(&mut stdout, root_rect).unwrap();
         }
+    }
 
-        let event = c.unwrap();
-
-        match event {
-            Event::Key(key) => match key {
-                Key::Ctrl(c) => {
-                    if c == 'c' {
-                        break;
-                    }
-                }
-                _ => {}
-            },
-            _ => {}
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        for c in stdin.events() {
+            if sender.send(c).is_err() {
+                break;
+            }
         }
+    });
 
-        let result = root_component.dispatch_event(event);
-        let events = result.events;
+    // Ticks are sent on a fixed schedule, even while input keeps coming in
+    let mut next_tick = Instant::now() + TICK_INTERVAL;
+    loop {
+        let now = Instant::now();
+        let events = if now >= next_tick {
+            next_tick = now + TICK_INTERVAL;
+            vec![event::Event::Tick]
+        } else {
+            match receiver.recv_timeout(next_tick - now) {
+                Ok(Err(_)) | Err(mpsc::RecvTimeoutError::Timeout) => continue,
+                Ok(Ok(Event::Key(Key::Ctrl('c')))) => break,
+                Ok(Ok(event)) => root_component.dispatch_event(event).events,
+                Err(mpsc::RecvTimeoutError::Disconnected) => break,
+            }
+        };
         root_component.dispatch_events(&events);
         root_component.paint(&mut stdout, root_rect).unwrap();
     }
 
     write!(stdout, "{}", termion::cursor::Show).unwrap();
     write!(stdout, "{}", termion::screen::ToMainScreen).unwrap();
+
+    if let Some(session_path) = &session_path {
+        if let Err(err) = root_component.session().save(session_path, &session_root) {
+            // Back on the main screen, so this is still visible after exiting
+            eprintln!("Could not save the session: {}", err);
+        }
+    }
 }
 
 #[derive(Debug, StructOpt)]
@@ -118,31 +282,83 @@ struct Options {
     #[structopt(long = "ssh")]
     ssh: bool,
 
+    #[structopt(
+        long = "tab-width",
+        help = "Number of columns between tab stops. Defaults to 4, or tab_width in the config."
+    )]
+    tab_width: Option<usize>,
+
+    #[structopt(
+        long = "large-file-threshold",
+        help = "Size in MiB above which files are opened read-only without loading them into memory. Defaults to 32, or large_file_threshold in the config."
+    )]
+    large_file_threshold: Option<u64>,
+
     #[structopt(
         parse(from_str),
-        help = "Directory to open. Current directory if unspecified."
+        help = "Directory or file[:line[:column]] to open. Current directory if unspecified."
     )]
     directory_or_ssh_options: Vec<String>,
 }
 
+// Problems with the keymap are reported before the terminal is taken over.
+fn load_keymap() -> Keymap {
+    let path = match keymap::default_keymap_path() {
+        None => return Keymap::new(),
+        Some(path) => path,
+    };
+    match Keymap::load(&path) {
+        Ok(keymap) => keymap,
+        Err(err) => {
+            eprintln!("Could not load the keymap: {}", err);
+            std::process::exit(1);
+        }
+    }
+}
+
+// The global settings, overridden by the ones of the project if it is local. Problems are
+// reported like those of the keymap.
+fn load_settings(project_path: Option<&Path>) -> Settings {
+    let global_path = settings::default_settings_path();
+    let project_path =
+        project_path.map(|project_path| project_path.join(settings::PROJECT_SETTINGS_FILE_NAME));
+    match Settings::load(global_path.as_deref(), project_path.as_deref()) {
+        Ok(settings) => settings,
+        Err(err) => {
+            eprintln!("Could not load the settings: {}", err);
+            std::process::exit(1);
+        }
+    }
+}
+
 fn main() {
     let options = Options::from_args();
-    let config = if options.ssh {
-        Config {
-            location_config: LocationConfig::Remote(SshConfig::new(
-                options.directory_or_ssh_options,
-            )),
-        }
+    let keymap = load_keymap();
+    let location_config = if options.ssh {
+        LocationConfig::Remote(SshConfig::new(options.directory_or_ssh_options))
     } else {
-        Config {
-            location_config: LocationConfig::Local(LocalConfig {
-                directory_path: if options.directory_or_ssh_options.len() == 0 {
-                    PathBuf::from(".")
-                } else {
-                    PathBuf::from(options.directory_or_ssh_options.first().unwrap())
-                },
-            }),
-        }
+        LocationConfig::Local(LocalConfig::new(
+            match options.directory_or_ssh_options.first() {
+                None => ".",
+                Some(argument) => argument,
+            },
+        ))
+    };
+    let settings = load_settings(match &location_config {
+        LocationConfig::Local(local_config) => Some(&local_config.directory_path),
+        LocationConfig::Remote(_) => None,
+    });
+    let config = Config {
+        location_config,
+        tab_width: options.tab_width.or(settings.tab_width).unwrap_or(4),
+        // Thresholds too large to count in bytes just mean that no file is ever large
+        large_file_threshold: options
+            .large_file_threshold
+            .or(settings.large_file_threshold)
+            .unwrap_or(32)
+            .saturating_mul(1024 * 1024),
+        keymap,
+        settings,
     };
 
     run(config);
@@ -1,20 +1,54 @@
+use crate::display_width::{displayed_grapheme, grapheme_width, width, DEFAULT_TAB_WIDTH};
 use crate::terminal::Rect;
 use crate::terminal::SPACES;
 use std::io::Write;
+use unicode_segmentation::UnicodeSegmentation;
 
 pub fn paint_truncated_text<Writer: Write>(
     stream: &mut Writer,
     text: &str,
     target_width: u16,
 ) -> std::io::Result<()> {
-    let cleaned_text = text.replace("\t", "     ");
-    let text_slice = match cleaned_text.char_indices().nth(target_width as usize) {
-        None => &cleaned_text,
-        Some((index, _)) => &cleaned_text[0..index],
-    };
-    write!(stream, "{}", text_slice)?;
-    let num_spaces = (target_width as usize) - text_slice.chars().count();
-    write!(stream, "{}", &SPACES[0..num_spaces])
+    paint_text_columns(stream, text, 0, target_width, DEFAULT_TAB_WIDTH)
+}
+
+// Paints the columns [first_column, first_column + target_width) of the text, padding with spaces.
+// Wide graphemes that are only partially visible are painted as spaces.
+pub fn paint_text_columns<Writer: Write>(
+    stream: &mut Writer,
+    text: &str,
+    first_column: usize,
+    target_width: u16,
+    tab_width: usize,
+) -> std::io::Result<()> {
+    let last_column = first_column + target_width as usize;
+    let mut column = 0;
+    let mut num_painted_columns = 0;
+    for grapheme in text.graphemes(true) {
+        let next_column = column + grapheme_width(grapheme, column, tab_width);
+        if next_column > last_column {
+            break;
+        }
+
+        if column >= first_column {
+            write!(
+                stream,
+                "{}",
+                displayed_grapheme(grapheme, column, tab_width)
+            )?;
+            num_painted_columns += next_column - column;
+        } else if next_column > first_column {
+            write!(stream, "{:1$}", "", next_column - first_column)?;
+            num_painted_columns += next_column - first_column;
+        }
+        column = next_column;
+    }
+    write!(
+        stream,
+        "{:1$}",
+        "",
+        target_width as usize - num_painted_columns
+    )
 }
 
 pub fn paint_empty_lines<Writer: Write>(stream: &mut Writer, rect: Rect) -> std::io::Result<()> {
@@ -28,3 +62,27 @@ pub fn paint_empty_lines<Writer: Write>(stream: &mut Writer, rect: Rect) -> std:
     }
     Ok(())
 }
+
+// Paints left_text aligned to the left and right_text aligned to the right, giving priority to
+// left_text if both do not fit.
+pub fn paint_left_and_right_text<Writer: Write>(
+    stream: &mut Writer,
+    left_text: &str,
+    right_text: &str,
+    target_width: u16,
+) -> std::io::Result<()> {
+    let left_width = width(left_text, DEFAULT_TAB_WIDTH);
+    let right_width = width(right_text, DEFAULT_TAB_WIDTH);
+    if left_width + right_width > target_width as usize {
+        return paint_truncated_text(stream, left_text, target_width);
+    }
+
+    let num_spaces = (target_width as usize) - left_width - right_width;
+    write!(
+        stream,
+        "{}{}{}",
+        left_text,
+        &SPACES[0..num_spaces],
+        right_text
+    )
+}
@@ -0,0 +1,247 @@
+/*
+    Copyright 2020, John Pothier
+    This file is part of Five.
+
+    Five is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    Five is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with Five.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+// Keeps the contents of recently previewed files in memory, so that moving back and forth through the
+// file tree doesn't read the same files again. Files can also be prefetched by a background thread.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::thread;
+use std::time::SystemTime;
+
+pub const DEFAULT_MAX_CACHED_BYTES: usize = 64 * 1024 * 1024;
+// Bigger files would push out too many others
+const MAX_CACHED_FILE_FRACTION: usize = 8;
+
+struct CachedFile {
+    modified: Option<SystemTime>,
+    data: Arc<Vec<u8>>,
+}
+
+struct PrefetchedFile {
+    path: String,
+    modified: Option<SystemTime>,
+    data: Vec<u8>,
+}
+
+pub struct PreviewCache {
+    files: HashMap<String, CachedFile>,
+    // Least recently used first
+    order: VecDeque<String>,
+    num_cached_bytes: usize,
+    max_cached_bytes: usize,
+    prefetch_sender: mpsc::Sender<Vec<String>>,
+    prefetched_receiver: mpsc::Receiver<PrefetchedFile>,
+}
+
+fn modified_time(path: &str) -> Option<SystemTime> {
+    std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+fn read_file(path: String, max_file_size: usize) -> Option<PrefetchedFile> {
+    let metadata = std::fs::metadata(&path).ok()?;
+    if !metadata.is_file() || metadata.len() > max_file_size as u64 {
+        return None;
+    }
+    let data = std::fs::read(&path).ok()?;
+    Some(PrefetchedFile {
+        path,
+        modified: metadata.modified().ok(),
+        data,
+    })
+}
+
+fn prefetch(
+    requests: mpsc::Receiver<Vec<String>>,
+    prefetched: mpsc::Sender<PrefetchedFile>,
+    max_file_size: usize,
+) {
+    while let Ok(mut paths) = requests.recv() {
+        // Only the latest request matters, the selection has moved on from the others
+        while let Ok(newer_paths) = requests.try_recv() {
+            paths = newer_paths;
+        }
+        for path in paths {
+            if let Some(file) = read_file(path, max_file_size) {
+                if prefetched.send(file).is_err() {
+                    return;
+                }
+            }
+        }
+    }
+}
+
+impl PreviewCache {
+    pub fn new(max_cached_bytes: usize) -> PreviewCache {
+        let (prefetch_sender, requests) = mpsc::channel();
+        let (prefetched, prefetched_receiver) = mpsc::channel();
+        let max_file_size = max_cached_bytes / MAX_CACHED_FILE_FRACTION;
+        thread::spawn(move || prefetch(requests, prefetched, max_file_size));
+
+        PreviewCache {
+            files: HashMap::new(),
+            order: VecDeque::new(),
+            num_cached_bytes: 0,
+            max_cached_bytes,
+            prefetch_sender,
+            prefetched_receiver,
+        }
+    }
+
+    fn touch(&mut self, path: &str) {
+        if let Some(position) = self.order.iter().position(|cached| cached == path) {
+            let path = self.order.remove(position).unwrap();
+            self.order.push_back(path);
+        }
+    }
+
+    fn insert(&mut self, path: String, modified: Option<SystemTime>, data: Arc<Vec<u8>>) {
+        if data.len() > self.max_cached_bytes / MAX_CACHED_FILE_FRACTION {
+            return;
+        }
+        self.remove(&path);
+        self.num_cached_bytes += data.len();
+        while self.num_cached_bytes > self.max_cached_bytes {
+            match self.order.pop_front() {
+                Some(oldest) => self.remove(&oldest),
+                None => break,
+            }
+        }
+        self.order.push_back(path.clone());
+        self.files.insert(path, CachedFile { modified, data });
+    }
+
+    pub fn remove(&mut self, path: &str) {
+        if let Some(file) = self.files.remove(path) {
+            self.num_cached_bytes -= file.data.len();
+            self.order.retain(|cached| cached != path);
+        }
+    }
+
+    fn receive_prefetched_files(&mut self) {
+        while let Ok(file) = self.prefetched_receiver.try_recv() {
+            // Don't let prefetching push out files that were actually looked at
+            if !self.files.contains_key(&file.path) {
+                self.insert(file.path, file.modified, Arc::new(file.data));
+            }
+        }
+    }
+
+    // Returns None if the file isn't cached, or has changed since it was.
+    pub fn get(&mut self, path: &str) -> Option<Arc<Vec<u8>>> {
+        self.receive_prefetched_files();
+        let file = self.files.get(path)?;
+        if file.modified != modified_time(path) {
+            self.remove(path);
+            return None;
+        }
+        let data = Arc::clone(&file.data);
+        self.touch(path);
+        Some(data)
+    }
+
+    pub fn load(&mut self, path: &str) -> std::io::Result<Arc<Vec<u8>>> {
+        if let Some(data) = self.get(path) {
+            return Ok(data);
+        }
+        let modified = modified_time(path);
+        let data = Arc::new(std::fs::read(path)?);
+        self.insert(String::from(path), modified, Arc::clone(&data));
+        Ok(data)
+    }
+
+    // Files that are already cached are skipped.
+    pub fn prefetch(&mut self, paths: Vec<String>) {
+        self.receive_prefetched_files();
+        let paths = paths
+            .into_iter()
+            .filter(|path| !self.files.contains_key(path))
+            .collect();
+        // The thread only stops when the cache is dropped
+        self.prefetch_sender.send(paths).unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::preview_cache::*;
+    use std::path::PathBuf;
+
+    fn write_temp_file(name: &str, content: &[u8]) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "five-{}-preview-cache-{}",
+            std::process::id(),
+            name
+        ));
+        std::fs::write(&path, content).unwrap();
+        path
+    }
+
+    #[test]
+    fn evict_least_recently_used() {
+        // Room for exactly 8 files
+        let paths: Vec<String> = (0..9)
+            .map(|i| {
+                let path = write_temp_file(&format!("lru-{}", i), &[0; 20]);
+                String::from(path.to_str().unwrap())
+            })
+            .collect();
+        let mut cache = PreviewCache::new(160);
+        for path in &paths[..8] {
+            cache.load(path).unwrap();
+        }
+        cache.get(&paths[0]).unwrap();
+        cache.load(&paths[8]).unwrap();
+
+        assert!(cache.get(&paths[0]).is_some());
+        assert!(cache.get(&paths[1]).is_none());
+        assert!(cache.get(&paths[8]).is_some());
+        for path in paths {
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn skip_files_too_large_to_cache() {
+        let path = write_temp_file("too-large", &[0; 100]);
+        let path = path.to_str().unwrap();
+        let mut cache = PreviewCache::new(160);
+        assert_eq!(cache.load(path).unwrap().len(), 100);
+        assert!(cache.get(path).is_none());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn prefetch_in_background() {
+        let path = write_temp_file("prefetch", b"prefetched");
+        let path = path.to_str().unwrap();
+        let mut cache = PreviewCache::new(1024);
+        cache.prefetch(vec![String::from(path)]);
+        let data = loop {
+            if let Some(data) = cache.get(path) {
+                break data;
+            }
+            thread::yield_now();
+        };
+        assert_eq!(*data, b"prefetched");
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -16,26 +16,424 @@
     along with Five.  If not, see <https://www.gnu.org/licenses/>.
 */
 
+use crate::commands::Command;
+use crate::diagnostics::Severity;
+use crate::file_history::FileHistory;
 use crate::indexer::index::FileIndexEntry;
 use crate::indexer::index::Index;
+use crate::indexer::symbols::Symbol;
+use crate::text_position::TextPosition;
+use std::collections::HashMap;
+use std::path::Path;
 
-pub type QuickOpenResult = FileIndexEntry;
+#[derive(Clone)]
+pub enum QuickOpenItem {
+    File(FileIndexEntry),
+    // Opening a folder shows it in the file tree
+    Folder(String),
+}
+
+impl QuickOpenItem {
+    pub fn path(&self) -> &str {
+        match self {
+            QuickOpenItem::File(index_entry) => &index_entry.path,
+            QuickOpenItem::Folder(path) => path,
+        }
+    }
+}
+
+// A file or folder listed in quick open, shown by name with the folder it is in next to it.
+#[derive(Clone)]
+pub struct QuickOpenResult {
+    pub item: QuickOpenItem,
+    pub name: String,
+    // Relative to the root of the index, and empty for items at the root
+    pub parent: String,
+}
+
+impl QuickOpenResult {
+    fn new(index: &Index, item: QuickOpenItem) -> QuickOpenResult {
+        let relative_path = Path::new(index.relative_path(item.path()));
+        let name = relative_path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or_default();
+        let parent = relative_path
+            .parent()
+            .and_then(|parent| parent.to_str())
+            .unwrap_or_default();
+        QuickOpenResult {
+            name: String::from(name),
+            parent: String::from(parent),
+            item,
+        }
+    }
+}
+
+// A command listed in the command palette, with the keys it is bound to.
+#[derive(Clone)]
+pub struct CommandResult {
+    pub command: Command,
+    pub name: &'static str,
+    pub description: &'static str,
+    pub binding: Option<String>,
+}
 
-pub fn get_quick_open_results(index: &Index, query: &str) -> Vec<QuickOpenResult> {
+// A place in a file, such as a reference that the language server found, with the text of its line.
+// Problems that a build reported have their message instead.
+#[derive(Clone)]
+pub struct LocationResult {
+    pub path: String,
+    // The column counts graphemes
+    pub position: TextPosition,
+    pub text: String,
+    pub severity: Option<Severity>,
+}
+
+// How well a file or folder matches the query, lower being better: 0 if its name starts with the
+// query, 1 if its name contains it, and 2 if its path relative to the root contains it, such as
+// "components/root" for "src/components/root.rs". None if it doesn't match.
+fn path_match_score(relative_path: &str, query: &str) -> Option<u8> {
+    let relative_path = relative_path.to_lowercase();
+    let name = match relative_path.rfind('/') {
+        Some(separator_index) => &relative_path[separator_index + 1..],
+        None => &relative_path,
+    };
+    if name.starts_with(query) {
+        Some(0)
+    } else if name.contains(query) {
+        Some(1)
+    } else if relative_path.contains(query) {
+        Some(2)
+    } else {
+        None
+    }
+}
+
+// An empty query lists the recently opened files that still exist, most recent first. Otherwise
+// the files and folders that match are listed best match first, and among equally good matches,
+// the files opened often and recently first.
+pub fn get_quick_open_results(
+    index: &Index,
+    query: &str,
+    history: &FileHistory,
+    now: u64,
+) -> Vec<QuickOpenResult> {
     let normalized_query = query.to_lowercase();
-    let mut result: Vec<QuickOpenResult> = Vec::new();
     if normalized_query.is_empty() {
-        return result;
+        let entries: HashMap<&str, &FileIndexEntry> = index
+            .files
+            .iter()
+            .map(|index_entry| (index_entry.path.as_str(), index_entry))
+            .collect();
+        return history
+            .files()
+            .iter()
+            .filter_map(|file| entries.get(file.path.as_str()))
+            .map(|entry| QuickOpenResult::new(index, QuickOpenItem::File((*entry).clone())))
+            .collect();
+    }
+
+    let frecencies = history.frecencies(now);
+    let file_matches = index.files.iter().filter_map(|index_entry| {
+        let score = path_match_score(index.relative_path(&index_entry.path), &normalized_query)?;
+        let frecency = frecencies.get(index_entry.path.as_str()).copied();
+        Some((
+            score,
+            frecency.unwrap_or(0.0),
+            QuickOpenItem::File(index_entry.clone()),
+        ))
+    });
+    // Folders come after files that match as well
+    let folder_matches = index.folders.iter().filter_map(|path| {
+        let score = path_match_score(index.relative_path(path), &normalized_query)?;
+        Some((score, 0.0, QuickOpenItem::Folder(path.clone())))
+    });
+    let mut matches: Vec<(u8, f64, QuickOpenItem)> = file_matches.chain(folder_matches).collect();
+    matches.sort_by(|(a_score, a_frecency, _), (b_score, b_frecency, _)| {
+        a_score
+            .cmp(b_score)
+            .then_with(|| b_frecency.total_cmp(a_frecency))
+    });
+    matches
+        .into_iter()
+        .map(|(_, _, item)| QuickOpenResult::new(index, item))
+        .collect()
+}
+
+// How well the text matches the query, lower being better: 0 if the text starts with the query, 1 if
+// it contains it, and 2 if it contains its characters in order. None if it doesn't match at all.
+fn match_score(text: &str, query: &str) -> Option<u8> {
+    if text.starts_with(query) {
+        return Some(0);
+    }
+    if text.contains(query) {
+        return Some(1);
+    }
+    let mut text_chars = text.chars();
+    if query
+        .chars()
+        .all(|c| text_chars.any(|text_char| text_char == c))
+    {
+        Some(2)
+    } else {
+        None
+    }
+}
+
+// Commands match on their description or their name, best matches first. All commands match an empty
+// query, so that the palette starts out listing everything.
+pub fn get_command_results(commands: &[CommandResult], query: &str) -> Vec<CommandResult> {
+    let normalized_query = query.to_lowercase();
+    let mut results: Vec<(u8, &CommandResult)> = commands
+        .iter()
+        .filter_map(|command| {
+            let name = command.name.replace('_', " ");
+            let description = command.description.to_lowercase();
+            let score = match (
+                match_score(&description, &normalized_query),
+                match_score(&name, &normalized_query),
+            ) {
+                (Some(a), Some(b)) => Some(a.min(b)),
+                (a, b) => a.or(b),
+            };
+            score.map(|score| (score, command))
+        })
+        .collect();
+    results.sort_by_key(|(score, _)| *score);
+    results
+        .into_iter()
+        .map(|(_, command)| command.clone())
+        .collect()
+}
+
+// Symbols match on their name, best matches first, and otherwise keep their order. All symbols match
+// an empty query.
+pub fn get_symbol_results(symbols: &[Symbol], query: &str) -> Vec<Symbol> {
+    let normalized_query = query.to_lowercase();
+    let mut results: Vec<(u8, &Symbol)> = symbols
+        .iter()
+        .filter_map(|symbol| {
+            let score = match_score(&symbol.name.to_lowercase(), &normalized_query)?;
+            Some((score, symbol))
+        })
+        .collect();
+    results.sort_by_key(|(score, _)| *score);
+    results
+        .into_iter()
+        .map(|(_, symbol)| symbol.clone())
+        .collect()
+}
+
+// Locations match on the text of their line or on their path, best matches first, and otherwise keep
+// their order. All locations match an empty query.
+pub fn get_location_results(locations: &[LocationResult], query: &str) -> Vec<LocationResult> {
+    let normalized_query = query.to_lowercase();
+    let mut results: Vec<(u8, &LocationResult)> = locations
+        .iter()
+        .filter_map(|location| {
+            let score = match (
+                match_score(&location.text.to_lowercase(), &normalized_query),
+                match_score(&location.path.to_lowercase(), &normalized_query),
+            ) {
+                (Some(a), Some(b)) => Some(a.min(b)),
+                (a, b) => a.or(b),
+            };
+            score.map(|score| (score, location))
+        })
+        .collect();
+    results.sort_by_key(|(score, _)| *score);
+    results
+        .into_iter()
+        .map(|(_, location)| location.clone())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::commands::Command;
+    use crate::file_history::FileHistory;
+    use crate::indexer::index::{FileMetadata, FileTreeFolder, FileTreeNode};
+    use crate::indexer::symbols::extract_symbols;
+    use crate::quick_open::*;
+    use std::path::Path;
+
+    fn file(path: &str) -> FileTreeNode {
+        FileTreeNode::File(FileIndexEntry::new(Path::new(path), FileMetadata::unknown()).unwrap())
     }
 
-    for index_entry in &index.files {
-        if index_entry
-            .normalized_filename
-            .starts_with(&normalized_query)
-        {
-            result.push(index_entry.clone());
+    fn folder(path: &str, children: Vec<FileTreeNode>) -> FileTreeNode {
+        FileTreeNode::Folder(FileTreeFolder {
+            children,
+            folder_name: String::from(Path::new(path).file_name().unwrap().to_str().unwrap()),
+            path: String::from(path),
+        })
+    }
+
+    fn test_index(paths: &[&str]) -> Index {
+        Index::new(folder(
+            "/five",
+            paths.iter().map(|path| file(path)).collect(),
+        ))
+    }
+
+    #[test]
+    fn match_relative_paths() {
+        let index = Index::new(folder(
+            "/five",
+            vec![
+                folder(
+                    "/five/src",
+                    vec![
+                        folder(
+                            "/five/src/components",
+                            vec![
+                                file("/five/src/components/mod.rs"),
+                                file("/five/src/components/root.rs"),
+                            ],
+                        ),
+                        file("/five/src/main.rs"),
+                        file("/five/src/mod.rs"),
+                    ],
+                ),
+                file("/five/Cargo.toml"),
+            ],
+        ));
+        let results = |query| -> Vec<String> {
+            get_quick_open_results(&index, query, &FileHistory::default(), 0)
+                .iter()
+                .map(|result| match &result.item {
+                    QuickOpenItem::File(_) => format!("{} in {}", result.name, result.parent),
+                    QuickOpenItem::Folder(_) => format!("{}/ in {}", result.name, result.parent),
+                })
+                .collect()
+        };
+        assert_eq!(
+            results("components/ROOT"),
+            vec!["root.rs in src/components"]
+        );
+        assert_eq!(
+            results("mod"),
+            vec!["mod.rs in src/components", "mod.rs in src"]
+        );
+        // Names come before paths, and folders after files that match as well
+        assert_eq!(
+            results("comp"),
+            vec![
+                "components/ in src",
+                "mod.rs in src/components",
+                "root.rs in src/components"
+            ]
+        );
+        assert_eq!(results("cargo"), vec!["Cargo.toml in "]);
+        assert_eq!(results("src/m"), vec!["main.rs in src", "mod.rs in src"]);
+    }
+
+    #[test]
+    fn rank_files_by_frecency() {
+        let index = test_index(&["/five/main.rs", "/five/mod.rs", "/five/menu.rs"]);
+        let now = 1_000_000;
+        let mut history = FileHistory::default();
+        history.record_open("/five/menu.rs", now - 100);
+        history.record_open("/five/deleted.rs", now - 50);
+        history.record_open("/five/mod.rs", now);
+        let paths = |query| -> Vec<String> {
+            get_quick_open_results(&index, query, &history, now)
+                .iter()
+                .map(|result| String::from(result.item.path()))
+                .collect()
+        };
+        // Recent files that no longer exist are left out
+        assert_eq!(paths(""), vec!["/five/mod.rs", "/five/menu.rs"]);
+        assert_eq!(
+            paths("m"),
+            vec!["/five/mod.rs", "/five/menu.rs", "/five/main.rs"]
+        );
+        assert_eq!(paths("MA"), vec!["/five/main.rs"]);
+    }
+
+    fn command(command: Command, name: &'static str, description: &'static str) -> CommandResult {
+        CommandResult {
+            command,
+            name,
+            description,
+            binding: None,
         }
     }
 
-    result
+    #[test]
+    fn match_commands() {
+        let commands = vec![
+            command(
+                Command::ToggleWrap,
+                "toggle_wrap",
+                "Turn soft wrap on or off",
+            ),
+            command(Command::Save, "save", "Save the open file"),
+            command(Command::QuickOpen, "quick_open", "Find a file by name"),
+        ];
+        let names = |query| -> Vec<&str> {
+            get_command_results(&commands, query)
+                .iter()
+                .map(|result| result.name)
+                .collect()
+        };
+        assert_eq!(names(""), vec!["toggle_wrap", "save", "quick_open"]);
+        assert_eq!(names("SAVE"), vec!["save"]);
+        assert_eq!(names("toggle wrap"), vec!["toggle_wrap"]);
+        assert_eq!(names("file"), vec!["save", "quick_open"]);
+        assert_eq!(names("fnd"), vec!["quick_open"]);
+        assert_eq!(names("xyz"), Vec::<&str>::new());
+    }
+
+    #[test]
+    fn match_symbols() {
+        let symbols = extract_symbols(
+            "/five/root.rs",
+            "pub struct RootComponent {\nfn paint_result() {\nfn start_quick_open() {\nmod tests {\n",
+        );
+        let names = |query| -> Vec<String> {
+            get_symbol_results(&symbols, query)
+                .into_iter()
+                .map(|symbol| symbol.name)
+                .collect()
+        };
+        assert_eq!(
+            names(""),
+            vec!["RootComponent", "paint_result", "start_quick_open", "tests"]
+        );
+        assert_eq!(names("Start"), vec!["start_quick_open"]);
+        assert_eq!(
+            names("t"),
+            vec!["tests", "RootComponent", "paint_result", "start_quick_open"]
+        );
+        assert_eq!(names("pr"), vec!["paint_result"]);
+        assert_eq!(names("xyz"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn match_locations() {
+        let location = |path: &str, line, text: &str| LocationResult {
+            path: String::from(path),
+            position: TextPosition::new(line, 4),
+            text: String::from(text),
+            severity: None,
+        };
+        let locations = vec![
+            location("/five/main.rs", 3, "let root = new_root();"),
+            location("/five/root.rs", 10, "fn new_root() -> Root {"),
+            location("/five/root.rs", 20, "new_root()"),
+        ];
+        let lines = |query| -> Vec<usize> {
+            get_location_results(&locations, query)
+                .into_iter()
+                .map(|location| location.position.line)
+                .collect()
+        };
+        assert_eq!(lines(""), vec![3, 10, 20]);
+        assert_eq!(lines("Root {"), vec![10]);
+        assert_eq!(lines("root.rs"), vec![10, 20]);
+        assert_eq!(lines("new"), vec![20, 3, 10]);
+        assert_eq!(lines("xyz"), Vec::<usize>::new());
+    }
 }
@@ -0,0 +1,325 @@
+/*
+    Copyright 2020, John Pothier
+    This file is part of Five.
+
+    Five is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    Five is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with Five.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use crate::components::directory_tree::{SortOrder, TreeMode};
+use crate::file_history::RecentFile;
+use crate::text_position::TextPosition;
+use std::convert::TryFrom;
+use std::path::{Path, PathBuf};
+
+// What is remembered about each directory between launches: the open file, where the cursor was
+// in the files opened recently, the state of the tree, the quick open queries and which files are
+// opened the most.
+
+// Older positions are forgotten beyond this many files
+pub const MAX_FILE_STATES: usize = 200;
+pub const MAX_QUERY_HISTORY: usize = 50;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct FileState {
+    pub path: String,
+    pub cursor: TextPosition,
+    pub start_line: usize,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TreeState {
+    pub mode: TreeMode,
+    pub sort_order: SortOrder,
+    pub selected_path: Option<String>,
+    pub current_folder_path: Option<String>,
+    pub expanded_folders: Vec<String>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Session {
+    pub open_file: Option<String>,
+    pub file_view_focused: bool,
+    // Most recently viewed last
+    pub files: Vec<FileState>,
+    pub tree: Option<TreeState>,
+    // Most recent last
+    pub quick_open_history: Vec<String>,
+    pub recent_files: Vec<RecentFile>,
+}
+
+// Where state that isn't configuration goes: $XDG_STATE_HOME/five, or ~/.local/state/five.
+pub fn state_dir() -> Option<PathBuf> {
+    let state_home = match std::env::var_os("XDG_STATE_HOME") {
+        Some(state_home) if !state_home.is_empty() => PathBuf::from(state_home),
+        _ => PathBuf::from(std::env::var_os("HOME")?)
+            .join(".local")
+            .join("state"),
+    };
+    Some(state_home.join("five"))
+}
+
+// FNV-1a, which unlike the standard library's hasher is guaranteed to stay the same between
+// builds, so that the file names do too.
+fn stable_hash(text: &str) -> u64 {
+    text.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+// The file that holds the session of the root, which is a local path or an ssh target.
+pub fn session_path(root: &str) -> Option<PathBuf> {
+    Some(
+        state_dir()?
+            .join("sessions")
+            .join(format!("{:016x}.toml", stable_hash(root))),
+    )
+}
+
+// The folder that items deleted from under the root are moved to, inside the given state folder, which
+// is on the remote host for an ssh target.
+pub fn trash_folder(state_dir: &Path, root: &str) -> PathBuf {
+    state_dir
+        .join("trash")
+        .join(format!("{:016x}", stable_hash(root)))
+}
+
+fn get_string(table: &toml::Table, key: &str) -> Option<String> {
+    table.get(key)?.as_str().map(String::from)
+}
+
+fn get_strings(table: &toml::Table, key: &str) -> Vec<String> {
+    match table.get(key).and_then(|value| value.as_array()) {
+        None => vec![],
+        Some(values) => values
+            .iter()
+            .filter_map(|value| value.as_str().map(String::from))
+            .collect(),
+    }
+}
+
+fn get_usize(table: &toml::Table, key: &str) -> usize {
+    table
+        .get(key)
+        .and_then(|value| value.as_integer())
+        .and_then(|value| usize::try_from(value).ok())
+        .unwrap_or(0)
+}
+
+fn strings_value(strings: &[String]) -> toml::Value {
+    toml::Value::Array(
+        strings
+            .iter()
+            .map(|string| toml::Value::String(string.clone()))
+            .collect(),
+    )
+}
+
+fn usize_value(value: usize) -> toml::Value {
+    toml::Value::Integer(i64::try_from(value).unwrap_or(i64::MAX))
+}
+
+fn get_u64(table: &toml::Table, key: &str) -> Option<u64> {
+    u64::try_from(table.get(key)?.as_integer()?).ok()
+}
+
+fn u64_value(value: u64) -> toml::Value {
+    toml::Value::Integer(i64::try_from(value).unwrap_or(i64::MAX))
+}
+
+impl TreeState {
+    fn to_table(&self) -> toml::Table {
+        let mut table = toml::Table::new();
+        let mode = match self.mode {
+            TreeMode::Expandable => "expandable",
+            TreeMode::DrillDown => "drill_down",
+        };
+        table.insert("mode".into(), mode.into());
+        table.insert("sort_order".into(), self.sort_order.name().into());
+        if let Some(selected_path) = &self.selected_path {
+            table.insert("selected".into(), selected_path.as_str().into());
+        }
+        if let Some(current_folder_path) = &self.current_folder_path {
+            table.insert("current_folder".into(), current_folder_path.as_str().into());
+        }
+        table.insert("expanded".into(), strings_value(&self.expanded_folders));
+        table
+    }
+
+    fn from_table(table: &toml::Table) -> TreeState {
+        TreeState {
+            mode: match table.get("mode").and_then(|mode| mode.as_str()) {
+                Some("drill_down") => TreeMode::DrillDown,
+                _ => TreeMode::Expandable,
+            },
+            sort_order: get_string(table, "sort_order")
+                .and_then(|name| SortOrder::from_name(&name))
+                .unwrap_or(SortOrder::Name),
+            selected_path: get_string(table, "selected"),
+            current_folder_path: get_string(table, "current_folder"),
+            expanded_folders: get_strings(table, "expanded"),
+        }
+    }
+}
+
+impl Session {
+    // A missing or unreadable session starts over rather than getting in the way.
+    pub fn load(path: &Path) -> Session {
+        std::fs::read_to_string(path)
+            .ok()
+            .and_then(|text| Session::from_toml(&text))
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, path: &Path, root: &str) -> std::io::Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, self.to_toml(root))
+    }
+
+    pub fn to_toml(&self, root: &str) -> String {
+        let mut table = toml::Table::new();
+        // Only there for whoever looks at the file, since its name is a hash
+        table.insert("root".into(), root.into());
+        if let Some(open_file) = &self.open_file {
+            table.insert("open_file".into(), open_file.as_str().into());
+        }
+        table.insert("file_view_focused".into(), self.file_view_focused.into());
+        table.insert(
+            "quick_open_history".into(),
+            strings_value(&self.quick_open_history),
+        );
+        if let Some(tree) = &self.tree {
+            table.insert("tree".into(), toml::Value::Table(tree.to_table()));
+        }
+        let files = self
+            .files
+            .iter()
+            .map(|file| {
+                let mut file_table = toml::Table::new();
+                file_table.insert("path".into(), file.path.as_str().into());
+                file_table.insert("line".into(), usize_value(file.cursor.line));
+                file_table.insert("column".into(), usize_value(file.cursor.column));
+                file_table.insert("start_line".into(), usize_value(file.start_line));
+                toml::Value::Table(file_table)
+            })
+            .collect();
+        table.insert("files".into(), toml::Value::Array(files));
+        let recent_files = self
+            .recent_files
+            .iter()
+            .map(|file| {
+                let mut file_table = toml::Table::new();
+                file_table.insert("path".into(), file.path.as_str().into());
+                file_table.insert("open_count".into(), u64_value(file.open_count.into()));
+                file_table.insert("last_opened".into(), u64_value(file.last_opened));
+                toml::Value::Table(file_table)
+            })
+            .collect();
+        table.insert("recent_files".into(), toml::Value::Array(recent_files));
+        table.to_string()
+    }
+
+    pub fn from_toml(text: &str) -> Option<Session> {
+        let table: toml::Table = text.parse().ok()?;
+        let files = match table.get("files").and_then(|files| files.as_array()) {
+            None => vec![],
+            Some(files) => files
+                .iter()
+                .filter_map(|file| {
+                    let file = file.as_table()?;
+                    Some(FileState {
+                        path: get_string(file, "path")?,
+                        cursor: TextPosition::new(
+                            get_usize(file, "line"),
+                            get_usize(file, "column"),
+                        ),
+                        start_line: get_usize(file, "start_line"),
+                    })
+                })
+                .collect(),
+        };
+        let recent_files = match table.get("recent_files").and_then(|files| files.as_array()) {
+            None => vec![],
+            Some(files) => files
+                .iter()
+                .filter_map(|file| {
+                    let file = file.as_table()?;
+                    Some(RecentFile {
+                        path: get_string(file, "path")?,
+                        open_count: u32::try_from(get_u64(file, "open_count")?).ok()?,
+                        last_opened: get_u64(file, "last_opened")?,
+                    })
+                })
+                .collect(),
+        };
+        Some(Session {
+            open_file: get_string(&table, "open_file"),
+            file_view_focused: table
+                .get("file_view_focused")
+                .and_then(|focused| focused.as_bool())
+                .unwrap_or(false),
+            files,
+            tree: table
+                .get("tree")
+                .and_then(|tree| tree.as_table())
+                .map(TreeState::from_table),
+            quick_open_history: get_strings(&table, "quick_open_history"),
+            recent_files,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn save_and_load() {
+        let session = Session {
+            open_file: Some(String::from("/home/me/project/src/main.rs")),
+            file_view_focused: true,
+            files: vec![FileState {
+                path: String::from("/home/me/project/src/main.rs"),
+                cursor: TextPosition::new(41, 7),
+                start_line: 30,
+            }],
+            tree: Some(TreeState {
+                mode: TreeMode::DrillDown,
+                sort_order: SortOrder::Size,
+                selected_path: Some(String::from("/home/me/project/src/main.rs")),
+                current_folder_path: Some(String::from("/home/me/project/src")),
+                expanded_folders: vec![String::from("/home/me/project/src")],
+            }),
+            quick_open_history: vec![String::from("main"), String::from("lib:12")],
+            recent_files: vec![RecentFile {
+                path: String::from("/home/me/project/src/main.rs"),
+                open_count: 3,
+                last_opened: 1_600_000_000,
+            }],
+        };
+        let text = session.to_toml("/home/me/project");
+        assert_eq!(Session::from_toml(&text), Some(session));
+        assert_eq!(Session::from_toml(""), Some(Session::default()));
+        assert_eq!(Session::from_toml("files = ["), None);
+    }
+
+    #[test]
+    fn name_session_files() {
+        let path = session_path("/home/me/project").unwrap();
+        assert_eq!(path, session_path("/home/me/project").unwrap());
+        assert_ne!(path, session_path("ssh me@host:/home/me/project").unwrap());
+        assert!(path.to_str().unwrap().ends_with(".toml"));
+    }
+}
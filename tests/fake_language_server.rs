@@ -0,0 +1,133 @@
+/*
+    Copyright 2020, John Pothier
+    This file is part of Five.
+
+    Five is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    Five is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with Five.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+// Checks that the fake language server used by the unit tests follows its script.
+
+use serde_json::{json, Value};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::PathBuf;
+use std::process::{ChildStdout, Command, Stdio};
+
+// The server is an example, which `cargo test` builds into the examples folder next to the deps
+// folder of this test. Only testing some of the targets, such as with --test, leaves it out.
+fn fake_language_server() -> Option<PathBuf> {
+    let path = std::env::current_exe()
+        .ok()?
+        .parent()?
+        .parent()?
+        .join("examples")
+        .join("fake_language_server");
+    if !path.exists() {
+        eprintln!("Skipped: the fake language server was not built, run cargo build --examples");
+        return None;
+    }
+    Some(path)
+}
+
+fn send(stdin: &mut impl Write, message: Value) {
+    let content = message.to_string();
+    write!(
+        stdin,
+        "Content-Length: {}\r\n\r\n{}",
+        content.len(),
+        content
+    )
+    .unwrap();
+    stdin.flush().unwrap();
+}
+
+fn receive(stdout: &mut BufReader<ChildStdout>) -> Value {
+    let mut content_length = 0;
+    loop {
+        let mut header = String::new();
+        stdout.read_line(&mut header).unwrap();
+        match header.trim_end().strip_prefix("Content-Length: ") {
+            Some(length) => content_length = length.parse().unwrap(),
+            None if header.trim_end().is_empty() => break,
+            None => {}
+        }
+    }
+    let mut content = vec![0; content_length];
+    stdout.read_exact(&mut content).unwrap();
+    serde_json::from_slice(&content).unwrap()
+}
+
+#[test]
+fn follow_script() {
+    let server_path = match fake_language_server() {
+        Some(server_path) => server_path,
+        None => return,
+    };
+    let mut server = Command::new(server_path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdin = server.stdin.take().unwrap();
+    let mut stdout = BufReader::new(server.stdout.take().unwrap());
+
+    send(
+        &mut stdin,
+        json!({"jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {}}),
+    );
+    let response = receive(&mut stdout);
+    assert_eq!(response["id"], 1);
+    assert_eq!(
+        response["result"]["capabilities"]["textDocumentSync"]["change"],
+        2
+    );
+
+    let uri = "file:///main.rs";
+    send(
+        &mut stdin,
+        json!({"jsonrpc": "2.0", "method": "textDocument/didOpen", "params": {
+            "textDocument": {"uri": uri, "languageId": "rust", "version": 1, "text": "fn main() {}\n"},
+        }}),
+    );
+    assert_eq!(receive(&mut stdout)["params"]["diagnostics"], json!([]));
+    send(
+        &mut stdin,
+        json!({"jsonrpc": "2.0", "method": "textDocument/didChange", "params": {
+            "textDocument": {"uri": uri, "version": 2},
+            "contentChanges": [{
+                "range": {"start": {"line": 0, "character": 11}, "end": {"line": 0, "character": 11}},
+                "text": " error ",
+            }],
+        }}),
+    );
+    let diagnostics = &receive(&mut stdout)["params"]["diagnostics"];
+    assert_eq!(
+        diagnostics[0]["range"]["start"],
+        json!({"line": 0, "character": 12})
+    );
+    assert_eq!(diagnostics[0]["severity"], 1);
+
+    send(
+        &mut stdin,
+        json!({"jsonrpc": "2.0", "id": 2, "method": "textDocument/hover", "params": {
+            "textDocument": {"uri": uri},
+            "position": {"line": 0, "character": 4},
+        }}),
+    );
+    let response = receive(&mut stdout);
+    assert_eq!(response["id"], 2);
+    assert_eq!(response["result"]["contents"]["value"], "`main`");
+
+    send(&mut stdin, json!({"jsonrpc": "2.0", "method": "exit"}));
+    assert!(server.wait().unwrap().success());
+}